@@ -0,0 +1,152 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use gdal::{Dataset, Driver};
+use gdal::raster::{GDALDataType, GdalType};
+use crate::catalog::ImageryFile;
+
+/// the most rows of a band that are copied at once, so that large bands aren't read into memory whole.
+const MAX_STRIP_ROWS: usize = 256;
+
+/// counts extractions so concurrent requests for the same band write to different temporary files.
+static EXTRACT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// BandCache extracts single bands out of catalogued files into GeoTIFFs, so that clients can
+/// download one band (e.g. NIR) without the whole file.
+/// Extracted bands are kept in a cache directory and reused for later requests of the same version of the file,
+/// so that a file that changes (and is rescanned) has its bands extracted again.
+#[derive(Debug, Clone)]
+pub struct BandCache {
+  dir: PathBuf
+}
+
+impl BandCache {
+    pub fn new(dir: PathBuf) -> BandCache {
+      BandCache { dir }
+    }
+
+    /// returns the path to a GeoTIFF containing only band `band` (starting at 1) of `file`,
+    /// extracting it if it hasn't been already for the file's current version (see ImageryFile::version).
+    pub fn get(&self, collection_id: &str, item_id: &str, file: &ImageryFile, band: isize) -> Result<PathBuf, String> {
+      let mut hasher = DefaultHasher::new();
+      file.version().hash(&mut hasher);
+      let path = self.dir.join(collection_id).join(format!("{}_{:08x}_b{}.tif", item_id, hasher.finish() as u32, band));
+      if path.exists() {
+        return Ok(path)
+      }
+
+      fs::create_dir_all(path.parent().unwrap()).map_err(|e| e.to_string())?;
+
+      // extract to a temporary file first, so that a partially written band is never served.
+      let tmp = path.with_extension(format!("tmp{}", EXTRACT_COUNT.fetch_add(1, Ordering::Relaxed)));
+      let result = extract_band(file, band, &tmp).and_then(|_| fs::rename(&tmp, &path).map_err(|e| e.to_string()));
+      if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+      }
+      result.map(|_| path)
+    }
+}
+
+impl Default for BandCache {
+  /// a cache directory for this run of the service, so that bands left over from earlier runs (possibly
+  /// extracted by another version of autostac) aren't reused.
+  fn default() -> Self {
+    BandCache::new(env::temp_dir().join(format!("autostac-bands-{}", process::id())))
+  }
+}
+
+/// writes band `band` of `file` to a new single band GeoTIFF at `out`, keeping the band's data type,
+/// nodata value, and the file's georeferencing.
+pub fn extract_band(file: &ImageryFile, band: isize, out: &Path) -> Result<(), String> {
+  let src = file.open().map_err(|e| e.to_string())?;
+  let band_type = src.rasterband(band).map_err(|e| e.to_string())?.band_type();
+
+  let result = match band_type {
+    GDALDataType::GDT_Byte => copy_band::<u8>(&src, band, out),
+    GDALDataType::GDT_UInt16 => copy_band::<u16>(&src, band, out),
+    GDALDataType::GDT_Int16 => copy_band::<i16>(&src, band, out),
+    GDALDataType::GDT_UInt32 => copy_band::<u32>(&src, band, out),
+    GDALDataType::GDT_Int32 => copy_band::<i32>(&src, band, out),
+    GDALDataType::GDT_Float32 => copy_band::<f32>(&src, band, out),
+    GDALDataType::GDT_Float64 => copy_band::<f64>(&src, band, out),
+    _ => return Err(format!("unsupported data type for band {}", band)),
+  };
+  result.map_err(|e| e.to_string())
+}
+
+fn copy_band<T: GdalType + Copy>(src: &Dataset, band: isize, out: &Path) -> gdal::errors::Result<()> {
+  let src_band = src.rasterband(band)?;
+  let size = src_band.size();
+
+  let driver = Driver::get("GTiff")?;
+  let mut dst = driver.create_with_band_type::<T>(&out.to_string_lossy(), size.0 as isize, size.1 as isize, 1)?;
+  if let Ok(geo_transform) = src.geo_transform() {
+    dst.set_geo_transform(&geo_transform)?;
+  }
+  dst.set_projection(&src.projection())?;
+
+  let mut dst_band = dst.rasterband(1)?;
+  if let Some(nodata) = src_band.no_data_value() {
+    dst_band.set_no_data_value(nodata)?;
+  }
+
+  // copy a strip of rows at a time: the height of the source's blocks, up to MAX_STRIP_ROWS.
+  let strip_rows = src_band.block_size().1.clamp(1, MAX_STRIP_ROWS);
+  let mut row = 0;
+  while row < size.1 {
+    let strip = (size.0, strip_rows.min(size.1 - row));
+    let data = src_band.read_as::<T>((0, row as isize), strip, strip, None)?;
+    dst_band.write((0, row as isize), strip, &data)?;
+    row += strip.1;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use gdal::Dataset;
+  use gdal::raster::GDALDataType;
+  use crate::bands::BandCache;
+  use crate::catalog::{ImageryFile, ScanOptions};
+  use crate::catalog::test_utils::{test_dir, write_geotiff};
+
+  #[test]
+  fn test_extract_band() {
+    let dir = test_dir("bands");
+    let src = dir.join("scene.tif");
+    write_geotiff(&src, 4);
+    let file = ImageryFile::new(
+      &Dataset::open(&src).unwrap(),
+      src.to_owned(),
+      src.display().to_string(),
+      "scene",
+      "imagery",
+      &ScanOptions::default()
+    ).unwrap();
+
+    let cache = BandCache::new(dir.join("cache"));
+    let path = cache.get("imagery", "scene", &file, 2).unwrap();
+    assert_eq!(path.parent().unwrap(), dir.join("cache").join("imagery"));
+    assert!(path.file_name().unwrap().to_string_lossy().ends_with("_b2.tif"));
+    assert_eq!(cache.get("imagery", "scene", &file, 2).unwrap(), path);
+
+    let band = Dataset::open(&path).unwrap();
+    assert_eq!(band.raster_count(), 1);
+    assert_eq!(band.raster_size(), (10, 10));
+    assert_eq!(band.rasterband(1).unwrap().band_type(), GDALDataType::GDT_Byte);
+    assert_eq!(band.geo_transform().unwrap(), [-123.5, 0.01, 0., 48.6, 0., -0.01]);
+
+    // bands that don't exist can't be extracted.
+    assert!(cache.get("imagery", "scene", &file, 5).is_err());
+
+    // a changed file has its bands extracted again.
+    write_geotiff(&src, 3);
+    let changed = cache.get("imagery", "scene", &file, 2).unwrap();
+    assert_ne!(changed, path);
+    assert!(changed.exists());
+  }
+}
@@ -0,0 +1,242 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// number of consecutive failed S3 calls before the breaker opens.
+pub const S3_FAILURE_THRESHOLD: u32 = 5;
+
+/// how long an open breaker fast-fails before allowing a trial call.
+pub const S3_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// The state of a CircuitBreaker.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreakerState {
+  /// calls are attempted normally.
+  Closed,
+  /// calls fail fast without being attempted.
+  Open,
+  /// the cooldown has passed and the next call is a trial. If it succeeds, the breaker closes.
+  /// Other calls fail fast until the trial finishes.
+  HalfOpen
+}
+
+/// The error returned from a call made through a CircuitBreaker.
+#[derive(Debug)]
+pub enum BreakerError<E> {
+  /// the breaker is open and the call was not attempted.
+  Open,
+  /// the call was attempted and failed.
+  Failed(E)
+}
+
+impl<E> BreakerError<E> {
+    /// maps the error of a failed call, e.g. to a message.
+    pub fn map<F, O: FnOnce(E) -> F>(self, op: O) -> BreakerError<F> {
+      match self {
+        BreakerError::Open => BreakerError::Open,
+        BreakerError::Failed(e) => BreakerError::Failed(op(e)),
+      }
+    }
+}
+
+#[derive(Debug)]
+struct BreakerInner {
+  consecutive_failures: u32,
+  opened_at: Option<Instant>,
+  /// whether a half-open breaker's trial call is being made.
+  trial_in_flight: bool
+}
+
+/// CircuitBreaker protects the service from a flapping backend (e.g. an S3 server).
+/// After `failure_threshold` consecutive failures, calls fail fast for `cooldown` instead
+/// of each waiting on the backend.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+  failure_threshold: u32,
+  cooldown: Duration,
+  inner: Mutex<BreakerInner>
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> CircuitBreaker {
+      CircuitBreaker {
+        failure_threshold,
+        cooldown,
+        inner: Mutex::new(BreakerInner {
+          consecutive_failures: 0,
+          opened_at: None,
+          trial_in_flight: false
+        })
+      }
+    }
+
+    /// the current state of the breaker.
+    pub fn state(&self) -> BreakerState {
+      self.state_at(Instant::now())
+    }
+
+    fn state_at(&self, now: Instant) -> BreakerState {
+      let inner = self.inner.lock().unwrap();
+      match inner.opened_at {
+        Some(t) if now.duration_since(t) >= self.cooldown => BreakerState::HalfOpen,
+        Some(_) => BreakerState::Open,
+        None => BreakerState::Closed,
+      }
+    }
+
+    pub fn record_success(&self) {
+      let mut inner = self.inner.lock().unwrap();
+      inner.consecutive_failures = 0;
+      inner.opened_at = None;
+      inner.trial_in_flight = false;
+    }
+
+    pub fn record_failure(&self) {
+      self.record_failure_at(Instant::now())
+    }
+
+    fn record_failure_at(&self, now: Instant) {
+      let mut inner = self.inner.lock().unwrap();
+      inner.consecutive_failures += 1;
+      inner.trial_in_flight = false;
+
+      // a failed trial call (or reaching the threshold) (re)opens the breaker.
+      if inner.opened_at.is_some() || inner.consecutive_failures >= self.failure_threshold {
+        inner.opened_at = Some(now);
+      }
+    }
+
+    /// whether a call can be attempted now. Only one call (the trial) is let through a half-open breaker,
+    /// and the caller must record its result or release the trial.
+    fn admit_at(&self, now: Instant) -> Admission {
+      let mut inner = self.inner.lock().unwrap();
+      match inner.opened_at {
+        Some(t) if now.duration_since(t) >= self.cooldown && !inner.trial_in_flight => {
+          inner.trial_in_flight = true;
+          Admission::Trial
+        },
+        Some(_) => Admission::Refused,
+        None => Admission::Call,
+      }
+    }
+
+    /// lets another trial through if a trial call was abandoned (e.g. its request was dropped) before it finished.
+    fn release_trial(&self) {
+      self.inner.lock().unwrap().trial_in_flight = false;
+    }
+
+    /// make a call through the breaker. If the breaker is open (or half-open with a trial already being made),
+    /// the call is not attempted.
+    pub async fn call<F, Fut, T, E>(&self, f: F) -> Result<T, BreakerError<E>>
+      where F: FnOnce() -> Fut, Fut: Future<Output = Result<T, E>>
+    {
+      let _trial = match self.admit_at(Instant::now()) {
+        Admission::Refused => return Err(BreakerError::Open),
+        Admission::Trial => Some(TrialGuard(self)),
+        Admission::Call => None,
+      };
+
+      match f().await {
+        Ok(v) => {
+          self.record_success();
+          Ok(v)
+        },
+        Err(e) => {
+          self.record_failure();
+          Err(BreakerError::Failed(e))
+        }
+      }
+    }
+}
+
+/// whether CircuitBreaker::call can make a call: normally, as a half-open breaker's trial, or not at all.
+enum Admission {
+  Call,
+  Trial,
+  Refused
+}
+
+/// releases a half-open breaker's trial when the call finishes or is dropped.
+struct TrialGuard<'a>(&'a CircuitBreaker);
+
+impl Drop for TrialGuard<'_> {
+  fn drop(&mut self) {
+    self.0.release_trial();
+  }
+}
+
+impl Default for CircuitBreaker {
+  fn default() -> Self {
+    CircuitBreaker::new(S3_FAILURE_THRESHOLD, S3_COOLDOWN)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::{Duration, Instant};
+  use crate::breaker::{BreakerError, BreakerState, CircuitBreaker};
+
+  /// a mock backend that is either up or down.
+  async fn backend(up: bool) -> Result<(), ()> {
+    if up { Ok(()) } else { Err(()) }
+  }
+
+  #[rocket::async_test]
+  async fn test_breaker_transitions() {
+    let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+
+    // failures below the threshold leave the breaker closed.
+    for _ in 0..2 {
+      assert!(matches!(breaker.call(|| backend(false)).await, Err(BreakerError::Failed(_))));
+    }
+    assert_eq!(breaker.state(), BreakerState::Closed);
+
+    // the third consecutive failure opens it, and calls fail fast.
+    assert!(breaker.call(|| backend(false)).await.is_err());
+    assert_eq!(breaker.state(), BreakerState::Open);
+    assert!(matches!(breaker.call(|| backend(true)).await, Err(BreakerError::Open)));
+
+    // after the cooldown, the breaker is half-open. A failed trial reopens it.
+    let later = Instant::now() + Duration::from_secs(61);
+    assert_eq!(breaker.state_at(later), BreakerState::HalfOpen);
+    breaker.record_failure_at(later);
+    assert_eq!(breaker.state_at(later), BreakerState::Open);
+
+    // a successful trial closes it again.
+    let much_later = later + Duration::from_secs(61);
+    assert_eq!(breaker.state_at(much_later), BreakerState::HalfOpen);
+    breaker.record_success();
+    assert_eq!(breaker.state(), BreakerState::Closed);
+    assert!(breaker.call(|| backend(true)).await.is_ok());
+  }
+
+  #[rocket::async_test]
+  async fn test_half_open_trial() {
+    // with no cooldown, the breaker is half-open as soon as it opens.
+    let breaker = CircuitBreaker::new(1, Duration::from_secs(0));
+    assert!(breaker.call(|| backend(false)).await.is_err());
+    assert_eq!(breaker.state(), BreakerState::HalfOpen);
+
+    // only one trial is made at a time: other calls fail fast while it's waiting on the backend.
+    let (finish, finished) = rocket::tokio::sync::oneshot::channel::<()>();
+    let trial = breaker.call(|| async { finished.await.map_err(|_| ()) });
+    let others = async {
+      assert!(matches!(breaker.call(|| backend(true)).await, Err(BreakerError::Open)));
+      assert!(matches!(breaker.call(|| backend(true)).await, Err(BreakerError::Open)));
+      finish.send(()).unwrap();
+    };
+    let (trial, _) = rocket::tokio::join!(trial, others);
+    assert!(trial.is_ok());
+    assert_eq!(breaker.state(), BreakerState::Closed);
+
+    // a trial that's dropped before it finishes lets the next call be the trial.
+    assert!(breaker.call(|| backend(false)).await.is_err());
+    rocket::tokio::select! {
+      biased;
+      _ = breaker.call(std::future::pending::<Result<(), ()>>) => unreachable!(),
+      _ = async {} => (),
+    }
+    assert!(breaker.call(|| backend(true)).await.is_ok());
+    assert_eq!(breaker.state(), BreakerState::Closed);
+  }
+}
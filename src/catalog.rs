@@ -1,30 +1,53 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
 use std::f64;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::path::Path;
 use std::path::PathBuf;
 use std::fs;
-use chrono::{DateTime, Utc, TimeZone};
+use std::future::Future;
+use std::sync::{atomic, Arc, Once, RwLock};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc, TimeZone};
 use geo::point;
 use geo::prelude::HaversineDistance;
 use http;
+use log::{debug, error, info, warn};
 use geo::polygon;
 use geo::algorithm::intersects::Intersects;
 use geo::algorithm::contains::Contains;
-use gdal::{Dataset, Metadata};
+use geo::algorithm::coordinate_position::{CoordinatePosition, CoordPos};
+use geo::algorithm::line_intersection::{line_intersection, LineIntersection};
+use geo::algorithm::simplify::Simplify;
+use gdal::{Dataset, DatasetOptions, GeoTransform, Metadata};
 use geo::prelude::BoundingRect;
 use geojson::Feature;
+use rayon::prelude::*;
+use regex::Regex;
+use rstar::{RTree, RTreeObject, AABB};
 use geojson::FeatureCollection;
 use geojson;
-use geo_types::{Polygon, Geometry};
+use geo_types::{Coordinate, Line, Polygon, MultiPolygon, Geometry, Rect};
 use s3;
 use serde_json::Value;
 use serde_json::to_value;
 use serde_json::{Map};
-use serde::{Serialize};
+use serde::{Deserialize, Serialize};
 use url;
+use crate::breaker::{BreakerError, CircuitBreaker};
+use crate::links;
+use crate::vsi;
+use crate::mapping::PropertyMapping;
+use crate::sensor;
+use crate::sidecar::{self, Sidecar};
 use crate::stac;
-use crate::stac::ToStacLink;
+use crate::stacignore::{StacIgnore, STACIGNORE};
+use crate::collection_config::{CollectionConfig, COLLECTION_JSON, COLLECTION_TOML};
+use crate::scan_cache::{self, ScanCache};
 use crate::transform;
+use crate::manifest::{self, ManifestEntry};
+use crate::metrics;
 
 /// Service represents the raster imagery service.
 /// For v0.0.1, the idea is that imagery of various sources can be filtered, or automatically
@@ -38,180 +61,676 @@ pub struct Service {
   pub title: String,
   pub description: String,
   pub base_url: url::Url,
-  pub collections: HashMap<String, ImageryCollection>
+  /// build links from the X-Forwarded-Host and X-Forwarded-Proto headers of each request
+  /// instead of base_url. Only enable this behind a proxy that sets these headers.
+  pub trust_forwarded_headers: bool,
+  /// add an asset for each band to items (see ImageryFile::to_stac_item).
+  pub band_assets: bool,
+  /// the most features returned for a single request, regardless of the `limit` requested by the client.
+  pub max_features: usize,
+  /// the circuit breaker that every S3 call goes through, kept for the life of the service so that
+  /// rescans (e.g. POST /admin/reload) fail fast while S3 is unavailable. Its state is in GET /metrics.
+  pub s3_breaker: Arc<CircuitBreaker>,
+  /// the collections, which can be replaced while the service is running (see rescan_collections).
+  pub collections: SharedCollections
+}
+
+/// collections shared between the server and anything that updates them, e.g. the directory watcher.
+/// The catalog itself is shared too, so that a request can keep a snapshot of it (see Service::collections)
+/// while the collections are replaced.
+pub type SharedCollections = Arc<RwLock<Arc<Catalog>>>;
+
+/// Catalog is every collection, by id, and a spatial index of the files in all of them, so that searches
+/// across collections don't have to check each collection's index. It derefs to the collections.
+/// The index is built when the catalog is created (see From), so collections are replaced by creating
+/// a new Catalog rather than being changed in place.
+#[derive(Debug, Default)]
+pub struct Catalog {
+  collections: HashMap<String, ImageryCollection>,
+  index: RTree<CatalogEntry>
+}
+
+/// CatalogEntry is an entry in the catalog's spatial index: the bounding rectangle of a file's
+/// footprint, the id of its collection, and the position of the file in that collection.
+#[derive(Debug)]
+struct CatalogEntry {
+  envelope: AABB<[f64; 2]>,
+  collection: String,
+  file: usize
+}
+
+impl RTreeObject for CatalogEntry {
+  type Envelope = AABB<[f64; 2]>;
+
+  fn envelope(&self) -> Self::Envelope {
+    self.envelope
+  }
+}
+
+impl From<HashMap<String, ImageryCollection>> for Catalog {
+  fn from(collections: HashMap<String, ImageryCollection>) -> Self {
+    let entries = collections.values()
+      .flat_map(|c| c.index.iter().map(move |entry| CatalogEntry {
+        envelope: entry.envelope,
+        collection: c.id.to_owned(),
+        file: entry.file
+      }))
+      .collect();
+    Catalog { collections, index: RTree::bulk_load(entries) }
+  }
+}
+
+impl std::ops::Deref for Catalog {
+  type Target = HashMap<String, ImageryCollection>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.collections
+  }
+}
+
+impl Catalog {
+  /// returns the files in any collection whose bounding rectangle intersects `rect`, sorted by
+  /// collection id and then in the order they are stored in each collection. These are candidates
+  /// for exact geometry tests (see ImageryCollection::candidates).
+  pub fn candidates(&self, rect: Option<Rect<f64>>) -> Vec<&ImageryFile> {
+    let rect = match rect {
+      Some(r) => r,
+      None => return Vec::new(),
+    };
+    let mut found: Vec<&CatalogEntry> = self.index.locate_in_envelope_intersecting(&rect_envelope(rect)).collect();
+    found.sort_unstable_by(|a, b| a.collection.cmp(&b.collection).then(a.file.cmp(&b.file)));
+    found.into_iter()
+      .filter_map(|entry| self.collections.get(&entry.collection).map(|c| &c.files[entry.file]))
+      .collect()
+  }
+
+  /// the collections, e.g. to change them and create a new Catalog.
+  pub fn into_collections(self) -> HashMap<String, ImageryCollection> {
+    self.collections
+  }
 }
 
 impl Service {
-    pub fn stac_landing(&self) -> stac::LandingPage {
+    /// a snapshot of the current collections. The collections can be replaced while it's held
+    /// (e.g. by a rescan), but the snapshot doesn't change.
+    pub fn collections(&self) -> Arc<Catalog> {
+      self.collections.read().unwrap().clone()
+    }
+
+    /// the base URL that items link their band assets from (see ImageryFile::to_stac_feature),
+    /// or None if the service doesn't add band assets.
+    pub fn band_assets_url<'a>(&self, base_url: &'a url::Url) -> Option<&'a url::Url> {
+      if self.band_assets { Some(base_url) } else { None }
+    }
+
+    pub fn stac_landing(&self, base_url: &url::Url) -> stac::LandingPage {
       stac::LandingPage::new(
         self.id.to_owned(),
         self.title.to_owned(),
         self.description.to_owned(),
-        &self.base_url,
-        self.collections.as_stac_collections_vec(
-          &self.base_url.join("collections/").unwrap()
-        )
+        base_url,
+        self.collections().as_stac_collections_vec(base_url)
+      )
+    }
+
+    /// the base URL for links in the response to a request with the given X-Forwarded-Host
+    /// and X-Forwarded-Proto headers. The headers replace the scheme and host of base_url (keeping its path)
+    /// if the service trusts forwarded headers; otherwise, or if they don't make a valid URL, base_url is used.
+    pub fn request_base_url(&self, forwarded_host: Option<&str>, forwarded_proto: Option<&str>) -> url::Url {
+      if !self.trust_forwarded_headers || (forwarded_host.is_none() && forwarded_proto.is_none()) {
+        return self.base_url.clone()
+      }
+
+      // with more than one proxy, the headers are lists and the first value is from the client-facing proxy.
+      let first = |v: &str| v.split(',').next().unwrap_or_default().trim().to_owned();
+      let scheme = forwarded_proto.map(first).unwrap_or_else(|| self.base_url.scheme().to_owned());
+      let host = forwarded_host.map(first)
+        .unwrap_or_else(|| self.base_url[url::Position::BeforeHost..url::Position::AfterPort].to_owned());
+
+      // the host can't add to the path, or include a username.
+      let valid_host = !host.contains(&['/', '\\', '?', '#', '@'][..]);
+      match url::Url::parse(&format!("{}://{}{}", scheme, host, self.base_url.path())) {
+        Ok(url) if valid_host && (url.scheme() == "http" || url.scheme() == "https") && url.host_str().is_some() => url,
+        _ => {
+          warn!("ignoring invalid forwarded headers (host {:?}, proto {:?})", forwarded_host, forwarded_proto);
+          self.base_url.clone()
+        }
+      }
+    }
+}
+
+/// the metadata item that a file's datetime is read from by default (used by Sentinel-2 imagery).
+const DEFAULT_TIMESTAMP_KEY: &str = "PRODUCT_START_TIME";
+
+/// ScanOptions control how files are catalogued.
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+  /// GDAL metadata items to pass through into item properties.
+  pub property_mapping: PropertyMapping,
+  /// GDAL metadata items that a file's datetime can be read from, in order of preference.
+  pub timestamp_keys: Vec<String>,
+  /// a pattern that finds a file's datetime in its file name (in a `datetime` group), for files
+  /// without a datetime in their metadata. See filename_datetime_pattern.
+  pub datetime_pattern: Option<Regex>,
+  /// base URL of an HTTPS host (e.g. a CDN in front of the S3 bucket) serving the bucket's objects.
+  /// S3 objects are advertised with an alternate link to this host if it is set.
+  pub s3_https_url: Option<String>,
+  /// whether S3 objects are linked with the bucket in the path or in the host name.
+  pub s3_url_style: S3UrlStyle,
+  /// catalog files in subdirectories of collection directories too, instead of only the files directly inside them.
+  pub recursive: bool,
+  /// GDAL open options (`KEY=VALUE`) used to open each file that is catalogued. See open_dataset.
+  pub open_options: Vec<String>,
+  /// the datetime given to files without one in their metadata or file name (1900-01-01 by default).
+  /// With None, their datetime is null.
+  pub default_datetime: Option<DateTime<Utc>>,
+  /// the metadata of files that have already been read, so that only new and changed files are opened.
+  pub cache: Option<Arc<ScanCache>>
+}
+
+impl ScanOptions {
+    /// describes the options that change the metadata read from a file, so that the scan cache
+    /// can be discarded if they change.
+    pub fn cache_key(&self) -> String {
+      format!(
+        "properties={:?} timestamp_keys={:?} datetime_pattern={:?} open_options={:?} default_datetime={:?}",
+        self.property_mapping.properties,
+        self.timestamp_keys,
+        self.datetime_pattern.as_ref().map(|p| p.as_str()),
+        self.open_options,
+        self.default_datetime
       )
     }
+
+    /// reads a file's metadata from the scan cache, if it was cached at this version.
+    fn cached_file(&self, key: &str, version: Option<&str>, path: PathBuf, href: String, filename: &str, collection_id: &str) -> Option<ImageryFile> {
+      let (mut properties, boundary) = self.cache.as_ref()?.get(key, version?)?;
+      debug!("using cached metadata for {}", key);
+      properties.path = href;
+      properties.filename = filename.to_owned();
+      Some(ImageryFile {
+        path,
+        filename: filename.to_owned(),
+        boundary,
+        properties,
+        collection_id: collection_id.to_owned(),
+        s3_object: None,
+        sidecar: None
+      })
+    }
+
+    /// adds a file's metadata to the scan cache.
+    fn cache_file(&self, key: &str, version: Option<&str>, file: &ImageryFile) {
+      if let (Some(cache), Some(version)) = (&self.cache, version) {
+        cache.insert(key, version, &file.properties, &file.boundary);
+      }
+    }
+}
+
+impl Default for ScanOptions {
+  fn default() -> Self {
+    ScanOptions {
+      property_mapping: PropertyMapping::default(),
+      timestamp_keys: vec![DEFAULT_TIMESTAMP_KEY.to_owned()],
+      datetime_pattern: None,
+      s3_https_url: None,
+      s3_url_style: S3UrlStyle::Path,
+      recursive: false,
+      open_options: Vec::new(),
+      default_datetime: Some(default_timestamp()),
+      cache: None
+    }
+  }
 }
 
 /// Convert a list of imagery metadata into a GeoJSON FeatureCollection
 pub trait AsFeatureCollection {
-  /// converts a collection of files into a GeoJSON FeatureCollection
-  fn as_feature_collection(&self) -> FeatureCollection;
+  /// converts a collection of files into a GeoJSON FeatureCollection. Bands are assets too if
+  /// `band_assets` (the base URL they're linked from) is given (see ImageryFile::to_stac_feature).
+  fn as_feature_collection(&self, band_assets: Option<&url::Url>) -> FeatureCollection;
+
+  /// converts a page of files into a GeoJSON FeatureCollection, with links to other pages and
+  /// the number of matching files (as `context` and `numberMatched`/`numberReturned`).
+  fn as_feature_collection_page(&self, links: Vec<stac::StacLink>, context: stac::Context, band_assets: Option<&url::Url>) -> FeatureCollection;
+
+  /// converts a collection of files into a GeoJSON FeatureCollection of their footprints,
+  /// with only minimal properties. Footprints are simplified if a tolerance is given.
+  fn as_footprint_collection(&self, tolerance: Option<f64>) -> FeatureCollection;
+}
+
+/// ContainsMode controls how `contains` queries treat a query geometry that touches
+/// the boundary of an image's footprint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContainsMode {
+  /// the footprint must contain the query geometry in the DE-9IM sense: the query can't be
+  /// outside the footprint, and must share some of the footprint's interior.
+  /// A query geometry that only lies on the footprint's boundary is not contained.
+  Strict,
+  /// the query geometry must intersect the footprint and have no part outside it.
+  /// Geometries lying on the footprint's boundary are treated as contained.
+  Covers
 }
 
-pub trait ImageContainsPolygon {
-  fn contains_polygon(&self, geom: &Polygon<f64>) -> Vec<ImageryFile>;
+pub trait ImageBestResolution {
+  fn best_resolution_match(&self, resolution: f64) -> Option<&ImageryFile>;
+  fn highest_resolution(&self) -> Option<&ImageryFile>;
 }
 
-pub trait ImageIntersectsGeom {
-  fn intersects(&self, geom: &Geometry<f64>) -> Vec<ImageryFile>;
+pub trait ImageDatetimeRange {
+  /// keeps the files with a timestamp between start and end (inclusive). A start or end of None is open-ended.
+  fn datetime_range(self, start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> Self;
 }
 
+/// the version of the next ImageryCollection that's created (see ImageryCollection::version).
+static NEXT_COLLECTION_VERSION: atomic::AtomicU64 = atomic::AtomicU64::new(1);
+
 /// ImageryCollection stores metadata about spectral imagery files such as
 /// satellite imagery.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ImageryCollection {
   pub id: String,
+  /// a number that's different for every collection that's created, so that anything rendered from a
+  /// collection's files (e.g. its thumbnail) can tell when the collection has been rescanned.
+  version: u64,
   title: String,
   description: String,
-  files: Vec<ImageryFile>
+  license: Option<String>,
+  keywords: Vec<String>,
+  providers: Vec<stac::Provider>,
+  files: Vec<ImageryFile>,
+  /// spatial index of the bounding rectangles of `files`.
+  index: RTree<IndexedFile>
+}
+
+/// IndexedFile is an entry in a collection's spatial index: the bounding rectangle
+/// of a file's footprint, and the position of the file in the collection.
+#[derive(Debug, Clone)]
+struct IndexedFile {
+  envelope: AABB<[f64; 2]>,
+  file: usize
+}
+
+impl RTreeObject for IndexedFile {
+  type Envelope = AABB<[f64; 2]>;
+
+  fn envelope(&self) -> Self::Envelope {
+    self.envelope
+  }
+}
+
+/// converts a bounding rectangle into an R-tree envelope.
+fn rect_envelope(rect: Rect<f64>) -> AABB<[f64; 2]> {
+  AABB::from_corners([rect.min().x, rect.min().y], [rect.max().x, rect.max().y])
+}
+
+/// gives every file in a collection a unique item id. Files can end up with the same id, e.g. `scene.tif` and
+/// `scene.jp2` in the same directory, or two `scene.tif` files listed in an HTTP manifest. The first of them
+/// (by path) keeps the id, and the others are given a suffix made from a short hash of their path
+/// (e.g. `scene-1f0c3b2a`), which stays the same each time the collection is scanned.
+fn make_item_ids_unique(collection_id: &str, files: &mut [ImageryFile]) {
+  let mut taken: HashSet<String> = files.iter().map(|f| f.filename.to_owned()).collect();
+  let mut order: Vec<usize> = (0..files.len()).collect();
+  order.sort_by(|a, b| (&files[*a].filename, &files[*a].path).cmp(&(&files[*b].filename, &files[*b].path)));
+
+  // the index of the first file with each id, in path order.
+  let mut first: Option<(String, usize)> = None;
+  for i in order {
+    let first_i = match &first {
+      Some((id, first_i)) if *id == files[i].filename => *first_i,
+      _ => {
+        first = Some((files[i].filename.to_owned(), i));
+        continue
+      },
+    };
+    let mut hasher = DefaultHasher::new();
+    files[i].path.hash(&mut hasher);
+    let mut id = format!("{}-{:08x}", files[i].filename, hasher.finish() as u32);
+    while taken.contains(&id) {
+      id.push('_');
+    }
+    warn!(
+      "{} and {} have the same item id ({}) in collection {}. Using id {} for {}.",
+      files[first_i].path.display(), files[i].path.display(), files[i].filename, collection_id, id, files[i].path.display()
+    );
+    taken.insert(id.to_owned());
+    files[i].filename = id.to_owned();
+    files[i].properties.filename = id;
+  }
 }
 
 impl ImageryCollection {
-  /// Create a new ImageryCollection, populated with files found by
-  /// collect_files.
-  pub fn new_from_dir(id: String, title: String, description: String, dir: PathBuf) -> ImageryCollection {
-    let files = ImageryCollection::collect_files(dir, &id);
+  /// Create a new ImageryCollection from files that have already been catalogued,
+  /// indexing their footprints.
+  pub fn new(id: String, title: String, description: String, mut files: Vec<ImageryFile>) -> ImageryCollection {
+    make_item_ids_unique(&id, &mut files);
+    let index = RTree::bulk_load(
+      files.iter()
+        .enumerate()
+        .filter_map(|(i, f)| f.boundary.bounding_rect().map(|rect| IndexedFile { envelope: rect_envelope(rect), file: i }))
+        .collect()
+    );
     ImageryCollection{
       id,
+      version: NEXT_COLLECTION_VERSION.fetch_add(1, atomic::Ordering::Relaxed),
       title,
       description,
-      files
+      license: None,
+      keywords: Vec::new(),
+      providers: Vec::new(),
+      files,
+      index
     }
   }
 
-  /// register_images searches the imagery directory and collects
-  /// metadata about valid images.  Images are valid if they can be
-  /// opened by GDAL.
-  fn collect_files(dir: PathBuf, collection_id: &str) -> Vec<ImageryFile> {
-    let img_dir = fs::read_dir(dir).unwrap();
-
-    let mut coverage: Vec<ImageryFile> = Vec::new();
+  /// the collection's version. Rescanning a collection creates a new one, with a new version.
+  pub fn version(&self) -> u64 {
+    self.version
+  }
 
-    // iterate through the files in img_dir and capture information
-    for file in img_dir {
-      let file = file.unwrap();
-      let path = file.path();
+  /// applies the details from a collection config file. The title and description are only
+  /// replaced if the config file has them.
+  pub fn configure(&mut self, config: CollectionConfig) {
+    if let Some(title) = config.title {
+      self.title = title;
+    }
+    if let Some(description) = config.description {
+      self.description = description;
+    }
+    self.license = config.license;
+    self.keywords = config.keywords;
+    self.providers = config.providers;
+  }
 
-      // skip if not a file.
-      if !path.is_file() {
-        continue;
-      }
+  /// Create a new ImageryCollection, populated with files found by
+  /// collect_files. Also returns a summary of the files that were found.
+  /// The title and description can be overridden by a config file in the directory (see CollectionConfig).
+  pub fn new_from_dir(
+    id: String,
+    title: String,
+    description: String,
+    dir: PathBuf,
+    options: &ScanOptions
+  ) -> (ImageryCollection, ScanSummary) {
+    let config = CollectionConfig::from_dir(&dir);
+    let (files, summary) = ImageryCollection::collect_files(dir, &id, options);
+    let mut collection = ImageryCollection::new(id, title, description, files);
+    collection.configure(config);
+    (collection, summary)
+  }
 
-      let filename = file.path().as_path().file_stem().unwrap().to_str().unwrap().to_owned();
-      println!("processing {}", path.as_path().display().to_string());
+  /// register_images searches the imagery directory and collects
+  /// metadata about valid images.  Images are valid if they can be
+  /// opened by GDAL.
+  fn collect_files(dir: PathBuf, collection_id: &str, options: &ScanOptions) -> (Vec<ImageryFile>, ScanSummary) {
+    let ignore = StacIgnore::from_dir(&dir);
+    let files = list_files(&dir, options.recursive);
+    let sidecars = sidecar::sidecars(&files);
+    let auxiliary = sidecar::auxiliary_files(&files);
+    // each file is opened (with its own GDAL dataset) in parallel.
+    let results: Vec<Result<ImageryFile, SkipReason>> = files
+      .par_iter()
+      .filter(|path| path.strip_prefix(&dir).map(|p| !CONFIG_FILES.iter().any(|f| p == Path::new(f))).unwrap_or(false))
+      .map(|path| {
+        // the path within the collection directory, with `/` separators like S3 keys.
+        let relative = path.strip_prefix(&dir).unwrap();
+
+        // skip files listed in the .stacignore file.
+        if ignore.is_ignored(&slash_path(relative)) {
+          return Err(SkipReason::Ignored);
+        }
 
-      // open the dataset using GDAL.
-      let dataset = match Dataset::open(&path) {
-        Ok(ds) => ds,
-        Err(_) => continue,
-      };
+        // sidecars are merged into the file they describe instead of being catalogued.
+        if sidecars.contains(path) {
+          return Err(SkipReason::Sidecar);
+        }
 
-      let img = ImageryFile::new(&dataset, path, &filename, collection_id);
+        // GDAL reads auxiliary files (e.g. external overviews) along with the file they belong to.
+        if auxiliary.contains(path) {
+          debug!("skipping {}: auxiliary file of another dataset", path.display());
+          return Err(SkipReason::Auxiliary);
+        }
 
-      coverage.push(img);
-    }
-    coverage
+        // files in subdirectories keep their relative path in their id (e.g. `2021/08/scene`),
+        // so that files with the same name in different directories don't collide.
+        let filename = slash_path(&relative.with_extension(""));
+        let href = path.as_path().display().to_string();
+
+        // files that haven't changed (along with their auxiliary files) since they were cached aren't opened again.
+        // Sidecars aren't cached, so changes to them are picked up on every scan.
+        let version = scan_cache::dataset_version(path);
+        let img = match options.cached_file(&href, version.as_deref(), path.to_owned(), href.to_owned(), &filename, collection_id) {
+          Some(img) => img,
+          None => {
+            info!("processing {}", href);
+
+            // open the dataset using GDAL.
+            let dataset = match open_dataset(path, options) {
+              Ok(ds) => ds,
+              Err(e) => {
+                debug!("skipping {}: {}", path.display(), e);
+                return Err(SkipReason::Unreadable)
+              },
+            };
+
+            let img = ImageryFile::new(&dataset, path.to_owned(), href.to_owned(), &filename, collection_id, options).map_err(|e| {
+              warn!("skipping {}: {}", path.display(), e);
+              SkipReason::InvalidGeoreference
+            })?;
+            options.cache_file(&href, version.as_deref(), &img);
+            img
+          },
+        };
+        Ok(img.with_sidecar(Sidecar::for_file(path)))
+      })
+      .collect();
+
+    ScanSummary::from_results(results)
   }
 
   /// Create a new collection from a prefix in an S3 bucket.
   /// It's expected that all the collections are based on common prefixes (e.g. subfolders)
   /// in a single S3 bucket. If anybody wants to use this differently, post an issue.
+  #[allow(clippy::too_many_arguments)]
   pub async fn new_from_s3_prefix(
     id: &str,
     title: &str,
     description: &str,
     s3_host: &str,
-    client: &s3::Client,
+    access: &S3Access,
     bucket: &str,
-    prefix: &str
-  ) -> ImageryCollection {
-
+    prefix: &str,
+    breaker: &CircuitBreaker,
+    options: &ScanOptions
+  ) -> Result<(ImageryCollection, ScanSummary), ScanError> {
+    // a prefix that can't be listed is catalogued as empty, but the scan stops if S3 is unavailable.
+    let objects = match list_s3_objects(access, bucket, prefix, breaker).await {
+      Ok(objects) => objects,
+      Err(BreakerError::Open) => return Err(ScanError::Unavailable),
+      Err(BreakerError::Failed(e)) => {
+        warn!("could not list objects in prefix {}: {}", prefix, e);
+        Vec::new()
+      },
+    };
+    let keys: Vec<String> = objects.iter().map(|o| o.key.to_owned()).collect();
+
+    // read the prefix's .stacignore and collection config files, if it has them.
+    let config_keys: Vec<String> = CONFIG_FILES.iter().map(|f| String::from(prefix) + "/" + f).collect();
+    let ignore_key = String::from(prefix) + "/" + STACIGNORE;
+    let ignore = match keys.contains(&ignore_key) {
+      true => read_s3_object(access, bucket, &ignore_key, breaker).await.map(|c| StacIgnore::parse(&c)).unwrap_or_default(),
+      false => StacIgnore::default(),
+    };
+    let mut config = CollectionConfig::default();
+    for name in &[COLLECTION_JSON, COLLECTION_TOML] {
+      let key = String::from(prefix) + "/" + name;
+      if keys.contains(&key) {
+        if let Some(contents) = read_s3_object(access, bucket, &key, breaker).await {
+          config = CollectionConfig::parse(&key, &contents);
+        }
+        break;
+      }
+    }
 
-    // the rust AWS client expects AWS_S3_ENDPOINT to include the scheme (http/https),
-    // but GDAL expects AWS_S3_ENDPOINT to only include the host/port.
-    let endpoint = s3_host.parse::<http::Uri>().unwrap();
-    let hostname = endpoint.authority().expect("Expected a host and port in AWS_S3_ENDPOINT").as_str();
-    let _ = gdal::config::set_config_option("AWS_S3_ENDPOINT", hostname);
+    let auxiliary = sidecar::auxiliary_files(&keys.iter().map(PathBuf::from).collect::<Vec<_>>());
+
+    // each object is opened (with its own GDAL dataset) in parallel.
+    let dir = String::from(prefix) + "/";
+    let results: Vec<Result<ImageryFile, SkipReason>> = objects.par_iter()
+      .filter(|o| !config_keys.contains(&o.key))
+      .filter_map(|object| match object.key.strip_prefix(&dir) {
+        Some(key_no_prefix) => Some((object, key_no_prefix)),
+        None => {
+          warn!("skipping {}: not under {}", object.key, dir);
+          None
+        },
+      })
+      .map(|(object, key_no_prefix)| {
+        let key = &object.key;
+        if ignore.is_ignored(key_no_prefix) {
+          return Err(SkipReason::Ignored);
+        }
+        if auxiliary.contains(Path::new(key)) {
+          return Err(SkipReason::Auxiliary);
+        }
 
-    let mut files = Vec::new();
+        let path = String::from("/vsis3/") + bucket + "/" + key;
+
+        // create a link to this object on the S3 server.
+        let href = s3_object_href(s3_host, bucket, key, options.s3_url_style);
+
+        // objects can also be downloaded with an S3 client, or over HTTPS if a CDN serves the bucket.
+        let https_href = match &options.s3_https_url {
+          Some(url) => String::from(url.trim_end_matches('/')) + "/" + key,
+          None => href.to_owned(),
+        };
+
+        // objects that haven't changed (by ETag) since they were cached aren't opened again.
+        let version = object.etag.as_deref();
+        let cached = options.cached_file(&path, version, PathBuf::from(&path), href.to_owned(), key_no_prefix, id);
+        let mut img = match cached {
+          Some(img) => img,
+          None => {
+            let vsipath = Path::new(&path);
+            let dataset = match open_dataset(vsipath, options) {
+              Ok(ds) => ds,
+              Err(_) => {
+                warn!("Failed to open {}", key);
+                return Err(SkipReason::Unreadable)
+              },
+            };
+            info!("processing {}", key);
+
+            match ImageryFile::new(
+              &dataset,
+              PathBuf::from(&path),
+              href,
+              key_no_prefix,
+              id,
+              options
+            ) {
+              Ok(img) => {
+                options.cache_file(&path, version, &img);
+                img
+              },
+              Err(e) => {
+                warn!("skipping {}: {}", key, e);
+                return Err(SkipReason::InvalidGeoreference)
+              },
+            }
+          },
+        };
+        img.s3_object = Some(S3Object {
+          bucket: bucket.to_owned(),
+          key: key.to_owned(),
+          https_href,
+          etag: object.etag.to_owned()
+        });
+        img.properties.modified = object.last_modified;
+        Ok(img)
+      }).collect();
+
+    let (files, summary) = ScanSummary::from_results(results);
+    let mut collection = ImageryCollection::new(id.to_string(), title.to_string(), description.to_string(), files);
+    collection.configure(config);
+    Ok((collection, summary))
+  }
 
-    let results = client
-      .list_objects()
-      .bucket(bucket)
-      .prefix(prefix)
-      .send().await;
-    
-    let results = results.expect("could not get objects");
-
-    for r in results.contents.unwrap() {
-      let key = r.key.unwrap();
-      let path = String::from("/vsis3/") + bucket + "/" + &key;
-      let vsipath = Path::new(&path);
-      let dataset = match Dataset::open(&vsipath) {
+  /// creates a collection from files listed in an HTTP manifest. Files are read with GDAL's /vsicurl/
+  /// filesystem, and their assets link to the URLs they were listed with.
+  pub fn new_from_urls(
+    id: &str,
+    name: &str,
+    entries: &[ManifestEntry],
+    options: &ScanOptions
+  ) -> (ImageryCollection, ScanSummary) {
+    // each file is opened (with its own GDAL dataset) in parallel.
+    let results: Vec<Result<ImageryFile, SkipReason>> = entries.par_iter().map(|entry| {
+      let path = String::from("/vsicurl/") + &entry.url;
+      let dataset = match open_dataset(Path::new(&path), options) {
         Ok(ds) => ds,
         Err(_) => {
-          println!("Failed to open {}", key);
-          continue
+          warn!("Failed to open {}", entry.url);
+          return Err(SkipReason::Unreadable)
         },
       };
-      println!("processing {}", key);
+      info!("processing {}", entry.url);
 
-      // create a link to this object on the S3 server.
-      // we might need to make this more configurable (bucket.example.com vs example.com/bucket)
-      let href = String::from(s3_host) + "/" + bucket + "/" + &key;
-      let key_no_prefix = key.strip_prefix(&(String::from(prefix) + "/")).unwrap();
-
-      let img = ImageryFile::new(
+      ImageryFile::new(
         &dataset,
-        href.into(),
-        key_no_prefix,
-        prefix
-      );
-      files.push(img);
-    }
-
-    ImageryCollection{
-      id: id.to_string(),
-      title: title.to_string(),
-      description: description.to_string(),
-      files
-    }
+        PathBuf::from(&path),
+        entry.url.to_owned(),
+        &entry.filename,
+        id,
+        options
+      ).map_err(|e| {
+        warn!("skipping {}: {}", entry.url, e);
+        SkipReason::InvalidGeoreference
+      })
+    }).collect();
+
+    let (files, summary) = ScanSummary::from_results(results);
+    (ImageryCollection::new(id.to_string(), name.to_string(), name.to_string(), files), summary)
   }
 
   pub fn stac_collection(
     &self,
     base_url: &url::Url
   ) -> stac::Collection {
-    let collection_url = base_url
-      .join("collections/").unwrap()
-      .join(&(self.id.to_owned() + "/")).unwrap();
+    // collections without any files cover the whole world.
+    let bbox = match files_bounding_rect(self.all()) {
+      Some(rect) => [rect.min().x, rect.min().y, rect.max().x, rect.max().y],
+      None => [-180., -90., 180., 90.],
+    };
+    // files without a timestamp (with a null datetime, or given the default datetime) are left out of the interval.
+    let timestamps = self.all().iter()
+      .filter(|f| !f.properties.timestamp_defaulted)
+      .filter_map(|f| f.properties.timestamp);
+    let interval = [timestamps.clone().min(), timestamps.max()];
+
+    let extent = stac::Extent {
+      spatial: stac::SpatialExtent { bbox: vec![bbox] },
+      temporal: stac::TemporalExtent { interval: vec![interval] }
+    };
 
     let mut collection = stac::Collection::new(
       self.id.to_owned(),
       self.title.to_owned(),
       self.description.to_owned(),
+      self.license.to_owned(),
+      Some(self.providers.to_owned()),
+      extent,
       Vec::new(),
     );
+    collection.keywords = self.keywords.to_owned();
 
-    collection.links.push(collection.root_link(base_url));
-    collection.links.push(collection.self_link(base_url));
+    collection.links.push(links::root_link(base_url));
+    collection.links.push(links::parent_link(base_url, None));
+    collection.links.push(links::collection_self_link(base_url, &self.id));
+    collection.links.push(links::collection_items_link(base_url, &self.id));
+    collection.links.push(links::collection_tilejson_link(base_url, &self.id));
 
     for f in self.all() {
-      let item = f.to_stac_feature();
-      collection.links.push(item.to_stac_link(&collection_url));
+      collection.links.push(links::item_link(base_url, &self.id, &f.filename));
     }
 
     collection
@@ -222,6 +741,19 @@ impl ImageryCollection {
     &self.files
   }
 
+  /// the range of web mercator zoom levels (min, max) that suits the collection's files: from the zoom level
+  /// that matches the resolution of its coarsest file to the one that matches its finest file
+  /// (see transform::zoom_for_resolution). A collection without files covers every zoom level.
+  pub fn zoom_range(&self) -> (u8, u8) {
+    let resolutions = self.files.iter().map(|f| f.properties.resolution.avg());
+    let finest = resolutions.clone().fold(f64::INFINITY, f64::min);
+    let coarsest = resolutions.fold(f64::NEG_INFINITY, f64::max);
+    match self.files.is_empty() {
+      true => (0, transform::MAX_ZOOM),
+      false => (transform::zoom_for_resolution(coarsest), transform::zoom_for_resolution(finest)),
+    }
+  }
+
 
   /// get an item by its ID.
   pub fn get_item(&self, item_id: String) -> Option<&ImageryFile> {
@@ -232,70 +764,118 @@ impl ImageryCollection {
     }
     None
   }
+
+  /// returns the files whose bounding rectangle intersects `rect`, in the order they are stored in
+  /// the collection. These are candidates for exact geometry tests: only their footprints
+  /// can intersect or contain a geometry with that bounding rectangle.
+  fn candidates(&self, rect: Option<Rect<f64>>) -> Vec<&ImageryFile> {
+    let rect = match rect {
+      Some(r) => r,
+      None => return Vec::new(),
+    };
+    let mut found: Vec<usize> = self.index
+      .locate_in_envelope_intersecting(&rect_envelope(rect))
+      .map(|entry| entry.file)
+      .collect();
+    found.sort_unstable();
+    found.into_iter().map(|i| &self.files[i]).collect()
+  }
+
   /// Returns files in ImageryCollection that intersect with geom (lat/lng / EPSG:4326)
-  pub fn intersects(&self, geom: &Geometry<f64>) -> Vec<ImageryFile> {
-    let mut matching_files: Vec<ImageryFile> = Vec::new();
-    for f in self.files.iter() {
+  pub fn intersects(&self, geom: &Geometry<f64>) -> Vec<&ImageryFile> {
+    let mut matching_files: Vec<&ImageryFile> = Vec::new();
+    for f in self.candidates(geom.bounding_rect()) {
         if f.boundary.intersects(geom) {
-            matching_files.push(f.to_owned());
+            matching_files.push(f);
         }
     };
     matching_files
   }
-  /// returns files in a vector of ImageryFiles whose extent contains geom (geom should use lat/lng)
-  /// todo: make more generic
-  pub fn contains(&self, geom: &Polygon<f64>) -> Vec<ImageryFile> {
-    let mut matching_files: Vec<ImageryFile> = Vec::new();
-    for f in self.files.iter() {
-        if f.boundary.contains(geom) {
-            matching_files.push(f.to_owned());
+  /// returns files in a vector of ImageryFiles whose extent contains geom (geom should use lat/lng).
+  /// geom can be a Polygon, a MultiPolygon, or a GeometryCollection of them (see polygons).
+  pub fn contains(&self, geom: &Geometry<f64>, mode: ContainsMode) -> Vec<&ImageryFile> {
+    let mut matching_files: Vec<&ImageryFile> = Vec::new();
+    for f in self.candidates(geom.bounding_rect()) {
+        if f.footprint_contains(geom, mode) {
+            matching_files.push(f);
         }
     };
     matching_files
   }
 }
 
-impl AsFeatureCollection for Vec<ImageryFile> {
+impl AsFeatureCollection for Vec<&ImageryFile> {
   /// converts a vec of ImageryFiles into a FeatureCollection
-  fn as_feature_collection(&self) -> FeatureCollection {
+  fn as_feature_collection(&self, band_assets: Option<&url::Url>) -> FeatureCollection {
     let mut fc = FeatureCollection {
       bbox: None,
       features: vec![],
       foreign_members: None
     };
     for rast in self {
-        fc.features.push(rast.to_stac_feature());
+        fc.features.push(rast.to_stac_feature(band_assets));
     };
     fc
   }
+
+  fn as_feature_collection_page(&self, links: Vec<stac::StacLink>, context: stac::Context, band_assets: Option<&url::Url>) -> FeatureCollection {
+    let mut fc = self.as_feature_collection(band_assets);
+    let mut foreign_members = Map::new();
+    foreign_members.insert(String::from("links"), to_value(links).unwrap());
+    foreign_members.insert(String::from("context"), to_value(context).unwrap());
+    foreign_members.insert(String::from("numberMatched"), to_value(context.matched).unwrap());
+    foreign_members.insert(String::from("numberReturned"), to_value(context.returned).unwrap());
+    fc.foreign_members = Some(foreign_members);
+    fc
+  }
+
+  /// converts a vec of ImageryFiles into a FeatureCollection of footprints
+  fn as_footprint_collection(&self, tolerance: Option<f64>) -> FeatureCollection {
+    FeatureCollection {
+      bbox: None,
+      features: self.iter().map(|rast| rast.to_footprint_feature(tolerance)).collect(),
+      foreign_members: None
+    }
+  }
 }
 
-impl ImageContainsPolygon for Vec<ImageryFile> {
-  /// returns files in a vector of ImageryFiles whose extent contains geom (geom should use lat/lng)
-  /// note on inconsistent naming:  `contains` conflicts with the contains method available on all Vectors.
-  /// todo: make more generic
-  fn contains_polygon(&self, geom: &Polygon<f64>) -> Vec<ImageryFile> {
-    let mut matching_files: Vec<ImageryFile> = Vec::new();
-    for f in self.iter() {
-        if f.boundary.contains(geom) {
-            matching_files.push(f.to_owned());
-        }
-    };
-    matching_files
+impl ImageDatetimeRange for Vec<&ImageryFile> {
+  fn datetime_range(mut self, start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> Self {
+    // files with a null datetime are only kept if the range is open at both ends.
+    self.retain(|f| match (start, f.properties.timestamp) {
+      (Some(s), Some(ts)) => ts >= s,
+      (Some(_), None) => false,
+      (None, _) => true,
+    });
+    self.retain(|f| match (end, f.properties.timestamp) {
+      (Some(e), Some(ts)) => ts <= e,
+      (Some(_), None) => false,
+      (None, _) => true,
+    });
+    self
   }
 }
 
-impl ImageIntersectsGeom for Vec<ImageryFile> {
+impl ImageBestResolution for Vec<&ImageryFile> {
+
+  /// picks the file that best matches a target resolution (meters per pixel), e.g. the
+  /// ground resolution of a tile at the requested zoom level.
+  /// The coarsest file that is at least as detailed as the target is chosen, so low zoom levels
+  /// don't read more pixels than they need. If no file is detailed enough, the most detailed
+  /// file is chosen (see highest_resolution).
+  fn best_resolution_match(&self, resolution: f64) -> Option<&ImageryFile> {
+    let res = |f: &&ImageryFile| f.properties.resolution.avg();
+    self.iter().copied()
+      .filter(|f| res(f) <= resolution)
+      .max_by(|a, b| res(a).partial_cmp(&res(b)).unwrap_or(std::cmp::Ordering::Equal))
+      .or_else(|| self.highest_resolution())
+  }
 
-  /// Returns files in ImageryCollection that intersect with geom (lat/lng / EPSG:4326)
-  fn intersects(&self, geom: &Geometry<f64>) -> Vec<ImageryFile> {
-    let mut matching_files: Vec<ImageryFile> = Vec::new();
-    for f in self.iter() {
-      if f.boundary.intersects(geom) {
-            matching_files.push(f.to_owned());
-        }
-    };
-    matching_files
+  /// picks the most detailed file (the one with the smallest pixels). If more than one file
+  /// has the same resolution, the first one is chosen.
+  fn highest_resolution(&self) -> Option<&ImageryFile> {
+    let res = |f: &&ImageryFile| f.properties.resolution.avg();
+    self.iter().copied().min_by(|a, b| res(a).partial_cmp(&res(b)).unwrap_or(std::cmp::Ordering::Equal))
   }
 }
 
@@ -312,9 +892,8 @@ impl AsSTACCollections for HashMap<String,ImageryCollection> {
 }
 
 /// Resolution represents the horizontal (x) and vertical (y)
-/// length of a single pixel, in the map units.
-/// TODO:  this needs to be converted to m, not use the base map units.
-#[derive(Debug, Clone, Copy, Serialize)]
+/// length of a single pixel on the ground, in meters.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Resolution {
     pub y: f64,
     pub x: f64
@@ -328,18 +907,94 @@ impl Resolution {
     }
 }
 
-#[derive(Debug,Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImageryFileProperties {
   pub path: String,
   pub filename: String,
   pub crs: String,
+  /// the EPSG code of `crs`, if it has one.
+  pub epsg: Option<u32>,
+  /// `crs` as WKT2, for files whose CRS has no EPSG code.
+  pub wkt2: Option<String>,
   pub resolution: Resolution,
   pub num_bands: u16,
+  /// the nodata value of each band, if it has one.
+  #[serde(with = "nodata_values")]
+  pub nodata: Vec<Option<f64>>,
   pub description: Option<String>,
   pub cloud_coverage: Option<f64>,
-  pub timestamp: DateTime<Utc>,
+  /// the file's datetime. None if it has no datetime (and ScanOptions::default_datetime is None).
+  pub timestamp: Option<DateTime<Utc>>,
+  /// whether `timestamp` is ScanOptions::default_datetime, because no datetime was found for the file.
+  pub timestamp_defaulted: bool,
+  /// when the file was last modified: its modification time for local files, or its last modified time for S3 objects.
+  /// Advertised as the item's `created` and `updated` times.
+  pub modified: Option<DateTime<Utc>>,
   pub red_band: Option<u16>,
-  pub ni_band: Option<u16>
+  pub ni_band: Option<u16>,
+  /// the file's overviews (e.g. the internal overviews of a Cloud-Optimized GeoTIFF), from the most to the least detailed.
+  pub overview_levels: Vec<Overview>,
+  /// properties passed through from GDAL metadata using a PropertyMapping.
+  pub custom_properties: Map<String, Value>
+}
+
+/// Overview is a reduced resolution copy of a file's data, that can be read instead of the full
+/// resolution data when less detail is needed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Overview {
+  pub width: usize,
+  pub height: usize,
+  pub resolution: Resolution
+}
+
+/// a nodata value as JSON: null for a band without nodata, and a string for
+/// values that JSON numbers can't hold ("nan", "inf" or "-inf").
+fn nodata_value(nodata: Option<f64>) -> Value {
+  match nodata {
+    None => Value::Null,
+    Some(v) if v.is_nan() => Value::from("nan"),
+    Some(v) if v.is_infinite() && v > 0. => Value::from("inf"),
+    Some(v) if v.is_infinite() => Value::from("-inf"),
+    Some(v) => Value::from(v),
+  }
+}
+
+/// (de)serializes a list of nodata values with nodata_value, so that NaN and infinite values survive JSON.
+mod nodata_values {
+  use serde::{Deserialize, Deserializer, Serializer};
+  use serde_json::Value;
+
+  pub fn serialize<S: Serializer>(nodata: &[Option<f64>], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(nodata.iter().map(|n| super::nodata_value(*n)))
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Option<f64>>, D::Error> {
+    let values = Vec::<Value>::deserialize(deserializer)?;
+    Ok(values.iter().map(|v| match v.as_str() {
+      Some("nan") => Some(f64::NAN),
+      Some("inf") => Some(f64::INFINITY),
+      Some("-inf") => Some(f64::NEG_INFINITY),
+      _ => v.as_f64(),
+    }).collect())
+  }
+}
+
+/// the location of a file that was catalogued from S3.
+#[derive(Debug, Clone)]
+pub struct S3Object {
+  pub bucket: String,
+  pub key: String,
+  /// where the object can be downloaded over HTTPS.
+  pub https_href: String,
+  /// the object's ETag, if the listing had one.
+  pub etag: Option<String>
+}
+
+impl S3Object {
+    /// the object's `s3://bucket/key` URL.
+    pub fn s3_url(&self) -> String {
+      format!("s3://{}/{}", self.bucket, self.key)
+    }
 }
 
 /// metadata about images
@@ -347,27 +1002,99 @@ pub struct ImageryFileProperties {
 pub struct ImageryFile {
   path: PathBuf,
   filename: String,
-  pub boundary: Polygon<f64>,
+  /// the image footprint in lat/lng (EPSG:4326).
+  /// Most footprints are a single polygon, but footprints that are split up
+  /// (e.g. across the antimeridian) have more than one.
+  pub boundary: MultiPolygon<f64>,
   pub properties: ImageryFileProperties,
-  collection_id: String
+  collection_id: String,
+  /// the bucket and key of files catalogued from S3.
+  pub s3_object: Option<S3Object>,
+  /// the STAC item written next to the file, if it has one (see Sidecar).
+  sidecar: Option<Sidecar>
 }
 
 impl ImageryFile {
+    /// the id of the collection the file is in.
+    pub fn collection_id(&self) -> &str {
+      &self.collection_id
+    }
+
+    /// merges a sidecar STAC item into the file. The sidecar's datetime (if it sets one) is used for
+    /// searches too; its other properties and its assets are added in to_stac_feature.
+    pub fn with_sidecar(mut self, sidecar: Option<Sidecar>) -> ImageryFile {
+      if let Some(datetime) = sidecar.as_ref().and_then(|s| s.datetime()) {
+        self.properties.timestamp = datetime;
+        self.properties.timestamp_defaulted = false;
+      }
+      self.sidecar = sidecar;
+      self
+    }
+
+    /// checks whether the image's footprint contains geom (geom should use lat/lng). See ContainsMode.
+    /// Geometries with several polygons are contained if every one of them is. Geometries
+    /// that aren't areas (see polygons) are never contained.
+    pub fn footprint_contains(&self, geom: &Geometry<f64>, mode: ContainsMode) -> bool {
+      let polys = match polygons(geom) {
+        Some(polys) => polys,
+        None => return false,
+      };
+      polys.iter().all(|poly| match mode {
+        ContainsMode::Strict => self.boundary.contains(poly),
+        ContainsMode::Covers => covers(&self.boundary, poly),
+      })
+    }
+
+    /// the current version of the file: its ETag if it was catalogued from S3, otherwise its size and
+    /// modification time and those of its auxiliary files (see scan_cache::dataset_version).
+    pub fn version(&self) -> Option<String> {
+      match &self.s3_object {
+        Some(object) => object.etag.to_owned(),
+        None => scan_cache::dataset_version(&self.path),
+      }
+    }
+
+    /// opens the file with GDAL.
+    pub fn open(&self) -> gdal::errors::Result<Dataset> {
+      Dataset::open(&self.path)
+    }
+
+    /// whether NDVI can be computed from the image, i.e. both its red and near-infrared bands are known.
+    pub fn ndvi_capable(&self) -> bool {
+      self.properties.red_band.is_some() && self.properties.ni_band.is_some()
+    }
+
     /// create a STAC ItemProperties object out of the ImageryFile's properties.
     pub fn stac_properties(&self) -> stac::ItemProperties {
       stac::ItemProperties {
         datetime: self.properties.timestamp,
         title: self.filename.to_owned(),
         description: self.properties.description.to_owned(),
-        created: None, // unimplemented
-        updated: None, // unimplemented
-        spatial_resolution: Some(self.properties.resolution.avg())
+        created: self.properties.modified,
+        updated: self.properties.modified,
+        gsd: Some(self.properties.resolution.avg()),
+        spatial_resolution: Some(self.properties.resolution.avg()),
+        proj_epsg: self.properties.epsg,
+        proj_wkt2: self.properties.wkt2.to_owned(),
+        eo_cloud_cover: self.properties.cloud_coverage,
+        nodata: self.properties.nodata.iter().map(|v| nodata_value(*v)).collect(),
+        ndvi_capable: self.ndvi_capable(),
+        overview_levels: self.properties.overview_levels.iter()
+          .map(|o| stac::OverviewLevel { width: o.width, height: o.height, spatial_resolution: o.resolution.avg() })
+          .collect(),
+        custom_properties: self.properties.custom_properties.to_owned()
       }
     }
 
-    /// a GeoJSON Feature with all the fields of a STAC Item 
-    pub fn to_stac_feature(&self) -> geojson::Feature {
-        let geometry = geojson::Geometry::from(&self.boundary);
+    /// a GeoJSON Feature with all the fields of a STAC Item.
+    /// With `band_assets` (the service's base URL), each band is also an asset (`band-1`, `band-2` etc.)
+    /// that downloads only that band.
+    pub fn to_stac_feature(&self, band_assets: Option<&url::Url>) -> geojson::Feature {
+        // single part footprints are written as a Polygon.
+        let geometry = match self.boundary.0.as_slice() {
+          [poly] => geojson::Geometry::from(poly),
+          _ => geojson::Geometry::from(&self.boundary),
+        };
         let bbox_rect = self.boundary.bounding_rect().unwrap();
         let bbox: Option<Vec<f64>> = Some(vec![
           bbox_rect.min().x,
@@ -381,13 +1108,50 @@ impl ImageryFile {
 
         // create the default "file" asset.
         // this points to the actual file that was catalogued.
-        // in the future, it might be nice to create assets from bands.
+        // files from S3 also advertise their s3:// and HTTPS URLs as alternate assets.
+        let mut alternate = Map::new();
+        let mut stac_extensions: Vec<&str> = Vec::new();
+        if properties.proj_epsg.is_some() || properties.proj_wkt2.is_some() {
+          stac_extensions.push(stac::PROJECTION_EXT);
+        }
+        // the file's bands are listed if the red or near-infrared band is known.
+        let eo_bands: Vec<stac::EoBand> = match self.properties.red_band.or(self.properties.ni_band) {
+          Some(_) => (1..=self.properties.num_bands).map(|b| self.eo_band(b)).collect(),
+          None => Vec::new(),
+        };
+        if properties.eo_cloud_cover.is_some() || !eo_bands.is_empty() || band_assets.is_some() {
+          stac_extensions.push(stac::EO_EXT);
+        }
+        if let Some(obj) = &self.s3_object {
+          alternate.insert(String::from("s3"), to_value(stac::AlternateAsset { href: obj.s3_url() }).unwrap());
+          alternate.insert(String::from("https"), to_value(stac::AlternateAsset { href: obj.https_href.to_owned() }).unwrap());
+          stac_extensions.push(stac::ALTERNATE_ASSETS_EXT);
+        }
         let file_asset: Value =  to_value(stac::ItemAsset{
-            href: self.properties.path.to_owned()
+            href: self.properties.path.to_owned(),
+            roles: vec![String::from("data")],
+            alternate,
+            eo_bands
         }).unwrap();
         assets.insert("file".to_string(), file_asset);
+        if let Some(base_url) = band_assets {
+          for band in 1..=self.properties.num_bands {
+            assets.insert(format!("band-{}", band), to_value(self.band_asset(base_url, band)).unwrap());
+          }
+        }
+
+        // the sidecar's properties and assets win over the ones found with GDAL.
+        let mut properties_map = properties.to_map();
+        if let Some(sidecar) = &self.sidecar {
+          properties_map.extend(sidecar.properties.to_owned());
+          assets.extend(sidecar.assets.to_owned());
+        }
 
         let mut foreign_members = Map::new();
+        foreign_members.insert(String::from("stac_version"), serde_json::Value::String(String::from(stac::STAC_VERSION)));
+        if !stac_extensions.is_empty() {
+          foreign_members.insert(String::from("stac_extensions"), to_value(stac_extensions).unwrap());
+        }
         foreign_members.insert(String::from("links"), serde_json::Value::Array(Vec::new()));
         foreign_members.insert(String::from("assets"), to_value(assets).unwrap());
         foreign_members.insert(String::from("collection"), serde_json::Value::String(self.collection_id.to_owned()));
@@ -396,109 +1160,392 @@ impl ImageryFile {
             id: Some(geojson::feature::Id::String(self.filename.to_owned())),
             bbox,
             geometry: Some(geometry),
-            properties: Some(properties.to_map()),
+            properties: Some(properties_map),
             foreign_members: Some(foreign_members)
         }
     }
 
-    /// Creates a new ImageryFile from a GDAL Dataset
-    pub fn new(dataset: &Dataset, path: PathBuf, filename: &str, collection_id: &str) -> ImageryFile {
-      let poly = get_extent(&dataset);
+    /// a STAC Item for the image, with self, parent, collection and root links.
+    /// With `band_assets`, each band is also an asset (see to_stac_feature).
+    pub fn to_stac_item(&self, base_url: &url::Url, band_assets: bool) -> geojson::Feature {
+        let mut feature = self.to_stac_feature(if band_assets { Some(base_url) } else { None });
+        let item_links = vec![
+          links::item_self_link(base_url, &self.collection_id, &self.filename),
+          links::parent_link(base_url, Some(&self.collection_id)),
+          links::item_collection_link(base_url, &self.collection_id),
+          links::root_link(base_url),
+        ];
+        if let Some(members) = feature.foreign_members.as_mut() {
+          members.insert(String::from("links"), to_value(item_links).unwrap());
+        }
+        feature
+    }
+
+    /// an asset for a single band (starting at 1) of the image, which links to the band download endpoint.
+    fn band_asset(&self, base_url: &url::Url, band: u16) -> stac::BandAsset {
+        stac::BandAsset {
+          href: links::band_url(base_url, &self.collection_id, &self.filename, band).to_string(),
+          media_type: String::from("image/tiff; application=geotiff"),
+          roles: vec![String::from("data")],
+          eo_bands: vec![self.eo_band(band)]
+        }
+    }
+
+    /// the eo:bands entry for a band (starting at 1), with its common name if it's the red or near-infrared band.
+    fn eo_band(&self, band: u16) -> stac::EoBand {
+        let common_name = if self.properties.red_band == Some(band) {
+          Some(String::from("red"))
+        } else if self.properties.ni_band == Some(band) {
+          Some(String::from("nir"))
+        } else {
+          None
+        };
+        stac::EoBand { name: format!("b{}", band), common_name }
+    }
+
+    /// a GeoJSON Feature with the footprint of the image and minimal properties (id and datetime),
+    /// for drawing coverage on a map.
+    pub fn to_footprint_feature(&self, tolerance: Option<f64>) -> geojson::Feature {
+        let boundary = match tolerance {
+          Some(epsilon) => self.boundary.simplify(&epsilon),
+          None => self.boundary.to_owned(),
+        };
+        let geometry = match boundary.0.as_slice() {
+          [poly] => geojson::Geometry::from(poly),
+          _ => geojson::Geometry::from(&boundary),
+        };
+
+        let mut properties = Map::new();
+        properties.insert(String::from("datetime"), to_value(self.properties.timestamp).unwrap());
+
+        Feature {
+            id: Some(geojson::feature::Id::String(self.filename.to_owned())),
+            bbox: None,
+            geometry: Some(geometry),
+            properties: Some(properties),
+            foreign_members: None
+        }
+    }
+
+    /// Creates a new ImageryFile from a GDAL Dataset.
+    /// `path` is the path the dataset was opened with (e.g. a /vsis3/ path for S3 objects), and
+    /// `href` is the link to the file that is advertised in the file's asset.
+    /// Returns an error if the dataset isn't georeferenced, or its footprint can't be converted to lat/lng.
+    /// Metadata that can't be parsed is logged and left out.
+    pub fn new(
+      dataset: &Dataset,
+      path: PathBuf,
+      href: String,
+      filename: &str,
+      collection_id: &str,
+      options: &ScanOptions
+    ) -> Result<ImageryFile, String> {
+      let geo_transform = dataset.geo_transform().map_err(|e| format!("no geotransform: {}", e))?;
+      let poly = get_extent(dataset, &geo_transform);
       let crs = dataset.projection();
+      debug!("{} crs: {}", path.display(), crs);
+      let epsg = transform::epsg_code(&crs);
+      let wkt2 = match epsg {
+        Some(_) => None,
+        None => transform::to_wkt2(&crs),
+      };
       let num_bands = dataset.raster_count() as u16;
+      let nodata: Vec<Option<f64>> = (1..=dataset.raster_count())
+        .map(|i| dataset.rasterband(i).ok().and_then(|b| b.no_data_value()))
+        .collect();
       
       // Check metadata for cloud coverage
       // this is the metadata key for Sentinel-2 imagery.
       // todo: confirm key for other sources.
       let cloud_coverage: Option<f64> = dataset
           .metadata_item("CLOUD_COVERAGE_ASSESSMENT", "")
-          .map(|s| s.parse::<f64>().unwrap());
-
-      // Check metadata for timestamp. "PRODUCT_START_TIME" is used, but
-      // need to confirm whether this is the most appropriate timestamp.
-      // Also need a default value for when timestamp isn't available. Right now we're using 1900/1/1.
-      let timestamp: DateTime<Utc> = match dataset
-          .metadata_item("PRODUCT_START_TIME", "") {
-            Some(ts) => DateTime::parse_from_rfc3339(&ts).unwrap().with_timezone(&Utc),
-            None => Utc.ymd(1900, 1, 1).and_hms(0, 0, 0),
-          };
-
+          .and_then(|s| match s.trim().parse::<f64>() {
+            Ok(c) => Some(c),
+            Err(_) => {
+              warn!("{}: ignoring invalid CLOUD_COVERAGE_ASSESSMENT `{}`", path.display(), s);
+              None
+            },
+          });
+
+      // Check metadata for timestamp. The first of the timestamp keys (PRODUCT_START_TIME by default)
+      // that has a valid datetime is used. If there isn't one, the datetime pattern is tried on the file name.
+      // Files without either are given the default datetime (1900/1/1 unless it's configured), or a null datetime.
+      let found: Option<DateTime<Utc>> = options.timestamp_keys.iter()
+          .filter_map(|key| dataset.metadata_item(key, "").map(|ts| (key, ts)))
+          .find_map(|(key, ts)| match parse_image_datetime(&ts) {
+            Some(dt) => Some(dt),
+            None => {
+              warn!("{}: ignoring invalid {} `{}`", path.display(), key, ts);
+              None
+            },
+          })
+          .or_else(|| options.datetime_pattern.as_ref().and_then(|pattern| datetime_from_filename(pattern, &path)));
+      let timestamp_defaulted = found.is_none() && options.default_datetime.is_some();
+      let timestamp = found.or(options.default_datetime);
+
+      // files on GDAL's virtual filesystems (e.g. /vsis3/) have no modification time here. S3 objects are given theirs
+      // from the bucket listing.
+      let modified: Option<DateTime<Utc>> = fs::metadata(&path).and_then(|m| m.modified()).ok().map(DateTime::from);
+
+      // find the red and near-infrared bands for the sensor (e.g. Sentinel-2 or Landsat), if it's known.
+      let (red_band, ni_band) = sensor::red_nir_bands(dataset);
 
       // capture the IMAGEDESCRIPTION tag.
       let description: Option<String> = dataset
           .metadata_item("TIFFTAG_IMAGEDESCRIPTION", "");
 
-      // convert extent polygon into lat/long
-      let boundary: Polygon<f64> = transform::transform_polygon(&poly, &crs, "EPSG:4326");
+      // convert extent polygon into lat/long. Extents that cross the antimeridian are split
+      // into a polygon on either side of it.
+      let boundary: MultiPolygon<f64> = transform::transform_polygon(&poly, &crs, "EPSG:4326")
+          .map(|p| transform::split_antimeridian(&p))
+          .map_err(|e| format!("could not convert footprint to lat/lng: {}", e))?;
+
+      let resolution = get_resolution(&crs, &geo_transform, dataset.raster_size())?;
+      let overview_levels = get_overviews(dataset, resolution);
 
       // add the file information to the coverage vector.
       let properties = ImageryFileProperties {
-          path: path.as_path().display().to_string(),
+          path: href,
           filename: filename.to_string(),
           crs: crs.to_owned(),
-          resolution: get_resolution(&dataset),
+          epsg,
+          wkt2,
+          resolution,
           description,
           num_bands,
+          nodata,
           cloud_coverage,
           timestamp,
-          red_band: None, // unimplemented
-          ni_band: None  // unimplemented
+          timestamp_defaulted,
+          modified,
+          red_band,
+          ni_band,
+          overview_levels,
+          custom_properties: options.property_mapping.extract(dataset)
       };
 
-      ImageryFile{
+      Ok(ImageryFile{
           path,
           filename: filename.to_string(),
           boundary,
           properties,
-          collection_id: collection_id.to_owned()
+          collection_id: collection_id.to_owned(),
+          s3_object: None,
+        sidecar: None
+      })
+    }
+}
+
+/// the bounding box (in lat/lng) of all the files' footprints. None if there are no files.
+pub fn files_bounding_rect(files: &[ImageryFile]) -> Option<Rect<f64>> {
+  files.iter()
+    .filter_map(|f| f.boundary.bounding_rect())
+    .fold(None, |bbox: Option<Rect<f64>>, rect| match bbox {
+      Some(b) => Some(Rect::new(
+        Coordinate { x: b.min().x.min(rect.min().x), y: b.min().y.min(rect.min().y) },
+        Coordinate { x: b.max().x.max(rect.max().x), y: b.max().y.max(rect.max().y) }
+      )),
+      None => Some(rect),
+    })
+}
+
+/// parses an image's datetime from a metadata item or its file name. Datetimes can be RFC 3339, in the format of the
+/// TIFF DateTime tag (`YYYY:MM:DD HH:MM:SS`), in the compact form used in file names (`YYYYMMDDTHHMMSS`), or a date
+/// (`YYYY-MM-DD` or `YYYYMMDD`, treated as midnight). Datetimes without a timezone are treated as UTC.
+fn parse_image_datetime(s: &str) -> Option<DateTime<Utc>> {
+  let s = s.trim();
+  if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+    return Some(dt.with_timezone(&Utc));
+  }
+  NaiveDateTime::parse_from_str(s, "%Y:%m:%d %H:%M:%S")
+    .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y%m%dT%H%M%S"))
+    .or_else(|_| NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|d| d.and_hms(0, 0, 0)))
+    .or_else(|_| NaiveDate::parse_from_str(s, "%Y%m%d").map(|d| d.and_hms(0, 0, 0)))
+    .map(|dt| DateTime::from_utc(dt, Utc))
+    .ok()
+}
+
+/// compiles a pattern for finding datetimes in file names. The pattern must have a group named `datetime`
+/// that matches the datetime, e.g. `_(?P<datetime>\d{8}T\d{6})_` for Sentinel-2 file names.
+pub fn filename_datetime_pattern(pattern: &str) -> Result<Regex, String> {
+  let re = Regex::new(pattern).map_err(|e| format!("invalid datetime pattern: {}", e))?;
+  if !re.capture_names().any(|name| name == Some("datetime")) {
+    return Err(format!("datetime pattern `{}` must have a group named `datetime`", pattern));
+  }
+  Ok(re)
+}
+
+/// finds a file's datetime in its file name, using the `datetime` group of `pattern`.
+fn datetime_from_filename(pattern: &Regex, path: &Path) -> Option<DateTime<Utc>> {
+  let name = path.file_name()?.to_str()?;
+  let found = pattern.captures(name)?.name("datetime")?.as_str();
+  let dt = parse_image_datetime(found);
+  if dt.is_none() {
+    warn!("{}: ignoring invalid datetime `{}` in file name", path.display(), found);
+  }
+  dt
+}
+
+/// the datetime given to files that don't have a timestamp in their metadata, unless another default is configured.
+pub fn default_timestamp() -> DateTime<Utc> {
+  Utc.ymd(1900, 1, 1).and_hms(0, 0, 0)
+}
+
+/// parses a default datetime for files without one: an RFC 3339 datetime, or `null` for a null datetime.
+pub fn parse_default_datetime(s: &str) -> Result<Option<DateTime<Utc>>, String> {
+  if s.trim().eq_ignore_ascii_case("null") {
+    return Ok(None)
+  }
+  DateTime::parse_from_rfc3339(s.trim())
+    .map(|dt| Some(dt.with_timezone(&Utc)))
+    .map_err(|e| format!("invalid default datetime `{}`: {}. Use an RFC 3339 datetime (e.g. 2000-01-01T00:00:00Z) or `null`", s, e))
+}
+
+/// checks that `geom` intersects `footprint` and that no part of `geom` is outside of it, counting
+/// the footprint's boundary as part of the footprint.
+/// geom can't be outside the footprint if none of its vertices (or the midpoints of its edges) are
+/// outside, none of its edges cross the footprint's boundary, and it doesn't surround a hole in the footprint.
+fn covers(footprint: &MultiPolygon<f64>, geom: &Polygon<f64>) -> bool {
+  if !footprint.intersects(geom) {
+    return false
+  }
+
+  let outside = |c: &Coordinate<f64>| footprint.coordinate_position(c) == CoordPos::Outside;
+  let footprint_edges: Vec<Line<f64>> = footprint.iter()
+    .flat_map(|p| p.exterior().lines().chain(p.interiors().iter().flat_map(|r| r.lines())).collect::<Vec<_>>())
+    .collect();
+
+  for edge in geom.exterior().lines().chain(geom.interiors().iter().flat_map(|r| r.lines())) {
+    let midpoint = Coordinate { x: (edge.start.x + edge.end.x) / 2., y: (edge.start.y + edge.end.y) / 2. };
+    if outside(&edge.start) || outside(&midpoint) {
+      return false
+    }
+
+    for footprint_edge in footprint_edges.iter() {
+      if let Some(LineIntersection::SinglePoint { is_proper: true, .. }) = line_intersection(edge, *footprint_edge) {
+        return false
       }
     }
+  }
+
+  !footprint.iter()
+    .flat_map(|p| p.interiors().iter().flat_map(|r| r.0.iter()))
+    .any(|c| geom.coordinate_position(c) == CoordPos::Inside)
+}
+
+/// the polygons that make up an area: a Polygon, MultiPolygon, Rect or Triangle, or a GeometryCollection of them.
+/// Returns None if the geometry has no polygons, or has a part that isn't an area (e.g. a point or a line).
+pub fn polygons(geom: &Geometry<f64>) -> Option<Vec<Polygon<f64>>> {
+  let polys = match geom {
+    Geometry::Polygon(poly) => vec![poly.to_owned()],
+    Geometry::MultiPolygon(multi) => multi.0.to_owned(),
+    Geometry::Rect(rect) => vec![rect.to_polygon()],
+    Geometry::Triangle(triangle) => vec![triangle.to_polygon()],
+    Geometry::GeometryCollection(collection) => {
+      let mut polys = Vec::new();
+      for part in collection.0.iter() {
+        polys.extend(polygons(part)?);
+      }
+      polys
+    },
+    _ => return None,
+  };
+  if polys.is_empty() {
+    return None
+  }
+  Some(polys)
+}
+
+/// GDAL config options that make opening files over the network faster: GDAL doesn't list the
+/// directory that each file is in, and only requests files with extensions that it might read
+/// (images, overviews, masks and metadata). Options that are already set (e.g. as environment
+/// variables) aren't changed.
+const NETWORK_CONFIG_DEFAULTS: [(&str, &str); 2] = [
+  ("GDAL_DISABLE_READDIR_ON_OPEN", "EMPTY_DIR"),
+  ("CPL_VSIL_CURL_ALLOWED_EXTENSIONS", ".tif,.tiff,.jp2,.j2k,.vrt,.ovr,.msk,.xml,.json")
+];
+
+static NETWORK_DEFAULTS: Once = Once::new();
+
+/// opens a file to catalog it with GDAL, using the open options in ScanOptions.
+/// Files on S3 (`/vsis3/`) or HTTP (`/vsicurl/`) are opened with the NETWORK_CONFIG_DEFAULTS.
+fn open_dataset(path: &Path, options: &ScanOptions) -> gdal::errors::Result<Dataset> {
+  let p = path.to_string_lossy();
+  if p.starts_with("/vsis3/") || p.starts_with("/vsicurl/") {
+    NETWORK_DEFAULTS.call_once(|| {
+      for (key, value) in NETWORK_CONFIG_DEFAULTS.iter() {
+        if gdal::config::get_config_option(key, "").unwrap_or_default().is_empty() {
+          let _ = gdal::config::set_config_option(key, value);
+        }
+      }
+    });
+  }
+
+  let open_options: Vec<&str> = options.open_options.iter().map(|o| o.as_str()).collect();
+  Dataset::open_ex(path, DatasetOptions { open_options: Some(&open_options), ..Default::default() })
 }
 
-/// get_resolution uses a raster image's geotransform to determine the resolution.
+/// checks a GDAL open option from the command line, which must be `KEY=VALUE`.
+pub fn gdal_open_option(option: &str) -> Result<String, String> {
+  match option.split_once('=') {
+    Some((key, _)) if !key.trim().is_empty() => Ok(option.to_owned()),
+    _ => Err(format!("invalid GDAL open option `{}`: expected KEY=VALUE (e.g. NUM_THREADS=ALL_CPUS)", option)),
+  }
+}
+
+/// get_resolution uses a raster image's geotransform to determine the size of its pixels in meters.
+/// The corners of the pixel at the center of the image are converted to lat/lng and the distances between them
+/// are measured on the ground, so the resolution is in meters whatever the units of the image's CRS are.
 /// https://gdal.org/tutorials/geotransforms_tut.html
-fn get_resolution(dataset: &Dataset) -> Resolution {
-  let crs = dataset.projection();
-  let [xmin, xsize, xskew, ymax, yskew, ysize] = dataset.geo_transform().unwrap();
-  let mut xpixel = (xsize.powi(2) + xskew.powi(2)).sqrt();
-  let mut ypixel = (ysize.powi(2) + yskew.powi(2)).sqrt();
-
-  // upper left
-  let p0 = point!(
-    x:    xmin,
-    y:    ymax 
+fn get_resolution(crs: &str, geo_transform: &GeoTransform, size: (usize, usize)) -> Result<Resolution, String> {
+  let [x0, x_size, x_skew, y0, y_skew, y_size] = *geo_transform;
+
+  // the position (in the image's CRS) of a pixel/line location in the image.
+  let position = |pixel: f64, line: f64| point!(
+    x: x0 + pixel * x_size + line * x_skew,
+    y: y0 + pixel * y_skew + line * y_size
   );
-  let p1 = transform::transform_point(p0, &crs, "EPSG:4326");
-
-  // HELP!  How do we check if our coordinates are latlng?
-  // this is a terrible method and can probably give a false positive in some areas.
-  if xmin.abs() < 180. && ymax.abs() < 90. && (p1.x() - p0.x()).abs() < 0.000001 {
-    // upper right
-    let x1 = point!(
-      x: xmin + xpixel,
-      y: ymax
-    );
-    let x1 = transform::transform_point(x1, &crs, "EPSG:4326");
+  let (pixel, line) = ((size.0 / 2) as f64, (size.1 / 2) as f64);
 
-    // lower left
-    let y1 = point!(
-      x: xmin,
-      y: ymax + ypixel
-    );
-    let y1 = transform::transform_point(y1, &crs, "EPSG:4326");
-    xpixel = p0.haversine_distance(&x1);
-    ypixel = p0.haversine_distance(&y1);
-  }
+  let corner = transform::transform_point(position(pixel, line), crs, "EPSG:4326")?;
+  let right = transform::transform_point(position(pixel + 1., line), crs, "EPSG:4326")?;
+  let below = transform::transform_point(position(pixel, line + 1.), crs, "EPSG:4326")?;
 
+  Ok(Resolution{x: corner.haversine_distance(&right), y: corner.haversine_distance(&below)})
+}
 
+/// the overviews of a dataset's first band (GDAL builds overviews for every band at the same sizes),
+/// sorted from the most to the least detailed. `resolution` is the resolution of the full size data.
+fn get_overviews(dataset: &Dataset, resolution: Resolution) -> Vec<Overview> {
+  let band = match dataset.rasterband(1) {
+    Ok(b) => b,
+    Err(_) => return Vec::new(),
+  };
+  let count = band.overview_count().unwrap_or(0);
+  let mut overviews: Vec<Overview> = (0..count)
+    .filter_map(|i| band.overview(i as isize).ok())
+    .map(|o| overview(resolution, dataset.raster_size(), o.size()))
+    .collect();
+  overviews.sort_by_key(|o| Reverse(o.width));
+  overviews
+}
 
-  Resolution{x: xpixel, y: ypixel}
+/// an overview of `size` pixels, of data that is `full_size` pixels at `resolution`.
+fn overview(resolution: Resolution, full_size: (usize, usize), size: (usize, usize)) -> Overview {
+  Overview {
+    width: size.0,
+    height: size.1,
+    resolution: Resolution {
+      x: resolution.x * full_size.0 as f64 / size.0.max(1) as f64,
+      y: resolution.y * full_size.1 as f64 / size.1.max(1) as f64
+    }
+  }
 }
 
 /// get_extent calculates the extent of a given dataset and
 /// returns a geo_types::Polygon representing it.
-fn get_extent(dataset: &Dataset) -> Polygon<f64> {
-  let [xmin, x_size, _, ymin, _, y_size] = dataset.geo_transform().unwrap();
+fn get_extent(dataset: &Dataset, geo_transform: &GeoTransform) -> Polygon<f64> {
+  let [xmin, x_size, _, ymin, _, y_size] = *geo_transform;
   let (width, height) = dataset.raster_size();
 
   // this calculation tosses out skew, but incorporating the pixel widths from
@@ -514,93 +1561,1804 @@ fn get_extent(dataset: &Dataset) -> Polygon<f64> {
   ]
 }
 
-/// looks for folders within `dir` and creates collections out of them.
-/// currently, this means that you should create a data directory that
-/// itself contains one or more folders that represent collections.
-/// e.g. the following 3 folders under the ./data dir:
-///   ./data/imagery
-///   ./data/dem
-///   ./data/sentinel2
-/// would create collections "imagery", "dem", and "sentinel2".
-pub fn collections_from_subdirs(dir: &str) -> HashMap<String, ImageryCollection> {
-  let mut collections: HashMap<String, ImageryCollection> = HashMap::new();
-  let data_dir = fs::read_dir(dir).unwrap();
+/// files in a collection directory (or S3 prefix) that configure the collection, instead of being catalogued.
+const CONFIG_FILES: [&str; 3] = [STACIGNORE, COLLECTION_JSON, COLLECTION_TOML];
+
+/// why a file found while scanning a collection was not catalogued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SkipReason {
+  /// the file matches a pattern in the collection's .stacignore file.
+  Ignored,
+  /// GDAL could not open the file, e.g. because it isn't a raster.
+  Unreadable,
+  /// the file opened, but its georeferencing is missing or couldn't be used (see ImageryFile::new).
+  InvalidGeoreference,
+  /// the file is the sidecar STAC item of another file (see Sidecar).
+  Sidecar,
+  /// the file is a GDAL auxiliary file (e.g. external overviews) of another file (see sidecar::AUXILIARY_SUFFIXES).
+  Auxiliary,
+}
 
-  for entry in data_dir {
-    let file = entry.unwrap();
-    let path = file.path();
+impl fmt::Display for SkipReason {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SkipReason::Ignored => write!(f, "listed in {}", STACIGNORE),
+      SkipReason::Unreadable => write!(f, "not readable by GDAL"),
+      SkipReason::InvalidGeoreference => write!(f, "missing or invalid georeferencing"),
+      SkipReason::Sidecar => write!(f, "sidecar metadata"),
+      SkipReason::Auxiliary => write!(f, "GDAL overviews or auxiliary files"),
+    }
+  }
+}
 
+/// ScanSummary counts the files found while scanning a collection, and why any were skipped.
+#[derive(Debug, Default, PartialEq)]
+pub struct ScanSummary {
+  pub scanned: usize,
+  pub catalogued: usize,
+  /// the number of files skipped for each reason.
+  pub skipped: BTreeMap<SkipReason, usize>
+}
+
+impl ScanSummary {
+  /// sorts the files that were catalogued by filename (they may have been scanned in any order),
+  /// and summarizes the results of scanning each file.
+  fn from_results(results: Vec<Result<ImageryFile, SkipReason>>) -> (Vec<ImageryFile>, ScanSummary) {
+    let mut summary = ScanSummary { scanned: results.len(), ..ScanSummary::default() };
+    let mut files = Vec::new();
+    for result in results {
+      match result {
+        Ok(f) => files.push(f),
+        Err(reason) => {
+          if reason == SkipReason::Unreadable {
+            metrics::GDAL_OPEN_FAILURES.fetch_add(1, atomic::Ordering::Relaxed);
+          }
+          *summary.skipped.entry(reason).or_insert(0) += 1
+        },
+      }
+    }
+    summary.catalogued = files.len();
+    files.sort_by(|a, b| a.filename.cmp(&b.filename));
+    (files, summary)
+  }
+}
+
+impl fmt::Display for ScanSummary {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} files scanned, {} catalogued", self.scanned, self.catalogued)?;
+    if !self.skipped.is_empty() {
+      let reasons: Vec<String> = self.skipped.iter().map(|(reason, n)| format!("{} {}", n, reason)).collect();
+      write!(f, ", {} skipped ({})", self.scanned - self.catalogued, reasons.join(", "))?;
+    }
+    Ok(())
+  }
+}
+
+/// logs the scan summary of each collection, once all of them have been scanned.
+fn log_scan_summaries(summaries: &[(String, ScanSummary)]) {
+  info!("catalogued {} collections", summaries.len());
+  for (id, summary) in summaries {
+    info!("  {}: {}", id, summary);
+  }
+}
+
+/// lists the files in `dir`, sorted by path. With `recursive`, files in subdirectories (at any depth)
+/// are listed too. Directories that have already been listed, e.g. through a symlink loop, are skipped.
+fn list_files(dir: &Path, recursive: bool) -> Vec<PathBuf> {
+  let mut files = Vec::new();
+  let mut visited: HashSet<PathBuf> = HashSet::new();
+  let mut dirs = vec![dir.to_path_buf()];
+
+  while let Some(dir) = dirs.pop() {
+    match fs::canonicalize(&dir) {
+      Ok(real_path) => if !visited.insert(real_path) {
+        warn!("skipping {}: directory was already scanned", dir.display());
+        continue
+      },
+      Err(e) => {
+        warn!("could not read {}: {}", dir.display(), e);
+        continue
+      },
+    }
+
+    let entries = match fs::read_dir(&dir) {
+      Ok(entries) => entries,
+      Err(e) => {
+        warn!("could not read {}: {}", dir.display(), e);
+        continue
+      },
+    };
+    for entry in entries {
+      let path = match entry {
+        Ok(e) => e.path(),
+        Err(e) => {
+          warn!("could not read an entry in {}: {}", dir.display(), e);
+          continue
+        },
+      };
+      if path.is_file() {
+        files.push(path);
+      } else if recursive && path.is_dir() {
+        dirs.push(path);
+      }
+    }
+  }
+  files.sort();
+  files
+}
+
+/// a relative path as a string with `/` separators.
+fn slash_path(path: &Path) -> String {
+  path.components()
+    .map(|c| c.as_os_str().to_string_lossy())
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+/// looks for folders within `dir` and creates collections out of them.
+/// currently, this means that you should create a data directory that
+/// itself contains one or more folders that represent collections.
+/// e.g. the following 3 folders under the ./data dir:
+///   ./data/imagery
+///   ./data/dem
+///   ./data/sentinel2
+/// would create collections "imagery", "dem", and "sentinel2".
+pub fn collections_from_subdirs(dir: &str, options: &ScanOptions) -> HashMap<String, ImageryCollection> {
+  let mut collections: HashMap<String, ImageryCollection> = HashMap::new();
+
+  let mut summaries: Vec<(String, ScanSummary)> = Vec::new();
+  for d in collection_dirs(dir) {
+    let (c, summary) = ImageryCollection::new_from_dir(
+      d.id.to_owned(),
+      d.name.to_owned(),
+      d.name,
+      d.path,
+      options
+    );
+    summaries.push((d.id.to_owned(), summary));
+    collections.insert(d.id, c);
+  }
+  log_scan_summaries(&summaries);
+  collections
+}
+
+/// rescans the collection directories in `dir` named in `changed`, and swaps them into `collections`.
+/// Collections whose directory was removed are dropped, new directories are added, and the
+/// other collections are kept as they are. Directories are scanned before the collections are locked,
+/// so that requests aren't held up by the scan.
+pub fn rescan_collections(dir: &str, changed: &HashSet<String>, options: &ScanOptions, collections: &RwLock<Arc<Catalog>>) {
+  let dirs = collection_dirs(dir);
+
+  let mut rescanned: HashMap<String, ImageryCollection> = HashMap::new();
+  let mut summaries: Vec<(String, ScanSummary)> = Vec::new();
+  for d in dirs.iter() {
+    let is_new = !collections.read().unwrap().contains_key(&d.id);
+    if !is_new && !changed.contains(&d.name) {
+      continue;
+    }
+    let (c, summary) = ImageryCollection::new_from_dir(
+      d.id.to_owned(),
+      d.name.to_owned(),
+      d.name.to_owned(),
+      d.path.to_owned(),
+      options
+    );
+    summaries.push((d.id.to_owned(), summary));
+    rescanned.insert(d.id.to_owned(), c);
+  }
+  log_scan_summaries(&summaries);
+  if let Some(cache) = &options.cache {
+    cache.save(false);
+  }
+
+  // the old catalog is only copied if a request still has a snapshot of it.
+  let mut catalog = collections.write().unwrap();
+  let mut collections = match Arc::try_unwrap(std::mem::take(&mut *catalog)) {
+    Ok(old) => old.into_collections(),
+    Err(snapshot) => snapshot.collections.clone(),
+  };
+  collections.retain(|id, _| {
+    let keep = dirs.iter().any(|d| &d.id == id);
+    if !keep {
+      info!("removed collection {}", id);
+    }
+    keep
+  });
+  collections.extend(rescanned);
+  *catalog = Arc::new(Catalog::from(collections));
+}
+
+/// a subdirectory of the catalog directory, which is catalogued as a collection.
+struct CollectionDir {
+  id: String,
+  /// the directory name.
+  name: String,
+  path: PathBuf
+}
+
+/// lists the collection directories in `dir`, with their collection ids.
+fn collection_dirs(dir: &str) -> Vec<CollectionDir> {
+  let entries = match fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(e) => {
+      error!("could not read catalog directory {}: {}", dir, e);
+      return Vec::new()
+    },
+  };
+
+  // sort the directories so that collection ids are stable if any of them collide.
+  let mut data_dir: Vec<PathBuf> = entries
+    .filter_map(|entry| match entry {
+      Ok(e) => Some(e.path()),
+      Err(e) => {
+        warn!("could not read an entry in {}: {}", dir, e);
+        None
+      },
+    })
+    .collect();
+  data_dir.sort();
+
+  let mut ids: HashSet<String> = HashSet::new();
+  let mut dirs = Vec::new();
+  for path in data_dir {
     // skip if not a file.
     if !path.is_dir() {
       continue;
     }
 
-    // yikes
-    let dirname = file.path().as_path().file_stem().unwrap().to_str().unwrap().to_owned();
+    let dirname = match path.file_stem() {
+      Some(name) => name.to_string_lossy().to_string(),
+      None => continue,
+    };
+    let id = unique_collection_id(&dirname, |id| ids.contains(id));
+    ids.insert(id.to_owned());
+    dirs.push(CollectionDir { id, name: dirname, path });
+  }
+  dirs
+}
 
-    let c = ImageryCollection::new_from_dir(
-      dirname.to_owned(),
-      dirname.to_owned(),
-      dirname.to_owned(),
-      path
-    );
-    collections.insert(dirname, c);
+/// converts a directory or prefix name into a collection id, e.g. `Land Use` becomes `land-use`.
+/// Ids are lowercase, with each run of characters other than letters, digits and `_` replaced by a `-`.
+pub fn slugify(name: &str) -> String {
+  let mut slug = String::new();
+  for c in name.chars() {
+    if c.is_alphanumeric() || c == '_' {
+      slug.extend(c.to_lowercase());
+    } else if !slug.is_empty() && !slug.ends_with('-') {
+      slug.push('-');
+    }
   }
-  collections
+
+  let slug = slug.trim_end_matches('-');
+  if slug.is_empty() {
+    return String::from("collection")
+  }
+  slug.to_owned()
+}
+
+/// returns the id for a new collection named `name`. If another collection already has the same id
+/// (e.g. `Land Use` and `land-use`), a numeric suffix is added so that neither collection is lost.
+fn unique_collection_id(name: &str, is_taken: impl Fn(&str) -> bool) -> String {
+  let slug = slugify(name);
+  if !is_taken(&slug) {
+    return slug
+  }
+
+  let mut n = 2;
+  while is_taken(&format!("{}-{}", slug, n)) {
+    n += 1;
+  }
+  let id = format!("{}-{}", slug, n);
+  warn!("collection {} has the same id as another collection ({}). Using id {} instead.", name, slug, id);
+  id
 }
 
 /* S3 integration */
 
+/// reads a small text file stored in S3, such as a .stacignore file.
+/// Returns None (after logging a warning) if the file can't be read.
+async fn read_s3_object(access: &S3Access, bucket: &str, key: &str, breaker: &CircuitBreaker) -> Option<String> {
+  let result = breaker.call(|| async move {
+    match access {
+      S3Access::Client(client) => {
+        let obj = client.get_object().bucket(bucket).key(key).send().await.map_err(|e| e.to_string())?;
+        let data = obj.body.collect().await.map_err(|e| e.to_string())?;
+        Ok(String::from_utf8_lossy(&data.into_bytes()).into_owned())
+      },
+      S3Access::Anonymous => vsi::read_to_string(&(String::from("/vsis3/") + bucket + "/" + key))
+        .ok_or_else(|| String::from("the object could not be read")),
+    }
+  }).await;
+
+  match result {
+    Ok(contents) => Some(contents),
+    Err(BreakerError::Open) => {
+      warn!("S3 is unavailable, could not read {}", key);
+      None
+    },
+    Err(BreakerError::Failed(e)) => {
+      warn!("could not read {}: {}", key, e);
+      None
+    },
+  }
+}
+
+/// S3UrlStyle is how an object's bucket is addressed in its URL: in the path (`example.com/bucket/key`,
+/// which MinIO uses) or in the host name (`bucket.example.com/key`, AWS's virtual-hosted style).
+/// It sets both the links to S3 objects and GDAL's AWS_VIRTUAL_HOSTING option.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum S3UrlStyle {
+  Path,
+  VirtualHost
+}
+
+impl std::str::FromStr for S3UrlStyle {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "path" => Ok(S3UrlStyle::Path),
+      "virtual-host" => Ok(S3UrlStyle::VirtualHost),
+      _ => Err(format!("invalid S3 URL style `{}`. Use path or virtual-host", s)),
+    }
+  }
+}
+
+/// parses the S3 host (AWS_S3_ENDPOINT), which needs a scheme and a host, e.g. http://localhost:9000.
+pub fn parse_s3_host(s: &str) -> Result<http::Uri, String> {
+  let uri = s.parse::<http::Uri>().map_err(|e| format!("invalid S3 host `{}`: {}", s, e))?;
+  match (uri.scheme(), uri.authority()) {
+    (Some(_), Some(_)) => Ok(uri),
+    _ => Err(format!("invalid S3 host `{}`: expected a scheme, host and port (e.g. http://localhost:9000)", s)),
+  }
+}
+
+/// the URL of an object on the S3 host `s3_host` (which includes the scheme, e.g. http://localhost:9000).
+fn s3_object_href(s3_host: &str, bucket: &str, key: &str, style: S3UrlStyle) -> String {
+  let s3_host = s3_host.trim_end_matches('/');
+  if style == S3UrlStyle::VirtualHost {
+    if let Ok(uri) = s3_host.parse::<http::Uri>() {
+      if let (Some(scheme), Some(authority)) = (uri.scheme_str(), uri.authority()) {
+        return format!("{}://{}.{}/{}", scheme, bucket, authority, key);
+      }
+    }
+  }
+  String::from(s3_host) + "/" + bucket + "/" + key
+}
+
+/// S3Access is how the objects in an S3 bucket are listed and read.
+/// The S3 client always signs its requests, so buckets are read anonymously through GDAL's /vsis3/
+/// filesystem (with AWS_NO_SIGN_REQUEST) when no credentials are supplied.
+pub enum S3Access {
+  Client(s3::Client),
+  Anonymous
+}
+
+/// an object in an S3 listing. The ETag identifies the version of the object (for the scan cache). Neither it
+/// nor the object's last modified time are known for buckets that are read anonymously.
+struct ListedObject {
+  key: String,
+  etag: Option<String>,
+  last_modified: Option<DateTime<Utc>>
+}
+
+/// lists every object under `prefix`. Keys are listed under `prefix/`, so that the objects of a prefix
+/// that only starts the same way (e.g. `imagery-old` for `imagery`) aren't included.
+async fn list_s3_objects(
+  access: &S3Access,
+  bucket: &str,
+  prefix: &str,
+  breaker: &CircuitBreaker
+) -> Result<Vec<ListedObject>, BreakerError<String>> {
+  let dir = String::from(prefix) + "/";
+  let client = match access {
+    S3Access::Client(client) => client,
+    S3Access::Anonymous => {
+      return Ok(vsi::read_dir_recursive(&(String::from("/vsis3/") + bucket + "/" + &dir))
+        .into_iter()
+        .filter(|path| !path.ends_with('/'))
+        .map(|path| ListedObject { key: dir.to_owned() + &path, etag: None, last_modified: None })
+        .collect())
+    },
+  };
+
+  let dir = dir.as_str();
+  list_objects_under(dir, |token| async move {
+    let r = breaker.call(|| {
+      let mut req = client.list_objects_v2().bucket(bucket).prefix(dir);
+      if let Some(t) = token {
+        req = req.continuation_token(t);
+      }
+      req.send()
+    }).await;
+
+    r.map_err(|e| e.map(|e| e.to_string())).map(|r| ListPage {
+      items: r.contents.unwrap_or_default().into_iter()
+        .filter_map(|o| {
+          let etag = o.e_tag;
+          let last_modified = o.last_modified.map(|t| Utc.timestamp(t.epoch_seconds(), t.epoch_subsecond_nanos()));
+          o.key.map(|key| ListedObject { key, etag, last_modified })
+        })
+        .collect(),
+      next: if r.is_truncated { r.next_continuation_token } else { None }
+    })
+  }).await
+}
+
+/// lists the objects under `dir` (a prefix with a trailing `/`) with list_all_pages. Objects outside of `dir`
+/// are left out, in case the server returned any.
+async fn list_objects_under<F, Fut, E>(dir: &str, fetch: F) -> Result<Vec<ListedObject>, E>
+  where F: FnMut(Option<String>) -> Fut, Fut: Future<Output = Result<ListPage<ListedObject>, E>>
+{
+  let objects = list_all_pages(fetch).await?;
+  Ok(objects.into_iter()
+    .filter(|o| {
+      let listed = o.key.starts_with(dir);
+      if !listed {
+        warn!("skipping {}: not under {}", o.key, dir);
+      }
+      listed
+    })
+    .collect())
+}
+
+/// lists the top level prefixes (subdirectories) of a bucket, without a trailing `/`.
+async fn list_s3_prefixes(
+  access: &S3Access,
+  bucket: &str,
+  root: &str,
+  breaker: &CircuitBreaker
+) -> Result<Vec<String>, BreakerError<String>> {
+  let client = match access {
+    S3Access::Client(client) => client,
+    S3Access::Anonymous => {
+      let dir = String::from("/vsis3/") + bucket + "/" + root;
+      return Ok(vsi::read_dir(&dir)
+        .into_iter()
+        .filter(|name| vsi::is_dir(&(dir.trim_end_matches('/').to_owned() + "/" + name)))
+        .map(|name| String::from(root) + &name)
+        .collect())
+    },
+  };
+
+  let results = list_all_pages(|token| async move {
+    let r = breaker.call(|| {
+      let mut req = client.list_objects_v2().bucket(bucket).delimiter("/");
+      if !root.is_empty() {
+        req = req.prefix(root);
+      }
+      if let Some(t) = token {
+        req = req.continuation_token(t);
+      }
+      req.send()
+    }).await;
+
+    r.map_err(|e| e.map(|e| e.to_string())).map(|r| ListPage {
+      items: r.common_prefixes.unwrap_or_default()
+        .into_iter()
+        .filter_map(|p| p.prefix)
+        .map(|p| p.trim_end_matches('/').to_owned())
+        .collect(),
+      next: if r.is_truncated { r.next_continuation_token } else { None }
+    })
+  }).await;
+
+  if let Err(BreakerError::Failed(e)) = &results {
+    warn!("could not list bucket {}: {}", bucket, e);
+  }
+  results
+}
+
+/// the prefix that collections are listed under: empty for the bucket root, otherwise the
+/// prefix with a trailing slash (e.g. `sentinel-2/2021/`).
+fn s3_root_prefix(prefix: Option<&str>) -> String {
+  match prefix.map(|p| p.trim_matches('/')) {
+    Some(p) if !p.is_empty() => String::from(p) + "/",
+    _ => String::new(),
+  }
+}
+
+/// one page of an S3 listing: the keys (or prefixes) in the page, and the continuation token for the
+/// next page if the listing was truncated.
+struct ListPage<T> {
+  items: Vec<T>,
+  next: Option<String>
+}
+
+/// collects every page of an S3 listing. S3 returns at most 1000 keys per request, so `fetch` is called
+/// with the continuation token from the previous page (None for the first page) until a page isn't truncated.
+async fn list_all_pages<T, F, Fut, E>(mut fetch: F) -> Result<Vec<T>, E>
+  where F: FnMut(Option<String>) -> Fut, Fut: Future<Output = Result<ListPage<T>, E>>
+{
+  let mut items = Vec::new();
+  let mut token = None;
+  loop {
+    let page = fetch(token).await?;
+    items.extend(page.items);
+    match page.next {
+      Some(next) => token = Some(next),
+      None => return Ok(items),
+    }
+  }
+}
+
 /// Creates collections from an S3 bucket.
 /// Collections are created from object prefixes.
 /// For now, objects need to have a prefix to get put into a collection, e.g.:
 /// mybucket/imagery/img1.tif will put img1.tif into an `imagery` collection.
+/// S3 calls go through `breaker`, so that an unavailable S3 server fails the scan fast
+/// instead of every prefix waiting on it.
+#[allow(clippy::too_many_arguments)]
 pub async fn collections_from_s3(
-  s3_host: &str,
+  s3_host: &http::Uri,
   s3_bucket: &str,
-  s3_access_key: &str,
-  s3_secret_key: &str
-) -> HashMap<String, ImageryCollection> {
+  s3_prefix: Option<&str>,
+  s3_access_key: Option<&str>,
+  s3_secret_key: Option<&str>,
+  s3_region: &str,
+  breaker: &CircuitBreaker,
+  options: &ScanOptions
+) -> Result<HashMap<String, ImageryCollection>, ScanError> {
   let mut collections: HashMap<String, ImageryCollection> = HashMap::new();
-  
-  
-  let creds = s3::Credentials::from_keys(s3_access_key, s3_secret_key, None);
-
-  let region = s3::Region::new("us-west-1");
-  let uri = s3_host.parse::<http::Uri>().unwrap();
-  let s3_config = s3::Config::builder()
-      .region(region)
-      .endpoint_resolver(s3::Endpoint::immutable(uri))
-      // .credentials_provider(s3::CredentialsProvider)
-      .credentials_provider(creds)
-      .build();
-
-  let s3_client = s3::Client::from_conf(s3_config);
-
-  println!("Scanning S3 bucket {} for collections of images", s3_bucket);
-
-  let results = s3_client
-    .list_objects()
-    .bucket(s3_bucket)
-    .delimiter("/")
-    .send().await;
-
-  let prefixes = results.unwrap().common_prefixes;
-
-  for p in prefixes.unwrap() {
-      let prefix_name = p.prefix.unwrap().trim_end_matches('/').to_owned();
-      let c = ImageryCollection::new_from_s3_prefix(
-        &prefix_name,
-        &prefix_name, // in the future, a discoverable config file might be nice.
-        &prefix_name,
-        &s3_host,
-        &s3_client,
+
+  // the rust AWS client expects AWS_S3_ENDPOINT to include the scheme (http/https),
+  // but GDAL expects AWS_S3_ENDPOINT to only include the host/port (see parse_s3_host).
+  let hostname = s3_host.authority().map(|a| a.as_str()).unwrap_or_default();
+  let _ = gdal::config::set_config_option("AWS_S3_ENDPOINT", hostname);
+  let _ = gdal::config::set_config_option("AWS_REGION", s3_region);
+  let virtual_hosting = match options.s3_url_style {
+    S3UrlStyle::Path => "FALSE",
+    S3UrlStyle::VirtualHost => "TRUE",
+  };
+  let _ = gdal::config::set_config_option("AWS_VIRTUAL_HOSTING", virtual_hosting);
+
+  // public buckets can be catalogued without credentials.
+  let access = match (s3_access_key, s3_secret_key) {
+    (Some(access_key), Some(secret_key)) => {
+      let creds = s3::Credentials::from_keys(access_key, secret_key, None);
+      let region = s3::Region::new(s3_region.to_owned());
+      let s3_config = s3::Config::builder()
+          .region(region)
+          .endpoint_resolver(s3::Endpoint::immutable(s3_host.to_owned()))
+          .credentials_provider(creds)
+          .build();
+      S3Access::Client(s3::Client::from_conf(s3_config))
+    },
+    (access_key, secret_key) => {
+      if access_key.is_some() || secret_key.is_some() {
+        warn!("both an S3 access key and secret key are needed to use credentials. Reading bucket {} anonymously.", s3_bucket);
+      }
+      let _ = gdal::config::set_config_option("AWS_NO_SIGN_REQUEST", "YES");
+      S3Access::Anonymous
+    },
+  };
+
+  let root = s3_root_prefix(s3_prefix);
+  info!("Scanning S3 bucket {} for collections of images", String::from(s3_bucket) + "/" + &root);
+
+  let host = s3_host.to_string();
+  let mut summaries: Vec<(String, ScanSummary)> = Vec::new();
+  for prefix_name in list_s3_prefixes(&access, s3_bucket, &root, breaker).await? {
+      // collections are named by their prefix relative to the root prefix.
+      let name = prefix_name.strip_prefix(&root).unwrap_or(&prefix_name);
+      let id = unique_collection_id(name, |id| collections.contains_key(id));
+      let (c, summary) = ImageryCollection::new_from_s3_prefix(
+        &id,
+        name, // a collection config file in the prefix can override the title and description.
+        name,
+        &host,
+        &access,
         s3_bucket,
-        &prefix_name
-      ).await;
-      collections.insert(prefix_name.to_string(), c);
+        &prefix_name,
+        breaker,
+        options
+      ).await?;
+      summaries.push((id.to_owned(), summary));
+      collections.insert(id, c);
   }
+  log_scan_summaries(&summaries);
 
+  Ok(collections)
+}
 
-  collections
+/// Creates collections from the files listed in an HTTP manifest (see manifest::parse),
+/// with a collection for each collection name in the manifest.
+pub fn collections_from_manifest(manifest: &str, options: &ScanOptions) -> Result<HashMap<String, ImageryCollection>, String> {
+  let mut collections: HashMap<String, ImageryCollection> = HashMap::new();
+
+  let contents = fs::read_to_string(manifest).map_err(|e| format!("could not read HTTP manifest {}: {}", manifest, e))?;
+  info!("Scanning the files listed in {} for collections of images", manifest);
+
+  let mut files_by_collection: BTreeMap<String, Vec<ManifestEntry>> = BTreeMap::new();
+  for entry in manifest::parse(&contents) {
+    files_by_collection.entry(entry.collection.to_owned()).or_default().push(entry);
+  }
+
+  let mut summaries: Vec<(String, ScanSummary)> = Vec::new();
+  for (name, entries) in files_by_collection {
+    let id = unique_collection_id(&name, |id| collections.contains_key(id));
+    let (c, summary) = ImageryCollection::new_from_urls(&id, &name, &entries, options);
+    summaries.push((id.to_owned(), summary));
+    collections.insert(id, c);
+  }
+  log_scan_summaries(&summaries);
+
+  Ok(collections)
+}
+
+/// CatalogSource is where the catalog's collections are read from: the subdirectories of a directory,
+/// the prefixes of an S3 bucket, or the files listed in an HTTP manifest. It's kept so that the catalog can be rescanned while the service runs.
+#[derive(Debug, Clone)]
+pub enum CatalogSource {
+  Dir(String),
+  S3 {
+    host: http::Uri,
+    bucket: String,
+    prefix: Option<String>,
+    access_key: Option<String>,
+    secret_key: Option<String>,
+    region: String
+  },
+  Manifest(String)
+}
+
+/// ScanError is why a CatalogSource couldn't be scanned.
+#[derive(Debug)]
+pub enum ScanError {
+  /// the S3 circuit breaker is open, so the scan wasn't attempted (or was stopped).
+  Unavailable,
+  /// the source couldn't be read.
+  Failed(String)
+}
+
+impl std::fmt::Display for ScanError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      ScanError::Unavailable => write!(f, "S3 is unavailable"),
+      ScanError::Failed(e) => write!(f, "{}", e),
+    }
+  }
+}
+
+impl From<BreakerError<String>> for ScanError {
+  fn from(e: BreakerError<String>) -> Self {
+    match e {
+      BreakerError::Open => ScanError::Unavailable,
+      BreakerError::Failed(e) => ScanError::Failed(e),
+    }
+  }
+}
+
+/// runs a scan on a blocking thread, so that it doesn't hold up the async runtime.
+async fn blocking<F>(scan: F) -> Result<HashMap<String, ImageryCollection>, ScanError>
+  where F: FnOnce() -> Result<HashMap<String, ImageryCollection>, String> + Send + 'static
+{
+  match rocket::tokio::task::spawn_blocking(scan).await {
+    Ok(result) => result.map_err(ScanError::Failed),
+    Err(e) => Err(ScanError::Failed(format!("the scan did not finish: {}", e))),
+  }
+}
+
+impl CatalogSource {
+    /// scans the source for collections (see collections_from_subdirs, collections_from_s3 and collections_from_manifest).
+    /// S3 sources are scanned through `breaker`. Directories and manifests are scanned on a blocking thread,
+    /// since GDAL reads their files synchronously.
+    pub async fn scan(&self, options: &ScanOptions, breaker: &CircuitBreaker) -> Result<HashMap<String, ImageryCollection>, ScanError> {
+      let collections = match self {
+        CatalogSource::Dir(dir) => {
+          let (dir, options) = (dir.to_owned(), options.clone());
+          blocking(move || match fs::read_dir(&dir) {
+            Ok(_) => Ok(collections_from_subdirs(&dir, &options)),
+            Err(e) => Err(format!("could not read directory {}: {}", dir, e)),
+          }).await?
+        },
+        CatalogSource::S3 { host, bucket, prefix, access_key, secret_key, region } => collections_from_s3(
+          host,
+          bucket,
+          prefix.as_deref(),
+          access_key.as_deref(),
+          secret_key.as_deref(),
+          region,
+          breaker,
+          options
+        ).await?,
+        CatalogSource::Manifest(manifest) => {
+          let (manifest, options) = (manifest.to_owned(), options.clone());
+          blocking(move || collections_from_manifest(&manifest, &options)).await?
+        },
+      };
+      // every file was seen, so files that are no longer in the catalog can be dropped from the cache.
+      if let Some(cache) = &options.cache {
+        cache.save(true);
+      }
+      Ok(collections)
+    }
+}
+
+#[cfg(test)]
+pub mod test_utils {
+  use super::*;
+  use std::env;
+  use std::process;
+  use gdal::Driver;
+  use gdal::spatial_ref::SpatialRef;
+
+  /// creates an empty directory for a test to write files into.
+  pub fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("autostac-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  /// writes a small 10x10 pixel EPSG:4326 GeoTIFF.
+  pub fn write_geotiff(path: &Path, bands: isize) {
+    let driver = Driver::get("GTiff").unwrap();
+    let mut dataset = driver.create(path.to_str().unwrap(), 10, 10, bands).unwrap();
+    dataset.set_geo_transform(&[-123.5, 0.01, 0., 48.6, 0., -0.01]).unwrap();
+    dataset.set_projection(&SpatialRef::from_epsg(4326).unwrap().to_wkt().unwrap()).unwrap();
+  }
+
+  /// creates an ImageryFile with the given footprint without opening a dataset.
+  pub fn imagery_file(filename: &str, collection_id: &str, boundary: impl Into<MultiPolygon<f64>>) -> ImageryFile {
+    ImageryFile {
+      path: PathBuf::from(filename),
+      filename: filename.to_string(),
+      boundary: boundary.into(),
+      properties: ImageryFileProperties {
+        path: filename.to_string(),
+        filename: filename.to_string(),
+        crs: String::from("EPSG:4326"),
+        epsg: Some(4326),
+        wkt2: None,
+        resolution: Resolution { x: 1., y: 1. },
+        num_bands: 1,
+        nodata: vec![None],
+        description: None,
+        cloud_coverage: None,
+        timestamp: Some(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)),
+        timestamp_defaulted: false,
+        modified: None,
+        red_band: None,
+        ni_band: None,
+        overview_levels: Vec::new(),
+        custom_properties: Map::new()
+      },
+      collection_id: collection_id.to_string(),
+      s3_object: None,
+      sidecar: None
+    }
+  }
+
+  /// creates an ImageryCollection from files that have already been created.
+  pub fn imagery_collection(id: &str, files: Vec<ImageryFile>) -> ImageryCollection {
+    ImageryCollection::new(id.to_string(), id.to_string(), id.to_string(), files)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::{HashMap, HashSet};
+  use std::fs;
+  use std::path::PathBuf;
+  use std::sync::{Arc, RwLock};
+  use gdal::{Dataset, Driver, Metadata};
+  use gdal::spatial_ref::SpatialRef;
+  use geo::{point, polygon};
+  use geo::prelude::BoundingRect;
+  use geo_types::{Geometry, GeometryCollection, MultiPolygon};
+  use chrono::{DateTime, TimeZone, Utc};
+  use crate::catalog::{
+    AsFeatureCollection, collections_from_subdirs, default_timestamp, rescan_collections, filename_datetime_pattern, gdal_open_option, list_all_pages, list_objects_under, parse_default_datetime, parse_s3_host, parse_image_datetime, s3_object_href, s3_root_prefix, slugify, Catalog, ContainsMode, ImageBestResolution,
+    ImageDatetimeRange, ImageryCollection, ImageryFile, ListPage, ListedObject, Resolution, S3Object, S3UrlStyle, ScanOptions, Service,
+    SkipReason
+  };
+  use crate::scan_cache;
+  use crate::stac;
+  use crate::transform;
+  use crate::catalog::test_utils::{imagery_collection, imagery_file, test_dir, write_geotiff};
+
+  #[test]
+  fn test_collect_files_stacignore() {
+    let dir = test_dir("stacignore");
+    write_geotiff(&dir.join("scene_1.tif"), 1);
+    write_geotiff(&dir.join("scene_2.tif"), 1);
+    write_geotiff(&dir.join("scene_3_tmp.tif"), 1);
+    fs::write(dir.join(".stacignore"), "# scratch files\n*_tmp.tif\nscene_2.tif\n").unwrap();
+
+    let (files, summary) = ImageryCollection::collect_files(dir, "imagery", &ScanOptions::default());
+    let names: Vec<&str> = files.iter().map(|f| f.filename.as_str()).collect();
+    assert_eq!(names, vec!["scene_1"]);
+    assert_eq!(summary.to_string(), "3 files scanned, 1 catalogued, 2 skipped (2 listed in .stacignore)");
+  }
+
+  #[test]
+  fn test_collect_files_recursive() {
+    let dir = test_dir("recursive");
+    fs::create_dir_all(dir.join("2021/08")).unwrap();
+    fs::create_dir_all(dir.join("2022")).unwrap();
+    write_geotiff(&dir.join("scene.tif"), 1);
+    write_geotiff(&dir.join("2021/08/scene.tif"), 1);
+    write_geotiff(&dir.join("2022/scene_tmp.tif"), 1);
+    fs::write(dir.join(".stacignore"), "*_tmp.tif\n").unwrap();
+    // a symlink back to the collection directory is only scanned once.
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(&dir, dir.join("2022/loop")).unwrap();
+
+    // only files directly in the collection directory are catalogued by default.
+    let (files, _) = ImageryCollection::collect_files(dir.to_owned(), "imagery", &ScanOptions::default());
+    let names: Vec<&str> = files.iter().map(|f| f.filename.as_str()).collect();
+    assert_eq!(names, vec!["scene"]);
+
+    // files in subdirectories are identified by their path within the collection.
+    let options = ScanOptions { recursive: true, ..ScanOptions::default() };
+    let (files, _) = ImageryCollection::collect_files(dir, "imagery", &options);
+    let names: Vec<&str> = files.iter().map(|f| f.filename.as_str()).collect();
+    assert_eq!(names, vec!["2021/08/scene", "scene"]);
+  }
+
+  #[test]
+  fn test_gdal_open_option() {
+    assert_eq!(gdal_open_option("NUM_THREADS=ALL_CPUS"), Ok(String::from("NUM_THREADS=ALL_CPUS")));
+    assert_eq!(gdal_open_option("GEOREF_SOURCES=INTERNAL,WORLDFILE"), Ok(String::from("GEOREF_SOURCES=INTERNAL,WORLDFILE")));
+    assert!(gdal_open_option("NUM_THREADS").is_err());
+    assert!(gdal_open_option("=4").is_err());
+  }
+
+  #[test]
+  fn test_collect_files_skips_bad_files() {
+    let dir = test_dir("badfiles");
+    write_geotiff(&dir.join("scene.tif"), 1);
+    // a file that isn't a raster, and a raster without a geotransform.
+    fs::write(dir.join("notes.txt"), "not imagery").unwrap();
+    let driver = Driver::get("GTiff").unwrap();
+    driver.create(dir.join("ungeoreferenced.tif").to_str().unwrap(), 10, 10, 1).unwrap();
+
+    // metadata that can't be parsed is left out instead of aborting the scan.
+    let mut ds = driver.create(dir.join("bad_metadata.tif").to_str().unwrap(), 10, 10, 1).unwrap();
+    ds.set_geo_transform(&[-123.5, 0.01, 0., 48.6, 0., -0.01]).unwrap();
+    ds.set_projection(&SpatialRef::from_epsg(4326).unwrap().to_wkt().unwrap()).unwrap();
+    ds.set_metadata_item("CLOUD_COVERAGE_ASSESSMENT", "n/a", "").unwrap();
+    ds.set_metadata_item("PRODUCT_START_TIME", "yesterday", "").unwrap();
+    drop(ds);
+
+    let (files, summary) = ImageryCollection::collect_files(dir.to_owned(), "imagery", &ScanOptions::default());
+    let names: Vec<&str> = files.iter().map(|f| f.filename.as_str()).collect();
+    assert_eq!(names, vec!["bad_metadata", "scene"]);
+    assert_eq!(files[0].properties.cloud_coverage, None);
+    assert_eq!(files[0].properties.timestamp, Some(default_timestamp()));
+
+    // skipped files are counted by reason.
+    assert_eq!(summary.scanned, 4);
+    assert_eq!(summary.catalogued, 2);
+    assert_eq!(summary.skipped.get(&SkipReason::Unreadable), Some(&1));
+    assert_eq!(summary.skipped.get(&SkipReason::InvalidGeoreference), Some(&1));
+
+    // files are opened with the GDAL open options, e.g. ignoring their georeferencing.
+    let options = ScanOptions { open_options: vec![String::from("GEOREF_SOURCES=NONE")], ..ScanOptions::default() };
+    let (files, summary) = ImageryCollection::collect_files(dir.to_owned(), "imagery", &options);
+    assert!(files.is_empty());
+    assert_eq!(summary.skipped.get(&SkipReason::InvalidGeoreference), Some(&3));
+
+    // missing directories are logged and give an empty catalog.
+    assert!(ImageryCollection::collect_files(dir.join("missing"), "imagery", &ScanOptions::default()).0.is_empty());
+    assert!(collections_from_subdirs(dir.join("missing").to_str().unwrap(), &ScanOptions::default()).is_empty());
+  }
+
+  #[test]
+  fn test_contains_modes() {
+    let footprint = polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)];
+    let collection = imagery_collection("imagery", vec![imagery_file("scene", "imagery", footprint.clone())]);
+
+    // a query polygon equal to the footprint is contained in both modes.
+    assert_eq!(collection.contains(&footprint.clone().into(), ContainsMode::Strict).len(), 1);
+    assert_eq!(collection.contains(&footprint.into(), ContainsMode::Covers).len(), 1);
+
+    // a polygon inside the footprint that shares one of its edges.
+    let bottom_half = polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 0.5), (x: 0., y: 0.5)];
+    assert_eq!(collection.contains(&bottom_half.clone().into(), ContainsMode::Strict).len(), 1);
+    assert_eq!(collection.contains(&bottom_half.into(), ContainsMode::Covers).len(), 1);
+
+    // a (zero area) polygon lying on the footprint's boundary is only covered.
+    let on_boundary = polygon![(x: 0.2, y: 0.), (x: 0.8, y: 0.), (x: 0.5, y: 0.)];
+    assert_eq!(collection.contains(&on_boundary.clone().into(), ContainsMode::Strict).len(), 0);
+    assert_eq!(collection.contains(&on_boundary.into(), ContainsMode::Covers).len(), 1);
+
+    // touching the footprint from outside, or partly outside, is not contained in either mode.
+    let touching = polygon![(x: 1., y: 0.), (x: 2., y: 0.), (x: 2., y: 1.), (x: 1., y: 1.)];
+    let partial = polygon![(x: 0.5, y: 0.5), (x: 2., y: 0.5), (x: 2., y: 0.8), (x: 0.5, y: 0.8)];
+    for query in &[touching, partial] {
+      assert_eq!(collection.contains(&query.clone().into(), ContainsMode::Strict).len(), 0);
+      assert_eq!(collection.contains(&query.clone().into(), ContainsMode::Covers).len(), 0);
+    }
+
+    // an edge that leaves the footprint through a notch, with all of its vertices inside.
+    let notched = polygon![
+      (x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0.6, y: 1.),
+      (x: 0.6, y: 0.5), (x: 0.4, y: 0.5), (x: 0.4, y: 1.), (x: 0., y: 1.)
+    ];
+    let collection = imagery_collection("imagery", vec![imagery_file("scene", "imagery", notched)]);
+    let across_notch = polygon![(x: 0.2, y: 0.7), (x: 0.8, y: 0.7), (x: 0.8, y: 0.9), (x: 0.2, y: 0.9)];
+    assert_eq!(collection.contains(&across_notch.into(), ContainsMode::Covers).len(), 0);
+  }
+
+  #[test]
+  fn test_catalog_index() {
+    let square = |x: f64| polygon![(x: x, y: 0.), (x: x + 1., y: 0.), (x: x + 1., y: 1.), (x: x, y: 1.)];
+    let mut collections = HashMap::new();
+    collections.insert(String::from("roads"), imagery_collection("roads", vec![imagery_file("road_1", "roads", square(0.)), imagery_file("road_2", "roads", square(5.))]));
+    collections.insert(String::from("air"), imagery_collection("air", vec![imagery_file("air_1", "air", square(0.5)), imagery_file("air_2", "air", square(0.2))]));
+    let catalog = Catalog::from(collections);
+    assert_eq!(catalog.len(), 2);
+
+    // candidates come from every collection, sorted by collection and then by position in the collection.
+    let query = polygon![(x: 0.6, y: 0.2), (x: 0.8, y: 0.2), (x: 0.8, y: 0.8), (x: 0.6, y: 0.8)];
+    let names: Vec<&str> = catalog.candidates(query.bounding_rect()).into_iter().map(|f| f.filename.as_str()).collect();
+    assert_eq!(names, vec!["air_1", "air_2", "road_1"]);
+    assert_eq!(catalog.candidates(square(5.).bounding_rect()).len(), 1);
+    assert!(catalog.candidates(None).is_empty());
+    assert!(Catalog::default().candidates(query.bounding_rect()).is_empty());
+  }
+
+  #[test]
+  fn test_spatial_index() {
+    // a 10x10 grid of 1 degree images.
+    let mut files = Vec::new();
+    for x in 0..10 {
+      for y in 0..10 {
+        let (x, y) = (x as f64, y as f64);
+        let footprint = polygon![(x: x, y: y), (x: x + 1., y: y), (x: x + 1., y: y + 1.), (x: x, y: y + 1.)];
+        files.push(imagery_file(&format!("scene_{}_{}", x, y), "imagery", footprint));
+      }
+    }
+    let collection = imagery_collection("imagery", files);
+
+    // only the image the query falls in needs an exact geometry test.
+    let query = polygon![(x: 3.2, y: 4.2), (x: 3.8, y: 4.2), (x: 3.8, y: 4.8), (x: 3.2, y: 4.8)];
+    let candidates = collection.candidates(query.bounding_rect());
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(collection.contains(&query.clone().into(), ContainsMode::Strict)[0].filename, "scene_3_4");
+    assert_eq!(collection.intersects(&Geometry::Polygon(query))[0].filename, "scene_3_4");
+
+    // results keep the order of the files in the collection.
+    let query = polygon![(x: 0.5, y: 0.5), (x: 1.5, y: 0.5), (x: 1.5, y: 1.5), (x: 0.5, y: 1.5)];
+    let names: Vec<&str> = collection.intersects(&Geometry::Polygon(query)).into_iter().map(|f| f.filename.as_str()).collect();
+    assert_eq!(names, vec!["scene_0_0", "scene_0_1", "scene_1_0", "scene_1_1"]);
+
+    // nothing is tested for a query outside the collection.
+    let query = polygon![(x: 20., y: 20.), (x: 21., y: 20.), (x: 21., y: 21.), (x: 20., y: 21.)];
+    assert!(collection.candidates(query.bounding_rect()).is_empty());
+  }
+
+  #[test]
+  fn test_s3_alternate_assets() {
+    let mut file = imagery_file("scene.tif", "imagery", polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)]);
+    file.properties.path = String::from("http://localhost:9000/mybucket/imagery/scene.tif");
+    file.s3_object = Some(S3Object {
+      bucket: String::from("mybucket"),
+      key: String::from("imagery/scene.tif"),
+      https_href: String::from("https://cdn.example.com/imagery/scene.tif"),
+      etag: None
+    });
+
+    let feature = file.to_stac_feature(None);
+    let members = feature.foreign_members.unwrap();
+    let asset = &members["assets"]["file"];
+    assert_eq!(asset["href"], "http://localhost:9000/mybucket/imagery/scene.tif");
+    assert_eq!(asset["alternate"]["s3"]["href"], "s3://mybucket/imagery/scene.tif");
+    assert_eq!(asset["alternate"]["https"]["href"], "https://cdn.example.com/imagery/scene.tif");
+    assert!(members["stac_extensions"].as_array().unwrap().contains(&stac::ALTERNATE_ASSETS_EXT.into()));
+
+    // files that weren't catalogued from S3 don't have alternate assets.
+    file.s3_object = None;
+    let members = file.to_stac_feature(None).foreign_members.unwrap();
+    assert!(members["assets"]["file"].get("alternate").is_none());
+    assert!(!members["stac_extensions"].as_array().unwrap().contains(&stac::ALTERNATE_ASSETS_EXT.into()));
+  }
+
+  #[test]
+  fn test_s3_object_href() {
+    let key = "imagery/scene.tif";
+    assert_eq!(s3_object_href("http://localhost:9000", "mybucket", key, S3UrlStyle::Path), "http://localhost:9000/mybucket/imagery/scene.tif");
+    assert_eq!(
+      s3_object_href("https://s3.us-east-1.amazonaws.com/", "mybucket", key, S3UrlStyle::VirtualHost),
+      "https://mybucket.s3.us-east-1.amazonaws.com/imagery/scene.tif"
+    );
+    assert_eq!("virtual-host".parse(), Ok(S3UrlStyle::VirtualHost));
+    assert!("subdomain".parse::<S3UrlStyle>().is_err());
+  }
+
+  #[test]
+  fn test_parse_s3_host() {
+    let host = parse_s3_host("http://localhost:9000").unwrap();
+    assert_eq!(host.authority().unwrap().as_str(), "localhost:9000");
+    assert!(parse_s3_host("localhost:9000").is_err());
+    assert!(parse_s3_host("http://").is_err());
+  }
+
+  #[test]
+  fn test_s3_root_prefix() {
+    assert_eq!(s3_root_prefix(None), "");
+    assert_eq!(s3_root_prefix(Some("/")), "");
+    assert_eq!(s3_root_prefix(Some("sentinel-2/2021")), "sentinel-2/2021/");
+    assert_eq!(s3_root_prefix(Some("/sentinel-2/2021/")), "sentinel-2/2021/");
+  }
+
+  #[test]
+  fn test_projection_extension() {
+    let dir = test_dir("projection");
+    let src = dir.join("scene.tif");
+    write_geotiff(&src, 1);
+    let file = ImageryFile::new(
+      &Dataset::open(&src).unwrap(),
+      src.to_owned(),
+      src.display().to_string(),
+      "scene",
+      "imagery",
+      &ScanOptions::default()
+    ).unwrap();
+    assert_eq!(file.properties.epsg, Some(4326));
+
+    let feature = file.to_stac_feature(None);
+    let properties = feature.properties.unwrap();
+    assert_eq!(properties["proj:epsg"], 4326);
+    assert!(properties.get("proj:wkt2").is_none());
+    assert_eq!(feature.foreign_members.unwrap()["stac_extensions"][0], stac::PROJECTION_EXT);
+
+    // files with a CRS that has no EPSG code are described with WKT2 instead.
+    let mut file = file;
+    file.properties.epsg = None;
+    file.properties.wkt2 = Some(String::from("PROJCRS[\"custom\"]"));
+    let properties = file.to_stac_feature(None).properties.unwrap();
+    assert!(properties.get("proj:epsg").is_none());
+    assert_eq!(properties["proj:wkt2"], "PROJCRS[\"custom\"]");
+
+    // files without any CRS information don't use the extension.
+    file.properties.wkt2 = None;
+    assert!(file.to_stac_feature(None).foreign_members.unwrap().get("stac_extensions").is_none());
+  }
+
+  #[test]
+  fn test_resolution_in_meters() {
+    let dir = test_dir("resolution");
+    let driver = Driver::get("GTiff").unwrap();
+    let open = |path: &std::path::Path| ImageryFile::new(
+      &Dataset::open(path).unwrap(),
+      path.to_owned(),
+      path.display().to_string(),
+      "scene",
+      "imagery",
+      &ScanOptions::default()
+    ).unwrap();
+
+    // 30 m pixels in UTM zone 10N, centered on the zone's central meridian.
+    let utm = dir.join("utm.tif");
+    {
+      let mut ds = driver.create(utm.to_str().unwrap(), 100, 100, 1).unwrap();
+      ds.set_geo_transform(&[498500., 30., 0., 5378000., 0., -30.]).unwrap();
+      ds.set_projection(&SpatialRef::from_epsg(32610).unwrap().to_wkt().unwrap()).unwrap();
+    }
+    let file = open(&utm);
+    let resolution = file.properties.resolution;
+    assert!((resolution.x - 30.).abs() < 0.3 && (resolution.y - 30.).abs() < 0.3, "{:?}", resolution);
+    let properties = file.to_stac_feature(None).properties.unwrap();
+    assert_eq!(properties["gsd"], resolution.avg());
+    assert_eq!(properties["gsd"], properties["spatial_resolution"]);
+
+    // 0.01 degree pixels around 48.55 N. A degree of latitude is about 111.2 km, and a degree of
+    // longitude is shorter by cos(latitude).
+    let latlng = dir.join("latlng.tif");
+    write_geotiff(&latlng, 1);
+    let resolution = open(&latlng).properties.resolution;
+    assert!((resolution.y - 1112.).abs() < 11., "{:?}", resolution);
+    assert!((resolution.x - 1112. * 48.55_f64.to_radians().cos()).abs() < 8., "{:?}", resolution);
+  }
+
+  #[test]
+  fn test_overview_levels() {
+    let path = test_dir("overviews").join("cog.tif");
+    {
+      let driver = Driver::get("GTiff").unwrap();
+      let mut ds = driver.create(path.to_str().unwrap(), 100, 80, 1).unwrap();
+      ds.set_geo_transform(&[498500., 30., 0., 5378000., 0., -30.]).unwrap();
+      ds.set_projection(&SpatialRef::from_epsg(32610).unwrap().to_wkt().unwrap()).unwrap();
+      ds.build_overviews("NEAREST", &[4, 2], &[]).unwrap();
+    }
+    let file = ImageryFile::new(
+      &Dataset::open(&path).unwrap(),
+      path.to_owned(),
+      path.display().to_string(),
+      "cog",
+      "imagery",
+      &ScanOptions::default()
+    ).unwrap();
+
+    // overviews are sorted from the most detailed, and their pixels are larger by the overview's factor.
+    let levels = &file.properties.overview_levels;
+    assert_eq!(levels.iter().map(|o| (o.width, o.height)).collect::<Vec<_>>(), vec![(50, 40), (25, 20)]);
+    assert!((levels[0].resolution.avg() - 60.).abs() < 0.6, "{:?}", levels[0]);
+    assert!((levels[1].resolution.avg() - 120.).abs() < 1.2, "{:?}", levels[1]);
+    let properties = file.to_stac_feature(None).properties.unwrap();
+    assert_eq!(properties["overview_levels"][1]["width"], 25);
+    assert_eq!(properties["overview_levels"][1]["spatial_resolution"], levels[1].resolution.avg());
+
+    // files without overviews don't have the property.
+    let path = test_dir("overviews").join("plain.tif");
+    write_geotiff(&path, 1);
+    let file = ImageryFile::new(&Dataset::open(&path).unwrap(), path.to_owned(), path.display().to_string(), "plain", "imagery", &ScanOptions::default()).unwrap();
+    assert!(file.properties.overview_levels.is_empty());
+    assert!(file.to_stac_feature(None).properties.unwrap().get("overview_levels").is_none());
+  }
+
+  #[test]
+  fn test_antimeridian_footprint() {
+    // a 120 x 100 km scene in the Bering Strait, in UTM zone 1N. Its west edge is about 1.3 degrees
+    // west of the antimeridian, and its east edge about 1.3 degrees east of it.
+    let path = test_dir("antimeridian").join("bering.tif");
+    {
+      let driver = Driver::get("GTiff").unwrap();
+      let mut ds = driver.create(path.to_str().unwrap(), 100, 100, 1).unwrap();
+      ds.set_geo_transform(&[300000., 1200., 0., 7300000., 0., -1000.]).unwrap();
+      ds.set_projection(&SpatialRef::from_epsg(32601).unwrap().to_wkt().unwrap()).unwrap();
+    }
+    let file = ImageryFile::new(
+      &Dataset::open(&path).unwrap(),
+      path.to_owned(),
+      path.display().to_string(),
+      "bering",
+      "imagery",
+      &ScanOptions::default()
+    ).unwrap();
+
+    // the footprint is split into a part on either side of the antimeridian.
+    let parts: Vec<_> = file.boundary.iter().map(|p| p.bounding_rect().unwrap()).collect();
+    assert_eq!(parts.len(), 2, "{:?}", file.boundary);
+    assert!(parts[0].min().x > 178. && parts[0].max().x == 180., "{:?}", parts[0]);
+    assert!(parts[1].min().x == -180. && parts[1].max().x < -178., "{:?}", parts[1]);
+    let feature = serde_json::to_value(file.to_stac_feature(None)).unwrap();
+    assert_eq!(feature["geometry"]["type"], "MultiPolygon");
+
+    // only points that are actually in the scene intersect it, on either side of the antimeridian.
+    let collection = imagery_collection("imagery", vec![file]);
+    assert_eq!(collection.intersects(&Geometry::Point(point!(x: 179.5, y: 65.5))).len(), 1);
+    assert_eq!(collection.intersects(&Geometry::Point(point!(x: -179.5, y: 65.5))).len(), 1);
+    assert!(collection.intersects(&Geometry::Point(point!(x: 0., y: 65.5))).is_empty());
+  }
+
+  #[test]
+  fn test_parse_image_datetime() {
+    let expected = Utc.ymd(2021, 8, 1).and_hms(18, 30, 0);
+    assert_eq!(parse_image_datetime("2021-08-01T18:30:00Z"), Some(expected));
+    assert_eq!(parse_image_datetime("2021-08-01T11:30:00-07:00"), Some(expected));
+    assert_eq!(parse_image_datetime(" 2021:08:01 18:30:00 "), Some(expected));
+    assert_eq!(parse_image_datetime("2021-08-01"), Some(Utc.ymd(2021, 8, 1).and_hms(0, 0, 0)));
+    assert_eq!(parse_image_datetime("20210801T183000"), Some(expected));
+    assert_eq!(parse_image_datetime("20210801"), Some(Utc.ymd(2021, 8, 1).and_hms(0, 0, 0)));
+    assert_eq!(parse_image_datetime("yesterday"), None);
+  }
+
+  #[test]
+  fn test_datetime_from_filename() {
+    let dir = test_dir("datetime_from_filename");
+    let path = dir.join("S2A_MSIL1C_20210809T185919_N0301_R013_T10UDV_20210809T223531.tif");
+    write_geotiff(&path, 1);
+    let timestamp = |path: &std::path::Path, pattern: Option<&str>| {
+      let options = ScanOptions { datetime_pattern: pattern.map(|p| filename_datetime_pattern(p).unwrap()), ..ScanOptions::default() };
+      ImageryFile::new(&Dataset::open(path).unwrap(), path.to_owned(), path.display().to_string(), "scene", "imagery", &options)
+        .unwrap()
+        .properties
+        .timestamp
+    };
+
+    // the first match of the pattern is used.
+    let sentinel = r"_(?P<datetime>\d{8}T\d{6})_";
+    assert_eq!(timestamp(&path, Some(sentinel)), Some(Utc.ymd(2021, 8, 9).and_hms(18, 59, 19)));
+    assert_eq!(timestamp(&path, None), Some(default_timestamp()));
+
+    // a datetime in the file's metadata is preferred over its file name.
+    let tagged = dir.join("S2B_MSIL1C_20200101T000000_N0301.tif");
+    write_geotiff(&tagged, 1);
+    Dataset::open(&tagged).unwrap().set_metadata_item("PRODUCT_START_TIME", "2021-08-01T18:30:00Z", "").unwrap();
+    assert_eq!(timestamp(&tagged, Some(sentinel)), Some(Utc.ymd(2021, 8, 1).and_hms(18, 30, 0)));
+
+    assert!(filename_datetime_pattern(r"\d{8}").is_err());
+    assert!(filename_datetime_pattern(r"(?P<datetime>\d{8}").is_err());
+  }
+
+  #[test]
+  fn test_timestamp_keys() {
+    let path = test_dir("timestamp_keys").join("scene.tif");
+    write_geotiff(&path, 1);
+    {
+      let mut ds = Dataset::open(&path).unwrap();
+      ds.set_metadata_item("TIFFTAG_DATETIME", "2021:08:01 18:30:00", "").unwrap();
+      ds.set_metadata_item("PRODUCT_START_TIME", "2020-01-01T00:00:00Z", "").unwrap();
+      ds.set_metadata_item("ACQUISITION_DATE", "n/a", "").unwrap();
+    }
+    let timestamp = |keys: &[&str]| {
+      let options = ScanOptions { timestamp_keys: keys.iter().map(|k| k.to_string()).collect(), ..ScanOptions::default() };
+      ImageryFile::new(&Dataset::open(&path).unwrap(), path.to_owned(), path.display().to_string(), "scene", "imagery", &options)
+        .unwrap()
+        .properties
+        .timestamp
+    };
+
+    // PRODUCT_START_TIME is used by default.
+    assert_eq!(timestamp(&["PRODUCT_START_TIME"]), Some(Utc.ymd(2020, 1, 1).and_hms(0, 0, 0)));
+    // the first key with a valid datetime is used, skipping missing keys and invalid datetimes.
+    assert_eq!(
+      timestamp(&["MISSING", "ACQUISITION_DATE", "TIFFTAG_DATETIME", "PRODUCT_START_TIME"]),
+      Some(Utc.ymd(2021, 8, 1).and_hms(18, 30, 0))
+    );
+    assert_eq!(timestamp(&["MISSING", "ACQUISITION_DATE"]), Some(default_timestamp()));
+
+    // a datetime found in the file isn't mistaken for the default, even if it's the same.
+    Dataset::open(&path).unwrap().set_metadata_item("PRODUCT_START_TIME", "1900-01-01T00:00:00Z", "").unwrap();
+    let file = ImageryFile::new(&Dataset::open(&path).unwrap(), path.to_owned(), path.display().to_string(), "scene", "imagery", &ScanOptions::default()).unwrap();
+    assert_eq!(file.properties.timestamp, Some(default_timestamp()));
+    assert!(!file.properties.timestamp_defaulted);
+  }
+
+  #[test]
+  fn test_default_datetime() {
+    assert_eq!(parse_default_datetime("2000-01-01T00:00:00Z"), Ok(Some(Utc.ymd(2000, 1, 1).and_hms(0, 0, 0))));
+    assert_eq!(parse_default_datetime("null"), Ok(None));
+    assert!(parse_default_datetime("2000-01-01").is_err());
+
+    // files without a timestamp get the default datetime, or a null datetime.
+    let path = test_dir("default_datetime").join("scene.tif");
+    write_geotiff(&path, 1);
+    let item = |default_datetime: Option<DateTime<Utc>>| {
+      let options = ScanOptions { default_datetime, ..ScanOptions::default() };
+      ImageryFile::new(&Dataset::open(&path).unwrap(), path.to_owned(), path.display().to_string(), "scene", "imagery", &options)
+        .unwrap()
+    };
+    let dated = item(Some(Utc.ymd(2000, 1, 1).and_hms(0, 0, 0)));
+    assert_eq!(dated.properties.timestamp, Some(Utc.ymd(2000, 1, 1).and_hms(0, 0, 0)));
+    assert!(dated.properties.timestamp_defaulted);
+
+    let undated = item(None);
+    assert_eq!(undated.properties.timestamp, None);
+    assert!(!undated.properties.timestamp_defaulted);
+    assert_eq!(serde_json::to_value(undated.stac_properties()).unwrap()["datetime"], serde_json::Value::Null);
+
+    // items with a null datetime are left out of searches with a datetime range.
+    let files = [dated, undated];
+    assert_eq!(files.iter().collect::<Vec<_>>().datetime_range(None, None).len(), 2);
+    assert_eq!(files.iter().collect::<Vec<_>>().datetime_range(Some(Utc.ymd(1990, 1, 1).and_hms(0, 0, 0)), None).len(), 1);
+  }
+
+  #[test]
+  fn test_modified() {
+    let path = test_dir("modified").join("scene.tif");
+    write_geotiff(&path, 1);
+    let modified: DateTime<Utc> = fs::metadata(&path).unwrap().modified().unwrap().into();
+    let file = ImageryFile::new(&Dataset::open(&path).unwrap(), path.to_owned(), path.display().to_string(), "scene", "imagery", &ScanOptions::default())
+      .unwrap();
+    assert_eq!(file.properties.modified, Some(modified));
+
+    let properties = serde_json::to_value(file.stac_properties().to_map()).unwrap();
+    assert_eq!(properties["created"], serde_json::to_value(modified).unwrap());
+    assert_eq!(properties["updated"], serde_json::to_value(modified).unwrap());
+  }
+
+  #[test]
+  fn test_sidecar() {
+    let dir = test_dir("sidecar");
+    write_geotiff(&dir.join("scene.tif"), 1);
+    write_geotiff(&dir.join("plain.tif"), 1);
+    fs::write(dir.join("scene.json"), serde_json::json!({
+      "type": "Feature",
+      "properties": {"datetime": "2021-08-09T18:59:19Z", "title": "Victoria", "platform": "sentinel-2a"},
+      "assets": {"metadata": {"href": "scene.xml", "roles": ["metadata"]}}
+    }).to_string()).unwrap();
+
+    let (files, summary) = ImageryCollection::collect_files(dir.to_owned(), "imagery", &ScanOptions::default());
+    let names: Vec<&str> = files.iter().map(|f| f.filename.as_str()).collect();
+    assert_eq!(names, vec!["plain", "scene"]);
+    assert_eq!(summary.skipped.get(&SkipReason::Sidecar), Some(&1));
+
+    // the sidecar's properties and assets are merged over the ones from GDAL.
+    let scene = &files[1];
+    assert_eq!(scene.properties.timestamp, Some(Utc.ymd(2021, 8, 9).and_hms(18, 59, 19)));
+    let feature = serde_json::to_value(scene.to_stac_feature(None)).unwrap();
+    assert_eq!(feature["properties"]["title"], "Victoria");
+    assert_eq!(feature["properties"]["platform"], "sentinel-2a");
+    assert_eq!(feature["properties"]["datetime"], "2021-08-09T18:59:19Z");
+    assert_eq!(feature["assets"]["metadata"]["href"], dir.join("scene.xml").display().to_string());
+    assert_eq!(feature["assets"]["file"]["href"], dir.join("scene.tif").display().to_string());
+
+    // files without a sidecar are unchanged.
+    let plain = serde_json::to_value(files[0].to_stac_feature(None)).unwrap();
+    assert_eq!(plain["properties"]["title"], "plain");
+    assert!(plain["assets"].get("metadata").is_none());
+  }
+
+  #[test]
+  fn test_auxiliary_files() {
+    let dir = test_dir("auxiliary");
+    let scene = dir.join("scene.tif");
+    write_geotiff(&scene, 1);
+    // external overviews are GeoTIFFs too, so they'd otherwise be catalogued as items of their own.
+    write_geotiff(&dir.join("scene.tif.ovr"), 1);
+    fs::write(dir.join("scene.tif.aux.xml"), "<PAMDataset></PAMDataset>").unwrap();
+    // overviews left behind by a file that was removed.
+    write_geotiff(&dir.join("removed.tif.ovr"), 1);
+
+    let (files, summary) = ImageryCollection::collect_files(dir.to_owned(), "imagery", &ScanOptions::default());
+    let names: Vec<&str> = files.iter().map(|f| f.filename.as_str()).collect();
+    assert_eq!(names, vec!["scene"]);
+    assert_eq!(summary.skipped.get(&SkipReason::Auxiliary), Some(&3));
+    assert_eq!(summary.skipped.get(&SkipReason::Unreadable), None);
+
+    // auxiliary files are part of the file's version in the scan cache, so changing them rescans the file.
+    let version = scan_cache::dataset_version(&scene).unwrap();
+    fs::write(dir.join("scene.tif.msk"), "mask").unwrap();
+    assert_ne!(scan_cache::dataset_version(&scene).unwrap(), version);
+  }
+
+  #[test]
+  fn test_nodata() {
+    let dir = test_dir("nodata");
+    let src = dir.join("scene.tif");
+    write_geotiff(&src, 3);
+    {
+      let dataset = Dataset::open(&src).unwrap();
+      dataset.rasterband(1).unwrap().set_no_data_value(-9999.).unwrap();
+      dataset.rasterband(3).unwrap().set_no_data_value(f64::NAN).unwrap();
+    }
+    let file = ImageryFile::new(
+      &Dataset::open(&src).unwrap(),
+      src.to_owned(),
+      src.display().to_string(),
+      "scene",
+      "imagery",
+      &ScanOptions::default()
+    ).unwrap();
+    assert_eq!(file.properties.nodata[..2], [Some(-9999.), None]);
+
+    let properties = file.to_stac_feature(None).properties.unwrap();
+    assert_eq!(properties["nodata"], serde_json::json!([-9999., null, "nan"]));
+  }
+
+  #[test]
+  fn test_eo_cloud_cover() {
+    let mut file = imagery_file("scene", "imagery", polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)]);
+    file.properties.cloud_coverage = Some(12.5);
+    let feature = file.to_stac_feature(None);
+    assert_eq!(feature.properties.unwrap()["eo:cloud_cover"], 12.5);
+    assert!(feature.foreign_members.unwrap()["stac_extensions"].as_array().unwrap().contains(&stac::EO_EXT.into()));
+
+    // files without cloud cover metadata don't use the extension.
+    file.properties.cloud_coverage = None;
+    let feature = file.to_stac_feature(None);
+    assert!(feature.properties.unwrap().get("eo:cloud_cover").is_none());
+    assert!(!feature.foreign_members.unwrap()["stac_extensions"].as_array().unwrap().contains(&stac::EO_EXT.into()));
+  }
+
+  #[test]
+  fn test_collection_config() {
+    let base_url = url::Url::parse("http://localhost:8000").unwrap();
+    let dir = test_dir("collection_config");
+    write_geotiff(&dir.join("scene.tif"), 1);
+
+    // collections without a config file use the directory name.
+    let (collection, _) = ImageryCollection::new_from_dir(
+      String::from("imagery"), String::from("imagery"), String::from("imagery"), dir.to_owned(), &ScanOptions::default()
+    );
+    let stac_collection = serde_json::to_value(collection.stac_collection(&base_url)).unwrap();
+    assert_eq!(stac_collection["title"], "imagery");
+    // the license is always given, since STAC requires it.
+    assert_eq!(stac_collection["license"], "other");
+    assert!(stac_collection.get("providers").is_none());
+
+    fs::write(dir.join("collection.toml"), r#"
+      title = "Victoria orthophotos"
+      license = "CC-BY-4.0"
+      keywords = ["orthophoto"]
+
+      [[providers]]
+      name = "City of Victoria"
+      roles = ["producer"]
+    "#).unwrap();
+    let (collection, summary) = ImageryCollection::new_from_dir(
+      String::from("imagery"), String::from("imagery"), String::from("imagery"), dir, &ScanOptions::default()
+    );
+    let stac_collection = serde_json::to_value(collection.stac_collection(&base_url)).unwrap();
+    assert_eq!(stac_collection["title"], "Victoria orthophotos");
+    assert_eq!(stac_collection["description"], "imagery");
+    assert_eq!(stac_collection["license"], "CC-BY-4.0");
+    assert_eq!(stac_collection["keywords"], serde_json::json!(["orthophoto"]));
+    assert_eq!(stac_collection["providers"], serde_json::json!([{"name": "City of Victoria", "roles": ["producer"]}]));
+
+    // the config file isn't catalogued.
+    assert_eq!((summary.scanned, summary.catalogued), (1, 1));
+  }
+
+  #[test]
+  fn test_collection_extent() {
+    let collection = imagery_collection("imagery", vec![
+      imagery_file("a", "imagery", polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)]),
+      imagery_file("b", "imagery", polygon![(x: 2., y: -1.), (x: 3., y: -1.), (x: 3., y: 0.5), (x: 2., y: 0.5)]),
+    ]);
+    let base_url = url::Url::parse("http://localhost:8000").unwrap();
+    let extent = serde_json::to_value(collection.stac_collection(&base_url)).unwrap()["extent"].to_owned();
+    assert_eq!(extent["spatial"]["bbox"], serde_json::json!([[0., -1., 3., 1.]]));
+
+    // an empty collection covers the whole world.
+    let empty = imagery_collection("empty", Vec::new());
+    let extent = serde_json::to_value(empty.stac_collection(&base_url)).unwrap()["extent"].to_owned();
+    assert_eq!(extent["spatial"]["bbox"], serde_json::json!([[-180., -90., 180., 90.]]));
+    assert_eq!(extent["temporal"]["interval"], serde_json::json!([[null, null]]));
+  }
+
+  #[test]
+  fn test_collection_temporal_extent() {
+    let square = polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)];
+    let base_url = url::Url::parse("http://localhost:8000").unwrap();
+    let interval = |files: Vec<ImageryFile>| {
+      let collection = imagery_collection("imagery", files);
+      serde_json::to_value(collection.stac_collection(&base_url)).unwrap()["extent"]["temporal"]["interval"].to_owned()
+    };
+
+    let mut a = imagery_file("a", "imagery", square.clone());
+    a.properties.timestamp = Some(Utc.ymd(2021, 3, 1).and_hms(10, 0, 0));
+    let mut b = imagery_file("b", "imagery", square.clone());
+    b.properties.timestamp = Some(Utc.ymd(2020, 6, 15).and_hms(0, 0, 0));
+    let mut undated = imagery_file("undated", "imagery", square.clone());
+    undated.properties.timestamp = Some(default_timestamp());
+    undated.properties.timestamp_defaulted = true;
+    let mut null = imagery_file("null", "imagery", square);
+    null.properties.timestamp = None;
+
+    // a single item gives a closed interval starting and ending at its datetime.
+    assert_eq!(interval(vec![a.clone()]), serde_json::json!([["2021-03-01T10:00:00Z", "2021-03-01T10:00:00Z"]]));
+
+    // files without a timestamp don't extend the interval.
+    assert_eq!(
+      interval(vec![a, b, undated.clone(), null]),
+      serde_json::json!([["2020-06-15T00:00:00Z", "2021-03-01T10:00:00Z"]])
+    );
+    assert_eq!(interval(vec![undated]), serde_json::json!([[null, null]]));
+  }
+
+  #[rocket::async_test]
+  async fn test_list_all_pages() {
+    // 2500 keys, listed 1000 at a time like S3 does. The continuation token is the index of the next key.
+    let keys: Vec<String> = (0..2500).map(|i| format!("imagery/scene_{}.tif", i)).collect();
+    let mut tokens = Vec::new();
+    let listed = list_all_pages(|token: Option<String>| {
+      tokens.push(token.to_owned());
+      let start = token.map(|t| t.parse::<usize>().unwrap()).unwrap_or(0);
+      let end = (start + 1000).min(keys.len());
+      let page = ListPage {
+        items: keys[start..end].to_vec(),
+        next: if end < keys.len() { Some(end.to_string()) } else { None }
+      };
+      async move { Ok::<_, ()>(page) }
+    }).await.unwrap();
+
+    assert_eq!(listed, keys);
+    assert_eq!(tokens, vec![None, Some(String::from("1000")), Some(String::from("2000"))]);
+
+    // a failure listing any page fails the whole listing.
+    let failed = list_all_pages(|token| async move {
+      match token {
+        None => Ok(ListPage { items: vec![String::from("imagery/scene.tif")], next: Some(String::from("1")) }),
+        Some(_) => Err("could not list page"),
+      }
+    }).await;
+    assert_eq!(failed, Err("could not list page"));
+  }
+
+  #[rocket::async_test]
+  async fn test_list_objects_under() {
+    // a server that matches keys by prefix, like S3 does. `imagery-old/` starts with `imagery` but isn't part of it.
+    let keys = ["imagery-old/scene.tif", "imagery/scene_1.tif", "imagery/2021/scene_2.tif", "imagery.tif"];
+    let object = |key: &str| ListedObject { key: key.to_owned(), etag: None, last_modified: None };
+    let listed = list_objects_under("imagery/", |_| async move {
+      Ok::<_, ()>(ListPage { items: keys.iter().filter(|k| k.starts_with("imagery")).map(|k| object(k)).collect(), next: None })
+    }).await.unwrap();
+    let listed: Vec<&str> = listed.iter().map(|o| o.key.as_str()).collect();
+    assert_eq!(listed, vec!["imagery/scene_1.tif", "imagery/2021/scene_2.tif"]);
+  }
+
+  #[test]
+  fn test_item_links() {
+    let file = imagery_file("scene_1", "imagery", polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 0.)]);
+    let base_url = url::Url::parse("https://example.com/stac/").unwrap();
+    let members = file.to_stac_item(&base_url, false).foreign_members.unwrap();
+    let links: Vec<(&str, &str)> = members["links"].as_array().unwrap().iter()
+      .map(|l| (l["rel"].as_str().unwrap(), l["href"].as_str().unwrap()))
+      .collect();
+    assert_eq!(links, vec![
+      ("self", "https://example.com/stac/collections/imagery/scene_1"),
+      ("parent", "https://example.com/stac/collections/imagery"),
+      ("collection", "https://example.com/stac/collections/imagery"),
+      ("root", "https://example.com/stac/"),
+    ]);
+  }
+
+  #[test]
+  fn test_band_assets() {
+    let mut file = imagery_file("scene_1", "imagery", polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 0.)]);
+    file.properties.num_bands = 4;
+    file.properties.red_band = Some(3);
+    file.properties.ni_band = Some(4);
+    let base_url = url::Url::parse("https://example.com/").unwrap();
+
+    let members = file.to_stac_item(&base_url, true).foreign_members.unwrap();
+    let assets = members["assets"].as_object().unwrap();
+    assert_eq!(assets.len(), 5);
+    assert_eq!(assets["file"]["roles"], serde_json::json!(["data"]));
+    assert_eq!(assets["band-1"]["href"], "https://example.com/collections/imagery/scene_1/assets/b1");
+    assert_eq!(assets["band-1"]["eo:bands"], serde_json::json!([{"name": "b1"}]));
+    assert_eq!(assets["band-3"]["eo:bands"][0]["common_name"], "red");
+    assert_eq!(assets["band-4"]["eo:bands"][0]["common_name"], "nir");
+    assert!(members["stac_extensions"].as_array().unwrap().contains(&stac::EO_EXT.into()));
+
+    // features in collections and searches have the same band assets as the item.
+    let feature = file.to_stac_feature(Some(&base_url)).foreign_members.unwrap();
+    assert_eq!(feature["assets"], members["assets"]);
+    let fc = vec![&file].as_feature_collection(Some(&base_url));
+    assert_eq!(fc.features[0].foreign_members.as_ref().unwrap()["assets"], members["assets"]);
+
+    // band assets are only added when enabled, but the file asset lists the bands when the red or nir band is known.
+    let members = file.to_stac_item(&base_url, false).foreign_members.unwrap();
+    assert_eq!(members["assets"].as_object().unwrap().len(), 1);
+    assert_eq!(members["assets"]["file"]["eo:bands"][2], serde_json::json!({"name": "b3", "common_name": "red"}));
+    assert!(members["stac_extensions"].as_array().unwrap().contains(&stac::EO_EXT.into()));
+
+    file.properties.red_band = None;
+    file.properties.ni_band = None;
+    let members = file.to_stac_item(&base_url, false).foreign_members.unwrap();
+    assert!(members["assets"]["file"].get("eo:bands").is_none());
+  }
+
+  #[test]
+  fn test_ndvi_capable() {
+    let mut file = imagery_file("scene", "imagery", polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)]);
+    file.properties.red_band = Some(3);
+    assert_eq!(file.to_stac_feature(None).properties.unwrap()["ndvi_capable"], false);
+    file.properties.ni_band = Some(4);
+    assert_eq!(file.to_stac_feature(None).properties.unwrap()["ndvi_capable"], true);
+  }
+
+  #[test]
+  fn test_request_base_url() {
+    let mut svc = Service {
+      id: String::from("autostac"),
+      title: String::from("Autostac"),
+      description: String::from("Autostac"),
+      base_url: url::Url::parse("http://localhost:8000/stac/").unwrap(),
+      trust_forwarded_headers: false,
+      band_assets: false,
+      max_features: 10000,
+      s3_breaker: Default::default(),
+      collections: Default::default()
+    };
+    // forwarded headers are ignored unless the service trusts them.
+    assert_eq!(svc.request_base_url(Some("example.com"), Some("https")).as_str(), "http://localhost:8000/stac/");
+
+    svc.trust_forwarded_headers = true;
+    assert_eq!(svc.request_base_url(None, None).as_str(), "http://localhost:8000/stac/");
+    assert_eq!(svc.request_base_url(Some("example.com"), Some("https")).as_str(), "https://example.com/stac/");
+    assert_eq!(svc.request_base_url(None, Some("https")).as_str(), "https://localhost:8000/stac/");
+    assert_eq!(svc.request_base_url(Some("example.com:8443"), None).as_str(), "http://example.com:8443/stac/");
+    // the first value is used when proxies add to the headers.
+    assert_eq!(svc.request_base_url(Some("example.com, internal"), Some("https,http")).as_str(), "https://example.com/stac/");
+
+    // invalid headers fall back to the base URL.
+    assert_eq!(svc.request_base_url(Some("example.com/evil"), Some("javascript")).as_str(), "http://localhost:8000/stac/");
+    assert_eq!(svc.request_base_url(Some("example.com/evil"), Some("https")).as_str(), "http://localhost:8000/stac/");
+  }
+
+  #[test]
+  fn test_slugify() {
+    assert_eq!(slugify("Land Use"), "land-use");
+    assert_eq!(slugify("land-use"), "land-use");
+    assert_eq!(slugify("  Orthophotos (2021) "), "orthophotos-2021");
+    assert_eq!(slugify("dem_1m"), "dem_1m");
+    assert_eq!(slugify("???"), "collection");
+  }
+
+  #[test]
+  fn test_collection_slug_collision() {
+    let dir = test_dir("slugs");
+    for name in &["Land Use", "land-use"] {
+      fs::create_dir(dir.join(name)).unwrap();
+      write_geotiff(&dir.join(name).join("scene.tif"), 1);
+    }
+
+    let collections = collections_from_subdirs(dir.to_str().unwrap(), &ScanOptions::default());
+    assert_eq!(collections.len(), 2);
+
+    let first = &collections["land-use"];
+    let second = &collections["land-use-2"];
+    assert_eq!(first.title, "Land Use");
+    assert_eq!(second.title, "land-use");
+    assert_eq!(first.all().len(), 1);
+    assert_eq!(second.all().len(), 1);
+    assert_eq!(second.all()[0].collection_id, "land-use-2");
+  }
+
+  #[test]
+  fn test_item_id_collision() {
+    let file = |path: &str| ImageryFile { path: PathBuf::from(path), ..imagery_file("scene", "imagery", polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.)]) };
+    let files = vec![file("b/scene.tif"), file("a/scene.tif"), file("c/scene.tif"), imagery_file("scene_2", "imagery", polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.)])];
+    let collection = imagery_collection("imagery", files.clone());
+
+    // the first file by path keeps its id, and the others are given a suffix.
+    let ids: Vec<&str> = collection.all().iter().map(|f| f.filename.as_str()).collect();
+    assert_eq!(ids[1], "scene");
+    assert_eq!(ids[3], "scene_2");
+    let unique: HashSet<&&str> = ids.iter().collect();
+    assert_eq!(unique.len(), 4);
+    for (file, id) in collection.all().iter().zip(ids.iter()) {
+      assert!(id.starts_with("scene"));
+      assert_eq!(file.properties.filename, *id);
+      assert_eq!(collection.get_item(id.to_string()).unwrap().path, file.path);
+    }
+
+    // ids stay the same each time the files are catalogued.
+    let again = imagery_collection("imagery", files);
+    let again_ids: Vec<&str> = again.all().iter().map(|f| f.filename.as_str()).collect();
+    assert_eq!(again_ids, ids);
+
+    // files with the same name and different extensions in a collection directory.
+    let dir = test_dir("item-id-collision");
+    fs::create_dir(dir.join("imagery")).unwrap();
+    write_geotiff(&dir.join("imagery").join("scene.tif"), 1);
+    write_geotiff(&dir.join("imagery").join("scene.tiff"), 1);
+    let collections = collections_from_subdirs(dir.to_str().unwrap(), &ScanOptions::default());
+    let ids: HashSet<&str> = collections["imagery"].all().iter().map(|f| f.filename.as_str()).collect();
+    assert_eq!(ids.len(), 2);
+    assert!(ids.contains("scene"));
+  }
+
+  #[test]
+  fn test_rescan_collections() {
+    let dir = test_dir("rescan");
+    for name in &["a", "b"] {
+      fs::create_dir(dir.join(name)).unwrap();
+      write_geotiff(&dir.join(name).join("scene.tif"), 1);
+    }
+    let root = dir.to_str().unwrap();
+    let collections = RwLock::new(Arc::new(Catalog::from(collections_from_subdirs(root, &ScanOptions::default()))));
+
+    // only changed collections are rescanned, which gives them a new version.
+    let version = |id: &str| collections.read().unwrap()[id].version();
+    let (a, b) = (version("a"), version("b"));
+    write_geotiff(&dir.join("a").join("scene_2.tif"), 1);
+    write_geotiff(&dir.join("b").join("scene_2.tif"), 1);
+    let changed: HashSet<String> = vec![String::from("a")].into_iter().collect();
+    rescan_collections(root, &changed, &ScanOptions::default(), &collections);
+    assert_eq!(collections.read().unwrap()["a"].all().len(), 2);
+    assert_eq!(collections.read().unwrap()["b"].all().len(), 1);
+    assert_ne!(version("a"), a);
+    assert_eq!(version("b"), b);
+
+    // new directories are added and removed directories are dropped.
+    fs::remove_dir_all(dir.join("b")).unwrap();
+    fs::create_dir(dir.join("c")).unwrap();
+    write_geotiff(&dir.join("c").join("scene.tif"), 1);
+    rescan_collections(root, &HashSet::new(), &ScanOptions::default(), &collections);
+    let mut ids: Vec<String> = collections.read().unwrap().keys().cloned().collect();
+    ids.sort();
+    assert_eq!(ids, vec!["a", "c"]);
+  }
+
+  #[test]
+  fn test_highest_resolution() {
+    let footprint = polygon![
+      (x: 0., y: 0.),
+      (x: 1., y: 0.),
+      (x: 1., y: 1.),
+      (x: 0., y: 1.)
+    ];
+    let mut low_res = imagery_file("low_res", "imagery", footprint.clone());
+    low_res.properties.resolution = Resolution { x: 100., y: 100. };
+    let mut high_res = imagery_file("high_res", "imagery", footprint.clone());
+    high_res.properties.resolution = Resolution { x: 1., y: 1. };
+    let mut also_high_res = imagery_file("also_high_res", "imagery", footprint);
+    also_high_res.properties.resolution = Resolution { x: 1., y: 1. };
+
+    let files = vec![&low_res, &high_res, &also_high_res];
+    assert_eq!(files.highest_resolution().unwrap().filename, "high_res");
+    assert!(Vec::<&ImageryFile>::new().highest_resolution().is_none());
+  }
+
+  #[test]
+  fn test_best_resolution_match() {
+    let footprint = polygon![
+      (x: 0., y: 0.),
+      (x: 1., y: 0.),
+      (x: 1., y: 1.),
+      (x: 0., y: 1.)
+    ];
+    let mut low_res = imagery_file("low_res", "imagery", footprint.clone());
+    low_res.properties.resolution = Resolution { x: 100., y: 100. };
+    let mut high_res = imagery_file("high_res", "imagery", footprint);
+    high_res.properties.resolution = Resolution { x: 1., y: 1. };
+    let files = vec![&high_res, &low_res];
+
+    // low zoom: both files are detailed enough, so the lower resolution one is read.
+    let z6 = transform::tile_resolution(32, 31, 6);
+    assert_eq!(files.best_resolution_match(z6).unwrap().filename, "low_res");
+
+    // high zoom: only the high resolution file is detailed enough.
+    let z14 = transform::tile_resolution(8192, 8191, 14);
+    assert_eq!(files.best_resolution_match(z14).unwrap().filename, "high_res");
+
+    // past the native resolution of every file, the most detailed file is used.
+    let z20 = transform::tile_resolution(524288, 524287, 20);
+    assert_eq!(files.best_resolution_match(z20).unwrap().filename, "high_res");
+
+    assert!(Vec::<&ImageryFile>::new().best_resolution_match(z6).is_none());
+  }
+
+  #[test]
+  fn test_zoom_range() {
+    let footprint = polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)];
+    let mut sentinel2 = imagery_file("sentinel2", "imagery", footprint.clone());
+    sentinel2.properties.resolution = Resolution { x: 10., y: 10. };
+    let mut landsat = imagery_file("landsat", "imagery", footprint.clone());
+    landsat.properties.resolution = Resolution { x: 30., y: 30. };
+    let mut ortho = imagery_file("ortho", "imagery", footprint);
+    ortho.properties.resolution = Resolution { x: 0.1, y: 0.1 };
+
+    // 30 m is between zoom levels 12 (38.2 m) and 13 (19.1 m), 10 m is between 13 and 14 (9.6 m),
+    // and 10 cm is between 20 (15 cm) and 21 (7.5 cm).
+    assert_eq!(imagery_collection("imagery", vec![sentinel2.clone()]).zoom_range(), (14, 14));
+    assert_eq!(imagery_collection("imagery", vec![sentinel2.clone(), landsat.clone()]).zoom_range(), (13, 14));
+    assert_eq!(imagery_collection("imagery", vec![landsat, sentinel2, ortho]).zoom_range(), (13, 21));
+    assert_eq!(imagery_collection("imagery", Vec::new()).zoom_range(), (0, transform::MAX_ZOOM));
+  }
+
+  #[test]
+  fn test_multipolygon_footprint() {
+    // a footprint made of two squares with a gap between them.
+    let footprint = MultiPolygon(vec![
+      polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)],
+      polygon![(x: 2., y: 0.), (x: 3., y: 0.), (x: 3., y: 1.), (x: 2., y: 1.)],
+    ]);
+    let collection = imagery_collection("imagery", vec![imagery_file("scene", "imagery", footprint)]);
+
+    let in_second_part: Geometry<f64> = point!(x: 2.5, y: 0.5).into();
+    let in_gap: Geometry<f64> = point!(x: 1.5, y: 0.5).into();
+    assert_eq!(collection.intersects(&in_second_part).len(), 1);
+    assert_eq!(collection.intersects(&in_gap).len(), 0);
+
+    // contained by one part
+    let small = polygon![(x: 2.2, y: 0.2), (x: 2.8, y: 0.2), (x: 2.8, y: 0.8), (x: 2.2, y: 0.8)];
+    assert_eq!(collection.contains(&small.clone().into(), ContainsMode::Strict).len(), 1);
+
+    // every polygon of a MultiPolygon or GeometryCollection query must be contained, by either part.
+    let in_first_part = polygon![(x: 0.2, y: 0.2), (x: 0.8, y: 0.2), (x: 0.8, y: 0.8), (x: 0.2, y: 0.8)];
+    let outside = polygon![(x: 4.2, y: 0.2), (x: 4.8, y: 0.2), (x: 4.8, y: 0.8), (x: 4.2, y: 0.8)];
+    let both_parts = MultiPolygon(vec![small.clone(), in_first_part.clone()]);
+    assert_eq!(collection.contains(&both_parts.into(), ContainsMode::Strict).len(), 1);
+    assert_eq!(collection.contains(&MultiPolygon(vec![small.clone(), outside]).into(), ContainsMode::Covers).len(), 0);
+    let parts = GeometryCollection(vec![small.into(), MultiPolygon(vec![in_first_part]).into()]);
+    assert_eq!(collection.contains(&Geometry::GeometryCollection(parts), ContainsMode::Strict).len(), 1);
+
+    // geometries that aren't areas are never contained.
+    assert_eq!(collection.contains(&in_second_part, ContainsMode::Covers).len(), 0);
+    assert_eq!(collection.contains(&Geometry::GeometryCollection(GeometryCollection(vec![])), ContainsMode::Covers).len(), 0);
+
+    // spans both parts, but also covers the gap between them.
+    let spanning = polygon![(x: 0.5, y: 0.2), (x: 2.5, y: 0.2), (x: 2.5, y: 0.8), (x: 0.5, y: 0.8)];
+    assert_eq!(collection.contains(&spanning.clone().into(), ContainsMode::Strict).len(), 0);
+    assert_eq!(collection.contains(&spanning.clone().into(), ContainsMode::Covers).len(), 0);
+    assert_eq!(collection.intersects(&spanning.into()).len(), 1);
+  }
+
+  #[test]
+  fn test_single_part_footprint_is_polygon() {
+    let img = imagery_file("scene", "imagery", polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)]);
+    let feature = img.to_stac_feature(None);
+    assert!(matches!(feature.geometry.unwrap().value, geojson::Value::Polygon(_)));
+    assert_eq!(feature.bbox, Some(vec![0., 0., 1., 1.]));
+  }
 }
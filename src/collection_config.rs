@@ -0,0 +1,114 @@
+use std::fs;
+use std::path::Path;
+use log::warn;
+use serde::Deserialize;
+use crate::stac::Provider;
+
+/// the names of the config file that can be added to each collection directory (or S3 prefix).
+/// If a collection has both, the JSON file is used.
+pub const COLLECTION_JSON: &str = "collection.json";
+pub const COLLECTION_TOML: &str = "collection.toml";
+
+/// CollectionConfig holds details about a collection that can't be found from its files, read from a
+/// `collection.json` or `collection.toml` file in the collection directory. Every field is optional, and
+/// collections without a config file (or without a title or description) use the directory name instead.
+///
+///     title = "Victoria orthophotos"
+///     description = "2021 orthophotos of Victoria, BC"
+///     license = "CC-BY-4.0"
+///     keywords = ["orthophoto", "victoria"]
+///
+///     [[providers]]
+///     name = "City of Victoria"
+///     roles = ["producer", "licensor"]
+///     url = "https://www.victoria.ca"
+///
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CollectionConfig {
+  pub title: Option<String>,
+  pub description: Option<String>,
+  pub license: Option<String>,
+  pub keywords: Vec<String>,
+  pub providers: Vec<Provider>
+}
+
+impl CollectionConfig {
+    /// parse the contents of a collection.json file.
+    pub fn parse_json(contents: &str) -> Result<CollectionConfig, String> {
+      serde_json::from_str(contents).map_err(|e| format!("invalid {}: {}", COLLECTION_JSON, e))
+    }
+
+    /// parse the contents of a collection.toml file.
+    pub fn parse_toml(contents: &str) -> Result<CollectionConfig, String> {
+      toml::from_str(contents).map_err(|e| format!("invalid {}: {}", COLLECTION_TOML, e))
+    }
+
+    /// parse a config file, using its name to tell JSON and TOML files apart.
+    /// Invalid files are logged and ignored.
+    pub fn parse(name: &str, contents: &str) -> CollectionConfig {
+      let config = match name.ends_with(COLLECTION_TOML) {
+        true => CollectionConfig::parse_toml(contents),
+        false => CollectionConfig::parse_json(contents),
+      };
+      config.unwrap_or_else(|e| {
+        warn!("{}: {}", name, e);
+        CollectionConfig::default()
+      })
+    }
+
+    /// read the config file in `dir`. If there isn't one, the defaults are used.
+    pub fn from_dir(dir: &Path) -> CollectionConfig {
+      for name in &[COLLECTION_JSON, COLLECTION_TOML] {
+        let path = dir.join(name);
+        if let Ok(contents) = fs::read_to_string(&path) {
+          return CollectionConfig::parse(&path.display().to_string(), &contents);
+        }
+      }
+      CollectionConfig::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::collection_config::CollectionConfig;
+  use crate::stac::Provider;
+
+  #[test]
+  fn test_parse_collection_config() {
+    let json = r#"{
+      "title": "Victoria orthophotos",
+      "license": "CC-BY-4.0",
+      "keywords": ["orthophoto"],
+      "providers": [{"name": "City of Victoria", "roles": ["producer"], "url": "https://www.victoria.ca"}]
+    }"#;
+    let toml = r#"
+      title = "Victoria orthophotos"
+      license = "CC-BY-4.0"
+      keywords = ["orthophoto"]
+
+      [[providers]]
+      name = "City of Victoria"
+      roles = ["producer"]
+      url = "https://www.victoria.ca"
+    "#;
+    let expected = CollectionConfig {
+      title: Some(String::from("Victoria orthophotos")),
+      description: None,
+      license: Some(String::from("CC-BY-4.0")),
+      keywords: vec![String::from("orthophoto")],
+      providers: vec![Provider {
+        name: String::from("City of Victoria"),
+        description: None,
+        roles: vec![String::from("producer")],
+        url: Some(String::from("https://www.victoria.ca"))
+      }]
+    };
+    assert_eq!(CollectionConfig::parse_json(json), Ok(expected.clone()));
+    assert_eq!(CollectionConfig::parse_toml(toml), Ok(expected));
+
+    // invalid files are ignored.
+    assert!(CollectionConfig::parse_json(r#"{"keywords": "orthophoto"}"#).is_err());
+    assert_eq!(CollectionConfig::parse("collection.toml", "title = "), CollectionConfig::default());
+  }
+}
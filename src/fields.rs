@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// the fields of an item that are returned when a search asks for fields without listing any to include.
+/// https://github.com/radiantearth/stac-api-spec/tree/v1.0.0-beta.2/fragments/fields
+const DEFAULT_FIELDS: [&str; 8] = ["type", "stac_version", "id", "bbox", "geometry", "properties.datetime", "links", "assets"];
+
+/// Fields is the `fields` parameter of a search (the STAC API fields extension), which trims the items in the
+/// results. Fields are named by their path in the item, e.g. `properties.datetime`.
+/// * with `include`, only the included fields (and the default fields) are returned.
+/// * with only `exclude`, every field except the excluded ones is returned.
+/// * with neither, only the default fields are returned.
+///
+/// A field that is both included and excluded is included.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Fields {
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  include: Vec<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  exclude: Vec<String>
+}
+
+impl Fields {
+    /// trims a serialized item to its included fields.
+    pub fn apply(&self, item: &mut Value) {
+      let include: Vec<&str> = match self.include.is_empty() && !self.exclude.is_empty() {
+        true => Vec::new(),
+        false => DEFAULT_FIELDS.iter().copied().chain(self.include.iter().map(|f| f.as_str())).collect(),
+      };
+
+      if !include.is_empty() {
+        let mut trimmed = Value::Object(Map::new());
+        for field in &include {
+          copy_field(item, &mut trimmed, &path(field));
+        }
+        *item = trimmed;
+      }
+
+      // an excluded field is only removed if it isn't included, and doesn't hold a field that is.
+      let holds_included = |field: &str| include.iter().any(|i| *i == field || i.starts_with(&format!("{}.", field)));
+      for field in self.exclude.iter().filter(|f| !holds_included(f)) {
+        remove_field(item, &path(field));
+      }
+    }
+}
+
+fn path(field: &str) -> Vec<&str> {
+  field.split('.').collect()
+}
+
+/// copies the field at `path` (if there is one) from one object to another, creating any objects it's nested in.
+fn copy_field(from: &Value, to: &mut Value, path: &[&str]) {
+  let (key, rest) = match path.split_first() {
+    Some(p) => p,
+    None => return,
+  };
+  let (value, to) = match (from.get(key), to.as_object_mut()) {
+    (Some(value), Some(to)) => (value, to),
+    _ => return,
+  };
+  if rest.is_empty() {
+    to.insert(key.to_string(), value.clone());
+  } else if value.is_object() {
+    let nested = to.entry(key.to_string()).or_insert_with(|| Value::Object(Map::new()));
+    copy_field(value, nested, rest);
+  }
+}
+
+/// removes the field at `path`, if there is one.
+fn remove_field(value: &mut Value, path: &[&str]) {
+  match path {
+    [] => (),
+    [key] => {
+      if let Some(object) = value.as_object_mut() {
+        object.remove(*key);
+      }
+    },
+    [key, rest @ ..] => {
+      if let Some(nested) = value.get_mut(*key) {
+        remove_field(nested, rest);
+      }
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+  use crate::fields::Fields;
+
+  fn item() -> serde_json::Value {
+    json!({
+      "type": "Feature",
+      "stac_version": "1.0.0",
+      "id": "scene",
+      "bbox": [0., 0., 1., 1.],
+      "geometry": {"type": "Point", "coordinates": [0.5, 0.5]},
+      "properties": {"datetime": "2021-08-09T18:59:19Z", "gsd": 10., "eo:cloud_cover": 5.},
+      "links": [],
+      "assets": {"file": {"href": "scene.tif"}}
+    })
+  }
+
+  fn fields(include: &[&str], exclude: &[&str]) -> Fields {
+    Fields {
+      include: include.iter().map(|f| f.to_string()).collect(),
+      exclude: exclude.iter().map(|f| f.to_string()).collect()
+    }
+  }
+
+  #[test]
+  fn test_default_fields() {
+    let mut trimmed = item();
+    Fields::default().apply(&mut trimmed);
+    let mut expected = item();
+    expected["properties"] = json!({"datetime": "2021-08-09T18:59:19Z"});
+    assert_eq!(trimmed, expected);
+  }
+
+  #[test]
+  fn test_include() {
+    let mut trimmed = item();
+    fields(&["properties.gsd", "properties.missing", "nothing"], &[]).apply(&mut trimmed);
+    assert_eq!(trimmed["properties"], json!({"datetime": "2021-08-09T18:59:19Z", "gsd": 10.}));
+    assert_eq!(trimmed["assets"], item()["assets"]);
+    assert!(trimmed.get("nothing").is_none());
+  }
+
+  #[test]
+  fn test_exclude() {
+    let mut trimmed = item();
+    fields(&[], &["properties.gsd", "assets"]).apply(&mut trimmed);
+    assert_eq!(trimmed["properties"], json!({"datetime": "2021-08-09T18:59:19Z", "eo:cloud_cover": 5.}));
+    assert!(trimmed.get("assets").is_none());
+    assert_eq!(trimmed["geometry"], item()["geometry"]);
+  }
+
+  #[test]
+  fn test_include_and_exclude() {
+    // included fields win over excluded ones.
+    let mut trimmed = item();
+    fields(&["properties"], &["properties.gsd", "geometry", "properties.datetime"]).apply(&mut trimmed);
+    assert_eq!(trimmed["properties"], json!({"datetime": "2021-08-09T18:59:19Z", "eo:cloud_cover": 5.}));
+    assert_eq!(trimmed["geometry"], item()["geometry"]);
+
+    let mut trimmed = item();
+    fields(&["properties.gsd"], &["properties"]).apply(&mut trimmed);
+    assert_eq!(trimmed["properties"], json!({"datetime": "2021-08-09T18:59:19Z", "gsd": 10.}));
+  }
+}
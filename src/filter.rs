@@ -0,0 +1,180 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use crate::catalog::ImageryFile;
+
+/// the only filter language (`filter-lang`) that search accepts.
+pub static CQL2_JSON: &str = "cql2-json";
+
+/// Filter is a CQL2 filter expression over item properties.
+/// Only comparisons of a property with a literal, combined with `and` and `or`, are supported:
+///
+///     {"op": "and", "args": [
+///       {"op": "<=", "args": [{"property": "eo:cloud_cover"}, 10]},
+///       {"op": ">=", "args": [{"property": "datetime"}, {"timestamp": "2021-01-01T00:00:00Z"}]}
+///     ]}
+///
+/// https://docs.ogc.org/DRAFTS/21-065.html
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+  And(Vec<Filter>),
+  Or(Vec<Filter>),
+  Compare(Comparison, Property, Literal)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+  Lt,
+  Le,
+  Eq,
+  Gt,
+  Ge
+}
+
+/// the item properties that can be filtered on (see stac::queryables).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Property {
+  CloudCover,
+  /// `gsd` or `spatial_resolution`.
+  Gsd,
+  Datetime
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+  Number(f64),
+  Timestamp(DateTime<Utc>)
+}
+
+impl Filter {
+    /// parse a CQL2-JSON filter. Errors name the operator, property or value that isn't supported.
+    pub fn from_json(filter: &Value) -> Result<Filter, String> {
+      let op = filter.get("op").and_then(|op| op.as_str())
+        .ok_or_else(|| format!("invalid filter expression `{}`. Expressions must have an `op` and `args`", filter))?;
+      let args = filter.get("args").and_then(|args| args.as_array())
+        .ok_or_else(|| format!("filter operator `{}` must have a list of `args`", op))?;
+
+      let comparison = match op {
+        "and" | "or" => {
+          if args.len() < 2 {
+            return Err(format!("filter operator `{}` needs at least two arguments", op));
+          }
+          let args = args.iter().map(Filter::from_json).collect::<Result<Vec<_>, _>>()?;
+          return Ok(if op == "and" { Filter::And(args) } else { Filter::Or(args) });
+        },
+        "<" => Comparison::Lt,
+        "<=" => Comparison::Le,
+        "=" => Comparison::Eq,
+        ">" => Comparison::Gt,
+        ">=" => Comparison::Ge,
+        _ => return Err(format!(
+          "unsupported filter operator `{}`. Supported operators are <, <=, =, >, >=, and, or", op
+        )),
+      };
+
+      let (property, literal) = match args.as_slice() {
+        [property, literal] => (property, literal),
+        _ => return Err(format!("filter operator `{}` needs two arguments: a property and a value", op)),
+      };
+      let property = match property.get("property").and_then(|p| p.as_str()) {
+        Some("eo:cloud_cover") => Property::CloudCover,
+        Some("gsd") | Some("spatial_resolution") => Property::Gsd,
+        Some("datetime") => Property::Datetime,
+        Some(p) => return Err(format!(
+          "unsupported filter property `{}`. Supported properties are eo:cloud_cover, gsd, spatial_resolution, datetime", p
+        )),
+        None => return Err(format!("the first argument of `{}` must be a property, e.g. {{\"property\": \"gsd\"}}", op)),
+      };
+      let literal = match property {
+        Property::Datetime => {
+          // timestamps can be given as {"timestamp": "..."} or as a string.
+          let ts = literal.get("timestamp").unwrap_or(literal);
+          ts.as_str()
+            .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+            .map(|ts| Literal::Timestamp(ts.with_timezone(&Utc)))
+            .ok_or_else(|| format!("invalid filter value `{}` for datetime. Use an RFC3339 timestamp", literal))?
+        },
+        _ => literal.as_f64()
+          .map(Literal::Number)
+          .ok_or_else(|| format!("invalid filter value `{}`. Numeric properties must be compared to a number", literal))?,
+      };
+      Ok(Filter::Compare(comparison, property, literal))
+    }
+
+    /// checks whether an image matches the filter. Images without the property (e.g. no cloud cover) don't match.
+    pub fn matches(&self, image: &ImageryFile) -> bool {
+      match self {
+        Filter::And(filters) => filters.iter().all(|f| f.matches(image)),
+        Filter::Or(filters) => filters.iter().any(|f| f.matches(image)),
+        Filter::Compare(comparison, property, literal) => {
+          let ordering = match (property, literal) {
+            (Property::CloudCover, Literal::Number(n)) => image.properties.cloud_coverage.and_then(|c| c.partial_cmp(n)),
+            (Property::Gsd, Literal::Number(n)) => image.properties.resolution.avg().partial_cmp(n),
+            (Property::Datetime, Literal::Timestamp(ts)) => image.properties.timestamp.map(|t| t.cmp(ts)),
+            _ => None,
+          };
+          match ordering {
+            Some(o) => match comparison {
+              Comparison::Lt => o.is_lt(),
+              Comparison::Le => o.is_le(),
+              Comparison::Eq => o.is_eq(),
+              Comparison::Gt => o.is_gt(),
+              Comparison::Ge => o.is_ge(),
+            },
+            None => false,
+          }
+        },
+      }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::{TimeZone, Utc};
+  use geo::polygon;
+  use serde_json::json;
+  use crate::catalog::ImageryFile;
+  use crate::catalog::test_utils::imagery_file;
+  use crate::filter::{Comparison, Filter, Literal, Property};
+
+  #[test]
+  fn test_parse_filter() {
+    let filter = json!({"op": "and", "args": [
+      {"op": "<=", "args": [{"property": "eo:cloud_cover"}, 10]},
+      {"op": ">", "args": [{"property": "datetime"}, {"timestamp": "2021-01-01T00:00:00Z"}]}
+    ]});
+    assert_eq!(Filter::from_json(&filter), Ok(Filter::And(vec![
+      Filter::Compare(Comparison::Le, Property::CloudCover, Literal::Number(10.)),
+      Filter::Compare(Comparison::Gt, Property::Datetime, Literal::Timestamp(Utc.ymd(2021, 1, 1).and_hms(0, 0, 0)))
+    ])));
+
+    // errors name the unsupported token.
+    let err = Filter::from_json(&json!({"op": "like", "args": [{"property": "title"}, "S2%"]})).unwrap_err();
+    assert!(err.contains("`like`"), "{}", err);
+    let err = Filter::from_json(&json!({"op": "=", "args": [{"property": "title"}, "scene"]})).unwrap_err();
+    assert!(err.contains("`title`"), "{}", err);
+    assert!(Filter::from_json(&json!({"op": "<", "args": [{"property": "gsd"}, "10"]})).is_err());
+    assert!(Filter::from_json(&json!({"op": "and", "args": [{"op": "<", "args": [{"property": "gsd"}, 10]}]})).is_err());
+  }
+
+  #[test]
+  fn test_filter_matches() {
+    let square = polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)];
+    let mut image = imagery_file("scene", "imagery", square);
+    image.properties.cloud_coverage = Some(5.);
+    image.properties.resolution.x = 10.;
+    image.properties.resolution.y = 10.;
+    let matches = |image: &ImageryFile, filter| Filter::from_json(&filter).unwrap().matches(image);
+
+    assert!(matches(&image, json!({"op": "<", "args": [{"property": "eo:cloud_cover"}, 10]})));
+    assert!(!matches(&image, json!({"op": ">=", "args": [{"property": "eo:cloud_cover"}, 10]})));
+    assert!(matches(&image, json!({"op": "=", "args": [{"property": "gsd"}, 10]})));
+    assert!(matches(&image, json!({"op": "or", "args": [
+      {"op": ">", "args": [{"property": "spatial_resolution"}, 30]},
+      {"op": "<", "args": [{"property": "datetime"}, "2022-01-01T00:00:00Z"]}
+    ]})));
+
+    // images without a cloud cover don't match cloud cover comparisons.
+    image.properties.cloud_coverage = None;
+    assert!(!matches(&image, json!({"op": "<", "args": [{"property": "eo:cloud_cover"}, 10]})));
+  }
+}
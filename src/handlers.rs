@@ -1,192 +1,1012 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
 use std::convert::TryFrom;
 use std::convert::TryInto;
 use std::f64;
+use std::hash::{Hash, Hasher};
+use std::io::Cursor;
+use std::sync::Arc;
 use std::u32;
 use std::u8;
+use chrono::{DateTime, Utc};
 use geo::polygon;
-use geo_types::{Geometry, Polygon};
+use geo::algorithm::intersects::Intersects;
+use geo::prelude::BoundingRect;
+use geo_types::{Geometry, MultiPolygon, Polygon, Rect};
 use catalog::AsFeatureCollection;
-use rocket::http::Status;
+use rocket::http::{ContentType, Status};
 use serde_json::{to_string};
-use rocket::{State, response::content::Json};
-use rocket::response::status::BadRequest;
+use rocket::{Request, State, response::content::{Custom, Html, Json}};
+use rocket::fs::NamedFile;
+use rocket::response::{self, Responder, Response};
+use rocket::response::status;
+use rocket::response::stream::ReaderStream;
+use rocket::request::{self, FromRequest, Outcome};
 use rocket::serde;
+use log::error;
 use wkt::Wkt;
-use crate::catalog::ImageContainsPolygon;
-use crate::catalog::ImageIntersectsGeom;
+use crate::catalog::ContainsMode;
+use crate::catalog::ImageBestResolution;
+use crate::catalog::ImageDatetimeRange;
 use crate::catalog::ImageryFile;
 use crate::transform;
 use crate::catalog;
+use crate::fields::Fields;
+use crate::filter::{self, Filter};
+use crate::html;
+use crate::links;
+use crate::metrics::Metrics;
+use crate::openapi;
+use crate::render::Colormap;
+use crate::stac;
+use crate::thumbnail::{self, Thumbnails};
+use crate::tilejson;
+use crate::tiles;
+use crate::tiles::{RasterTileRow, VectorTile, VectorTileRow};
+use crate::wms::{self, GetMap, WmsError, WmsParams};
+use crate::wmts;
+use crate::bands::BandCache;
+
+/// the number of features returned when the client doesn't supply a `limit`.
+const DEFAULT_LIMIT: usize = 10;
 
 enum SortOrder {
   Asc,
   Desc
 }
 
-fn bbox_to_bounds(bbox: Vec<f64>) -> Result<Geometry<f64>, BadRequest<String>> {
-  if bbox.len() != 4 || bbox[0] >= bbox[2] || bbox[1] >= bbox[3] {
-    return Err(BadRequest(Some("Invalid bbox. bbox must contain 4 numbers in the following format:  bbox=minx,miny,maxx,maxy".into())));
+/// Cache-Control header values for each class of route.
+/// The landing page and collections only change when the catalog is rebuilt,
+/// items are stable, and search results depend on the query.
+pub struct CachePolicy {
+  pub collections: String,
+  pub items: String,
+  pub search: String
+}
+
+/// Cached wraps a responder and sets its Cache-Control header.
+pub struct Cached<R>(pub R, pub String);
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Cached<R> {
+  fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+    let mut response = self.0.respond_to(request)?;
+    response.set_raw_header("Cache-Control", self.1);
+    Ok(response)
   }
-  let p: Polygon<f64> = polygon![
-    (x:bbox[0], y: bbox[1]),
-    (x:bbox[2], y: bbox[1]),
-    (x:bbox[2], y: bbox[3]),
-    (x:bbox[0], y: bbox[3]),
-  ];
-  let g: Geometry<f64> = p.into();
-  Ok(g)
 }
 
-/// parse WKT supplied in a query param
-fn query_to_bounds(query_str: &str) -> Result<Geometry<f64>, BadRequest<String>> {
-  // convert the contains query into a Geometry.
-  // WKT format is expected.
-  // If any errors occur, respond to the request with a 400 error.
-  let wkt_geom = match Wkt::from_str(query_str) {
-    Ok(w) => w,
-    Err(_) => return Err(BadRequest(
-        Some("Invalid WKT in `contains` query param. Example of a valid query: \
-            ?contains=POLYGON ((30 10, 40 40, 20 40, 10 20, 30 10))".into()))),
+/// ETagged wraps a responder and sets its ETag header to a hash of its body (see etag). If the request's
+/// If-None-Match header has the same ETag, the response is 304 Not Modified, without the body. Bodies only
+/// change when the catalog changes (e.g. when it is reloaded), so clients polling for changes are sent nothing new.
+pub struct ETagged<R>(R, Option<String>);
+
+impl ETagged<Json<String>> {
+    fn json(json: Json<String>) -> Self {
+      let tag = etag(&json.0);
+      ETagged(json, Some(tag))
+    }
+}
+
+impl ETagged<Formatted> {
+    /// tags JSON and HTML bodies. Streamed newline-delimited GeoJSON isn't tagged.
+    fn formatted(formatted: Formatted) -> Self {
+      let tag = match &formatted {
+        Formatted::Json(json) => Some(etag(&json.0)),
+        Formatted::Html(html) => Some(etag(&html.0)),
+        Formatted::Lines(_) => None,
+      };
+      ETagged(formatted, tag)
+    }
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for ETagged<R> {
+  fn respond_to(self, request: &'r Request<'_>) -> response::Result<'o> {
+    let mut response = self.0.respond_to(request)?;
+    if let Some(tag) = self.1 {
+      if request.headers().get("If-None-Match").any(|h| etag_matches(h, &tag)) {
+        response.set_status(Status::NotModified);
+        response.body_mut().take();
+      }
+      response.set_raw_header("ETag", tag);
+    }
+    Ok(response)
+  }
+}
+
+/// a strong ETag for a response body: a hash of the body, in quotes.
+fn etag(body: &str) -> String {
+  let mut hasher = DefaultHasher::new();
+  body.hash(&mut hasher);
+  format!("\"{:x}\"", hasher.finish())
+}
+
+/// whether an If-None-Match header (a list of ETags, or `*`) matches `tag`. Weak ETags (`W/"..."`) match
+/// the strong ETag with the same value, since If-None-Match uses weak comparison.
+fn etag_matches(if_none_match: &str, tag: &str) -> bool {
+  if_none_match.split(',')
+    .map(|t| t.trim())
+    .any(|t| t == "*" || t.trim_start_matches("W/") == tag)
+}
+
+/// Format is the format that the client asked for, with the `f` query parameter (`json`, `html` or `ndjson`)
+/// or the Accept header. JSON is the default. HTML is only used for the landing page and collections (so the
+/// API can be browsed in a web browser), and newline-delimited GeoJSON only for pages of features.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+  Json,
+  Html,
+  Lines
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Format {
+  type Error = ();
+
+  async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+    let format = match request.query_value::<&str>("f").and_then(|f| f.ok()) {
+      Some("html") => Format::Html,
+      Some("ndjson") => Format::Lines,
+      Some(_) => Format::Json,
+      None => match request.accept() {
+        Some(accept) if accept.media_types().any(|m| m.top() == "application" && m.sub() == "geo+json-seq") => Format::Lines,
+        // browsers prefer HTML.
+        Some(accept) if accept.preferred().media_type().is_html() => Format::Html,
+        _ => Format::Json,
+      },
+    };
+    Outcome::Success(format)
+  }
+}
+
+/// the items selected from a catalog for FeatureLines.
+type SelectItems = Box<dyn for<'a> FnOnce(&'a catalog::Catalog) -> Vec<&'a ImageryFile> + Send>;
+
+/// FeatureLines streams items as newline-delimited GeoJSON, one STAC Item per line. The items are selected
+/// from a snapshot of the catalog (see Service::collections) when the response is sent, and each item is
+/// serialized as it's sent, so neither the items nor the whole response are copied into memory. Items are
+/// trimmed to the search's fields, if it has any, and have band assets if the service adds them.
+pub struct FeatureLines {
+  catalog: Arc<catalog::Catalog>,
+  select: SelectItems,
+  fields: Option<Fields>,
+  band_assets: Option<url::Url>
+}
+
+impl FeatureLines {
+    /// streams the items that `select` picks from `catalog`. The query that `select` runs should already
+    /// have been checked, since errors can't be returned once the response has started.
+    pub fn new<F>(catalog: Arc<catalog::Catalog>, fields: Option<Fields>, band_assets: Option<&url::Url>, select: F) -> FeatureLines
+      where F: for<'a> FnOnce(&'a catalog::Catalog) -> Vec<&'a ImageryFile> + Send + 'static
+    {
+      FeatureLines { catalog, select: Box::new(select), fields, band_assets: band_assets.cloned() }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for FeatureLines {
+  fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+    let FeatureLines { catalog, select, fields, band_assets } = self;
+    let lines = ReaderStream! {
+      for image in select(&catalog) {
+        let feature = image.to_stac_feature(band_assets.as_ref());
+        let mut line = match &fields {
+          Some(fields) => {
+            let mut item = serde_json::to_value(feature).unwrap();
+            fields.apply(&mut item);
+            item.to_string()
+          },
+          None => to_string(&feature).unwrap(),
+        };
+        line.push('\n');
+        yield Cursor::new(line.into_bytes());
+      }
+    };
+    Response::build()
+      .header(ContentType::new("application", "geo+json-seq"))
+      .streamed_body(lines)
+      .ok()
+  }
+}
+
+/// Formatted is a response in one of the formats a client can ask for (see Format). Since the
+/// format can depend on the Accept header, responses vary by it.
+pub enum Formatted {
+  Json(Json<String>),
+  Html(Html<String>),
+  Lines(FeatureLines)
+}
+
+impl<'r> Responder<'r, 'static> for Formatted {
+  fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+    let mut response = match self {
+      Formatted::Json(json) => json.respond_to(request)?,
+      Formatted::Html(html) => html.respond_to(request)?,
+      Formatted::Lines(lines) => lines.respond_to(request)?,
+    };
+    response.set_raw_header("Vary", "Accept");
+    Ok(response)
+  }
+}
+
+/// a STAC object (e.g. the landing page or a collection) as JSON, or as an HTML page if the client asked for HTML.
+fn stac_response<T: serde::Serialize>(object: &T, format: Format) -> Formatted {
+  match format {
+    Format::Html => Formatted::Html(Html(html::page(&serde_json::to_value(object).unwrap()))),
+    _ => Formatted::Json(Json(to_string(object).unwrap())),
+  }
+}
+
+/// ApiError is an error response with a STAC API style JSON body (see stac::Exception), e.g.
+/// `{"code": "NotFound", "description": "collection imagery not found"}`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+  /// 404 Not Found, for collections, items and other resources that don't exist.
+  NotFound(String),
+  /// 400 Bad Request, for invalid parameters.
+  BadRequest(String),
+  /// any other status. Error statuses are given the same JSON body by the default catcher.
+  Status(Status)
+}
+
+impl ApiError {
+    /// the 404 for a collection that isn't in the catalog.
+    fn collection_not_found(collection_id: &str) -> ApiError {
+      ApiError::NotFound(format!("collection {} not found", collection_id))
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+  fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+    match self {
+      ApiError::NotFound(description) => exception(Status::NotFound, description).respond_to(request),
+      ApiError::BadRequest(description) => exception(Status::BadRequest, description).respond_to(request),
+      ApiError::Status(s) => s.respond_to(request),
+    }
+  }
+}
+
+/// a JSON error response. The code is the status's reason without spaces, e.g. NotFound.
+fn exception(status: Status, description: String) -> status::Custom<Json<String>> {
+  let exception = stac::Exception {
+    code: status.reason().unwrap_or("Error").replace(' ', ""),
+    description
+  };
+  status::Custom(status, Json(to_string(&exception).unwrap()))
+}
+
+/// gives errors that don't come from an ApiError (e.g. unknown routes, an invalid search body, or a
+/// rejected admin token) the same JSON body.
+#[catch(default)]
+pub fn default_catcher(status: Status, request: &Request) -> status::Custom<Json<String>> {
+  let description = match status.code {
+    404 => format!("{} not found", request.uri().path()),
+    _ => status.reason().unwrap_or("Error").to_owned(),
   };
+  exception(status, description)
+}
+
+/// BaseUrl is the base URL for the links in a response. It's the service's base URL, or
+/// one made from the request's forwarded headers if the service trusts them (see Service::request_base_url).
+pub struct BaseUrl(pub url::Url);
 
-  let bounds: Geometry<f64> = match wkt_geom.try_into() {
-      Ok(g) => g,
-      Err(_) => return Err(BadRequest(
-          Some("Invalid WKT in `contains` query param. Example of a valid query: \
-              ?contains=POLYGON ((30 10, 40 40, 20 40, 10 20, 30 10))".into()))),
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BaseUrl {
+  type Error = ();
+
+  async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+    match request.rocket().state::<catalog::Service>() {
+      Some(svc) => {
+        let headers = request.headers();
+        Outcome::Success(BaseUrl(svc.request_base_url(headers.get_one("X-Forwarded-Host"), headers.get_one("X-Forwarded-Proto"))))
+      },
+      None => Outcome::Failure((Status::InternalServerError, ()))
+    }
+  }
+}
+
+/// Reload is what POST /admin/reload needs to rescan the catalog.
+pub struct Reload {
+  /// the bearer token that reload requests must have. Reloading is disabled without a token.
+  pub token: Option<String>,
+  pub source: catalog::CatalogSource,
+  pub options: catalog::ScanOptions
+}
+
+/// AdminToken guards the admin routes. Requests must have an `Authorization: Bearer <token>` header
+/// with the service's admin token, or they are rejected with 401 Unauthorized. Admin routes aren't
+/// found (404) if the service doesn't have an admin token.
+pub struct AdminToken<'r>(&'r Reload);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminToken<'r> {
+  type Error = ();
+
+  async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+    let reload = match request.rocket().state::<Reload>() {
+      Some(r) => r,
+      None => return Outcome::Forward(()),
+    };
+    let token = match &reload.token {
+      Some(t) => t,
+      None => return Outcome::Forward(()),
     };
+    let supplied = request.headers().get_one("Authorization").and_then(|h| h.strip_prefix("Bearer "));
+    match supplied {
+      Some(s) if tokens_match(s.trim(), token) => Outcome::Success(AdminToken(reload)),
+      _ => Outcome::Failure((Status::Unauthorized, ())),
+    }
+  }
+}
+
+/// compares tokens in constant time (for tokens of the same length), so that the time taken
+/// doesn't reveal how much of a guessed token is right.
+fn tokens_match(supplied: &str, token: &str) -> bool {
+  supplied.len() == token.len() && supplied.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+const INVALID_BBOX: &str = "Invalid bbox. bbox must contain 4 or 6 numbers in the following format:  \
+  bbox=minx,miny,maxx,maxy or bbox=minx,miny,minz,maxx,maxy,maxz";
+
+/// converts a 2D (`minx,miny,maxx,maxy`) or 3D (`minx,miny,minz,maxx,maxy,maxz`) bbox to a polygon.
+/// Elevations in 3D bboxes are ignored, since footprints are 2D.
+/// A bbox whose minx is greater than its maxx crosses the antimeridian, and is split into a polygon
+/// on either side of it.
+fn bbox_to_bounds(bbox: Vec<f64>) -> Result<Geometry<f64>, ApiError> {
+  let (minx, miny, maxx, maxy) = match bbox[..] {
+    [minx, miny, maxx, maxy] | [minx, miny, _, maxx, maxy, _] => (minx, miny, maxx, maxy),
+    _ => return Err(ApiError::BadRequest(INVALID_BBOX.into())),
+  };
+  if minx == maxx || miny >= maxy {
+    return Err(ApiError::BadRequest(INVALID_BBOX.into()));
+  }
+  let rect = |minx: f64, maxx: f64| -> Polygon<f64> {
+    polygon![
+      (x: minx, y: miny),
+      (x: maxx, y: miny),
+      (x: maxx, y: maxy),
+      (x: minx, y: maxy),
+    ]
+  };
+  if minx > maxx {
+    if minx > 180. || maxx < -180. {
+      return Err(ApiError::BadRequest(INVALID_BBOX.into()));
+    }
+    return Ok(MultiPolygon(vec![rect(minx, 180.), rect(-180., maxx)]).into());
+  }
+  Ok(rect(minx, maxx).into())
+}
+
+/// parse a bbox supplied as a comma separated query param (e.g. `bbox=minx,miny,maxx,maxy`)
+fn parse_bbox(bbox: &str) -> Result<Vec<f64>, ApiError> {
+  bbox.split(',')
+    .map(|v| v.trim().parse::<f64>())
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|_| ApiError::BadRequest(INVALID_BBOX.into()))
+}
+
+/// parse a `limit` supplied by the client.
+/// limit must be a positive integer, and is clamped to `max` (the service's max_features), so pages are never
+/// longer than that whatever the client asks for. The page's context shows the clamped limit.
+fn parse_limit(limit: &str, max: usize) -> Result<usize, ApiError> {
+  match limit.trim().parse::<i64>() {
+    Ok(lim) if lim > 0 => Ok(std::cmp::min(lim as usize, max)),
+    _ => Err(ApiError::BadRequest(format!(
+      "Invalid limit `{}`. limit must be a positive integer (e.g. limit=10).", limit
+    )))
+  }
+}
+
+/// the `limit` of a request, or the default if it doesn't have one. Streamed newline-delimited GeoJSON
+/// isn't paged, so it has every matching item (up to `max`) by default.
+fn request_limit(limit: Option<&str>, format: Format, max: usize) -> Result<usize, ApiError> {
+  match limit {
+    Some(l) => parse_limit(l, max),
+    None if format == Format::Lines => Ok(max),
+    None => Ok(DEFAULT_LIMIT.min(max)),
+  }
+}
+
+/// parse an `offset` (the number of features to skip) supplied by the client.
+fn parse_offset(offset: &str) -> Result<usize, ApiError> {
+  offset.trim().parse::<usize>().map_err(|_| ApiError::BadRequest(format!(
+    "Invalid offset `{}`. offset must be 0 or a positive integer (e.g. offset=10).", offset
+  )))
+}
+
+/// a page of features, the offsets of the pages before and after it (if there are any),
+/// and the paging context (the number of features that matched the query).
+struct Page<T> {
+  images: Vec<T>,
+  prev: Option<usize>,
+  next: Option<usize>,
+  context: stac::Context
+}
 
-  Ok(bounds)
+/// returns the page of `limit` images starting at `offset`.
+fn paginate<T>(images: Vec<T>, offset: usize, limit: usize) -> Page<T> {
+  let next = match offset.saturating_add(limit) {
+    n if n < images.len() => Some(n),
+    _ => None,
+  };
+  let prev = match offset {
+    0 => None,
+    o => Some(o.saturating_sub(limit)),
+  };
+
+  let matched = images.len();
+  let images = images.into_iter().skip(offset).take(limit).collect::<Vec<_>>();
+  let context = stac::Context { returned: images.len(), limit, matched };
+
+  Page {
+    images,
+    prev,
+    next,
+    context
+  }
+}
+
+/// the links to the pages before and after a page, using `link` to create a link to the page at an offset.
+fn page_links<T>(page: &Page<T>, link: impl Fn(stac::StacRel, usize) -> stac::StacLink) -> Vec<stac::StacLink> {
+  let mut page_links = Vec::new();
+  if let Some(offset) = page.prev {
+    page_links.push(link(stac::StacRel::Prev, offset));
+  }
+  if let Some(offset) = page.next {
+    page_links.push(link(stac::StacRel::Next, offset));
+  }
+  page_links
+}
+
+/// sorts images by a `sortby` key: `spatial_resolution`, `cloud_cover` or `datetime`.
+/// Keys are sorted ascending, unless prefixed with `-` (descending).
+/// Images without a cloud cover (or a datetime) always sort last. Sorting by `ndvi_capable` puts capable images first.
+fn sort_images(images: &mut [&ImageryFile], sortby: &str) -> Result<(), ApiError> {
+  // note: Rocket parses + as whitespace.
+  // however, since + (ascending) is the default, that behavior doesn't seem to affect our
+  // ability to sort. The `+` prefix is only seen when it's encoded (e.g. `sortby=%2Bspatial_resolution`).
+  let sortby = sortby.trim();
+  let (sort_key, ordering) = match sortby.strip_prefix('-') {
+    Some(key) => (key, SortOrder::Desc),
+    None => (sortby.strip_prefix('+').unwrap_or(sortby), SortOrder::Asc),
+  };
+  let directed = |o: Ordering| match ordering {
+    SortOrder::Asc => o,
+    SortOrder::Desc => o.reverse(),
+  };
+
+  // hopefully a temporary measure.
+  // ideally we could sort by any field of a Serde Map<String, Value> relatively
+  // dynamically.
+  match sort_key {
+    "spatial_resolution" => images.sort_by(|a, b| directed(
+      a.properties.resolution.avg().partial_cmp(&b.properties.resolution.avg()).unwrap_or(Ordering::Equal)
+    )),
+    "datetime" => images.sort_by(|a, b| match (a.properties.timestamp, b.properties.timestamp) {
+      (Some(x), Some(y)) => directed(x.cmp(&y)),
+      (Some(_), None) => Ordering::Less,
+      (None, Some(_)) => Ordering::Greater,
+      (None, None) => Ordering::Equal,
+    }),
+    "cloud_cover" => images.sort_by(|a, b| match (a.properties.cloud_coverage, b.properties.cloud_coverage) {
+      (Some(x), Some(y)) => directed(x.partial_cmp(&y).unwrap_or(Ordering::Equal)),
+      (Some(_), None) => Ordering::Less,
+      (None, Some(_)) => Ordering::Greater,
+      (None, None) => Ordering::Equal,
+    }),
+    "ndvi_capable" => images.sort_by(|a, b| directed(b.ndvi_capable().cmp(&a.ndvi_capable()))),
+    _ => return Err(ApiError::BadRequest(format!(
+      "Invalid sortby `{}`. sortby supports `spatial_resolution`, `cloud_cover`, `datetime` and `ndvi_capable`. \
+      Please file an issue to request sorting by more fields.", sort_key
+    )))
+  }
+  Ok(())
+}
+
+/// QueryParams are the filters and sort order shared by filtered collections and search.
+/// Paging (`limit` and `offset`) is handled separately by paginate, since the links to
+/// other pages depend on the number of results.
+#[derive(Debug, Default)]
+struct QueryParams {
+  bbox: Option<Vec<f64>>,
+  intersects: Option<String>,
+  contains: Option<String>,
+  contains_mode: Option<String>,
+  datetime: Option<String>,
+  /// a CQL2-JSON filter on item properties (search only).
+  filter: Option<serde::json::Value>,
+  filter_lang: Option<String>,
+  sortby: Option<String>
+}
+
+/// a spatial filter: images must either intersect a geometry, or contain a polygon.
+enum SpatialFilter {
+  Intersects(Geometry<f64>),
+  Contains(Geometry<f64>, ContainsMode)
+}
+
+impl SpatialFilter {
+    fn matches(&self, image: &ImageryFile) -> bool {
+      match self {
+        SpatialFilter::Intersects(geom) => image.boundary.intersects(geom),
+        SpatialFilter::Contains(geom, mode) => image.footprint_contains(geom, *mode),
+      }
+    }
+
+    /// the bounding rectangle of the filter's geometry. Only files whose footprint's bounding rectangle
+    /// intersects it can match.
+    fn bounding_rect(&self) -> Option<Rect<f64>> {
+      match self {
+        SpatialFilter::Intersects(geom) | SpatialFilter::Contains(geom, _) => geom.bounding_rect(),
+      }
+    }
+}
+
+impl QueryParams {
+    /// parses the spatial filter. We only want to do one spatial operation, so only one of
+    /// bbox, intersects or contains can be used.
+    /// if bbox provided, we'll always do an intersects query (instead of contains)
+    fn spatial_filter(&self) -> Result<Option<SpatialFilter>, ApiError> {
+      match (&self.bbox, &self.intersects, &self.contains) {
+        (None, None, None) => Ok(None),
+        (Some(b), None, None) => Ok(Some(SpatialFilter::Intersects(bbox_to_bounds(b.to_vec())?))),
+        (None, Some(wkt), None) => Ok(Some(SpatialFilter::Intersects(query_to_bounds("intersects", wkt)?))),
+        (None, None, Some(wkt)) => {
+          // an image can only contain an area, so other geometry types (e.g. points) are rejected.
+          let geom = query_to_bounds("contains", wkt)?;
+          if catalog::polygons(&geom).is_none() {
+            return Err(ApiError::BadRequest(format!("{} {}", NOT_AN_AREA, WKT_EXAMPLE)));
+          }
+          Ok(Some(SpatialFilter::Contains(geom, parse_contains_mode(self.contains_mode.as_deref())?)))
+        },
+        _ => Err(ApiError::BadRequest("Use only one of: bbox, intersects or contains".into())),
+      }
+    }
+
+    /// parses the CQL2 filter, if there is one. Only the cql2-json filter language is supported.
+    fn property_filter(&self) -> Result<Option<Filter>, ApiError> {
+      let filter = match &self.filter {
+        Some(f) => f,
+        None => return Ok(None),
+      };
+      match self.filter_lang.as_deref() {
+        None if !filter.is_string() => (),
+        Some(lang) if lang == filter::CQL2_JSON => (),
+        lang => return Err(ApiError::BadRequest(format!(
+          "Unsupported filter-lang `{}`. Only cql2-json filters are supported.", lang.unwrap_or("cql2-text")
+        ))),
+      }
+      Filter::from_json(filter).map(Some).map_err(ApiError::BadRequest)
+    }
+}
+
+/// the images in a collection that match a query's spatial filter (all of them, if there isn't one).
+/// The collection's spatial index is used to avoid testing every file.
+/// Images are borrowed from the collection, so that only the images that are returned need to be copied.
+fn collection_candidates<'a>(collection: &'a catalog::ImageryCollection, params: &QueryParams) -> Result<Vec<&'a ImageryFile>, ApiError> {
+  Ok(match params.spatial_filter()? {
+    Some(SpatialFilter::Intersects(geom)) => collection.intersects(&geom),
+    Some(SpatialFilter::Contains(geom, mode)) => collection.contains(&geom, mode),
+    None => collection.all().iter().collect(),
+  })
+}
+
+/// filters images by a query's spatial filter, datetime and property filter, and sorts them by its sortby.
+/// Images from collection_candidates already match the spatial filter; it's applied here too
+/// so that any list of images can be queried.
+fn apply_query<'a>(mut images: Vec<&'a ImageryFile>, params: &QueryParams) -> Result<Vec<&'a ImageryFile>, ApiError> {
+  if let Some(filter) = params.spatial_filter()? {
+    images.retain(|image| filter.matches(image));
+  }
+
+  // filter on a datetime or interval.
+  if let Some(datetime) = &params.datetime {
+    let (start, end) = parse_datetime(datetime)?;
+    images = images.datetime_range(start, end);
+  }
+
+  // filter on item properties.
+  if let Some(filter) = params.property_filter()? {
+    images.retain(|image| filter.matches(image));
+  }
+
+  // handle sorting.
+  if let Some(s) = &params.sortby {
+    sort_images(&mut images, s)?;
+  }
+  Ok(images)
+}
+
+/// parse the `contains_mode` supplied by the client (`strict` or `covers`).
+/// defaults to strict if no mode is supplied.
+fn parse_contains_mode(mode: Option<&str>) -> Result<ContainsMode, ApiError> {
+  match mode.map(|m| m.trim()) {
+    None | Some("strict") => Ok(ContainsMode::Strict),
+    Some("covers") => Ok(ContainsMode::Covers),
+    Some(m) => Err(ApiError::BadRequest(format!(
+      "Invalid contains_mode `{}`. contains_mode must be either strict or covers.", m
+    )))
+  }
+}
+
+/// the start and end of a time range. None is open-ended.
+type DatetimeRange = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// parse a STAC `datetime` into the start and end of a time range.
+/// datetime is either a single RFC3339 datetime (e.g. `2020-01-01T00:00:00Z`), or an interval
+/// of two datetimes separated by a `/` with either end open (`..`), e.g. `../2020-01-01T00:00:00Z`.
+fn parse_datetime(datetime: &str) -> Result<DatetimeRange, ApiError> {
+  let invalid = || ApiError::BadRequest(format!(
+    "Invalid datetime `{}`. Use a single RFC3339 datetime (e.g. datetime=2020-01-01T00:00:00Z) or an interval \
+    with either end open (e.g. datetime=2020-01-01T00:00:00Z/.. or datetime=2020-01-01T00:00:00Z/2020-06-30T23:59:59Z).",
+    datetime
+  ));
+
+  let parse = |dt: &str| match dt.trim() {
+    ".." | "" => Ok(None),
+    dt => DateTime::parse_from_rfc3339(dt).map(|d| Some(d.with_timezone(&Utc))).map_err(|_| invalid()),
+  };
+
+  match datetime.split('/').collect::<Vec<_>>().as_slice() {
+    [instant] if !matches!(instant.trim(), ".." | "") => {
+      let instant = parse(instant)?;
+      Ok((instant, instant))
+    },
+    [start, end] => match (parse(start)?, parse(end)?) {
+      (None, None) => Err(invalid()),
+      (Some(s), Some(e)) if s > e => Err(invalid()),
+      range => Ok(range),
+    },
+    _ => Err(invalid()),
+  }
+}
+
+const WKT_EXAMPLE: &str = "Example of a valid query: ?contains=POLYGON ((30 10, 40 40, 20 40, 10 20, 30 10))";
+
+const NOT_AN_AREA: &str = "`contains` must be a POLYGON, a MULTIPOLYGON, or a GEOMETRYCOLLECTION of polygons.";
+
+/// parse a GeoJSON geometry or WKT supplied in a query param (`param` is the name of the param, for error messages)
+fn query_to_bounds(param: &str, query_str: &str) -> Result<Geometry<f64>, ApiError> {
+  // STAC clients send GeoJSON geometries, so try GeoJSON first.
+  if let Ok(geom) = serde_json::from_str::<geojson::Geometry>(query_str) {
+    return Geometry::try_from(geom.value).map_err(|_| ApiError::BadRequest(format!("Invalid GeoJSON geometry in `{}`", param)));
+  }
+
+  // convert the query into a Geometry.
+  // WKT format is expected.
+  // If any errors occur, respond to the request with a 400 error.
+  let invalid = || ApiError::BadRequest(format!("Invalid WKT in `{}` query param. {}", param, WKT_EXAMPLE));
+  let wkt_geom = Wkt::from_str(query_str).map_err(|_| invalid())?;
+  wkt_geom.try_into().map_err(|_| invalid())
 }
 
 /// STAC API Item endpoint
 /// returns a GeoJSON Feature representing the item.
 /// https://github.com/radiantearth/stac-api-spec/blob/master/stac-spec/item-spec/README.md
-#[get("/collections/<collection_id>/<item_id>")]
+/// This is ranked after other routes under a collection (e.g. /collections/<collection_id>/thumbnail)
+/// so that those aren't treated as item ids.
+#[get("/collections/<collection_id>/<item_id>", rank = 2)]
 pub fn get_collection_item(
   collection_id: String,
   item_id: String,
-  coverage: &State<catalog::Service>
-) -> Option<Json<String>> {
-  let collection = match coverage.collections.get(&collection_id) {
-      Some(c) => c,
-      None => return None, // becomes a 404
-  };
+  coverage: &State<catalog::Service>,
+  cache: &State<CachePolicy>,
+  base_url: BaseUrl,
+) -> Result<Cached<ETagged<Json<String>>>, ApiError> {
+  let collections = coverage.collections();
+  let collection = collections.get(&collection_id).ok_or_else(|| ApiError::collection_not_found(&collection_id))?;
 
-  let item = match collection.get_item(item_id) {
+  let item = match collection.get_item(item_id.to_owned()) {
       Some(i) => i,
-      None => return None, // 404
+      None => return Err(ApiError::NotFound(format!("item {} not found in collection {}", item_id, collection_id))),
+  };
+
+  let json = Json(to_string(&item.to_stac_item(&base_url.0, coverage.band_assets)).unwrap());
+  Ok(Cached(ETagged::json(json), cache.items.to_owned()))
+}
+
+/// Downloads a single band of an item as a GeoTIFF, e.g. /collections/imagery/scene_1/assets/b4
+/// for the 4th band. The band keeps its data type, nodata value and georeferencing.
+/// Bands are extracted from the file on the first request and cached.
+#[get("/collections/<collection_id>/<item_id>/assets/<asset>")]
+pub async fn get_item_band(
+  collection_id: String,
+  item_id: String,
+  asset: &str,
+  coverage: &State<catalog::Service>,
+  bands: &State<BandCache>,
+  cache: &State<CachePolicy>
+) -> Result<Cached<NamedFile>, ApiError> {
+  // look up the file, then release the collections before extracting the band on a blocking thread.
+  let (item, band) = {
+    let collections = coverage.collections();
+    let collection = collections.get(&collection_id).ok_or_else(|| ApiError::collection_not_found(&collection_id))?;
+
+    let item = match collection.get_item(item_id.to_owned()) {
+        Some(i) => i,
+        None => return Err(ApiError::NotFound(format!("item {} not found in collection {}", item_id, collection_id))),
+    };
+
+    // band assets are named b1, b2 etc.
+    let band = match asset.strip_prefix('b').and_then(|n| n.parse::<u16>().ok()) {
+        Some(n) if (1..=item.properties.num_bands).contains(&n) => n,
+        _ => return Err(ApiError::NotFound(format!("item {} has no asset {}", item_id, asset))),
+    };
+
+    (item.clone(), band)
+  };
+
+  let bands = BandCache::clone(bands);
+  let source = item.properties.path.to_owned();
+  let path = match blocking(move || bands.get(&collection_id, &item_id, &item, band as isize)).await {
+    Ok(p) => p,
+    Err(e) => {
+      error!("could not extract band {} from {}: {}", band, source, e);
+      return Err(ApiError::Status(Status::InternalServerError))
+    }
   };
 
-  Some(Json(to_string(&item.to_stac_feature()).unwrap()))
+  match NamedFile::open(&path).await {
+    Ok(f) => Ok(Cached(f, cache.items.to_owned())),
+    Err(_) => Err(ApiError::Status(Status::InternalServerError)),
+  }
+}
+
+/// the STAC Collection for an ImageryCollection, with a thumbnail asset if thumbnails are enabled.
+fn stac_collection(collection: &catalog::ImageryCollection, base_url: &url::Url, thumbnails: &Thumbnails) -> stac::Collection {
+  let mut stac_collection = collection.stac_collection(base_url);
+  if thumbnails.enabled {
+    let thumbnail = stac::CollectionAsset {
+      href: links::thumbnail_url(base_url, &collection.id).to_string(),
+      media_type: String::from("image/png"),
+      roles: vec![String::from("thumbnail")]
+    };
+    stac_collection.assets.insert(String::from("thumbnail"), serde_json::to_value(thumbnail).unwrap());
+  }
+  stac_collection
+}
+
+/// Lists every collection in the catalog, sorted by id. Responds with HTML if the client asks for it (see Format).
+/// https://github.com/radiantearth/stac-api-spec/blob/master/collections/README.md
+#[get("/collections")]
+pub fn get_collections(
+  coverage: &State<catalog::Service>,
+  cache: &State<CachePolicy>,
+  thumbnails: &State<Thumbnails>,
+  base_url: BaseUrl,
+  format: Format,
+) -> Cached<ETagged<Formatted>> {
+  let all_collections = coverage.collections();
+  let mut collections: Vec<&catalog::ImageryCollection> = all_collections.values().collect();
+  collections.sort_by(|a, b| a.id.cmp(&b.id));
+
+  let stac_collections = stac::Collections::new(
+    &base_url.0,
+    collections.into_iter().map(|c| stac_collection(c, &base_url.0, thumbnails)).collect()
+  );
+  Cached(ETagged::formatted(stac_response(&stac_collections, format)), cache.collections.to_owned())
 }
 
 /// Details for a single collection.  The collection that matches `collection_id`
-/// will be represented as a filtered FeatureCollection if an `intersects` or `contains` filter
-/// is supplied; or if no filter supplied, a STAC Collection will be returned.
+/// will be represented as a filtered FeatureCollection if a `bbox`, `intersects` or `contains` filter
+/// is supplied; or if no filter supplied, a STAC Collection (or an HTML page, see Format) will be returned.
 /// example:  /collections/imagery?intersects=POLYGON ((30 10, 40 40, 20 40, 10 20, 30 10))
-#[get("/collections/<collection_id>?<intersects>&<contains>&<sortby>&<limit>")]
+/// example:  /collections/imagery?bbox=-123.5,48.3,-123.2,48.6
+/// Filtered collections are paged with `limit` and `offset`, and link to the next and previous pages.
+/// Results can also be streamed as newline-delimited GeoJSON (see Format and FeatureLines), without paging or links.
+#[allow(clippy::too_many_arguments)]
+#[get("/collections/<collection_id>?<bbox>&<intersects>&<contains>&<contains_mode>&<sortby>&<limit>&<offset>")]
 pub fn get_collection(
   collection_id: String,
+  bbox: Option<&str>,
   intersects: Option<&str>,
   contains: Option<&str>,
+  contains_mode: Option<&str>,
   sortby: Option<&str>,
-  limit: Option<usize>,
+  limit: Option<&str>,
+  offset: Option<&str>,
   coverage: &State<catalog::Service>,
-) -> Result<Option<Json<String>>, BadRequest<String>> {
+  cache: &State<CachePolicy>,
+  thumbnails: &State<Thumbnails>,
+  base_url: BaseUrl,
+  format: Format,
+) -> Result<Cached<ETagged<Formatted>>, ApiError> {
 
-  // find our collection.  If None is returned by collections.get(), we'll return
-  // none too. This will turn into a 404 error.
-  let collection = match coverage.collections.get(&collection_id) {
-      Some(c) => c,
-      None => return Ok(None), // 404
-  };
+  // find our collection, or respond with a 404 error.
+  let collections = coverage.collections();
+  let collection = collections.get(&collection_id).ok_or_else(|| ApiError::collection_not_found(&collection_id))?;
 
   // check if any filters were supplied. If not, return a STAC collection.
-  if intersects.is_none() && contains.is_none() {
-      let stac_collection = &collection.stac_collection(&coverage.base_url);
-      return Ok(Some(Json(to_string(stac_collection).unwrap())));
+  if bbox.is_none() && intersects.is_none() && contains.is_none() {
+      let stac_collection = stac_collection(collection, &base_url.0, thumbnails);
+      return Ok(Cached(ETagged::formatted(stac_response(&stac_collection, format)), cache.collections.to_owned()));
   };
 
-  if intersects.is_some() && contains.is_some() {
-    return Err(BadRequest(Some("Use either intersects or contains, not both".into())))
+  let params = QueryParams {
+    bbox: bbox.map(parse_bbox).transpose()?,
+    intersects: intersects.map(String::from),
+    contains: contains.map(String::from),
+    contains_mode: contains_mode.map(String::from),
+    sortby: sortby.map(String::from),
+    ..Default::default()
+  };
+  // links to other pages repeat this request's query.
+  let mut href = links::collection_url(&base_url.0, &collection.id);
+  {
+    let mut query = href.query_pairs_mut();
+    let params = [
+      ("bbox", bbox), ("intersects", intersects), ("contains", contains), ("contains_mode", contains_mode), ("sortby", sortby)
+    ];
+    for (key, value) in params.iter() {
+      if let Some(v) = value {
+        query.append_pair(key, v);
+      }
+    }
   }
+  collection_page(&collections, collection, params, limit, offset, format, href, coverage, cache, &base_url.0)
+}
 
-  let mut filtered_images: Vec<ImageryFile> = Vec::new();
+/// The items in a collection as a paged FeatureCollection (as in OGC API Features), optionally filtered by
+/// `bbox` and `datetime`. Pages are `limit` items long, starting at `offset`, and link to the next and previous
+/// pages. Results can also be streamed as newline-delimited GeoJSON (see Format and FeatureLines), without paging or links.
+/// example: /collections/imagery/items?bbox=-123.5,48.3,-123.2,48.6&datetime=2021-01-01T00:00:00Z/..&limit=10
+#[allow(clippy::too_many_arguments)]
+#[get("/collections/<collection_id>/items?<bbox>&<datetime>&<limit>&<offset>")]
+pub fn get_collection_items(
+  collection_id: String,
+  bbox: Option<&str>,
+  datetime: Option<&str>,
+  limit: Option<&str>,
+  offset: Option<&str>,
+  coverage: &State<catalog::Service>,
+  cache: &State<CachePolicy>,
+  base_url: BaseUrl,
+  format: Format,
+) -> Result<Cached<ETagged<Formatted>>, ApiError> {
+  let collections = coverage.collections();
+  let collection = collections.get(&collection_id).ok_or_else(|| ApiError::collection_not_found(&collection_id))?;
 
-  // filter on possible intersects value
-  match intersects {
-    Some(wkt) => {
-      let bounds = query_to_bounds(wkt)?;
-      filtered_images = collection.intersects(&bounds);
-    },
-    None => (),
+  let params = QueryParams {
+    bbox: bbox.map(parse_bbox).transpose()?,
+    datetime: datetime.map(String::from),
+    ..Default::default()
   };
+  // links to other pages repeat this request's query.
+  let mut href = links::collection_items_url(&base_url.0, &collection.id);
+  {
+    let mut query = href.query_pairs_mut();
+    for (key, value) in [("bbox", bbox), ("datetime", datetime)].iter() {
+      if let Some(v) = value {
+        query.append_pair(key, v);
+      }
+    }
+  }
+  collection_page(&collections, collection, params, limit, offset, format, href, coverage, cache, &base_url.0)
+}
 
-  // filter on possible contains value
-  match contains {
-    Some(wkt) => {
-      let bounds = Polygon::try_from(query_to_bounds(wkt)?).unwrap();
-      filtered_images = collection.contains(&bounds);
-    },
-    None => (),
+/// the items in `collection` that match a query (used by get_collection and get_collection_items): a page of
+/// `limit` items starting at `offset`, with links to the pages before and after it (`href` with a limit and offset
+/// added), or every item streamed as newline-delimited GeoJSON if that's the format asked for.
+#[allow(clippy::too_many_arguments)]
+fn collection_page(
+  collections: &Arc<catalog::Catalog>,
+  collection: &catalog::ImageryCollection,
+  params: QueryParams,
+  limit: Option<&str>,
+  offset: Option<&str>,
+  format: Format,
+  href: url::Url,
+  coverage: &catalog::Service,
+  cache: &CachePolicy,
+  base_url: &url::Url,
+) -> Result<Cached<ETagged<Formatted>>, ApiError> {
+  let lim = request_limit(limit, format, coverage.max_features)?;
+  let offset = match offset {
+    Some(o) => parse_offset(o)?,
+    None => 0,
   };
+  if format == Format::Lines {
+    // the query is checked before the response starts, and the items are filtered as it's sent.
+    apply_query(Vec::new(), &params)?;
+    let collection_id = collection.id.to_owned();
+    let lines = FeatureLines::new(collections.clone(), None, coverage.band_assets_url(base_url), move |catalog| {
+      let images = catalog.get(&collection_id)
+        .and_then(|c| collection_candidates(c, &params).and_then(|images| apply_query(images, &params)).ok());
+      paginate(images.unwrap_or_default(), offset, lim).images
+    });
+    return Ok(Cached(ETagged::formatted(Formatted::Lines(lines)), cache.search.to_owned()));
+  }
 
-  // handle sorting.
-  // currently only "spatial_resolution" is supported.
-  match sortby {
-    Some(s) => {
-      let mut sort_key = s.trim();
-      let mut ordering = SortOrder::Asc;
-
-      // sort by ascending
-      // note: Rocket parses + as whitespace.
-      // however, since + (ascending) is the default, that behavior doesn't seem to affect our
-      // ability to sort. This code path will only be triggered using `sortby=%2Bspatial_resolution`
-      match s.strip_prefix("+") {
-        Some(v) => {
-          sort_key = v;
-        },
-        None => (),
-      }
+  let images = apply_query(collection_candidates(collection, &params)?, &params)?;
+  let page = paginate(images, offset, lim);
 
-      // sort by descending
-      match s.strip_prefix("-") {
-        Some(v) => {
-          ordering = SortOrder::Desc;
-          sort_key = v;
-        },
-        None => (),
-      }
+  let page_links = page_links(&page, |rel, offset| {
+    let mut href = href.clone();
+    href.query_pairs_mut()
+      .append_pair("limit", &lim.to_string())
+      .append_pair("offset", &offset.to_string());
+    links::page_link(rel, href)
+  });
 
-      // hopefully a temporary measure.
-      // ideally we could sort by any field of a Serde Map<String, Value> relatively
-      // dynamically.
-      if sort_key == "spatial_resolution" {
-        let cmp = match ordering {
-            SortOrder::Asc => |a: &ImageryFile, b: &ImageryFile| a.properties.resolution.avg().partial_cmp(&b.properties.resolution.avg()).unwrap(),
-            SortOrder::Desc => |a: &ImageryFile, b: &ImageryFile| b.properties.resolution.avg().partial_cmp(&a.properties.resolution.avg()).unwrap(),
-        } ;
-        filtered_images.sort_by(cmp)
-      }
-      else {
-        return Err(BadRequest(Some(
-              "sortby currently only supports `sortby=spatial_resolution`. \
-              Please file an issue to request sorting by more fields.".into()
-            )))
-      }     
-    },
-    None => (),
+  let items = page.images.as_feature_collection_page(page_links, page.context, coverage.band_assets_url(base_url));
+  Ok(Cached(ETagged::formatted(Formatted::Json(Json(to_string(&items).unwrap()))), cache.search.to_owned()))
+}
+
+/// The footprints of every item in a collection as a single GeoJSON FeatureCollection, for drawing
+/// a coverage overview. Each feature only has an id and datetime.
+/// This is not paginated and can be large for big collections. Use the same `bbox`, `intersects` or `contains`
+/// filters as get_collection to only return footprints in an area, and `simplify` (a tolerance in degrees)
+/// to reduce the size of each footprint.
+/// example: /collections/imagery/footprints?bbox=-123.5,48.3,-123.2,48.6&simplify=0.001
+#[allow(clippy::too_many_arguments)]
+#[get("/collections/<collection_id>/footprints?<bbox>&<intersects>&<contains>&<contains_mode>&<simplify>")]
+pub fn get_collection_footprints(
+  collection_id: String,
+  bbox: Option<&str>,
+  intersects: Option<&str>,
+  contains: Option<&str>,
+  contains_mode: Option<&str>,
+  simplify: Option<f64>,
+  coverage: &State<catalog::Service>,
+  cache: &State<CachePolicy>,
+) -> Result<Cached<Json<String>>, ApiError> {
+  let collections = coverage.collections();
+  let collection = collections.get(&collection_id).ok_or_else(|| ApiError::collection_not_found(&collection_id))?;
+
+  let params = QueryParams {
+    bbox: bbox.map(parse_bbox).transpose()?,
+    intersects: intersects.map(String::from),
+    contains: contains.map(String::from),
+    contains_mode: contains_mode.map(String::from),
+    ..Default::default()
+  };
+  let images = apply_query(collection_candidates(collection, &params)?, &params)?;
+
+  // footprints aren't paged, so requests for more than max_features of them are refused instead of truncated.
+  if images.len() > coverage.max_features {
+    return Err(ApiError::BadRequest(format!(
+      "{} footprints matched, more than the {} features that can be returned at once. Use bbox, intersects or contains to return fewer.",
+      images.len(), coverage.max_features
+    )))
   }
+  let footprints = images.as_footprint_collection(simplify);
+  Ok(Cached(Json(to_string(&footprints).unwrap()), cache.collections.to_owned()))
+}
 
-  match limit {
-    Some(lim) => {
-      filtered_images = filtered_images.into_iter().take(lim).collect::<Vec<_>>();
-    },
-    None => (),
+/// A PNG preview of a collection: a small mosaic of every file in the collection, covering the
+/// collection's extent. The thumbnail is rendered on the first request and kept in memory until the
+/// collection is rescanned.
+/// Only available if the service was started with `--collection-thumbnails`.
+#[get("/collections/<collection_id>/thumbnail")]
+pub async fn get_collection_thumbnail(
+  collection_id: String,
+  coverage: &State<catalog::Service>,
+  cache: &State<CachePolicy>,
+  thumbnails: &State<Thumbnails>,
+) -> Result<Cached<Custom<Vec<u8>>>, ApiError> {
+  if !thumbnails.enabled {
+    return Err(ApiError::NotFound(String::from("collection thumbnails are not enabled")))
   }
 
-  Ok(Some(Json(to_string(&filtered_images.as_feature_collection()).unwrap())))
+  let (version, files) = {
+    let collections = coverage.collections();
+    let collection = collections.get(&collection_id).ok_or_else(|| ApiError::collection_not_found(&collection_id))?;
+    if let Some(png) = thumbnails.get(&collection_id, collection.version()) {
+      return Ok(Cached(Custom(ContentType::PNG, png), cache.collections.to_owned()))
+    }
+    (collection.version(), collection.all().to_vec())
+  };
+
+  // reading every file in a large collection can be slow, so the thumbnail is rendered on a blocking thread.
+  match blocking(move || thumbnail::render_thumbnail(&files)).await {
+    Ok(png) => {
+      thumbnails.insert(&collection_id, version, png.to_owned());
+      Ok(Cached(Custom(ContentType::PNG, png), cache.collections.to_owned()))
+    },
+    Err(e) => {
+      error!("could not render thumbnail for collection {}: {}", collection_id, e);
+      Err(ApiError::Status(Status::InternalServerError))
+    }
+  }
 }
 
 /// preflight request for the search_all_collections POST endpoint.
@@ -196,169 +1016,837 @@ pub fn search_all_preflight() -> Status {
 }
 
 /// SearchRequest represents the request body schema expected by the search_all_collections endpoint
-#[derive(serde::Deserialize)]
+/// It is serialized into the body of the links to other pages of results.
+#[derive(serde::Deserialize, serde::Serialize)]
 pub struct SearchRequest {
+  /// only search these collections.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  collections: Option<Vec<String>>,
+  /// only return the items with these ids. Other filters are ignored.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  ids: Option<Vec<String>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   bbox: Option<Vec<f64>>,
-  intersects: Option<String>,
+  /// a GeoJSON geometry object (as in the STAC API spec), or a WKT string.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  intersects: Option<serde::json::Value>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   contains: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  contains_mode: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  datetime: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  filter: Option<serde::json::Value>,
+  #[serde(rename = "filter-lang", skip_serializing_if = "Option::is_none")]
+  filter_lang: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   sortby: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   limit: Option<serde::json::Value>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  offset: Option<serde::json::Value>,
+  /// only return these fields of each item (see Fields).
+  #[serde(skip_serializing_if = "Option::is_none")]
+  fields: Option<Fields>,
+}
+
+impl From<&SearchRequest> for QueryParams {
+  fn from(search: &SearchRequest) -> Self {
+    QueryParams {
+      bbox: search.bbox.to_owned(),
+      intersects: search.intersects.as_ref().map(|geom| match geom {
+        serde_json::Value::String(wkt) => wkt.to_owned(),
+        geojson => geojson.to_string(),
+      }),
+      contains: search.contains.to_owned(),
+      contains_mode: search.contains_mode.to_owned(),
+      datetime: search.datetime.to_owned(),
+      filter: search.filter.to_owned(),
+      filter_lang: search.filter_lang.to_owned(),
+      sortby: search.sortby.to_owned()
+    }
+  }
+}
+
+/// the collections to search, sorted by id: the collections named in a search request, or all of them.
+/// Unknown collection ids are ignored, so only the files of the named collections are filtered.
+fn search_collections<'a>(collections: &'a HashMap<String, catalog::ImageryCollection>, ids: Option<&[String]>) -> Vec<&'a catalog::ImageryCollection> {
+  let mut found: Vec<&catalog::ImageryCollection> = match ids {
+    Some(ids) => ids.iter().filter_map(|id| collections.get(id)).collect(),
+    None => collections.values().collect(),
+  };
+  found.sort_by(|a, b| a.id.cmp(&b.id));
+  found.dedup_by(|a, b| a.id == b.id);
+  found
+}
+
+/// the items that match a search, from the collections it names (or all of them).
+fn search_images<'a>(catalog: &'a catalog::Catalog, search: &SearchRequest, query: &QueryParams) -> Result<Vec<&'a ImageryFile>, ApiError> {
+  let collections = search_collections(catalog, search.collections.as_deref());
+  match &search.ids {
+    // items requested by id are returned whatever the other filters are.
+    Some(ids) => Ok(collections.iter()
+      .flat_map(|c| ids.iter().filter_map(move |id| c.get_item(id.to_owned())))
+      .collect()),
+    None => {
+      // use the index of every collection's files to find the files that could match the spatial filter.
+      let images: Vec<&ImageryFile> = match query.spatial_filter()? {
+        Some(filter) => catalog.candidates(filter.bounding_rect())
+          .into_iter()
+          .filter(|f| collections.iter().any(|c| c.id == f.collection_id()))
+          .collect(),
+        None => collections.iter().flat_map(|c| c.all().iter()).collect(),
+      };
+      apply_query(images, query)
+    }
+  }
 }
 
 /// search_all_collections allows searching through every collection in the catalog at once.
-/// note:  much of this code is the same/similar to the collections search, and could be factored out into a
-/// more modular function.
+/// Results are filtered and sorted the same way as filtered collections (see apply_query).
 /// this endpoint works with https://github.com/sat-utils/sat-api-browser
+/// Results can also be streamed as newline-delimited GeoJSON (see Format).
 #[post("/stac/search", data="<params>")]
 pub fn search_all_collections(
   params: serde::json::Json<SearchRequest>,
   coverage: &State<catalog::Service>,
- ) -> Result<Option<Json<String>>, BadRequest<String>> {
+  cache: &State<CachePolicy>,
+  base_url: BaseUrl,
+  format: Format,
+ ) -> Result<Cached<Formatted>, ApiError> {
+
+let query = QueryParams::from(&*params);
+let all_collections = coverage.collections();
+
+// `limit` can be supplied as an integer or a string.
+// github.com/sat-utils/sat-api-browser provides the limit as a string.
+let lim = match &params.limit {
+  Some(v) => match v {
+      // limit supplied as a JSON number.  e.g. `limit: 20`
+      serde_json::Value::Number(n) => parse_limit(&n.to_string(), coverage.max_features)?,
 
-let mut images: Vec<ImageryFile> = Vec::new();
+      // limit supplied as a JSON string.  e.g. `limit: "20"`
+      serde_json::Value::String(s) => parse_limit(s, coverage.max_features)?,
 
-// combine all the collections
-// depending on the performance we could possibly create an index over all the collections on startup.
-for (_, c) in coverage.collections.iter() {
-  images.extend(c.all().to_owned())
+      _ => return Err(ApiError::BadRequest("limit must be a positive integer (e.g. limit: 10)".into()))
+  },
+  // streams aren't paged (see request_limit).
+  None if format == Format::Lines => coverage.max_features,
+  None => DEFAULT_LIMIT.min(coverage.max_features),
+};
+
+// `offset` can also be supplied as an integer or a string.
+let offset = match &params.offset {
+  Some(v) => match v {
+      serde_json::Value::Number(n) => parse_offset(&n.to_string())?,
+      serde_json::Value::String(s) => parse_offset(s)?,
+      _ => return Err(ApiError::BadRequest("offset must be 0 or a positive integer (e.g. offset: 10)".into()))
+  },
+  None => 0,
+};
+if format == Format::Lines {
+  // the query is checked before the response starts, and the items are found as it's sent.
+  apply_query(Vec::new(), &query)?;
+  let search = params.into_inner();
+  let fields = search.fields.to_owned();
+  let lines = FeatureLines::new(all_collections, fields, coverage.band_assets_url(&base_url.0), move |catalog| {
+    paginate(search_images(catalog, &search, &query).unwrap_or_default(), offset, lim).images
+  });
+  return Ok(Cached(Formatted::Lines(lines), cache.search.to_owned()));
 }
 
-// We only want to do one spatial operation. To enforce this,
-// make a vec of bools representing all the possible spatial query params.
-// true becomes 1 when cast to an int, so we can add up all the `trues` to make
-// sure that only one (or none) was provided.
-let spatial_params_mask = vec![
-  params.intersects.is_some() as u8,
-  params.contains.is_some() as u8,
-  params.bbox.is_some() as u8
-];
+let images = search_images(&all_collections, &params, &query)?;
+let page = paginate(images, offset, lim);
+
+// links to other pages repeat this search, with a different offset.
+let page_links = page_links(&page, |rel, offset| {
+  let mut body = serde_json::to_value(&*params).unwrap();
+  body["limit"] = serde_json::Value::from(lim);
+  body["offset"] = serde_json::Value::from(offset);
+  links::search_page_link(&base_url.0, rel, body)
+});
 
-if spatial_params_mask.iter().sum::<u8>() > 1 {
-  return Err(BadRequest(Some("Use only one of: bbox, intersects or contains".into())))
+let collection = page.images.as_feature_collection_page(page_links, page.context, coverage.band_assets_url(&base_url.0));
+let body = match &params.fields {
+  // items are trimmed after they're serialized.
+  Some(fields) => {
+    let mut collection = serde_json::to_value(&collection).unwrap();
+    for item in collection["features"].as_array_mut().into_iter().flatten() {
+      fields.apply(item);
+    }
+    collection.to_string()
+  },
+  None => to_string(&collection).unwrap(),
+};
+Ok(Cached(Formatted::Json(Json(body)), cache.search.to_owned()))
 }
 
-// filter on a bbox.
-// if bbox provided, we'll always do an intersects query (instead of contains)
-match &params.bbox {
-  Some(b) => {
-    let bounds: Polygon<f64> = bbox_to_bounds(b.to_vec())?.try_into().unwrap();
-    images = images.intersects(&bounds);
+/// parse a `rescale` range supplied as a comma separated query param (`rescale=min,max`).
+fn parse_rescale(rescale: &str) -> Result<(f64, f64), ApiError> {
+  let invalid = || ApiError::BadRequest(format!("Invalid rescale `{}`. rescale must be two numbers: rescale=min,max", rescale));
+  let values = rescale.split(',')
+    .map(|v| v.trim().parse::<f64>())
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|_| invalid())?;
+  match values[..] {
+    [min, max] if min < max => Ok((min, max)),
+    _ => Err(invalid()),
   }
-  None => (),
 }
 
+/// parse the `bands` of a tile supplied as a comma separated query param: three bands (`bands=red,green,blue`)
+/// or a single band. Bands are numbered from 1.
+fn parse_bands(bands: &str) -> Result<Vec<isize>, ApiError> {
+  let invalid = || ApiError::BadRequest(format!(
+    "Invalid bands `{}`. bands must be one or three band numbers (starting at 1), e.g. bands=4,3,2", bands
+  ));
+  let values = bands.split(',')
+    .map(|v| v.trim().parse::<isize>())
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|_| invalid())?;
+  match values.len() {
+    1 | 3 if values.iter().all(|b| *b >= 1) => Ok(values),
+    _ => Err(invalid()),
+  }
+}
 
-// filter on possible intersects value
-match &params.intersects {
-  Some(wkt) => {
-    let bounds: Geometry<f64> = query_to_bounds(&wkt)?.try_into().unwrap();
-    images = images.intersects(&bounds);
-  },
-  None => (),
-};
+/// runs GDAL work (e.g. rendering a tile) on a blocking thread, so that it doesn't hold up other requests.
+async fn blocking<T, F>(work: F) -> Result<T, String>
+  where F: FnOnce() -> Result<T, String> + Send + 'static, T: Send + 'static
+{
+  match rocket::tokio::task::spawn_blocking(work).await {
+    Ok(result) => result,
+    Err(e) => Err(format!("did not finish: {}", e)),
+  }
+}
 
-// filter on possible contains value
-match &params.contains {
-  Some(wkt) => {
-    let bounds = query_to_bounds(&wkt)?.try_into().unwrap();
-    images = images.contains_polygon(&bounds);
-  },
-  None => (),
-};
+/// the 404 for a tile address that isn't in the web mercator grid.
+fn tile_not_found(x: u32, y: u32, z: u8) -> ApiError {
+  ApiError::NotFound(format!("tile {}/{}/{} is outside the tile grid", z, x, y))
+}
 
-// handle sorting.
-// currently only "spatial_resolution" is supported.
-match &params.sortby {
-  Some(s) => {
-    let mut sort_key = s.trim();
-    let mut ordering = SortOrder::Asc;
-
-    // sort by ascending
-    match s.strip_prefix("+") {
-      Some(v) => {
-        sort_key = v;
-      },
-      None => (),
-    }
+/// A PNG web mercator tile of a collection at its x/y/z address, for use as an XYZ layer
+/// (e.g. in Leaflet or MapLibre). `y` can have a `.png` extension.
+/// Tiles are 256x256 unless `tileSize` is 512 (for high DPI displays). `buffer` adds that many pixels
+/// around each edge of the tile (e.g. a 256 pixel tile with a buffer of 8 is 272x272).
+/// `rescale=min,max` stretches every band from min-max instead of the file's min/max (e.g. for DEMs), and
+/// single band files are drawn with `colormap` (`gray`, `viridis` or `terrain`; gray by default).
+/// `bands` picks the bands drawn as red, green and blue (e.g. `bands=4,3,2` for a false color composite),
+/// or a single band to draw with the colormap. By default, the first three bands are drawn as RGB.
+/// The tile is rendered from the intersecting file whose resolution best matches the tile's pixels (see best_resolution_match).
+/// Responds with 204 No Content if no file in the collection intersects the tile.
+/// example: /tiles/imagery/10/160/353.png?tileSize=512
+/// example: /tiles/dem/10/160/353.png?rescale=0,2500&colormap=terrain
+/// example: /tiles/imagery/10/160/353.png?bands=4,3,2
+#[allow(non_snake_case, clippy::too_many_arguments)]
+#[get("/tiles/<collection_id>/<z>/<x>/<y>?<tileSize>&<buffer>&<rescale>&<colormap>&<bands>")]
+pub async fn get_tiles(
+  collection_id: String,
+  z: u8,
+  x: u32,
+  y: RasterTileRow,
+  tileSize: Option<usize>,
+  buffer: Option<usize>,
+  rescale: Option<&str>,
+  colormap: Option<&str>,
+  bands: Option<&str>,
+  coverage: &State<catalog::Service>,
+  cache: &State<CachePolicy>,
+) -> Result<Cached<Custom<Vec<u8>>>, ApiError> {
+  let y = y.0;
+  if !transform::valid_tile(x, y, z) {
+    return Err(tile_not_found(x, y, z))
+  }
+  let size = tileSize.unwrap_or(tiles::TILE_SIZE);
+  if !tiles::TILE_SIZES.contains(&size) {
+    return Err(ApiError::BadRequest(format!("tileSize must be one of {:?}", tiles::TILE_SIZES)))
+  }
+  let buffer = buffer.unwrap_or(0);
+  if buffer > tiles::MAX_TILE_BUFFER {
+    return Err(ApiError::BadRequest(format!("buffer can't be more than {} pixels", tiles::MAX_TILE_BUFFER)))
+  }
+  let options = tiles::TileOptions {
+    size,
+    buffer,
+    rescale: rescale.map(parse_rescale).transpose()?,
+    colormap: match colormap {
+      Some(name) => Colormap::parse(name).ok_or_else(|| ApiError::BadRequest(format!(
+        "Invalid colormap `{}`. colormap must be one of gray, viridis or terrain.", name
+      )))?,
+      None => Colormap::Gray,
+    },
+    bands: bands.map(parse_bands).transpose()?
+  };
+  let source = {
+    let collections = coverage.collections();
+    let collection = collections.get(&collection_id).ok_or_else(|| ApiError::collection_not_found(&collection_id))?;
 
-    // sort by descending
-    match s.strip_prefix("-") {
-      Some(v) => {
-        ordering = SortOrder::Desc;
-        sort_key = v;
-      },
-      None => (),
+    // find files that could provide coverage for the tile, and pick the one whose resolution best matches the
+    // tile's pixels (larger tiles have smaller pixels). If none is detailed enough, the most detailed one is used.
+    let bounds: Geometry<f64> = transform::to_bounds(x, y, z).into();
+    let files_for_tile = collection.intersects(&bounds);
+    let resolution = transform::tile_resolution(x, y, z) * tiles::TILE_SIZE as f64 / size as f64;
+    match files_for_tile.best_resolution_match(resolution) {
+      Some(f) => f.clone(),
+      None => return Err(ApiError::Status(Status::NoContent)),
     }
+  };
+  if let Some(band) = options.bands.iter().flatten().find(|b| **b > source.properties.num_bands as isize) {
+    return Err(ApiError::BadRequest(format!(
+      "Invalid band {}. {} has {} bands.", band, source.properties.filename, source.properties.num_bands
+    )))
+  }
 
-    if sort_key == "spatial_resolution" {
-      let cmp = match ordering {
-          SortOrder::Asc => |a: &ImageryFile, b: &ImageryFile| a.properties.resolution.avg().partial_cmp(&b.properties.resolution.avg()).unwrap(),
-          SortOrder::Desc => |a: &ImageryFile, b: &ImageryFile| b.properties.resolution.avg().partial_cmp(&a.properties.resolution.avg()).unwrap(),
-      } ;
-      images.sort_by(cmp)
+  // rendering reads and warps the file with GDAL, so it's done on a blocking thread.
+  let path = source.properties.path.to_owned();
+  match blocking(move || tiles::render_tile(&source, x, y, z, &options)).await {
+    Ok(png) => Ok(Cached(Custom(ContentType::PNG, png), cache.collections.to_owned())),
+    Err(e) => {
+      error!("could not render tile {}/{}/{} from {}: {}", z, x, y, path, e);
+      Err(ApiError::Status(Status::InternalServerError))
     }
-    else {
-      return Err(BadRequest(Some(
-            "sortby currently only supports `sortby=spatial_resolution`. \
-            Please file an issue to request sorting by more fields.".into()
-          )))
-    }     
-  },
-  None => (),
+  }
 }
 
-// try to convert `limit` from a serde::json::Value into an integer (via a string, if necessary).
-// this is here so that we can accept limit as an integer or a string (see the comments below).
-// github.com/sat-utils/sat-api-browser provides the limit as a string.
-match &params.limit {
-  Some(v) => {
-    match v {
-        // limit supplied as a JSON number.  e.g. `limit: 20`
-        serde_json::Value::Number(n) => {
-          match n.as_u64() {
-            Some(lim) => {
-              images = images.into_iter().take(lim as usize).collect::<Vec<_>>();
-            },
-            None => (),
-          }
-        },
+/// A Mapbox Vector Tile of the footprints of a collection's files at its x/y/z address, for use as a vector
+/// tile layer. `y` must have a `.mvt` extension.
+/// Each footprint is clipped to the tile and has the properties `filename`, `spatial_resolution` and `collection`.
+/// Responds with 204 No Content if no file in the collection intersects the tile.
+/// example: /tiles/imagery/10/160/353.mvt
+#[get("/tiles/<collection_id>/<z>/<x>/<y>", rank = 2)]
+pub fn get_vector_tile(
+  collection_id: String,
+  z: u8,
+  x: u32,
+  y: VectorTileRow,
+  coverage: &State<catalog::Service>,
+  cache: &State<CachePolicy>,
+) -> Result<Cached<VectorTile>, ApiError> {
+  let y = y.0;
+  if !transform::valid_tile(x, y, z) {
+    return Err(tile_not_found(x, y, z))
+  }
+  let collections = coverage.collections();
+  let collection = collections.get(&collection_id).ok_or_else(|| ApiError::collection_not_found(&collection_id))?;
+
+  let bounds: Geometry<f64> = transform::to_bounds(x, y, z).into();
+  let tile = tiles::build_tile(&collection.intersects(&bounds), &collection_id, x, y, z);
+  if tile.is_empty() {
+    return Err(ApiError::Status(Status::NoContent))
+  }
+  Ok(Cached(VectorTile(tile), cache.collections.to_owned()))
+}
+
+/// A TileJSON document for a collection's raster tiles (see get_tiles), so that web maps like MapLibre can add
+/// the collection as a raster layer. Its bounds are the collection's spatial extent, and its zoom levels
+/// run from the resolution of the coarsest file to that of the finest (see ImageryCollection::zoom_range).
+/// example: /collections/imagery/tilejson.json
+#[get("/collections/<collection_id>/tilejson.json")]
+pub fn get_collection_tilejson(
+  collection_id: String,
+  coverage: &State<catalog::Service>,
+  cache: &State<CachePolicy>,
+  thumbnails: &State<Thumbnails>,
+  base_url: BaseUrl,
+) -> Result<Cached<Json<String>>, ApiError> {
+  let collections = coverage.collections();
+  let collection = collections.get(&collection_id).ok_or_else(|| ApiError::collection_not_found(&collection_id))?;
+
+  let stac_collection = stac_collection(collection, &base_url.0, thumbnails);
+  let tilejson = tilejson::tilejson(&base_url.0, &stac_collection, collection.zoom_range());
+  Ok(Cached(Json(to_string(&tilejson).unwrap()), cache.collections.to_owned()))
+}
 
-        // limit supplied as a JSON string.  e.g. `limit: "20"`
-        serde_json::Value::String(s) => {
-          match s.parse::<u64>() {
-            Ok(lim) => {
-              images = images.into_iter().take(lim as usize).collect::<Vec<_>>();
-            },
-            Err(_) => (),
+/// An OGC WMTS capabilities document, for clients like QGIS that speak WMTS. Each collection is a layer
+/// in the WebMercatorQuad tile matrix set, covering the collection's spatial extent.
+#[get("/wmts/1.0.0/WMTSCapabilities.xml")]
+pub fn get_wmts_capabilities(
+  coverage: &State<catalog::Service>,
+  cache: &State<CachePolicy>,
+  thumbnails: &State<Thumbnails>,
+  base_url: BaseUrl,
+) -> Cached<Custom<String>> {
+  let all_collections = coverage.collections();
+  let mut collections: Vec<&catalog::ImageryCollection> = all_collections.values().collect();
+  collections.sort_by(|a, b| a.id.cmp(&b.id));
+  let stac_collections: Vec<stac::Collection> = collections.into_iter()
+    .map(|c| stac_collection(c, &base_url.0, thumbnails))
+    .collect();
+
+  let xml = wmts::capabilities(&coverage.title, &coverage.description, &base_url.0, &stac_collections);
+  Cached(Custom(ContentType::XML, xml), cache.collections.to_owned())
+}
+
+/// A WMTS tile (RESTful encoding) of a collection. TileMatrix, TileCol and TileRow are the same as the
+/// z, x and y of the XYZ tile endpoint (see get_tiles).
+/// example: /wmts/tile/imagery/10/160/353.png
+#[get("/wmts/tile/<collection_id>/<z>/<x>/<y>")]
+pub async fn get_wmts_tile(
+  collection_id: String,
+  z: u8,
+  x: u32,
+  y: RasterTileRow,
+  coverage: &State<catalog::Service>,
+  cache: &State<CachePolicy>,
+) -> Result<Cached<Custom<Vec<u8>>>, ApiError> {
+  get_tiles(collection_id, z, x, y, None, None, None, None, None, coverage, cache).await
+}
+
+/// A WMS 1.3.0 endpoint for GIS tools that don't speak WMTS. Each collection is a layer.
+/// Supports `REQUEST=GetCapabilities`, and `REQUEST=GetMap` with `LAYERS`, `CRS` (`SRS` in WMS 1.1.1), `BBOX`,
+/// `WIDTH` and `HEIGHT`. Maps are PNGs, rendered from the files of each layer that intersect the map's extent.
+/// Maps that would be drawn from more than max_features files are refused.
+/// Errors are WMS ServiceExceptionReports.
+/// example: /wms?SERVICE=WMS&REQUEST=GetMap&LAYERS=imagery&CRS=EPSG:3857&BBOX=-13775786,6183449,-13736651,6222585&WIDTH=256&HEIGHT=256
+#[get("/wms")]
+pub async fn get_wms(
+  params: WmsParams,
+  coverage: &State<catalog::Service>,
+  cache: &State<CachePolicy>,
+  thumbnails: &State<Thumbnails>,
+  base_url: BaseUrl,
+) -> Result<Cached<Custom<Vec<u8>>>, WmsError> {
+  match params.get("service") {
+    Some(s) if !s.eq_ignore_ascii_case("WMS") => return Err(WmsError::new(None, format!("unsupported SERVICE {}", s))),
+    _ => (),
+  }
+  match params.get("request") {
+    Some(r) if r.eq_ignore_ascii_case("GetCapabilities") => {
+      let collections = coverage.collections();
+      let mut layers: Vec<&catalog::ImageryCollection> = collections.values().collect();
+      layers.sort_by(|a, b| a.id.cmp(&b.id));
+      let stac_collections: Vec<stac::Collection> = layers.into_iter()
+        .map(|c| stac_collection(c, &base_url.0, thumbnails))
+        .collect();
+      let xml = wms::capabilities(&coverage.title, &coverage.description, &base_url.0, &stac_collections);
+      Ok(Cached(Custom(ContentType::XML, xml.into_bytes()), cache.collections.to_owned()))
+    },
+    Some(r) if r.eq_ignore_ascii_case("GetMap") => {
+      let map = GetMap::parse(&params)?;
+      let footprint = map.footprint()?;
+      let files: Vec<ImageryFile> = {
+        let collections = coverage.collections();
+        let mut files: Vec<&ImageryFile> = Vec::new();
+        for layer in &map.layers {
+          let collection = collections.get(layer)
+            .ok_or_else(|| WmsError::new(Some("LayerNotDefined"), format!("layer {} not found", layer)))?;
+          files.extend(collection.intersects(&footprint));
         }
-        },
-        _ => ()
-    };
-    
-  },
-  None => (),
+        // every file on the map is opened and warped, so large maps of many files are refused.
+        if files.len() > coverage.max_features {
+          return Err(WmsError::new(None, format!(
+            "the map covers {} files, more than the {} that can be drawn at once. Use a smaller BBOX.",
+            files.len(), coverage.max_features
+          )))
+        }
+        files.into_iter().cloned().collect()
+      };
+
+      // rendering reads and warps the files with GDAL, so it's done on a blocking thread.
+      let layers = map.layers.join(",");
+      match blocking(move || map.render(files)).await {
+        Ok(png) => Ok(Cached(Custom(ContentType::PNG, png), cache.collections.to_owned())),
+        Err(e) => {
+          error!("could not render WMS map of {}: {}", layers, e);
+          Err(WmsError::new(None, "the map could not be rendered"))
+        }
+      }
+    },
+    Some(r) => Err(WmsError::new(Some("OperationNotSupported"), format!("unsupported REQUEST {}", r))),
+    None => Err(WmsError::new(Some("MissingParameterValue"), "a REQUEST (GetCapabilities or GetMap) is required")),
+  }
+}
+
+/// Rescans the catalog (the same way it's scanned when the service starts) and replaces every collection.
+/// Requests are served from the old collections until the scan is done. Responds with the id and
+/// number of items of each rebuilt collection.
+/// If the scan fails, the old collections are kept: the response is a 503 if S3 is unavailable
+/// (its circuit breaker is open), or a 502 if the source couldn't be read.
+/// Only available if the service was started with an `--admin-token` (see AdminToken).
+#[post("/admin/reload")]
+pub async fn admin_reload(admin: AdminToken<'_>, coverage: &State<catalog::Service>) -> Result<Json<String>, ApiError> {
+  let reload = admin.0;
+  let collections = match reload.source.scan(&reload.options, &coverage.s3_breaker).await {
+    Ok(collections) => collections,
+    Err(e) => {
+      error!("could not reload the catalog: {}", e);
+      return Err(match e {
+        catalog::ScanError::Unavailable => ApiError::Status(Status::ServiceUnavailable),
+        catalog::ScanError::Failed(_) => ApiError::Status(Status::BadGateway),
+      })
+    },
+  };
+
+  let mut rebuilt: Vec<serde_json::Value> = collections.values()
+    .map(|c| serde_json::json!({ "id": c.id, "items": c.all().len() }))
+    .collect();
+  rebuilt.sort_by(|a, b| a["id"].as_str().cmp(&b["id"].as_str()));
+
+  *coverage.collections.write().unwrap() = Arc::new(collections.into());
+  Ok(Json(to_string(&serde_json::json!({ "collections": rebuilt })).unwrap()))
+}
+
+/// the conformance classes that the API implements. These are the same classes listed on the landing page.
+#[get("/conformance")]
+pub fn get_conformance(cache: &State<CachePolicy>) -> Cached<Json<String>> {
+  let conformance = stac::Conformance { conforms_to: stac::conforms_to() };
+  Cached(Json(to_string(&conformance).unwrap()), cache.collections.to_owned())
 }
 
-Ok(Some(Json(to_string(&images.as_feature_collection()).unwrap())))
+/// the properties that items can be filtered by, as a JSON Schema. Every collection has the same queryables.
+#[get("/queryables")]
+pub fn get_queryables(cache: &State<CachePolicy>, base_url: BaseUrl) -> Cached<Custom<String>> {
+  let schema = stac::queryables(&links::queryables_url(&base_url.0));
+  Cached(Custom(ContentType::new("application", "schema+json"), to_string(&schema).unwrap()), cache.collections.to_owned())
 }
 
-/// returns a tile from a collection item covering the tile defined by its x/y/z address.
-/// work in progress, will probably be removed.
-#[get("/tiles/<collection_id>/<z>/<x>/<y>")]
-pub fn get_tiles(collection_id: String, z: u8, x:u32, y:u32, coverage: &State<catalog::Service>) -> String {
-  let bounds: Geometry<f64> = transform::to_bounds(x, y, z).try_into().unwrap();
-  let collection = coverage.collections.get(&collection_id).unwrap();
-  
-  // currently this just returns files that could provide coverage for the tile.
-  let files_for_tile = collection.all().intersects(&bounds);
+/// the properties that a collection's items can be filtered by, as a JSON Schema.
+#[get("/collections/<collection_id>/queryables")]
+pub fn get_collection_queryables(
+  collection_id: String,
+  coverage: &State<catalog::Service>,
+  cache: &State<CachePolicy>,
+  base_url: BaseUrl,
+) -> Result<Cached<Custom<String>>, ApiError> {
+  let collections = coverage.collections();
+  let collection = collections.get(&collection_id).ok_or_else(|| ApiError::collection_not_found(&collection_id))?;
+  let schema = stac::queryables(&links::collection_queryables_url(&base_url.0, &collection.id));
+  Ok(Cached(Custom(ContentType::new("application", "schema+json"), to_string(&schema).unwrap()), cache.collections.to_owned()))
+}
 
-  // stand-in for an actual tile
-  format!("{} {} {} :\n {:?} :\n {:?}", z, x, y, bounds, files_for_tile)
+/// the service's metrics (requests per route, search latency, the size of the catalog, GDAL open failures
+/// and the state of the S3 circuit breaker), in the Prometheus text format.
+#[get("/metrics")]
+pub fn get_metrics(coverage: &State<catalog::Service>, metrics: &State<Metrics>) -> Custom<String> {
+  let collections = coverage.collections();
+  let items = collections.values().map(|c| c.all().len()).sum();
+  let content_type = ContentType::with_params("text", "plain", ("version", "0.0.4"));
+  Custom(content_type, metrics.render(collections.len(), items, coverage.s3_breaker.state()))
 }
 
-/// STAC API landing page
+/// an OpenAPI 3.0 description of the API, linked from the landing page as `service-desc`.
+#[get("/api")]
+pub fn get_api(coverage: &State<catalog::Service>, cache: &State<CachePolicy>, base_url: BaseUrl) -> Cached<Custom<String>> {
+  let api = openapi::openapi(&coverage.title, &coverage.description, &base_url.0);
+  let content_type = ContentType::with_params("application", "vnd.oai.openapi+json", ("version", "3.0"));
+  Cached(Custom(content_type, to_string(&api).unwrap()), cache.collections.to_owned())
+}
+
+/// STAC API landing page, as JSON or as HTML (see Format).
 /// based on https://github.com/radiantearth/stac-api-spec/blob/master/overview.md#example-landing-page
 #[get("/")]
-pub fn landing(coverage: &State<catalog::Service>) -> Json<String> {
-  Json(to_string(&coverage.stac_landing()).unwrap())
+pub fn landing(coverage: &State<catalog::Service>, cache: &State<CachePolicy>, base_url: BaseUrl, format: Format) -> Cached<Formatted> {
+  Cached(stac_response(&coverage.stac_landing(&base_url.0), format), cache.collections.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+  use crate::catalog::ContainsMode;
+  use chrono::{TimeZone, Utc};
+  use geo::{polygon, Geometry, MultiPolygon, Polygon};
+  use serde_json::json;
+  use crate::catalog::ImageryFile;
+  use crate::catalog::test_utils::{imagery_collection, imagery_file};
+  use crate::stac;
+  use crate::handlers::{
+    apply_query, bbox_to_bounds, collection_candidates, etag_matches, paginate, parse_bbox, search_collections, parse_contains_mode, parse_datetime, parse_limit, parse_offset,
+    sort_images, ApiError, QueryParams, NOT_AN_AREA, WKT_EXAMPLE
+  };
+
+  #[test]
+  fn test_parse_limit() {
+    assert_eq!(parse_limit("20", 10000), Ok(20));
+    assert_eq!(parse_limit("100000000", 10000), Ok(10000));
+    assert_eq!(parse_limit("20", 5), Ok(5));
+    assert!(parse_limit("0", 10000).is_err());
+    assert!(parse_limit("-5", 10000).is_err());
+    assert!(parse_limit("abc", 10000).is_err());
+  }
+
+  #[test]
+  fn test_etag_matches() {
+    assert!(etag_matches("\"abc\"", "\"abc\""));
+    assert!(etag_matches("\"xyz\", W/\"abc\"", "\"abc\""));
+    assert!(etag_matches("*", "\"abc\""));
+    assert!(!etag_matches("\"xyz\"", "\"abc\""));
+    assert!(!etag_matches("abc", "\"abc\""));
+  }
+
+  #[test]
+  fn test_parse_contains_mode() {
+    assert_eq!(parse_contains_mode(None), Ok(ContainsMode::Strict));
+    assert_eq!(parse_contains_mode(Some("strict")), Ok(ContainsMode::Strict));
+    assert_eq!(parse_contains_mode(Some("covers")), Ok(ContainsMode::Covers));
+    assert!(parse_contains_mode(Some("within")).is_err());
+  }
+
+  #[test]
+  fn test_parse_datetime() {
+    let jan = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
+    let jun = Utc.ymd(2020, 6, 30).and_hms(23, 59, 59);
+
+    assert_eq!(parse_datetime("2020-01-01T00:00:00Z"), Ok((Some(jan), Some(jan))));
+    assert_eq!(parse_datetime("2020-01-01T00:00:00Z/2020-06-30T23:59:59Z"), Ok((Some(jan), Some(jun))));
+    assert_eq!(parse_datetime("../2020-01-01T00:00:00Z"), Ok((None, Some(jan))));
+    assert_eq!(parse_datetime("2020-01-01T00:00:00Z/.."), Ok((Some(jan), None)));
+    assert_eq!(parse_datetime("2020-01-01T08:00:00+08:00/"), Ok((Some(jan), None)));
+
+    assert!(parse_datetime("../..").is_err());
+    assert!(parse_datetime("..").is_err());
+    assert!(parse_datetime("2020-01-01").is_err());
+    assert!(parse_datetime("2020-06-30T23:59:59Z/2020-01-01T00:00:00Z").is_err());
+    assert!(parse_datetime("2020-01-01T00:00:00Z/2020-06-30T23:59:59Z/..").is_err());
+  }
+
+  #[test]
+  fn test_sort_images() {
+    let square = polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)];
+    let mut files = Vec::new();
+    for (i, cloud_cover) in [Some(40.), None, Some(5.), Some(20.)].iter().enumerate() {
+      let mut image = imagery_file(&format!("scene_{}", i), "imagery", square.clone());
+      image.properties.cloud_coverage = *cloud_cover;
+      image.properties.timestamp = Some(Utc.ymd(2021, 1, 1 + i as u32).and_hms(0, 0, 0));
+      image.properties.resolution.x = 10. - i as f64;
+      image.properties.resolution.y = 10. - i as f64;
+      // scene_1 and scene_3 can be used for NDVI.
+      if i % 2 == 1 {
+        image.properties.red_band = Some(3);
+        image.properties.ni_band = Some(4);
+      }
+      files.push(image);
+    }
+    let mut images: Vec<&ImageryFile> = files.iter().collect();
+    let names = |images: &[&ImageryFile]| images.iter().map(|f| f.properties.filename.to_owned()).collect::<Vec<_>>();
+
+    // images without a cloud cover sort last in either direction.
+    sort_images(&mut images, "cloud_cover").unwrap();
+    assert_eq!(names(&images), vec!["scene_2", "scene_3", "scene_0", "scene_1"]);
+    sort_images(&mut images, "-cloud_cover").unwrap();
+    assert_eq!(names(&images), vec!["scene_0", "scene_3", "scene_2", "scene_1"]);
+
+    sort_images(&mut images, "-datetime").unwrap();
+    assert_eq!(names(&images), vec!["scene_3", "scene_2", "scene_1", "scene_0"]);
+    sort_images(&mut images, "+datetime").unwrap();
+    assert_eq!(names(&images), vec!["scene_0", "scene_1", "scene_2", "scene_3"]);
+
+    sort_images(&mut images, "spatial_resolution").unwrap();
+    assert_eq!(names(&images), vec!["scene_3", "scene_2", "scene_1", "scene_0"]);
+
+    // NDVI capable images sort first, keeping their order.
+    sort_images(&mut images, "ndvi_capable").unwrap();
+    assert_eq!(names(&images), vec!["scene_3", "scene_1", "scene_2", "scene_0"]);
+    sort_images(&mut images, "-ndvi_capable").unwrap();
+    assert_eq!(names(&images), vec!["scene_2", "scene_0", "scene_3", "scene_1"]);
+
+    assert!(sort_images(&mut images, "title").is_err());
+  }
+
+  /// three 1 degree images in a row, from west to east, a day apart.
+  fn three_images() -> Vec<ImageryFile> {
+    let mut images = Vec::new();
+    for i in 0..3 {
+      let x = i as f64;
+      let mut image = imagery_file(&format!("scene_{}", i), "imagery", polygon![(x: x, y: 0.), (x: x + 1., y: 0.), (x: x + 1., y: 1.), (x: x, y: 1.)]);
+      image.properties.timestamp = Some(Utc.ymd(2021, 1, 1 + i as u32).and_hms(0, 0, 0));
+      images.push(image);
+    }
+    images
+  }
+
+  /// the filenames of the images that match `params`.
+  fn query_names(images: &[ImageryFile], params: QueryParams) -> Result<Vec<String>, ApiError> {
+    apply_query(images.iter().collect(), &params)
+      .map(|images| images.into_iter().map(|f| f.properties.filename.to_owned()).collect())
+  }
+
+  #[test]
+  fn test_apply_query() {
+    let images = three_images();
+    let names = |params: QueryParams| query_names(&images, params);
+
+    // no filters returns every image.
+    assert_eq!(names(QueryParams::default()), Ok(vec!["scene_0".into(), "scene_1".into(), "scene_2".into()]));
+
+    assert_eq!(
+      names(QueryParams { bbox: Some(vec![0.5, 0.2, 1.5, 0.8]), ..Default::default() }),
+      Ok(vec!["scene_0".into(), "scene_1".into()])
+    );
+    assert_eq!(
+      names(QueryParams { intersects: Some("POINT (2.5 0.5)".into()), ..Default::default() }),
+      Ok(vec!["scene_2".into()])
+    );
+
+    assert_eq!(
+      names(QueryParams { datetime: Some("2021-01-02T00:00:00Z/..".into()), sortby: Some("-datetime".into()), ..Default::default() }),
+      Ok(vec!["scene_2".into(), "scene_1".into()])
+    );
+
+    // only one spatial filter can be used, and invalid params are rejected.
+    assert!(names(QueryParams { bbox: Some(vec![0., 0., 1., 1.]), intersects: Some("POINT (0 0)".into()), ..Default::default() }).is_err());
+    assert!(names(QueryParams { datetime: Some("yesterday".into()), ..Default::default() }).is_err());
+    assert!(names(QueryParams { sortby: Some("title".into()), ..Default::default() }).is_err());
+  }
+
+  #[test]
+  fn test_apply_query_borrows() {
+    let collection = imagery_collection("imagery", three_images());
+
+    // the images that match are references to the collection's own files (even once sorted), and the images
+    // that are filtered out are only ever borrowed, so a query never clones an item.
+    let params = QueryParams { datetime: Some("2021-01-02T00:00:00Z/..".into()), sortby: Some("-datetime".into()), ..Default::default() };
+    let images: Vec<&ImageryFile> = apply_query(collection_candidates(&collection, &params).unwrap(), &params).unwrap();
+    assert_eq!(images.len(), 2);
+    for image in images {
+      let file = collection.all().iter().find(|f| f.properties.filename == image.properties.filename).unwrap();
+      assert!(std::ptr::eq(image, file));
+    }
+  }
+
+  #[test]
+  fn test_apply_query_contains() {
+    let images = three_images();
+    let names = |params: QueryParams| query_names(&images, params);
+
+    // contains only matches images that cover the whole polygon.
+    let contains = "POLYGON ((1.2 0.2, 1.8 0.2, 1.8 0.8, 1.2 0.8, 1.2 0.2))";
+    assert_eq!(names(QueryParams { contains: Some(contains.into()), ..Default::default() }), Ok(vec!["scene_1".into()]));
+    let spanning = "POLYGON ((0.5 0.2, 1.5 0.2, 1.5 0.8, 0.5 0.8, 0.5 0.2))";
+    assert_eq!(names(QueryParams { contains: Some(spanning.into()), ..Default::default() }), Ok(vec![]));
+    let edge = "POLYGON ((1 0, 1 1, 1 0))";
+    assert_eq!(
+      names(QueryParams { contains: Some(edge.into()), contains_mode: Some("covers".into()), ..Default::default() }),
+      Ok(vec!["scene_0".into(), "scene_1".into()])
+    );
+    assert!(names(QueryParams { contains: Some(contains.into()), contains_mode: Some("within".into()), ..Default::default() }).is_err());
+
+    // every polygon of a multi part geometry has to be contained, and geometries that aren't areas are rejected.
+    let multi = "MULTIPOLYGON (((0.2 0.2, 0.8 0.2, 0.8 0.8, 0.2 0.2)), ((1.2 0.2, 1.8 0.2, 1.8 0.8, 1.2 0.2)))";
+    assert_eq!(names(QueryParams { contains: Some(multi.into()), ..Default::default() }), Ok(vec![]));
+    let collection = "GEOMETRYCOLLECTION (POLYGON ((1.2 0.2, 1.8 0.2, 1.8 0.8, 1.2 0.2)), POLYGON ((1.3 0.3, 1.7 0.3, 1.7 0.7, 1.3 0.3)))";
+    assert_eq!(names(QueryParams { contains: Some(collection.into()), ..Default::default() }), Ok(vec!["scene_1".into()]));
+    assert_eq!(
+      names(QueryParams { contains: Some("POINT (1.5 0.5)".into()), ..Default::default() }).unwrap_err(),
+      ApiError::BadRequest(format!("{} {}", NOT_AN_AREA, WKT_EXAMPLE))
+    );
+    assert!(names(QueryParams { contains: Some("GEOMETRYCOLLECTION (POINT (1.5 0.5))".into()), ..Default::default() }).is_err());
+    assert!(names(QueryParams { contains: Some("LINESTRING (1.2 0.2, 1.8 0.8)".into()), ..Default::default() }).is_err());
+  }
+
+  #[test]
+  fn test_apply_query_intersects() {
+    let images = three_images();
+    let names = |params: QueryParams| query_names(&images, params);
+
+    // intersects takes GeoJSON geometries as well as WKT.
+    assert_eq!(
+      names(QueryParams { intersects: Some(r#"{"type": "Point", "coordinates": [2.5, 0.5]}"#.into()), ..Default::default() }),
+      Ok(vec!["scene_2".into()])
+    );
+    assert_eq!(
+      names(QueryParams { intersects: Some("POINT (1.5".into()), ..Default::default() }).unwrap_err(),
+      ApiError::BadRequest(format!("Invalid WKT in `intersects` query param. {}", WKT_EXAMPLE))
+    );
+  }
+
+  #[test]
+  fn test_apply_query_filter() {
+    let images = three_images();
+    let names = |params: QueryParams| query_names(&images, params);
+
+    let filter = json!({"op": "<", "args": [{"property": "datetime"}, {"timestamp": "2021-01-02T00:00:00Z"}]});
+    assert_eq!(
+      names(QueryParams { bbox: Some(vec![0.5, 0.2, 1.5, 0.8]), filter: Some(filter.clone()), ..Default::default() }),
+      Ok(vec!["scene_0".into()])
+    );
+    assert_eq!(
+      names(QueryParams { filter: Some(filter), filter_lang: Some("cql2-json".into()), ..Default::default() }),
+      Ok(vec!["scene_0".into()])
+    );
+
+    // CQL2 text and unsupported operators are rejected.
+    assert!(names(QueryParams { filter: Some(json!("eo:cloud_cover < 10")), ..Default::default() }).is_err());
+    let like = json!({"op": "like", "args": [{"property": "gsd"}, "1%"]});
+    assert_eq!(
+      names(QueryParams { filter: Some(like), ..Default::default() }).unwrap_err(),
+      ApiError::BadRequest("unsupported filter operator `like`. Supported operators are <, <=, =, >, >=, and, or".into())
+    );
+  }
+
+  #[test]
+  fn test_collection_candidates() {
+    // collections are narrowed with their spatial index before the query is applied.
+    let collection = imagery_collection("imagery", three_images());
+    let params = QueryParams { intersects: Some("POINT (2.5 0.5)".into()), ..Default::default() };
+    assert_eq!(collection_candidates(&collection, &params).unwrap().len(), 1);
+    assert_eq!(collection_candidates(&collection, &QueryParams::default()).unwrap().len(), 3);
+  }
+
+  #[test]
+  fn test_search_collections() {
+    let mut collections = HashMap::new();
+    for id in &["roads", "imagery", "lidar"] {
+      collections.insert(id.to_string(), imagery_collection(id, vec![]));
+    }
+    let ids = |names: Option<&[String]>| search_collections(&collections, names).into_iter().map(|c| c.id.to_owned()).collect::<Vec<_>>();
+
+    assert_eq!(ids(None), vec!["imagery", "lidar", "roads"]);
+    // only named collections are searched, once each; unknown ones are ignored.
+    let names = vec!["roads".to_string(), "missing".to_string(), "imagery".to_string(), "roads".to_string()];
+    assert_eq!(ids(Some(&names)), vec!["imagery", "roads"]);
+    assert!(ids(Some(&[])).is_empty());
+  }
+
+  #[test]
+  fn test_paginate() {
+    let square = polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)];
+    let images = (0..25).map(|i| imagery_file(&format!("scene_{}", i), "imagery", square.clone())).collect::<Vec<_>>();
+
+    let first = paginate(images.clone(), 0, 10);
+    assert_eq!(first.images.len(), 10);
+    assert_eq!((first.prev, first.next), (None, Some(10)));
+    assert_eq!(first.context, stac::Context { returned: 10, limit: 10, matched: 25 });
+
+    let middle = paginate(images.clone(), 10, 10);
+    assert_eq!(middle.images[0].properties.filename, "scene_10");
+    assert_eq!((middle.prev, middle.next), (Some(0), Some(20)));
+
+    let last = paginate(images.clone(), 20, 10);
+    assert_eq!(last.images.len(), 5);
+    assert_eq!((last.prev, last.next), (Some(10), None));
+    assert_eq!(last.context, stac::Context { returned: 5, limit: 10, matched: 25 });
+
+    // offsets that aren't a multiple of the limit page back to the start.
+    let odd = paginate(images, 5, 10);
+    assert_eq!((odd.prev, odd.next), (Some(0), Some(15)));
+
+    assert_eq!(parse_offset("20"), Ok(20));
+    assert!(parse_offset("-1").is_err());
+    assert!(parse_offset("abc").is_err());
+  }
+
+  #[test]
+  fn test_bbox_to_bounds() {
+    let square: Geometry<f64> = polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)].into();
+    assert_eq!(bbox_to_bounds(vec![0., 0., 1., 1.]), Ok(square.clone()));
+
+    // elevations in 6 number bboxes are ignored.
+    assert_eq!(bbox_to_bounds(vec![0., 0., -100., 1., 1., 2500.]), Ok(square));
+
+    // bboxes crossing the antimeridian are split.
+    let west: Polygon<f64> = polygon![(x: 170., y: 0.), (x: 180., y: 0.), (x: 180., y: 1.), (x: 170., y: 1.)];
+    let east: Polygon<f64> = polygon![(x: -180., y: 0.), (x: -170., y: 0.), (x: -170., y: 1.), (x: -180., y: 1.)];
+    assert_eq!(bbox_to_bounds(vec![170., 0., -170., 1.]), Ok(MultiPolygon(vec![west, east]).into()));
+
+    for invalid in &[vec![0., 0., 1.], vec![0., 0., 1., 1., 1.], vec![1., 0., 1., 1.], vec![0., 1., 0., 1., 0., 1.]] {
+      assert!(bbox_to_bounds(invalid.clone()).is_err(), "{:?}", invalid);
+    }
+  }
+
+  #[test]
+  fn test_parse_bbox() {
+    assert_eq!(parse_bbox("-123.5, 48.3,-123.2,48.6"), Ok(vec![-123.5, 48.3, -123.2, 48.6]));
+    assert!(parse_bbox("-123.5,48.3,abc,48.6").is_err());
+  }
 }
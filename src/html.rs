@@ -0,0 +1,90 @@
+use serde_json::Value;
+
+/// a minimal HTML page for a STAC object (e.g. the landing page, the list of collections, or a collection),
+/// so that the API can be browsed in a web browser. The page has the object's title and description, a list
+/// of its collections (if it has any), and its links. Links that have to be followed with POST are left out.
+pub fn page(object: &Value) -> String {
+  let text = |key: &str| object.get(key).and_then(|v| v.as_str());
+  let title = text("title").or_else(|| text("id")).unwrap_or("STAC API");
+
+  let mut body = format!("<h1>{}</h1>\n", escape(title));
+  if let Some(description) = text("description") {
+    body.push_str(&format!("<p>{}</p>\n", escape(description)));
+  }
+
+  if let Some(collections) = object.get("collections").and_then(|c| c.as_array()) {
+    body.push_str("<h2>Collections</h2>\n<ul>\n");
+    for collection in collections {
+      let id = collection.get("id").and_then(|v| v.as_str()).unwrap_or_default();
+      let title = collection.get("title").and_then(|v| v.as_str()).unwrap_or(id);
+      match link_href(collection, "self") {
+        Some(href) => body.push_str(&format!("<li><a href=\"{}\">{}</a></li>\n", escape(href), escape(title))),
+        None => body.push_str(&format!("<li>{}</li>\n", escape(title))),
+      }
+    }
+    body.push_str("</ul>\n");
+  }
+
+  if let Some(links) = object.get("links").and_then(|l| l.as_array()) {
+    body.push_str("<h2>Links</h2>\n<ul>\n");
+    for link in links.iter().filter(|l| l.get("method").and_then(|m| m.as_str()).unwrap_or("GET") == "GET") {
+      let rel = link.get("rel").and_then(|v| v.as_str()).unwrap_or_default();
+      let href = link.get("href").and_then(|v| v.as_str()).unwrap_or_default();
+      body.push_str(&format!("<li>{}: <a href=\"{}\">{}</a></li>\n", escape(rel), escape(href), escape(href)));
+    }
+    body.push_str("</ul>\n");
+  }
+
+  format!(
+    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+    escape(title),
+    body
+  )
+}
+
+/// the href of an object's first link with the relation `rel`.
+fn link_href<'a>(object: &'a Value, rel: &str) -> Option<&'a str> {
+  object.get("links")?.as_array()?.iter()
+    .find(|l| l.get("rel").and_then(|r| r.as_str()) == Some(rel))?
+    .get("href")?
+    .as_str()
+}
+
+/// escapes text for use in HTML (or XML) content and attribute values.
+pub fn escape(s: &str) -> String {
+  s.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+  use crate::html::{escape, page};
+
+  #[test]
+  fn test_escape() {
+    assert_eq!(escape("Roads & <Rivers>"), "Roads &amp; &lt;Rivers&gt;");
+    assert_eq!(escape(r#"the "best" imagery's"#), "the &quot;best&quot; imagery&apos;s");
+  }
+
+  #[test]
+  fn test_page() {
+    let html = page(&json!({
+      "title": "Imagery <catalog>",
+      "description": "Aerial photos",
+      "collections": [{"id": "air", "title": "Air photos", "links": [{"rel": "self", "href": "http://localhost/collections/air"}]}],
+      "links": [
+        {"rel": "root", "href": "http://localhost/"},
+        {"rel": "search", "href": "http://localhost/stac/search", "method": "POST"}
+      ]
+    }));
+    assert!(html.contains("<title>Imagery &lt;catalog&gt;</title>"));
+    assert!(html.contains("<p>Aerial photos</p>"));
+    assert!(html.contains("<li><a href=\"http://localhost/collections/air\">Air photos</a></li>"));
+    assert!(html.contains("root: <a href=\"http://localhost/\">"));
+    assert!(!html.contains("stac/search"));
+  }
+}
@@ -0,0 +1,251 @@
+use serde_json::Value;
+use url::Url;
+use crate::openapi::OPENAPI_MEDIA_TYPE;
+use crate::stac::{StacLink, StacRel};
+
+static JSON: &str = "application/json";
+static GEOJSON: &str = "application/geo+json";
+
+/// join path segments onto the service's base URL. All link hrefs are built here so that
+/// every link is absolute and matches the routes mounted in main.rs.
+///
+/// The base URL is always treated as a directory, whether or not it has a trailing slash
+/// (e.g. `http://example.com/stac` and `http://example.com/stac/` both produce
+/// `http://example.com/stac/collections/...`). Segments are percent-encoded, so ids containing
+/// characters like spaces or `#` still resolve.
+pub fn url(base_url: &Url, segments: &[&str]) -> Url {
+  let mut url = base_url.clone();
+  url.set_query(None);
+  url.set_fragment(None);
+  url.path_segments_mut()
+    .expect("base_url must be an absolute http(s) URL")
+    .pop_if_empty()
+    .extend(segments);
+  url
+}
+
+/// the URL of the list of collections, `/collections`.
+pub fn collections_url(base_url: &Url) -> Url {
+  url(base_url, &["collections"])
+}
+
+/// the URL of a collection, e.g. `/collections/imagery`.
+pub fn collection_url(base_url: &Url, collection_id: &str) -> Url {
+  url(base_url, &["collections", collection_id])
+}
+
+/// the URL of a collection's paged items, e.g. `/collections/imagery/items`.
+pub fn collection_items_url(base_url: &Url, collection_id: &str) -> Url {
+  url(base_url, &["collections", collection_id, "items"])
+}
+
+/// the URL of an item, e.g. `/collections/imagery/scene_1`.
+pub fn item_url(base_url: &Url, collection_id: &str, item_id: &str) -> Url {
+  url(base_url, &["collections", collection_id, item_id])
+}
+
+/// the URL of a single band of an item, e.g. `/collections/imagery/scene_1/assets/b4`.
+pub fn band_url(base_url: &Url, collection_id: &str, item_id: &str, band: u16) -> Url {
+  url(base_url, &["collections", collection_id, item_id, "assets", &format!("b{}", band)])
+}
+
+/// the URL of the queryables of every collection, `/queryables`.
+pub fn queryables_url(base_url: &Url) -> Url {
+  url(base_url, &["queryables"])
+}
+
+/// the URL of a collection's queryables, e.g. `/collections/imagery/queryables`.
+pub fn collection_queryables_url(base_url: &Url, collection_id: &str) -> Url {
+  url(base_url, &["collections", collection_id, "queryables"])
+}
+
+/// the URL of a collection's thumbnail, e.g. `/collections/imagery/thumbnail`.
+pub fn thumbnail_url(base_url: &Url, collection_id: &str) -> Url {
+  url(base_url, &["collections", collection_id, "thumbnail"])
+}
+
+/// the URL template of a collection's XYZ raster tiles, e.g. `/tiles/imagery/{z}/{x}/{y}.png`.
+/// The template variables are added after the URL is built, so that their braces aren't percent-encoded.
+pub fn tile_template(base_url: &Url, collection_id: &str) -> String {
+  format!("{}/{{z}}/{{x}}/{{y}}.png", url(base_url, &["tiles", collection_id]))
+}
+
+/// the URL of a collection's TileJSON document, e.g. `/collections/imagery/tilejson.json`.
+pub fn tilejson_url(base_url: &Url, collection_id: &str) -> Url {
+  url(base_url, &["collections", collection_id, "tilejson.json"])
+}
+
+/// the URL of the WMS endpoint, `/wms`.
+pub fn wms_url(base_url: &Url) -> Url {
+  url(base_url, &["wms"])
+}
+
+/// the URL of the WMTS capabilities document, `/wmts/1.0.0/WMTSCapabilities.xml`.
+pub fn wmts_capabilities_url(base_url: &Url) -> Url {
+  url(base_url, &["wmts", "1.0.0", "WMTSCapabilities.xml"])
+}
+
+/// the WMTS ResourceURL template for a collection's tiles, e.g. `/wmts/tile/imagery/{TileMatrix}/{TileCol}/{TileRow}.png`.
+/// The template variables are added after the URL is built, so that their braces aren't percent-encoded.
+pub fn wmts_tile_template(base_url: &Url, collection_id: &str) -> String {
+  format!("{}/{{TileMatrix}}/{{TileCol}}/{{TileRow}}.png", url(base_url, &["wmts", "tile", collection_id]))
+}
+
+fn link(rel: StacRel, media_type: &str, href: Url) -> StacLink {
+  StacLink {
+    rel,
+    media_type: media_type.to_owned(),
+    href: href.to_string(),
+    method: None,
+    body: None,
+    merge: None
+  }
+}
+
+/// a link to the landing page of the service.
+pub fn root_link(base_url: &Url) -> StacLink {
+  link(StacRel::Root, JSON, url(base_url, &[]))
+}
+
+/// the self link of the landing page.
+pub fn root_self_link(base_url: &Url) -> StacLink {
+  link(StacRel::SelfRel, JSON, url(base_url, &[]))
+}
+
+/// a link from the landing page to the list of collections.
+pub fn collections_data_link(base_url: &Url) -> StacLink {
+  link(StacRel::Data, JSON, collections_url(base_url))
+}
+
+/// the self link of the list of collections.
+pub fn collections_self_link(base_url: &Url) -> StacLink {
+  link(StacRel::SelfRel, JSON, collections_url(base_url))
+}
+
+/// a link from the landing page to one of its collections.
+pub fn collection_child_link(base_url: &Url, collection_id: &str) -> StacLink {
+  link(StacRel::Child, JSON, collection_url(base_url, collection_id))
+}
+
+/// the self link of a collection.
+pub fn collection_self_link(base_url: &Url, collection_id: &str) -> StacLink {
+  link(StacRel::SelfRel, JSON, collection_url(base_url, collection_id))
+}
+
+/// a link from a collection to its paged items.
+pub fn collection_items_link(base_url: &Url, collection_id: &str) -> StacLink {
+  link(StacRel::Items, GEOJSON, collection_items_url(base_url, collection_id))
+}
+
+/// a link from a collection to the TileJSON document of its raster tiles.
+pub fn collection_tilejson_link(base_url: &Url, collection_id: &str) -> StacLink {
+  link(StacRel::Tilejson, JSON, tilejson_url(base_url, collection_id))
+}
+
+/// a link from a collection to one of its items.
+pub fn item_link(base_url: &Url, collection_id: &str, item_id: &str) -> StacLink {
+  link(StacRel::Item, GEOJSON, item_url(base_url, collection_id, item_id))
+}
+
+/// the self link of an item.
+pub fn item_self_link(base_url: &Url, collection_id: &str, item_id: &str) -> StacLink {
+  link(StacRel::SelfRel, GEOJSON, item_url(base_url, collection_id, item_id))
+}
+
+/// a link from an item to the collection it belongs to.
+pub fn item_collection_link(base_url: &Url, collection_id: &str) -> StacLink {
+  link(StacRel::Collection, JSON, collection_url(base_url, collection_id))
+}
+
+/// a link to another page of a filtered collection. `href` includes the query for the page.
+pub fn page_link(rel: StacRel, href: Url) -> StacLink {
+  link(rel, GEOJSON, href)
+}
+
+/// a link from the landing page to the OpenAPI description of the API.
+pub fn service_desc_link(base_url: &Url) -> StacLink {
+  link(StacRel::ServiceDesc, OPENAPI_MEDIA_TYPE, url(base_url, &["api"]))
+}
+
+/// a link to the item search endpoint, for the landing page. Search is a POST request.
+pub fn search_link(base_url: &Url) -> StacLink {
+  StacLink {
+    method: Some(String::from("POST")),
+    ..link(StacRel::Search, GEOJSON, url(base_url, &["stac", "search"]))
+  }
+}
+
+/// a link to another page of search results. Search is a POST request, so the
+/// query for the page is sent as the body.
+pub fn search_page_link(base_url: &Url, rel: StacRel, body: Value) -> StacLink {
+  StacLink {
+    method: Some(String::from("POST")),
+    body: Some(body),
+    merge: Some(false),
+    ..link(rel, GEOJSON, url(base_url, &["stac", "search"]))
+  }
+}
+
+/// a link to the parent of a page. The parent of an item is its collection, and
+/// the parent of a collection (`collection_id` of None) is the landing page.
+pub fn parent_link(base_url: &Url, collection_id: Option<&str>) -> StacLink {
+  match collection_id {
+    Some(id) => link(StacRel::Parent, JSON, collection_url(base_url, id)),
+    None => link(StacRel::Parent, JSON, url(base_url, &[])),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use url::Url;
+  use crate::links::{collection_self_link, item_collection_link, item_self_link, parent_link, root_link, search_link, url};
+
+  #[test]
+  fn test_url_join() {
+    // trailing slashes on the base URL don't change the result.
+    for base in &["http://localhost:8000", "http://localhost:8000/"] {
+      let base = Url::parse(base).unwrap();
+      assert_eq!(url(&base, &[]).as_str(), "http://localhost:8000/");
+      assert_eq!(url(&base, &["collections", "imagery"]).as_str(), "http://localhost:8000/collections/imagery");
+    }
+
+    // a base URL with a path keeps it, with or without a trailing slash.
+    for base in &["https://example.com/stac", "https://example.com/stac/"] {
+      let base = Url::parse(base).unwrap();
+      assert_eq!(url(&base, &[]).as_str(), "https://example.com/stac/");
+      assert_eq!(url(&base, &["collections", "imagery"]).as_str(), "https://example.com/stac/collections/imagery");
+    }
+
+    // ids are percent-encoded instead of being interpreted as part of the URL.
+    let base = Url::parse("http://localhost:8000").unwrap();
+    assert_eq!(
+      url(&base, &["collections", "my imagery", "scene#1"]).as_str(),
+      "http://localhost:8000/collections/my%20imagery/scene%231"
+    );
+  }
+
+  #[test]
+  fn test_links_are_absolute() {
+    let base = Url::parse("https://example.com/stac").unwrap();
+    let links = vec![
+      root_link(&base),
+      collection_self_link(&base, "imagery"),
+      item_self_link(&base, "imagery", "scene_1"),
+      item_collection_link(&base, "imagery"),
+      parent_link(&base, Some("imagery")),
+      parent_link(&base, None),
+      search_link(&base),
+    ];
+
+    for l in links {
+      let href = Url::parse(&l.href).unwrap();
+      assert_eq!(href.host_str(), Some("example.com"));
+      assert!(href.path().starts_with("/stac/"));
+    }
+
+    assert_eq!(item_self_link(&base, "imagery", "scene_1").href, "https://example.com/stac/collections/imagery/scene_1");
+    assert_eq!(search_link(&base).href, "https://example.com/stac/stac/search");
+    assert_eq!(search_link(&base).method.as_deref(), Some("POST"));
+    assert_eq!(parent_link(&base, Some("imagery")).href, "https://example.com/stac/collections/imagery");
+  }
+}
@@ -3,14 +3,43 @@ use rocket::{Request, Response};
 use rocket::fairing::{Fairing, Info, Kind};
 use rocket::http::Header;
 use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::process;
+use std::sync::{Arc, RwLock};
 use std::u8;
+use log::{error, LevelFilter};
 use serde::Deserialize;
 use structopt::StructOpt;
 use structopt_toml::StructOptToml;
+mod bands;
+mod breaker;
 mod handlers;
+mod html;
+mod links;
+mod manifest;
 mod transform;
 mod catalog;
+mod collection_config;
+mod fields;
+mod filter;
+mod mapping;
+mod metrics;
+mod openapi;
+mod render;
+mod scan_cache;
+mod sensor;
+mod sidecar;
 mod stac;
+mod selftest;
+mod stacignore;
+mod thumbnail;
+mod tilejson;
+mod tiles;
+mod vsi;
+mod watch;
+mod wms;
+mod wmts;
 
 
 #[derive(Debug, Deserialize, StructOpt, StructOptToml)]
@@ -31,6 +60,41 @@ struct Opt {
     #[structopt(default_value = "./data", long, short = "d", env = "AUTOSTAC_CATALOG_DIR")]
     dir: String,
 
+    /// Catalog files in subdirectories of each collection directory (at any depth), not only the files directly inside it.
+    ///
+    /// Files in subdirectories are identified by their path within the collection, e.g. `2021/08/scene`.
+    #[structopt(long, env = "AUTOSTAC_RECURSIVE")]
+    recursive: bool,
+
+    /// A file to cache the metadata of each catalogued file in, so that restarts only open new and changed files.
+    ///
+    /// Files are read again if their size or modification time (or S3 ETag) changes. The cache is created if it
+    /// doesn't exist, and is ignored if the options that change how files are read (e.g. --property-mapping) change.
+    #[structopt(long, env = "AUTOSTAC_CACHE_FILE")]
+    cache_file: Option<String>,
+
+    /// Watch the catalog directory, and rescan collections when their files are added, changed or removed.
+    ///
+    /// Changes are gathered up until the directory has been quiet for a couple of seconds, so copying in a batch
+    /// of files only rescans each collection once. New directories become new collections.
+    #[structopt(long, env = "AUTOSTAC_WATCH", conflicts_with_all = &["s3", "http-manifest"])]
+    watch: bool,
+
+    /// Enable POST /admin/reload, which rescans the catalog, for requests with this bearer token.
+    ///
+    /// e.g. `curl -X POST -H "Authorization: Bearer $AUTOSTAC_ADMIN_TOKEN" http://localhost:8000/admin/reload`
+    #[structopt(long, env = "AUTOSTAC_ADMIN_TOKEN", hide_env_values = true)]
+    admin_token: Option<String>,
+
+    /// Catalog the files listed in this file instead of a directory: HTTP(S) URLs, one per line, each optionally
+    /// followed by the collection it goes into.
+    ///
+    /// Files are read with GDAL's /vsicurl/ filesystem, and their assets link to the listed URLs. Files without a
+    /// collection go into a collection named after the directory they are in, e.g. `imagery` for
+    /// `https://example.com/imagery/img1.tif`.
+    #[structopt(long, env = "AUTOSTAC_HTTP_MANIFEST", conflicts_with = "s3")]
+    http_manifest: Option<String>,
+
     /// Autostac will catalog from S3.
     /// Warning: uses AWS_S3_ENDPOINT, AWS_S3_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY.
     /// Ensure these values are not set to values you don't want to use.
@@ -50,7 +114,13 @@ struct Opt {
     #[structopt(long, env = "S3_BUCKET")]
     s3_bucket: Option<String>,
 
-    /// S3 access key
+    /// Only catalog part of the S3 bucket: collections are built from the prefixes under this prefix (e.g. `sentinel-2/2021/`).
+    ///
+    /// Collection ids are the prefix names relative to this prefix.
+    #[structopt(long, env = "AUTOSTAC_S3_PREFIX")]
+    s3_prefix: Option<String>,
+
+    /// S3 access key. Public buckets can be catalogued without an access key and secret key.
     #[structopt(long, env = "AWS_ACCESS_KEY_ID")]
     s3_access_key: Option<String>,
 
@@ -58,8 +128,22 @@ struct Opt {
     #[structopt(long, env = "AWS_SECRET_ACCESS_KEY")]
     s3_secret_key: Option<String>,
 
-    #[structopt(long, env="AWS_REGION")]
-    s3_region: Option<String>,
+    /// S3 region. Requests are signed for this region, so it must match the bucket's region.
+    #[structopt(long, env = "AWS_REGION", default_value = "us-east-1")]
+    s3_region: String,
+
+    /// Base URL of an HTTPS host (e.g. a CDN) that serves the S3 bucket's objects.
+    ///
+    /// Each S3 asset is advertised with alternate `s3://` and HTTPS links. If this isn't set,
+    /// the HTTPS link uses the S3 host.
+    #[structopt(long, env = "AUTOSTAC_S3_HTTPS_URL")]
+    s3_https_url: Option<String>,
+
+    /// How objects are addressed on the S3 host: `path` (host/bucket/key, e.g. for MinIO) or `virtual-host` (bucket.host/key).
+    ///
+    /// This sets both the links to each object and GDAL's AWS_VIRTUAL_HOSTING option.
+    #[structopt(default_value = "path", long, env = "AUTOSTAC_S3_URL_STYLE", possible_values = &["path", "virtual-host"])]
+    s3_url_style: catalog::S3UrlStyle,
 
     /// ID of the service (used for the STAC landing page)
     #[structopt(default_value = "autostac", long, env = "AUTOSTAC_SERVICE_ID")]
@@ -73,14 +157,153 @@ struct Opt {
     #[structopt(default_value = "An automatic STAC API from a directory or S3 bucket", long, env = "AUTOSTAC_SERVICE_DESCRIPTION")]
     description: String,
 
-    /// The base url that each collection will be advertised at.
-    // this needs to be rethought to ensure the URL is in sync with the address
-    // the service is listening at.
-    #[structopt(default_value = "./data", long, env = "AUTOSTAC_BASE_URL")]
-    base_url: String
+    /// A TOML file mapping GDAL metadata items to namespaced item properties.
+    ///
+    /// e.g.:
+    ///
+    ///     [properties]
+    ///     "planet:quality_category" = "QUALITY_CATEGORY"
+    #[structopt(long, env = "AUTOSTAC_PROPERTY_MAPPING")]
+    property_mapping: Option<String>,
+
+    /// GDAL metadata items to read each file's datetime from, in order of preference (comma separated).
+    ///
+    /// The first item that holds an RFC 3339 datetime, a date (`YYYY-MM-DD`) or a TIFF datetime (`YYYY:MM:DD HH:MM:SS`)
+    /// is used, e.g. `ACQUISITION_DATE,TIFFTAG_DATETIME,PRODUCT_START_TIME`. Files without any of them are given the
+    /// `--default-datetime`.
+    #[structopt(default_value = "PRODUCT_START_TIME", long, env = "AUTOSTAC_TIMESTAMP_KEYS", use_delimiter = true)]
+    timestamp_keys: Vec<String>,
+
+    /// The datetime of files without a datetime in their metadata (or file name), as an RFC 3339 datetime.
+    ///
+    /// Use `null` to give these files a null datetime instead, which leaves them out of datetime searches
+    /// and sorts them last. These items don't have a `start_datetime` and `end_datetime` either, so they
+    /// aren't valid STAC Items (which need both when `datetime` is null).
+    #[structopt(default_value = "1900-01-01T00:00:00Z", long, env = "AUTOSTAC_DEFAULT_DATETIME")]
+    default_datetime: String,
+
+    /// A GDAL open option (`KEY=VALUE`) to open each catalogued file with, e.g. `NUM_THREADS=ALL_CPUS`. Can be repeated.
+    ///
+    /// Open options depend on the driver (see https://gdal.org/drivers/raster/). Files on S3 are also opened with
+    /// GDAL_DISABLE_READDIR_ON_OPEN=EMPTY_DIR and CPL_VSIL_CURL_ALLOWED_EXTENSIONS set to image, overview and metadata
+    /// extensions, unless these are set in the environment.
+    #[structopt(long = "gdal-open-option", parse(try_from_str = catalog::gdal_open_option), number_of_values = 1)]
+    gdal_open_options: Vec<String>,
+
+    /// A regular expression that finds each file's datetime in its file name, for files without a datetime in their metadata.
+    ///
+    /// The datetime must be matched by a group named `datetime`, e.g. `_(?P<datetime>\d{8}T\d{6})_` for Sentinel-2
+    /// file names such as `S2A_MSIL1C_20210809T185919_N0301_R013_T10UDV_20210809T223531.tif`.
+    #[structopt(long, env = "AUTOSTAC_DATETIME_FROM_FILENAME")]
+    datetime_from_filename: Option<String>,
+
+    /// The public URL of the service, which all links are made from (e.g. https://example.com/stac/).
+    ///
+    /// Set this when the service is behind a reverse proxy. Defaults to the address and port
+    /// that the server listens on.
+    #[structopt(long, env = "AUTOSTAC_BASE_URL")]
+    base_url: Option<String>,
+
+    /// Build links from the X-Forwarded-Host and X-Forwarded-Proto headers of each request instead of the base URL.
+    ///
+    /// Only use this behind a proxy that sets (or removes) these headers, since clients could otherwise
+    /// choose the host that links point at.
+    #[structopt(long, env = "AUTOSTAC_TRUST_FORWARDED_HEADERS")]
+    trust_forwarded_headers: bool,
+
+    /// An origin that web pages can use the API from (e.g. `https://maps.example.com`). Can be repeated, or comma separated.
+    ///
+    /// `*` allows any origin. Otherwise, a request's `Origin` header is echoed back in Access-Control-Allow-Origin
+    /// if it's one of these origins, and Access-Control-Allow-Origin is left out for other origins.
+    #[structopt(default_value = "*", long = "cors-origin", env = "AUTOSTAC_CORS_ORIGIN", use_delimiter = true, number_of_values = 1)]
+    cors_origins: Vec<String>,
+
+    /// Don't add CORS headers to responses, e.g. when a proxy in front of the service handles CORS.
+    #[structopt(long, env = "AUTOSTAC_CORS_DISABLED")]
+    cors_disabled: bool,
+
+    /// Cache-Control header for the landing page and collections.
+    #[structopt(default_value = "public, max-age=3600", long, env = "AUTOSTAC_CACHE_CONTROL_COLLECTIONS")]
+    cache_control_collections: String,
+
+    /// Cache-Control header for items.
+    #[structopt(default_value = "public, max-age=86400", long, env = "AUTOSTAC_CACHE_CONTROL_ITEMS")]
+    cache_control_items: String,
+
+    /// Cache-Control header for search results (including filtered collections).
+    #[structopt(default_value = "no-cache", long, env = "AUTOSTAC_CACHE_CONTROL_SEARCH")]
+    cache_control_search: String,
+
+    /// Only log errors.
+    #[structopt(long, short = "q", conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Log debugging information, including details about each file as it is catalogued.
+    #[structopt(long, short = "v")]
+    verbose: bool,
+
+    /// Serve a PNG preview of each collection at /collections/<collection_id>/thumbnail.
+    ///
+    /// Thumbnails are rendered from every file in the collection the first time they're requested.
+    #[structopt(long, env = "AUTOSTAC_COLLECTION_THUMBNAILS")]
+    collection_thumbnails: bool,
+
+    /// Add an asset for each band of a file to its item (`band-1`, `band-2` etc.), with `eo:bands` metadata.
+    ///
+    /// Band assets are added to items in every response, including searches. Each band asset links to a GeoTIFF download of only that band.
+    #[structopt(long, env = "AUTOSTAC_BAND_ASSETS")]
+    band_assets: bool,
+
+    /// The most features returned for a single request.
+    ///
+    /// Pages of filtered collections, items and search results are never longer than this, whatever `limit` the
+    /// client asks for. Requests for more footprints than this are refused.
+    #[structopt(default_value = "10000", long, env = "AUTOSTAC_MAX_FEATURES")]
+    max_features: usize,
+
+    /// Check the catalog instead of starting the server.
+    ///
+    /// After scanning, a sample of items are checked to make sure that each file opens with GDAL and has a
+    /// valid footprint, a datetime and a CRS. Items that fail these checks are listed.
+    #[structopt(long)]
+    selftest: bool,
+
+    /// The number of items to check with --selftest.
+    #[structopt(default_value = "100", long)]
+    selftest_sample: usize,
+
+    /// Exit with an error if any items fail the --selftest checks.
+    #[structopt(long, requires = "selftest")]
+    selftest_strict: bool,
+
+    /// Validate the catalog against the STAC JSON schemas before starting the server.
+    ///
+    /// Each collection and item is checked against the STAC v1.0.0 schemas, which are downloaded once at startup.
+    /// Any problems are logged, and the server isn't started if there are any.
+    #[structopt(long, env = "AUTOSTAC_STRICT_STAC")]
+    strict_stac: bool
+}
+
+/// CORS adds CORS headers to every response, so that web pages on the allowed origins can use the API.
+/// With `*` in the allowed origins, any origin is allowed. Otherwise, a request's `Origin` is echoed back
+/// if it's allowed, and Access-Control-Allow-Origin is left out for other origins.
+pub struct CORS {
+    origins: Vec<String>
 }
 
-pub struct CORS;
+impl CORS {
+    pub fn new(origins: &[String]) -> CORS {
+        CORS { origins: origins.iter().map(|o| o.trim().trim_end_matches('/').to_owned()).collect() }
+    }
+
+    /// the Access-Control-Allow-Origin for a request from `origin`, if it's allowed.
+    fn allow_origin<'a>(&self, origin: Option<&'a str>) -> Option<&'a str> {
+        if self.origins.iter().any(|o| o == "*") {
+            return Some("*")
+        }
+        origin.filter(|origin| self.origins.iter().any(|o| o == origin))
+    }
+}
 
 // https://github.com/SergioBenitez/Rocket/issues/25#issuecomment-838566038
 #[rocket::async_trait]
@@ -92,8 +315,14 @@ impl Fairing for CORS {
         }
     }
 
-    async fn on_response<'r>(&self, _request: &'r Request<'_>, response: &mut Response<'r>) {
-        response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if let Some(origin) = self.allow_origin(request.headers().get_one("Origin")) {
+            response.set_header(Header::new("Access-Control-Allow-Origin", origin.to_owned()));
+        }
+        // responses depend on the request's origin unless every origin is allowed.
+        if !self.origins.iter().any(|o| o == "*") {
+            response.adjoin_header(Header::new("Vary", "Origin"));
+        }
         response.set_header(Header::new(
             "Access-Control-Allow-Methods",
             "GET, POST, OPTIONS",
@@ -102,49 +331,1229 @@ impl Fairing for CORS {
     }
 }
 
-#[rocket::main]
-async fn main() {
-
-    let opt = Opt::from_args();
-    let collections: HashMap<String, catalog::ImageryCollection>;
-
-    // if s3_host was supplied, create collections from S3.
-    if opt.s3_host.is_some() && opt.s3 {
-        collections = catalog::collections_from_s3(
-            &opt.s3_host.unwrap(),
-            &opt.s3_bucket.unwrap(),
-            &opt.s3_access_key.unwrap(),  // this shouldn't be required. todo: make it an Option.
-            &opt.s3_secret_key.unwrap()   // ^
-        ).await;
+/// the log level for autostac's own logs. By default, the files being catalogued and any problems
+/// reading them are logged.
+fn log_level(quiet: bool, verbose: bool) -> LevelFilter {
+    if quiet {
+        LevelFilter::Error
+    } else if verbose {
+        LevelFilter::Debug
     } else {
-        collections = catalog::collections_from_subdirs(&opt.dir);
+        LevelFilter::Info
     }
+}
 
-    // initialize a service catalog with some info about our service.
-    // todo: these should be cli flags or read from a config file.
-    let svc = catalog::Service {
-        id: String::from("autostac"),
-        title: String::from("Autostac Demo"),
-        description: String::from("Demo for the autostac remote sensing raster data service"),
-        base_url: url::Url::parse("http://localhost:8000").unwrap(),
-        collections
+/// sets up logging. Other crates only log warnings and errors, except for Rocket, which also
+/// logs its launch info and requests at the default level.
+fn init_logging(level: LevelFilter) {
+    env_logger::Builder::new()
+        .filter_level(LevelFilter::Warn.min(level))
+        .filter_module("rocket", LevelFilter::Info.min(level))
+        .filter_module(module_path!(), level)
+        .init();
+}
+
+/// the base URL that links are made from: `base_url` if it's set, otherwise the address and port
+/// in Rocket's config (with `localhost` for an unspecified address such as 0.0.0.0).
+/// The URL always ends with a trailing slash.
+fn base_url(base_url: Option<&str>, config: &rocket::Config) -> Result<url::Url, String> {
+    let base_url = match base_url {
+        Some(u) => u.to_owned(),
+        None if config.address.is_unspecified() => format!("http://localhost:{}/", config.port),
+        None => format!("http://{}/", SocketAddr::new(config.address, config.port)),
     };
+    let mut url = url::Url::parse(&base_url).map_err(|e| format!("invalid base URL {}: {}", base_url, e))?;
+    if url.cannot_be_a_base() || !(url.scheme() == "http" || url.scheme() == "https") {
+        return Err(format!("invalid base URL {}: must be an http or https URL", base_url));
+    }
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    Ok(url)
+}
 
-    // start application
-    let _app = rocket::build()
-        .attach(CORS)
+/// checks every collection and item in the catalog against the STAC JSON schemas (see stac::validate),
+/// logging each problem. Returns the number of collections and items that aren't valid, or an error if the
+/// schemas can't be downloaded. They're downloaded with blocking requests, so they're validated on a blocking thread.
+async fn validate_stac(
+    collections: &HashMap<String, catalog::ImageryCollection>,
+    base_url: &url::Url,
+    band_assets: bool
+) -> Result<usize, String> {
+    let mut documents: Vec<(&str, String, serde_json::Value)> = Vec::new();
+    for collection in collections.values() {
+        let value = serde_json::to_value(collection.stac_collection(base_url)).unwrap();
+        documents.push((stac::COLLECTION_SCHEMA, collection.id.to_owned(), value));
+        for file in collection.all() {
+            let value = serde_json::to_value(file.to_stac_item(base_url, band_assets)).unwrap();
+            documents.push((stac::ITEM_SCHEMA, format!("{}/{}", collection.id, file.properties.filename), value));
+        }
+    }
+
+    rocket::tokio::task::spawn_blocking(move || {
+        let item_schema = stac::schema(stac::ITEM_SCHEMA)?;
+        let collection_schema = stac::schema(stac::COLLECTION_SCHEMA)?;
+        let invalid = documents.iter()
+            .filter(|(schema, id, value)| {
+                let schema = if *schema == stac::ITEM_SCHEMA { &item_schema } else { &collection_schema };
+                let problems = stac::validate(schema, value);
+                for problem in problems.iter() {
+                    error!("{} is not valid STAC: {}", id, problem);
+                }
+                !problems.is_empty()
+            })
+            .count();
+        Ok(invalid)
+    }).await.map_err(|e| format!("validation did not finish: {}", e))?
+}
+
+/// builds the application with our service catalog and routes mounted.
+fn rocket(
+    svc: catalog::Service,
+    cache_policy: handlers::CachePolicy,
+    thumbnails: thumbnail::Thumbnails
+) -> rocket::Rocket<rocket::Build> {
+    rocket::build()
+        .attach(metrics::RequestMetrics)
         .manage(svc)
+        .manage(cache_policy)
+        .manage(thumbnails)
+        .manage(bands::BandCache::default())
+        .manage(metrics::Metrics::default())
         // STAC conforming API.
         // routes are slowly being moved here.
         .mount(
             "/",
             routes![
             handlers::get_collection_item,
+            handlers::get_item_band,
+            handlers::get_collections,
             handlers::get_collection,    
+            handlers::get_collection_items,
+            handlers::get_collection_footprints,
+            handlers::get_collection_thumbnail,
+            handlers::get_collection_tilejson,
             handlers::get_tiles,
+            handlers::get_vector_tile,
+            handlers::get_wmts_capabilities,
+            handlers::get_wmts_tile,
+            handlers::get_wms,
+            handlers::get_conformance,
+            handlers::get_api,
+            handlers::get_queryables,
+            handlers::get_collection_queryables,
             handlers::search_all_preflight,
             handlers::search_all_collections,
+            handlers::admin_reload,
+            handlers::get_metrics,
             handlers::landing
             ]
-        ).launch().await;
+        )
+        .register("/", catchers![handlers::default_catcher])
+}
+
+#[rocket::main]
+async fn main() {
+
+    let opt = Opt::from_args();
+    init_logging(log_level(opt.quiet, opt.verbose));
+    if opt.max_features == 0 {
+        panic!("--max-features must be at least 1");
+    }
+
+    let property_mapping = match &opt.property_mapping {
+        Some(path) => mapping::PropertyMapping::from_file(path).unwrap_or_else(|e| panic!("{}", e)),
+        None => mapping::PropertyMapping::default(),
+    };
+    let mut scan_options = catalog::ScanOptions {
+        property_mapping,
+        timestamp_keys: opt.timestamp_keys.to_owned(),
+        datetime_pattern: opt.datetime_from_filename.as_deref()
+            .map(|p| catalog::filename_datetime_pattern(p).unwrap_or_else(|e| panic!("{}", e))),
+        s3_https_url: opt.s3_https_url.to_owned(),
+        s3_url_style: opt.s3_url_style,
+        recursive: opt.recursive,
+        open_options: opt.gdal_open_options.to_owned(),
+        default_datetime: catalog::parse_default_datetime(&opt.default_datetime).unwrap_or_else(|e| panic!("{}", e)),
+        cache: None
+    };
+    if let Some(path) = &opt.cache_file {
+        let cache = scan_cache::ScanCache::load(Path::new(path), &scan_options.cache_key());
+        scan_options.cache = Some(Arc::new(cache));
+    }
+
+    // if an HTTP manifest was supplied, create collections from the files it lists;
+    // if s3_host was supplied, create collections from S3.
+    let source = match (&opt.http_manifest, &opt.s3_host, opt.s3) {
+        (Some(manifest), _, _) => catalog::CatalogSource::Manifest(manifest.to_owned()),
+        (None, Some(host), true) => catalog::CatalogSource::S3 {
+            host: catalog::parse_s3_host(host).unwrap_or_else(|e| panic!("{}", e)),
+            bucket: opt.s3_bucket.to_owned().unwrap(),
+            prefix: opt.s3_prefix.to_owned(),
+            access_key: opt.s3_access_key.to_owned(),
+            secret_key: opt.s3_secret_key.to_owned(),
+            region: opt.s3_region.to_owned()
+        },
+        _ => catalog::CatalogSource::Dir(opt.dir.to_owned()),
+    };
+    let s3_breaker = Arc::new(breaker::CircuitBreaker::default());
+    let collections = source.scan(&scan_options, &s3_breaker).await.unwrap_or_else(|e| panic!("could not scan the catalog: {}", e));
+
+    if opt.selftest {
+        let report = selftest::run(&collections, opt.selftest_sample);
+        report.print();
+        if opt.selftest_strict && !report.failures.is_empty() {
+            process::exit(1);
+        }
+        return;
+    }
+
+    // initialize a service catalog with some info about our service.
+    let base_url = base_url(opt.base_url.as_deref(), &rocket::Config::from(rocket::Config::figment()))
+        .unwrap_or_else(|e| panic!("{}", e));
+
+    if opt.strict_stac {
+        match validate_stac(&collections, &base_url, opt.band_assets).await {
+            Ok(0) => (),
+            Ok(invalid) => {
+                error!("{} collections and items are not valid STAC", invalid);
+                process::exit(1);
+            },
+            Err(e) => {
+                error!("could not validate the catalog: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    let svc = catalog::Service {
+        id: opt.id,
+        title: opt.title,
+        description: opt.description,
+        base_url,
+        trust_forwarded_headers: opt.trust_forwarded_headers,
+        band_assets: opt.band_assets,
+        max_features: opt.max_features,
+        s3_breaker,
+        collections: Arc::new(RwLock::new(Arc::new(collections.into())))
+    };
+
+    if opt.watch {
+        watch::watch(&opt.dir, scan_options.clone(), svc.collections.clone());
+    }
+    let reload = handlers::Reload {
+        token: opt.admin_token.to_owned(),
+        source,
+        options: scan_options
+    };
+
+    let cache_policy = handlers::CachePolicy {
+        collections: opt.cache_control_collections,
+        items: opt.cache_control_items,
+        search: opt.cache_control_search
+    };
+
+    let thumbnails = thumbnail::Thumbnails::new(opt.collection_thumbnails);
+
+    // start application
+    let mut app = rocket(svc, cache_policy, thumbnails).manage(reload);
+    if !opt.cors_disabled {
+        app = app.attach(CORS::new(&opt.cors_origins));
+    }
+    let _app = app.launch().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+    use geo::polygon;
+    use rocket::http::{ContentType, Header, Status};
+    use log::LevelFilter;
+    use rocket::local::blocking::{Client, LocalResponse};
+    use crate::breaker;
+    use crate::catalog;
+    use crate::catalog::test_utils::{imagery_collection, imagery_file, test_dir, write_geotiff};
+    use crate::handlers::{CachePolicy, Reload};
+    use crate::thumbnail::Thumbnails;
+
+    fn cache_policy() -> CachePolicy {
+        CachePolicy {
+            collections: String::from("max-age=60"),
+            items: String::from("max-age=3600"),
+            search: String::from("no-cache")
+        }
+    }
+
+    /// a service with the given collections and the defaults the tests expect. Tests that need other
+    /// settings override them with struct update syntax.
+    fn test_service(collections: HashMap<String, catalog::ImageryCollection>) -> catalog::Service {
+        catalog::Service {
+            id: String::from("autostac"),
+            title: String::from("Autostac Test"),
+            description: String::from("Test service"),
+            base_url: url::Url::parse("http://localhost:8000").unwrap(),
+            trust_forwarded_headers: false,
+            band_assets: false,
+            max_features: 10000,
+            s3_breaker: Default::default(),
+            collections: Arc::new(RwLock::new(Arc::new(collections.into())))
+        }
+    }
+
+    fn test_client() -> Client {
+        Client::tracked(test_rocket()).unwrap()
+    }
+
+    /// a client for a service with an "imagery" collection of unit-square files scene_0, scene_1 etc.
+    /// The service's defaults can be changed with svc, e.g. `|svc| catalog::Service { max_features: 2, ..svc }`.
+    fn client_with(files: usize, svc: impl FnOnce(catalog::Service) -> catalog::Service) -> Client {
+        let files = (0..files).map(|i| imagery_file(&format!("scene_{}", i), "imagery", polygon![
+            (x: 0., y: 0.),
+            (x: 1., y: 0.),
+            (x: 1., y: 1.),
+            (x: 0., y: 1.)
+        ])).collect();
+        let mut collections = HashMap::new();
+        collections.insert(String::from("imagery"), imagery_collection("imagery", files));
+        Client::tracked(super::rocket(svc(test_service(collections)), cache_policy(), Thumbnails::new(false))).unwrap()
+    }
+
+    /// the application (with a collection of one file) that test_client is made from.
+    fn test_rocket() -> rocket::Rocket<rocket::Build> {
+        let img = imagery_file("scene", "imagery", polygon![
+            (x: 0., y: 0.),
+            (x: 1., y: 0.),
+            (x: 1., y: 1.),
+            (x: 0., y: 1.)
+        ]);
+        let mut collections = HashMap::new();
+        collections.insert(String::from("imagery"), imagery_collection("imagery", vec![img]));
+
+        let svc = test_service(collections);
+        super::rocket(svc, cache_policy(), Thumbnails::new(false))
+    }
+
+    #[test]
+    fn test_log_level() {
+        assert_eq!(super::log_level(false, false), LevelFilter::Info);
+        assert_eq!(super::log_level(true, false), LevelFilter::Error);
+        assert_eq!(super::log_level(false, true), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn test_base_url() {
+        let config = rocket::Config::default();
+        assert_eq!(super::base_url(None, &config).unwrap().as_str(), "http://127.0.0.1:8000/");
+        let config = rocket::Config { address: "0.0.0.0".parse().unwrap(), port: 9000, ..rocket::Config::default() };
+        assert_eq!(super::base_url(None, &config).unwrap().as_str(), "http://localhost:9000/");
+
+        // a trailing slash is added if it's missing.
+        assert_eq!(super::base_url(Some("https://example.com/stac"), &config).unwrap().as_str(), "https://example.com/stac/");
+        assert_eq!(super::base_url(Some("https://example.com"), &config).unwrap().as_str(), "https://example.com/");
+
+        for invalid in &["example.com/stac", "ftp://example.com", "not a url"] {
+            assert!(super::base_url(Some(invalid), &config).is_err());
+        }
+    }
+
+    #[test]
+    fn test_forwarded_headers() {
+        let forwarded_link = |trust: bool| {
+            let svc = catalog::Service {
+                trust_forwarded_headers: trust,
+                ..test_service(HashMap::new())
+            };
+            let client = Client::tracked(super::rocket(svc, cache_policy(), Thumbnails::new(false))).unwrap();
+            let res = client.get("/")
+                .header(Header::new("X-Forwarded-Host", "stac.example.com"))
+                .header(Header::new("X-Forwarded-Proto", "https"))
+                .dispatch();
+            let landing: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+            landing["links"][0]["href"].as_str().unwrap().to_owned()
+        };
+        assert_eq!(forwarded_link(false), "http://localhost:8000/");
+        assert_eq!(forwarded_link(true), "https://stac.example.com/");
+    }
+
+    #[test]
+    fn test_cache_control_headers() {
+        let client = test_client();
+
+        let res = client.get("/").dispatch();
+        assert_eq!(res.headers().get_one("Cache-Control"), Some("max-age=60"));
+
+        let res = client.get("/collections/imagery").dispatch();
+        assert_eq!(res.headers().get_one("Cache-Control"), Some("max-age=60"));
+
+        let res = client.get("/collections/imagery/scene").dispatch();
+        assert_eq!(res.headers().get_one("Cache-Control"), Some("max-age=3600"));
+
+        let res = client.get("/collections/imagery?intersects=POINT(0.5%200.5)").dispatch();
+        assert_eq!(res.headers().get_one("Cache-Control"), Some("no-cache"));
+
+        let res = client.post("/stac/search").header(ContentType::JSON).body("{}").dispatch();
+        assert_eq!(res.headers().get_one("Cache-Control"), Some("no-cache"));
+
+        // errors should not be cached.
+        let res = client.get("/collections/missing").dispatch();
+        assert_eq!(res.headers().get_one("Cache-Control"), None);
+    }
+
+    #[test]
+    fn test_etag() {
+        let client = test_client();
+        for path in &["/collections", "/collections/imagery", "/collections/imagery/scene", "/collections/imagery?f=html"] {
+            let res = client.get(*path).dispatch();
+            assert_eq!(res.status(), Status::Ok);
+            let etag = res.headers().get_one("ETag").unwrap_or_else(|| panic!("{} has no ETag", path)).to_owned();
+
+            // the same ETag is sent until the response changes.
+            let res = client.get(*path).dispatch();
+            assert_eq!(res.headers().get_one("ETag"), Some(etag.as_str()));
+
+            let res = client.get(*path).header(Header::new("If-None-Match", etag.to_owned())).dispatch();
+            assert_eq!(res.status(), Status::NotModified);
+            assert_eq!(res.headers().get_one("ETag"), Some(etag.as_str()));
+            assert!(res.headers().get_one("Cache-Control").is_some());
+            assert_eq!(res.into_string().unwrap_or_default(), "");
+
+            let res = client.get(*path).header(Header::new("If-None-Match", "\"stale\"")).dispatch();
+            assert_eq!(res.status(), Status::Ok);
+        }
+
+        // JSON and HTML versions of the same collection have different ETags.
+        let json = client.get("/collections/imagery").dispatch();
+        let html = client.get("/collections/imagery?f=html").dispatch();
+        assert_ne!(json.headers().get_one("ETag"), html.headers().get_one("ETag"));
+    }
+
+    #[test]
+    fn test_collection_bbox() {
+        let client = test_client();
+        let features = |path: &str| -> usize {
+            let res = client.get(path).dispatch();
+            assert_eq!(res.status(), Status::Ok);
+            let page: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+            page["features"].as_array().unwrap().len()
+        };
+
+        assert_eq!(features("/collections/imagery?bbox=0.5,0.5,2,2"), 1);
+        assert_eq!(features("/collections/imagery?bbox=2,2,3,3"), 0);
+
+        // malformed bboxes, and bboxes combined with another spatial filter, are bad requests.
+        for query in &["bbox=0,0,1", "bbox=0,0,abc,1", "bbox=1,1,0,0", "bbox=0,0,1,1&intersects=POINT(0.5%200.5)"] {
+            let res = client.get(format!("/collections/imagery?{}", query)).dispatch();
+            assert_eq!(res.status(), Status::BadRequest, "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_error_responses() {
+        let client = test_client();
+        let error = |res: LocalResponse| -> serde_json::Value {
+            assert_eq!(res.content_type(), Some(ContentType::JSON));
+            serde_json::from_str(&res.into_string().unwrap()).unwrap()
+        };
+
+        let res = client.get("/collections/missing").dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+        assert_eq!(error(res), serde_json::json!({"code": "NotFound", "description": "collection missing not found"}));
+
+        let res = client.get("/collections/imagery/missing").dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+        assert_eq!(error(res)["description"], "item missing not found in collection imagery");
+
+        let res = client.get("/collections/imagery?bbox=0,0,1").dispatch();
+        assert_eq!(res.status(), Status::BadRequest);
+        assert_eq!(error(res)["code"], "BadRequest");
+
+        // errors that don't come from a handler have the same body.
+        let res = client.get("/not/a/route").dispatch();
+        assert_eq!(res.status(), Status::NotFound);
+        assert_eq!(error(res), serde_json::json!({"code": "NotFound", "description": "/not/a/route not found"}));
+
+        let res = client.post("/stac/search").header(ContentType::JSON).body("not json").dispatch();
+        assert_eq!(res.status(), Status::UnprocessableEntity);
+        assert_eq!(error(res)["code"], "UnprocessableEntity");
+
+        // a malformed limit is a bad request in every endpoint, instead of returning every item.
+        for path in &["/collections/imagery?limit=abc", "/collections/imagery/items?limit=abc"] {
+            let res = client.get(*path).dispatch();
+            assert_eq!(res.status(), Status::BadRequest, "{}", path);
+            assert_eq!(error(res)["description"], "Invalid limit `abc`. limit must be a positive integer (e.g. limit=10).");
+        }
+        for body in &[r#"{"limit": "abc"}"#, r#"{"limit": 10.5}"#, r#"{"limit": [10]}"#] {
+            let res = client.post("/stac/search").header(ContentType::JSON).body(*body).dispatch();
+            assert_eq!(res.status(), Status::BadRequest, "{}", body);
+            assert_eq!(error(res)["code"], "BadRequest");
+        }
+    }
+
+    /// the hrefs of the links on the page at `path`, relative to the base URL.
+    fn link_paths(client: &Client, path: &str) -> Vec<(String, String)> {
+        let res = client.get(path).dispatch();
+        let page: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        page["links"].as_array().unwrap().iter().map(|l| {
+            let href = l["href"].as_str().unwrap();
+            let path = href.strip_prefix("http://localhost:8000").expect("links should be absolute");
+            (l["rel"].as_str().unwrap().to_owned(), path.to_owned())
+        }).collect()
+    }
+
+    #[test]
+    fn test_links_resolve() {
+        let client = test_client();
+
+        let landing = link_paths(&client, "/");
+        assert!(landing.contains(&(String::from("root"), String::from("/"))));
+        assert!(landing.contains(&(String::from("child"), String::from("/collections/imagery"))));
+
+        assert!(landing.contains(&(String::from("data"), String::from("/collections"))));
+        assert!(landing.contains(&(String::from("search"), String::from("/stac/search"))));
+
+        let collection = link_paths(&client, "/collections/imagery");
+        assert!(collection.contains(&(String::from("self"), String::from("/collections/imagery"))));
+        assert!(collection.contains(&(String::from("parent"), String::from("/"))));
+        assert!(collection.contains(&(String::from("item"), String::from("/collections/imagery/scene"))));
+
+        // the search link is followed with POST.
+        let res = client.post("/stac/search").header(ContentType::JSON).body("{}").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+
+        let item = link_paths(&client, "/collections/imagery/scene");
+        assert!(item.contains(&(String::from("self"), String::from("/collections/imagery/scene"))));
+        assert!(item.contains(&(String::from("collection"), String::from("/collections/imagery"))));
+
+        for (rel, path) in landing.iter().chain(collection.iter()).chain(item.iter()).filter(|(rel, _)| rel != "search") {
+            let res = client.get(path.as_str()).dispatch();
+            assert_eq!(res.status(), Status::Ok, "{} link {} did not resolve", rel, path);
+        }
+    }
+
+    #[test]
+    fn test_conformance() {
+        let client = test_client();
+        let res = client.get("/conformance").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        let conformance: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        let classes = conformance["conformsTo"].as_array().unwrap();
+        assert!(classes.contains(&serde_json::json!("https://api.stacspec.org/v1.0.0-beta.2/core")));
+        assert!(classes.contains(&serde_json::json!("https://api.stacspec.org/v1.0.0-beta.2/item-search")));
+
+        // the landing page lists the same classes.
+        let landing: serde_json::Value = serde_json::from_str(&client.get("/").dispatch().into_string().unwrap()).unwrap();
+        assert_eq!(landing["conformsTo"], conformance["conformsTo"]);
+    }
+
+    #[test]
+    fn test_openapi() {
+        let client = test_client();
+        let res = client.get("/api").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.headers().get_one("Content-Type"), Some("application/vnd.oai.openapi+json; version=3.0"));
+        let api: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(api["openapi"], "3.0.3");
+        assert_eq!(api["servers"][0]["url"], "http://localhost:8000");
+        for path in &["/", "/collections", "/collections/{collectionId}", "/collections/{collectionId}/{itemId}"] {
+            assert!(api["paths"][path]["get"].is_object(), "{} is not described", path);
+        }
+        assert!(api["paths"]["/stac/search"]["post"].is_object());
+
+        // the landing page links to the API description.
+        let landing: serde_json::Value = serde_json::from_str(&client.get("/").dispatch().into_string().unwrap()).unwrap();
+        let service_desc = landing["links"].as_array().unwrap().iter().find(|l| l["rel"] == "service-desc").unwrap();
+        assert_eq!(service_desc["href"], "http://localhost:8000/api");
+        assert_eq!(service_desc["type"], "application/vnd.oai.openapi+json;version=3.0");
+    }
+
+    #[test]
+    fn test_queryables() {
+        let client = test_client();
+        let res = client.get("/queryables").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.headers().get_one("Content-Type"), Some("application/schema+json"));
+        let queryables: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(queryables["$id"], "http://localhost:8000/queryables");
+        for property in &["datetime", "eo:cloud_cover", "gsd", "spatial_resolution", "proj:epsg"] {
+            assert!(queryables["properties"][property]["type"].is_string(), "{} is not queryable", property);
+        }
+
+        let res = client.get("/collections/imagery/queryables").dispatch();
+        let collection: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(collection["$id"], "http://localhost:8000/collections/imagery/queryables");
+        assert_eq!(collection["properties"], queryables["properties"]);
+
+        assert_eq!(client.get("/collections/missing/queryables").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_cors() {
+        let origin = |o: &'static str| Header::new("Origin", o);
+
+        // by default, any origin is allowed.
+        let client = Client::tracked(test_rocket().attach(super::CORS::new(&[String::from("*")]))).unwrap();
+        let res = client.get("/collections").header(origin("https://maps.example.com")).dispatch();
+        assert_eq!(res.headers().get_one("Access-Control-Allow-Origin"), Some("*"));
+        assert_eq!(res.headers().get_one("Access-Control-Allow-Methods"), Some("GET, POST, OPTIONS"));
+        assert!(!res.headers().get("Vary").any(|v| v == "Origin"));
+
+        // with a list of origins, only those origins are echoed back.
+        let origins = [String::from("https://maps.example.com/"), String::from("https://other.example.com")];
+        let client = Client::tracked(test_rocket().attach(super::CORS::new(&origins))).unwrap();
+        let res = client.get("/collections").header(origin("https://maps.example.com")).dispatch();
+        assert_eq!(res.headers().get_one("Access-Control-Allow-Origin"), Some("https://maps.example.com"));
+        assert!(res.headers().get("Vary").any(|v| v == "Origin"));
+        let res = client.get("/collections").header(origin("https://evil.example.com")).dispatch();
+        assert_eq!(res.headers().get_one("Access-Control-Allow-Origin"), None);
+        let res = client.get("/collections").dispatch();
+        assert_eq!(res.headers().get_one("Access-Control-Allow-Origin"), None);
+
+        // without the fairing, no CORS headers are added.
+        let client = test_client();
+        let res = client.get("/collections").header(origin("https://maps.example.com")).dispatch();
+        assert_eq!(res.headers().get_one("Access-Control-Allow-Origin"), None);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let client = test_client();
+        client.get("/collections").dispatch();
+        client.get("/collections/imagery").dispatch();
+        client.get("/collections/missing").dispatch();
+        client.post("/stac/search").header(ContentType::JSON).body("{}").dispatch();
+
+        let res = client.get("/metrics").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.headers().get_one("Content-Type"), Some("text/plain; version=0.0.4"));
+        let text = res.into_string().unwrap();
+        assert!(text.contains("autostac_requests_total{method=\"GET\",route=\"/collections\"} 1\n"));
+        assert!(text.contains("autostac_requests_total{method=\"GET\",route=\"/collections/<collection_id>\"} 2\n"));
+        assert!(text.contains("autostac_search_duration_seconds_count 1\n"));
+        assert!(text.contains("autostac_collections 1\n"));
+        assert!(text.contains("autostac_items 1\n"));
+        assert!(text.contains("autostac_s3_breaker_state 0\n"));
+    }
+
+    #[test]
+    fn test_collections() {
+        let client = test_client();
+        let res = client.get("/collections").dispatch();
+        assert_eq!(res.headers().get_one("Cache-Control"), Some("max-age=60"));
+        let page: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+
+        let collections = page["collections"].as_array().unwrap();
+        assert_eq!(collections.len(), 1);
+        assert_eq!(collections[0]["id"], "imagery");
+        let rels: Vec<&str> = collections[0]["links"].as_array().unwrap().iter().map(|l| l["rel"].as_str().unwrap()).collect();
+        assert!(rels.contains(&"self") && rels.contains(&"root"));
+
+        let links = link_paths(&client, "/collections");
+        assert!(links.contains(&(String::from("root"), String::from("/"))));
+        assert!(links.contains(&(String::from("self"), String::from("/collections"))));
+
+        // a catalog without any collections lists an empty array.
+        let svc = test_service(HashMap::new());
+        let client = Client::tracked(super::rocket(svc, cache_policy(), Thumbnails::new(false))).unwrap();
+        let page: serde_json::Value = serde_json::from_str(&client.get("/collections").dispatch().into_string().unwrap()).unwrap();
+        assert_eq!(page["collections"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_pagination() {
+        let client = client_with(3, |svc| svc);
+        let page = |res: rocket::local::blocking::LocalResponse| -> serde_json::Value {
+            serde_json::from_str(&res.into_string().unwrap()).unwrap()
+        };
+        let link = |page: &serde_json::Value, rel: &str| page["links"].as_array().unwrap().iter().find(|l| l["rel"] == rel).cloned();
+
+        // filtered collections link to the next page, keeping the query.
+        let first = page(client.get("/collections/imagery?intersects=POINT%20(0.5%200.5)&limit=2").dispatch());
+        assert_eq!(first["features"].as_array().unwrap().len(), 2);
+        assert!(link(&first, "prev").is_none());
+        let next = link(&first, "next").unwrap();
+        let next_href = next["href"].as_str().unwrap();
+        assert_eq!(next_href, "http://localhost:8000/collections/imagery?intersects=POINT+%280.5+0.5%29&limit=2&offset=2");
+
+        let second = page(client.get(next_href.strip_prefix("http://localhost:8000").unwrap()).dispatch());
+        assert_eq!(second["features"].as_array().unwrap().len(), 1);
+        assert_eq!(second["features"][0]["id"], "scene_2");
+        assert!(link(&second, "next").is_none());
+        assert!(link(&second, "prev").unwrap()["href"].as_str().unwrap().ends_with("offset=0"));
+        // GET links don't have a method, body or merge.
+        let prev = link(&second, "prev").unwrap();
+        assert!(prev.get("method").is_none() && prev.get("body").is_none() && prev.get("merge").is_none());
+
+        // search pages are linked with the search body, with a different offset.
+        let first = page(client.post("/stac/search")
+            .header(ContentType::JSON)
+            .body(r#"{"intersects": "POINT (0.5 0.5)", "limit": 2}"#)
+            .dispatch());
+        let next = link(&first, "next").unwrap();
+        assert_eq!(next["method"], "POST");
+        assert_eq!(next["href"], "http://localhost:8000/stac/search");
+        assert_eq!(next["body"], serde_json::json!({"intersects": "POINT (0.5 0.5)", "limit": 2, "offset": 2}));
+        assert_eq!(next["merge"], false);
+
+        let second = page(client.post("/stac/search")
+            .header(ContentType::JSON)
+            .body(next["body"].to_string())
+            .dispatch());
+        assert_eq!(second["features"].as_array().unwrap().len(), 1);
+        assert_eq!(second["context"], serde_json::json!({"returned": 1, "limit": 2, "matched": 3}));
+        assert_eq!((second["numberMatched"].as_u64(), second["numberReturned"].as_u64()), (Some(3), Some(1)));
+
+        // without a limit, pages have 10 features.
+        let client = client_with(11, |svc| svc);
+        let first = page(client.post("/stac/search").header(ContentType::JSON).body("{}").dispatch());
+        assert_eq!(first["features"].as_array().unwrap().len(), 10);
+        let next = link(&first, "next").unwrap();
+        assert_eq!((next["body"]["limit"].as_u64(), next["body"]["offset"].as_u64()), (Some(10), Some(10)));
+        let first = page(client.get("/collections/imagery/items").dispatch());
+        assert_eq!(first["features"].as_array().unwrap().len(), 10);
+        assert!(link(&first, "next").unwrap()["href"].as_str().unwrap().ends_with("limit=10&offset=10"));
+    }
+
+    #[test]
+    fn test_ndjson() {
+        let client = test_client();
+        let lines = |res: LocalResponse| -> Vec<serde_json::Value> {
+            assert_eq!(res.status(), Status::Ok);
+            assert_eq!(res.content_type(), Some(ContentType::new("application", "geo+json-seq")));
+            res.into_string().unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+        };
+
+        let features = lines(client.get("/collections/imagery?bbox=0.5,0.5,2,2&f=ndjson").dispatch());
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0]["type"], "Feature");
+        assert_eq!(features[0]["id"], "scene");
+
+        let res = client.post("/stac/search")
+            .header(ContentType::JSON)
+            .header(Header::new("Accept", "application/geo+json-seq"))
+            .body("{}")
+            .dispatch();
+        assert_eq!(lines(res).len(), 1);
+        let res = client.post("/stac/search?f=ndjson").header(ContentType::JSON).body(r#"{"bbox": [10, 10, 11, 11]}"#).dispatch();
+        assert!(lines(res).is_empty());
+
+        // collections without a filter are still a STAC Collection.
+        let res = client.get("/collections/imagery?f=ndjson").dispatch();
+        assert_eq!(res.content_type(), Some(ContentType::JSON));
+
+        // the query is checked before the stream starts.
+        let res = client.get("/collections/imagery/items?datetime=yesterday&f=ndjson").dispatch();
+        assert_eq!(res.status(), Status::BadRequest);
+
+        // streams aren't paged, so they have every matching item unless a limit is given.
+        let client = client_with(12, |svc| svc);
+        assert_eq!(lines(client.get("/collections/imagery/items?f=ndjson").dispatch()).len(), 12);
+        assert_eq!(lines(client.get("/collections/imagery?bbox=0,0,1,1&f=ndjson").dispatch()).len(), 12);
+        assert_eq!(lines(client.get("/collections/imagery/items?limit=5&offset=10&f=ndjson").dispatch()).len(), 2);
+        assert_eq!(lines(client.post("/stac/search?f=ndjson").header(ContentType::JSON).body("{}").dispatch()).len(), 12);
+    }
+
+    #[test]
+    fn test_band_assets() {
+        let client = client_with(1, |svc| catalog::Service { band_assets: true, ..svc });
+        let json = |res: LocalResponse| -> serde_json::Value { serde_json::from_str(&res.into_string().unwrap()).unwrap() };
+
+        // every response with items has the band assets, not only single items.
+        let band = "http://localhost:8000/collections/imagery/scene_0/assets/b1";
+        assert_eq!(json(client.get("/collections/imagery/scene_0").dispatch())["assets"]["band-1"]["href"], band);
+        assert_eq!(json(client.get("/collections/imagery/items").dispatch())["features"][0]["assets"]["band-1"]["href"], band);
+        assert_eq!(json(client.get("/collections/imagery?bbox=0,0,1,1").dispatch())["features"][0]["assets"]["band-1"]["href"], band);
+        let res = client.post("/stac/search").header(ContentType::JSON).body("{}").dispatch();
+        assert_eq!(json(res)["features"][0]["assets"]["band-1"]["href"], band);
+        let res = client.post("/stac/search?f=ndjson").header(ContentType::JSON).body("{}").dispatch();
+        assert_eq!(json(res)["assets"]["band-1"]["href"], band);
+    }
+
+    #[test]
+    fn test_html() {
+        let client = test_client();
+
+        let res = client.get("/?f=html").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.content_type(), Some(ContentType::HTML));
+        assert!(res.into_string().unwrap().contains("/collections\">"));
+
+        let res = client.get("/collections").header(Header::new("Accept", "text/html,application/xhtml+xml")).dispatch();
+        assert_eq!(res.content_type(), Some(ContentType::HTML));
+        assert_eq!(res.headers().get_one("Vary"), Some("Accept"));
+        assert!(res.into_string().unwrap().contains("/collections/imagery\">"));
+
+        let res = client.get("/collections/imagery?f=html").dispatch();
+        assert_eq!(res.content_type(), Some(ContentType::HTML));
+
+        // JSON is the default, and can be asked for with f=json even from a browser.
+        assert_eq!(client.get("/collections").dispatch().content_type(), Some(ContentType::JSON));
+        let res = client.get("/?f=json").header(Header::new("Accept", "text/html")).dispatch();
+        assert_eq!(res.content_type(), Some(ContentType::JSON));
+    }
+
+    #[test]
+    fn test_max_features() {
+        let client = client_with(3, |svc| catalog::Service { max_features: 2, ..svc });
+        let page = |res: rocket::local::blocking::LocalResponse| -> serde_json::Value {
+            serde_json::from_str(&res.into_string().unwrap()).unwrap()
+        };
+
+        // larger limits are capped, and the context and next link show the capped page size.
+        let first = page(client.get("/collections/imagery/items?limit=100").dispatch());
+        assert_eq!(first["context"], serde_json::json!({"returned": 2, "limit": 2, "matched": 3}));
+        let next = first["links"].as_array().unwrap().iter().find(|l| l["rel"] == "next").unwrap();
+        assert!(next["href"].as_str().unwrap().ends_with("limit=2&offset=2"));
+
+        let search = page(client.post("/stac/search").header(ContentType::JSON).body(r#"{"limit": 100}"#).dispatch());
+        assert_eq!(search["context"], serde_json::json!({"returned": 2, "limit": 2, "matched": 3}));
+
+        // streamed newline-delimited GeoJSON is capped too.
+        let res = client.get("/collections/imagery/items?f=ndjson").dispatch();
+        assert_eq!(res.into_string().unwrap().lines().count(), 2);
+
+        // footprints aren't paged, so they're refused instead of truncated.
+        let res = client.get("/collections/imagery/footprints").dispatch();
+        assert_eq!(res.status(), Status::BadRequest);
+        let error = page(res);
+        assert!(error["description"].as_str().unwrap().contains("more than the 2 features"));
+        assert_eq!(client.get("/collections/imagery/footprints?bbox=10,10,11,11").dispatch().status(), Status::Ok);
+    }
+
+    #[test]
+    fn test_collection_footprints() {
+        let client = test_client();
+
+        let res = client.get("/collections/imagery/footprints").dispatch();
+        let fc: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        let features = fc["features"].as_array().unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0]["id"], "scene");
+        assert!(features[0]["properties"]["datetime"].is_string());
+        assert!(features[0].get("assets").is_none());
+
+        // footprints are filtered like collections.
+        let count = |query: &str| -> usize {
+            let res = client.get(format!("/collections/imagery/footprints?{}", query)).dispatch();
+            assert_eq!(res.status(), Status::Ok, "{}", query);
+            let fc: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+            fc["features"].as_array().unwrap().len()
+        };
+        assert_eq!(count("bbox=10,10,11,11"), 0);
+        assert_eq!(count("bbox=0.5,0.5,2,2"), 1);
+        assert_eq!(count("intersects=POINT(0.5%200.5)"), 1);
+        assert_eq!(count("intersects=POINT(10%2010)"), 0);
+        assert_eq!(count("contains=POLYGON((0.4%200.4,%200.6%200.4,%200.6%200.6,%200.4%200.6,%200.4%200.4))"), 1);
+
+        let res = client.get("/collections/imagery/footprints?bbox=0,0,1,1&intersects=POINT(0.5%200.5)").dispatch();
+        assert_eq!(res.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn test_contains_requires_polygon() {
+        let client = test_client();
+
+        // contains only accepts polygons.
+        let res = client.get("/collections/imagery?contains=POINT(0.5%200.5)").dispatch();
+        assert_eq!(res.status(), Status::BadRequest);
+        let res = client.post("/stac/search").header(ContentType::JSON).body(r#"{"contains": "POINT (0.5 0.5)"}"#).dispatch();
+        assert_eq!(res.status(), Status::BadRequest);
+    }
+
+    /// the number of features a POST /stac/search with `body` finds.
+    fn search_count(client: &Client, body: &str) -> usize {
+        let res = client.post("/stac/search").header(ContentType::JSON).body(body).dispatch();
+        assert_eq!(res.status(), Status::Ok, "{}", body);
+        let fc: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        fc["features"].as_array().unwrap().len()
+    }
+
+    #[test]
+    fn test_search_intersects_geojson() {
+        let client = test_client();
+        let search = |body: &str| search_count(&client, body);
+
+        // search takes GeoJSON geometries (as STAC clients send them) or WKT for intersects.
+        assert_eq!(search(r#"{"intersects": {"type": "Point", "coordinates": [0.5, 0.5]}}"#), 1);
+        assert_eq!(search(r#"{"intersects": {"type": "Point", "coordinates": [10, 10]}}"#), 0);
+        assert_eq!(search(r#"{"intersects": "POINT (0.5 0.5)"}"#), 1);
+    }
+
+    #[test]
+    fn test_search_ids_and_collections() {
+        let client = test_client();
+        let search = |body: &str| search_count(&client, body);
+
+        // items can be requested by id, whatever the other filters are, and searches can be limited to some collections.
+        assert_eq!(search(r#"{"ids": ["scene"], "bbox": [10, 10, 11, 11]}"#), 1);
+        assert_eq!(search(r#"{"ids": ["missing"]}"#), 0);
+        assert_eq!(search(r#"{"ids": ["scene"], "collections": ["other"]}"#), 0);
+        assert_eq!(search(r#"{"collections": ["imagery", "other"]}"#), 1);
+        assert_eq!(search(r#"{"collections": ["other"]}"#), 0);
+    }
+
+    #[test]
+    fn test_collection_items() {
+        let square = |x: f64| polygon![(x: x, y: 0.), (x: x + 1., y: 0.), (x: x + 1., y: 1.), (x: x, y: 1.)];
+        let mut collections = HashMap::new();
+        collections.insert(String::from("imagery"), imagery_collection("imagery", vec![
+            imagery_file("scene_1", "imagery", square(0.)),
+            imagery_file("scene_2", "imagery", square(10.))
+        ]));
+        let svc = test_service(collections);
+        let client = Client::tracked(super::rocket(svc, cache_policy(), Thumbnails::new(false))).unwrap();
+        let items = |query: &str| -> serde_json::Value {
+            let res = client.get(format!("/collections/imagery/items{}", query)).dispatch();
+            assert_eq!(res.status(), Status::Ok, "{}", query);
+            serde_json::from_str(&res.into_string().unwrap()).unwrap()
+        };
+        let ids = |page: &serde_json::Value| -> Vec<String> {
+            page["features"].as_array().unwrap().iter().map(|f| f["id"].as_str().unwrap().to_owned()).collect()
+        };
+
+        let page = items("");
+        assert_eq!(page["type"], "FeatureCollection");
+        assert_eq!(ids(&page), vec!["scene_1", "scene_2"]);
+        assert_eq!(ids(&items("?bbox=10.2,0.2,10.8,0.8")), vec!["scene_2"]);
+        assert_eq!(ids(&items("?datetime=2100-01-01T00:00:00Z/..")), Vec::<String>::new());
+
+        // pages link to the next and previous pages, with the same query.
+        let page = items("?bbox=0,0,20,1&limit=1");
+        assert_eq!(ids(&page), vec!["scene_1"]);
+        assert_eq!(page["numberMatched"], 2);
+        let next = page["links"].as_array().unwrap().iter().find(|l| l["rel"] == "next").unwrap();
+        assert_eq!(next["href"], "http://localhost:8000/collections/imagery/items?bbox=0%2C0%2C20%2C1&limit=1&offset=1");
+        let page = items("?bbox=0,0,20,1&limit=1&offset=1");
+        assert_eq!(ids(&page), vec!["scene_2"]);
+        assert!(page["links"].as_array().unwrap().iter().any(|l| l["rel"] == "prev"));
+        assert!(!page["links"].as_array().unwrap().iter().any(|l| l["rel"] == "next"));
+
+        // the collection links to its items.
+        let links = link_paths(&client, "/collections/imagery");
+        assert!(links.contains(&(String::from("items"), String::from("/collections/imagery/items"))));
+
+        assert_eq!(client.get("/collections/missing/items").dispatch().status(), Status::NotFound);
+        assert_eq!(client.get("/collections/imagery/items?limit=0").dispatch().status(), Status::BadRequest);
+        assert_eq!(client.get("/collections/imagery/items?bbox=1,2").dispatch().status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn test_search_fields() {
+        let client = test_client();
+        let search = |body: &str, accept: &str| -> serde_json::Value {
+            let res = client.post("/stac/search").header(ContentType::JSON).header(Header::new("Accept", accept.to_owned())).body(body).dispatch();
+            assert_eq!(res.status(), Status::Ok, "{}", body);
+            let text = res.into_string().unwrap();
+            let first = text.lines().next().unwrap();
+            serde_json::from_str(first).unwrap()
+        };
+
+        // without fields, every property is returned.
+        let fc = search("{}", "application/json");
+        assert!(fc["features"][0]["properties"].as_object().unwrap().len() > 1);
+
+        let fc = search(r#"{"fields": {"include": ["properties.gsd"], "exclude": ["assets"]}}"#, "application/json");
+        let item = &fc["features"][0];
+        assert_eq!(item["id"], "scene");
+        assert!(item["geometry"].is_object());
+        assert!(item.get("assets").is_none());
+        let mut properties: Vec<&String> = item["properties"].as_object().unwrap().keys().collect();
+        properties.sort();
+        assert_eq!(properties, vec!["datetime", "gsd"]);
+        assert_eq!(fc["numberReturned"], 1);
+
+        // streamed items are trimmed too.
+        let item = search(r#"{"fields": {"exclude": ["properties"]}}"#, "application/geo+json-seq");
+        assert_eq!(item["id"], "scene");
+        assert!(item.get("properties").is_none());
+    }
+
+    #[test]
+    fn test_admin_reload() {
+        // reloading is disabled without an admin token.
+        let client = test_client();
+        assert_eq!(client.post("/admin/reload").dispatch().status(), Status::NotFound);
+
+        let dir = test_dir("admin-reload");
+        std::fs::create_dir(dir.join("imagery")).unwrap();
+        write_geotiff(&dir.join("imagery").join("scene.tif"), 1);
+
+        let svc = test_service(HashMap::new());
+        let reload = Reload {
+            token: Some(String::from("secret")),
+            source: catalog::CatalogSource::Dir(dir.to_str().unwrap().to_owned()),
+            options: catalog::ScanOptions::default()
+        };
+        let client = Client::tracked(super::rocket(svc, cache_policy(), Thumbnails::new(false)).manage(reload)).unwrap();
+        assert_eq!(client.get("/collections/imagery").dispatch().status(), Status::NotFound);
+        let etag = client.get("/collections").dispatch().headers().get_one("ETag").unwrap().to_owned();
+
+        assert_eq!(client.post("/admin/reload").dispatch().status(), Status::Unauthorized);
+        let res = client.post("/admin/reload").header(Header::new("Authorization", "Bearer guess")).dispatch();
+        assert_eq!(res.status(), Status::Unauthorized);
+
+        let res = client.post("/admin/reload").header(Header::new("Authorization", "Bearer secret")).dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        let summary: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(summary["collections"], serde_json::json!([{"id": "imagery", "items": 1}]));
+        assert_eq!(client.get("/collections/imagery").dispatch().status(), Status::Ok);
+
+        // the rebuilt catalog's collections have a new ETag.
+        let res = client.get("/collections").header(Header::new("If-None-Match", etag.to_owned())).dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_ne!(res.headers().get_one("ETag"), Some(etag.as_str()));
+
+        // if the directory can't be read, the reload fails and the collections are kept.
+        std::fs::remove_dir_all(&dir).unwrap();
+        let res = client.post("/admin/reload").header(Header::new("Authorization", "Bearer secret")).dispatch();
+        assert_eq!(res.status(), Status::BadGateway);
+        assert_eq!(client.get("/collections/imagery").dispatch().status(), Status::Ok);
+    }
+
+    #[test]
+    fn test_admin_reload_s3_unavailable() {
+        // with the S3 circuit breaker open, the reload fails fast and the old collections are kept.
+        let breaker = breaker::CircuitBreaker::new(1, std::time::Duration::from_secs(600));
+        breaker.record_failure();
+        let svc = catalog::Service {
+            s3_breaker: Arc::new(breaker),
+            ..test_service(HashMap::new())
+        };
+        let reload = Reload {
+            token: Some(String::from("secret")),
+            source: catalog::CatalogSource::S3 {
+                host: catalog::parse_s3_host("http://localhost:9000").unwrap(),
+                bucket: String::from("imagery"),
+                prefix: None,
+                access_key: Some(String::from("access")),
+                secret_key: Some(String::from("secret")),
+                region: String::from("us-east-1")
+            },
+            options: catalog::ScanOptions::default()
+        };
+        let client = Client::tracked(super::rocket(svc, cache_policy(), Thumbnails::new(false)).manage(reload)).unwrap();
+        let res = client.post("/admin/reload").header(Header::new("Authorization", "Bearer secret")).dispatch();
+        assert_eq!(res.status(), Status::ServiceUnavailable);
+        assert!(client.get("/metrics").dispatch().into_string().unwrap().contains("autostac_s3_breaker_state 1\n"));
+    }
+
+    /// an "imagery" collection containing a single GeoTIFF, "scene", scanned from a new test directory.
+    fn geotiff_collections(name: &str, bands: isize) -> HashMap<String, catalog::ImageryCollection> {
+        let dir = test_dir(name);
+        let collection_dir = dir.join("imagery");
+        std::fs::create_dir(&collection_dir).unwrap();
+        write_geotiff(&collection_dir.join("scene.tif"), bands);
+        catalog::collections_from_subdirs(dir.to_str().unwrap(), &catalog::ScanOptions::default())
+    }
+
+    /// a client for a service with an "imagery" collection containing a single GeoTIFF, "scene".
+    fn geotiff_client(name: &str, bands: isize, thumbnails: bool) -> Client {
+        let svc = test_service(geotiff_collections(name, bands));
+        Client::tracked(super::rocket(svc, cache_policy(), Thumbnails::new(thumbnails))).unwrap()
+    }
+
+    #[test]
+    fn test_item_band() {
+        let client = geotiff_client("item-band", 4, false);
+
+        let res = client.get("/collections/imagery/scene/assets/b2").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.content_type(), Some(ContentType::TIFF));
+        let tiff = res.into_bytes().unwrap();
+        assert!(tiff.starts_with(b"II*\0") || tiff.starts_with(b"MM\0*"));
+
+        for asset in &["b0", "b5", "nir", "file"] {
+            let res = client.get(format!("/collections/imagery/scene/assets/{}", asset)).dispatch();
+            assert_eq!(res.status(), Status::NotFound);
+        }
+    }
+
+    #[test]
+    fn test_collection_thumbnail() {
+        let client = geotiff_client("thumbnail", 3, true);
+
+        let res = client.get("/collections/imagery").dispatch();
+        let collection: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        let href = collection["assets"]["thumbnail"]["href"].as_str().unwrap();
+        assert_eq!(href, "http://localhost:8000/collections/imagery/thumbnail");
+
+        let res = client.get("/collections/imagery/thumbnail").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.content_type(), Some(ContentType::PNG));
+        let png = res.into_bytes().unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+
+        // thumbnails are disabled by default.
+        let client = test_client();
+        let res = client.get("/collections/imagery").dispatch();
+        let collection: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert!(collection.get("assets").is_none());
+        assert_eq!(client.get("/collections/imagery/thumbnail").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_tiles() {
+        let client = geotiff_client("tiles", 3, false);
+
+        for path in &["/tiles/imagery/10/160/353", "/tiles/imagery/10/160/353.png"] {
+            let res = client.get(*path).dispatch();
+            assert_eq!(res.status(), Status::Ok);
+            assert_eq!(res.content_type(), Some(ContentType::PNG));
+            let png = res.into_bytes().unwrap();
+            assert!(png.starts_with(b"\x89PNG"));
+        }
+
+        // 512 pixel tiles, and tiles with a buffer around each edge. The PNG's width is in its IHDR chunk.
+        let width = |path: &str| {
+            let png = client.get(path).dispatch().into_bytes().unwrap();
+            u32::from_be_bytes([png[16], png[17], png[18], png[19]])
+        };
+        assert_eq!(width("/tiles/imagery/10/160/353.png"), 256);
+        assert_eq!(width("/tiles/imagery/10/160/353.png?tileSize=512"), 512);
+        assert_eq!(width("/tiles/imagery/10/160/353.png?tileSize=512&buffer=8"), 528);
+        // a fixed stretch and a colormap change how a single band file is drawn. The file's pixels are all 0,
+        // which is black by default.
+        let dem = geotiff_client("tiles-dem", 1, false);
+        let default = dem.get("/tiles/imagery/10/160/353.png").dispatch().into_bytes();
+        for path in &["/tiles/imagery/10/160/353.png?rescale=-1000,0", "/tiles/imagery/10/160/353.png?colormap=viridis"] {
+            let res = dem.get(*path).dispatch();
+            assert_eq!(res.status(), Status::Ok);
+            assert_ne!(res.into_bytes(), default);
+        }
+
+        // any of the file's bands can be drawn as RGB, or a single band with the colormap.
+        for path in &["/tiles/imagery/10/160/353.png?bands=3,2,1", "/tiles/imagery/10/160/353.png?bands=2&colormap=terrain"] {
+            assert_eq!(client.get(*path).dispatch().status(), Status::Ok);
+        }
+
+        for path in &[
+            "/tiles/imagery/10/160/353.png?tileSize=300",
+            "/tiles/imagery/10/160/353.png?buffer=1000",
+            "/tiles/imagery/10/160/353.png?rescale=10,0",
+            "/tiles/imagery/10/160/353.png?rescale=0",
+            "/tiles/imagery/10/160/353.png?colormap=rainbow",
+            "/tiles/imagery/10/160/353.png?bands=4,3,2",
+            "/tiles/imagery/10/160/353.png?bands=1,2",
+            "/tiles/imagery/10/160/353.png?bands=0"
+        ] {
+            assert_eq!(client.get(*path).dispatch().status(), Status::BadRequest);
+        }
+
+        // no imagery in the tile.
+        assert_eq!(client.get("/tiles/imagery/10/0/0").dispatch().status(), Status::NoContent);
+
+        for path in &["/tiles/imagery/1/5/0", "/tiles/imagery/10/160/abc", "/tiles/missing/10/160/353"] {
+            assert_eq!(client.get(*path).dispatch().status(), Status::NotFound);
+        }
+    }
+
+    #[test]
+    fn test_vector_tiles() {
+        let client = geotiff_client("vector-tiles", 1, false);
+
+        let res = client.get("/tiles/imagery/10/160/353.mvt").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.content_type(), Some(ContentType::new("application", "vnd.mapbox-vector-tile")));
+        assert!(res.headers().get_one("Content-Encoding").is_none());
+        let tile = res.into_bytes().unwrap();
+        assert!(tile.windows(9).any(|w| w == b"scene.tif"));
+
+        assert_eq!(client.get("/tiles/imagery/10/0/0.mvt").dispatch().status(), Status::NoContent);
+        for path in &["/tiles/imagery/1/5/0.mvt", "/tiles/imagery/10/160/353.pbf", "/tiles/missing/10/160/353.mvt"] {
+            assert_eq!(client.get(*path).dispatch().status(), Status::NotFound);
+        }
+    }
+
+    #[test]
+    fn test_wmts() {
+        let client = geotiff_client("wmts", 3, false);
+
+        let res = client.get("/wmts/1.0.0/WMTSCapabilities.xml").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.content_type(), Some(ContentType::XML));
+        let xml = res.into_string().unwrap();
+        assert!(xml.contains("<ows:Identifier>imagery</ows:Identifier>"));
+        assert!(xml.contains(r#"template="http://localhost:8000/wmts/tile/imagery/{TileMatrix}/{TileCol}/{TileRow}.png""#));
+        assert!(xml.contains("<TileMatrixSet>WebMercatorQuad</TileMatrixSet>"));
+        assert!(xml.contains("<MatrixWidth>1024</MatrixWidth>"));
+
+        // tiles are the same as the XYZ tiles.
+        let res = client.get("/wmts/tile/imagery/10/160/353.png").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.content_type(), Some(ContentType::PNG));
+        assert_eq!(res.into_bytes(), client.get("/tiles/imagery/10/160/353.png").dispatch().into_bytes());
+        assert_eq!(client.get("/wmts/tile/imagery/10/0/0.png").dispatch().status(), Status::NoContent);
+        assert_eq!(client.get("/wmts/tile/missing/10/160/353.png").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_wms() {
+        let client = geotiff_client("wms", 3, false);
+
+        let res = client.get("/wms?SERVICE=WMS&REQUEST=GetCapabilities").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.content_type(), Some(ContentType::XML));
+        let xml = res.into_string().unwrap();
+        assert!(xml.contains("<Name>imagery</Name>"));
+        assert!(xml.contains(r#"<OnlineResource xlink:href="http://localhost:8000/wms?"/>"#));
+
+        // the same map in web mercator, and in lat/lng (in WMS 1.3.0 and 1.1.1 axis order).
+        for query in &[
+            "LAYERS=imagery&CRS=EPSG:3857&BBOX=-13775786,6183449,-13736651,6222585",
+            "LAYERS=imagery&CRS=EPSG:4326&BBOX=48.5,-123.5,48.6,-123.4",
+            "version=1.1.1&layers=imagery&srs=EPSG:4326&bbox=-123.5,48.5,-123.4,48.6",
+        ] {
+            let res = client.get(format!("/wms?SERVICE=WMS&REQUEST=GetMap&WIDTH=200&HEIGHT=100&FORMAT=image/png&{}", query)).dispatch();
+            assert_eq!(res.status(), Status::Ok, "{}", query);
+            assert_eq!(res.content_type(), Some(ContentType::PNG));
+            let png = res.into_bytes().unwrap();
+            assert_eq!(u32::from_be_bytes([png[16], png[17], png[18], png[19]]), 200);
+            assert_eq!(u32::from_be_bytes([png[20], png[21], png[22], png[23]]), 100);
+        }
+
+        for query in &[
+            "REQUEST=GetMap&LAYERS=missing&CRS=EPSG:3857&BBOX=-13775786,6183449,-13736651,6222585&WIDTH=256&HEIGHT=256",
+            "REQUEST=GetMap&LAYERS=imagery&CRS=EPSG:3857&WIDTH=256&HEIGHT=256",
+            "REQUEST=GetFeatureInfo",
+            "SERVICE=WFS&REQUEST=GetCapabilities",
+            "",
+        ] {
+            let res = client.get(format!("/wms?{}", query)).dispatch();
+            assert_eq!(res.status(), Status::BadRequest, "{}", query);
+            assert!(res.into_string().unwrap().contains("<ServiceExceptionReport"));
+        }
+
+        // maps drawn from more than max_features files are refused.
+        let svc = catalog::Service { max_features: 0, ..test_service(geotiff_collections("wms-max-features", 3)) };
+        let client = Client::tracked(super::rocket(svc, cache_policy(), Thumbnails::new(false))).unwrap();
+        let res = client.get("/wms?SERVICE=WMS&REQUEST=GetMap&WIDTH=200&HEIGHT=100&LAYERS=imagery&CRS=EPSG:4326&BBOX=48.5,-123.5,48.6,-123.4").dispatch();
+        assert_eq!(res.status(), Status::BadRequest);
+        assert!(res.into_string().unwrap().contains("more than the 0 that can be drawn at once"));
+    }
+
+    #[test]
+    fn test_tilejson() {
+        let client = geotiff_client("tilejson", 3, false);
+
+        let res = client.get("/collections/imagery/tilejson.json").dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        assert_eq!(res.content_type(), Some(ContentType::JSON));
+        let tilejson: serde_json::Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(tilejson["tilejson"], "3.0.0");
+        assert_eq!(tilejson["tiles"], serde_json::json!(["http://localhost:8000/tiles/imagery/{z}/{x}/{y}.png"]));
+        let bounds: Vec<f64> = tilejson["bounds"].as_array().unwrap().iter().map(|v| v.as_f64().unwrap()).collect();
+        for (b, expected) in bounds.iter().zip(&[-123.5, 48.5, -123.4, 48.6]) {
+            assert!((b - expected).abs() < 1e-6);
+        }
+        // the file's pixels are about 900 m across, which is between zoom levels 7 and 8.
+        assert_eq!(tilejson["minzoom"], 8);
+        assert_eq!(tilejson["maxzoom"], 8);
+
+        // collections link to their TileJSON.
+        let links = link_paths(&client, "/collections/imagery");
+        assert!(links.contains(&(String::from("tilejson"), String::from("/collections/imagery/tilejson.json"))));
+
+        assert_eq!(client.get("/collections/missing/tilejson.json").dispatch().status(), Status::NotFound);
+    }
 }
@@ -0,0 +1,94 @@
+use log::warn;
+use url::Url;
+
+/// ManifestEntry is a file listed in an HTTP manifest.
+#[derive(Debug, PartialEq)]
+pub struct ManifestEntry {
+  /// the HTTP(S) URL the file is read from (through GDAL's /vsicurl/ filesystem) and linked to.
+  pub url: String,
+  /// the file's name, the last segment of its URL path.
+  pub filename: String,
+  /// the name of the collection the file goes into.
+  pub collection: String
+}
+
+/// parse the contents of an HTTP manifest: a list of HTTP(S) URLs, one per line, each optionally followed
+/// (after whitespace) by the name of the collection the file goes into. Files without a collection go into
+/// a collection named after the directory they are in, e.g. `imagery` for `https://example.com/imagery/img1.tif`.
+/// Blank lines and lines starting with `#` are skipped, as are lines that aren't HTTP(S) URLs of files.
+pub fn parse(contents: &str) -> Vec<ManifestEntry> {
+  let mut entries = Vec::new();
+  for line in contents.lines().map(|l| l.trim()) {
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let (url, collection) = match line.split_once(char::is_whitespace) {
+      Some((url, collection)) => (url, Some(collection.trim())),
+      None => (line, None),
+    };
+    match entry(url, collection) {
+      Ok(e) => entries.push(e),
+      Err(e) => warn!("skipping manifest line `{}`: {}", line, e),
+    }
+  }
+  entries
+}
+
+fn entry(url: &str, collection: Option<&str>) -> Result<ManifestEntry, String> {
+  let parsed = Url::parse(url).map_err(|e| e.to_string())?;
+  if parsed.scheme() != "http" && parsed.scheme() != "https" {
+    return Err(String::from("only HTTP(S) URLs can be catalogued"));
+  }
+
+  let segments: Vec<&str> = parsed.path_segments().map(|s| s.collect()).unwrap_or_default();
+  let filename = match segments.last() {
+    Some(name) if !name.is_empty() => name.to_string(),
+    _ => return Err(String::from("the URL is not a file")),
+  };
+
+  // without a collection column, the file's directory (or the host, for files at the root) names its collection.
+  let collection = match collection {
+    Some(c) => c.to_owned(),
+    None => match segments.len() {
+      n if n > 1 => segments[n - 2].to_owned(),
+      _ => parsed.host_str().unwrap_or_default().to_owned(),
+    },
+  };
+
+  Ok(ManifestEntry { url: url.to_owned(), filename, collection })
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::manifest::{parse, ManifestEntry};
+
+  #[test]
+  fn test_parse() {
+    let entries = parse("\
+      # imagery on the CDN\n\
+      https://example.com/imagery/img1.tif\n\
+      \n\
+      https://example.com/img2.tif\tAir Photos\n\
+      http://example.com/img3.tif\n\
+      s3://bucket/img4.tif\n\
+      https://example.com/imagery/\n\
+    ");
+    assert_eq!(entries, vec![
+      ManifestEntry {
+        url: String::from("https://example.com/imagery/img1.tif"),
+        filename: String::from("img1.tif"),
+        collection: String::from("imagery")
+      },
+      ManifestEntry {
+        url: String::from("https://example.com/img2.tif"),
+        filename: String::from("img2.tif"),
+        collection: String::from("Air Photos")
+      },
+      ManifestEntry {
+        url: String::from("http://example.com/img3.tif"),
+        filename: String::from("img3.tif"),
+        collection: String::from("example.com")
+      },
+    ]);
+  }
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use std::fs;
+use gdal::{Dataset, Metadata};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// PropertyMapping passes GDAL metadata items through into STAC item properties.
+/// This lets a deployment reproduce a provider's property schema (e.g. Planet or Maxar
+/// custom fields) in addition to the properties autostac extracts itself.
+///
+/// Mappings are read from a TOML file (mapping.toml). Each key is the namespaced property
+/// to emit, and each value is the GDAL metadata item to read it from:
+///
+///     [properties]
+///     "planet:quality_category" = "QUALITY_CATEGORY"
+///
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct PropertyMapping {
+  #[serde(default)]
+  pub properties: BTreeMap<String, String>
+}
+
+impl PropertyMapping {
+    /// read and validate a property mapping file.
+    pub fn from_file(path: &str) -> Result<PropertyMapping, String> {
+      let contents = fs::read_to_string(path)
+        .map_err(|e| format!("could not read property mapping {}: {}", path, e))?;
+      PropertyMapping::parse(&contents)
+    }
+
+    /// parse and validate the contents of a property mapping file.
+    pub fn parse(contents: &str) -> Result<PropertyMapping, String> {
+      let mapping: PropertyMapping = toml::from_str(contents)
+        .map_err(|e| format!("invalid property mapping: {}", e))?;
+
+      for property in mapping.properties.keys() {
+        validate_property_name(property)?;
+      }
+      Ok(mapping)
+    }
+
+    /// read each mapped metadata item from a dataset.
+    /// Metadata items that the dataset doesn't have are skipped.
+    pub fn extract(&self, dataset: &Dataset) -> Map<String, Value> {
+      let mut properties = Map::new();
+      for (property, metadata_key) in self.properties.iter() {
+        if let Some(v) = dataset.metadata_item(metadata_key, "") {
+          properties.insert(property.to_owned(), Value::String(v));
+        }
+      }
+      properties
+    }
+}
+
+/// custom properties must be namespaced like the STAC extensions are, e.g. `planet:quality_category`.
+/// The prefix must start with a lowercase letter and can contain lowercase letters,
+/// digits, `-` and `_`.
+fn validate_property_name(property: &str) -> Result<(), String> {
+  let err = || Err(format!(
+    "invalid property name `{}`. Properties must be namespaced (e.g. `planet:quality_category`)", property
+  ));
+
+  let (prefix, name) = match property.split_once(':') {
+    Some(p) => p,
+    None => return err(),
+  };
+
+  let valid_prefix = prefix.starts_with(|c: char| c.is_ascii_lowercase())
+    && prefix.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+  let valid_name = !name.is_empty() && !name.contains(':') && !name.contains(char::is_whitespace);
+
+  if !valid_prefix || !valid_name {
+    return err();
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use gdal::{Driver, Metadata};
+  use crate::mapping::{PropertyMapping, validate_property_name};
+
+  #[test]
+  fn test_extract_namespaced_property() {
+    let mapping = PropertyMapping::parse(
+      "[properties]\n\"planet:quality_category\" = \"QUALITY_CATEGORY\"\n"
+    ).unwrap();
+
+    let mut dataset = Driver::get("MEM").unwrap().create("", 1, 1, 1).unwrap();
+    dataset.set_metadata_item("QUALITY_CATEGORY", "standard", "").unwrap();
+
+    let properties = mapping.extract(&dataset);
+    assert_eq!(properties["planet:quality_category"], "standard");
+  }
+
+  #[test]
+  fn test_validate_property_name() {
+    assert!(validate_property_name("planet:quality_category").is_ok());
+    assert!(validate_property_name("msft:region").is_ok());
+    assert!(validate_property_name("quality_category").is_err());
+    assert!(validate_property_name(":quality_category").is_err());
+    assert!(validate_property_name("Planet:quality_category").is_err());
+    assert!(validate_property_name("planet:").is_err());
+  }
+}
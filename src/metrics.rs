@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use rocket::{Data, Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Method;
+use crate::breaker::BreakerState;
+
+/// the number of files that GDAL couldn't open while cataloguing (including rescans). Files are catalogued
+/// before the server starts and by the directory watcher, outside of any request, so this isn't kept in Metrics.
+pub static GDAL_OPEN_FAILURES: AtomicUsize = AtomicUsize::new(0);
+
+/// the upper bounds (in seconds) of the search latency histogram's buckets.
+const LATENCY_BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1., 2.5, 5.];
+
+/// Metrics counts the requests handled by the service, for GET /metrics. It's kept in managed state,
+/// and requests are recorded by the RequestMetrics fairing.
+#[derive(Debug, Default)]
+pub struct Metrics {
+  /// the number of requests for each method and route. Requests that didn't match a route are counted under `none`.
+  requests: Mutex<BTreeMap<(String, String), u64>>,
+  search_latency: Mutex<Histogram>
+}
+
+/// a Prometheus histogram of durations in seconds, with the buckets in LATENCY_BUCKETS.
+#[derive(Debug, Default)]
+struct Histogram {
+  /// the number of observations in each bucket (and all smaller buckets).
+  buckets: [u64; LATENCY_BUCKETS.len()],
+  count: u64,
+  sum: f64
+}
+
+impl Histogram {
+  fn observe(&mut self, seconds: f64) {
+    for (bucket, le) in self.buckets.iter_mut().zip(LATENCY_BUCKETS.iter()) {
+      if seconds <= *le {
+        *bucket += 1;
+      }
+    }
+    self.count += 1;
+    self.sum += seconds;
+  }
+}
+
+impl Metrics {
+    /// records a request for `route` that took `seconds`. Only searches are added to the latency histogram.
+    pub fn record(&self, method: Method, route: &str, seconds: f64) {
+      *self.requests.lock().unwrap().entry((method.to_string(), route.to_owned())).or_insert(0) += 1;
+      if method == Method::Post && route == "/stac/search" {
+        self.search_latency.lock().unwrap().observe(seconds);
+      }
+    }
+
+    /// the metrics in the Prometheus text format, along with the size of the catalog and the state of the
+    /// S3 circuit breaker. https://prometheus.io/docs/instrumenting/exposition_formats/
+    pub fn render(&self, collections: usize, items: usize, s3_breaker: BreakerState) -> String {
+      let mut text = String::new();
+
+      text.push_str("# HELP autostac_requests_total The number of requests handled, by route.\n");
+      text.push_str("# TYPE autostac_requests_total counter\n");
+      for ((method, route), n) in self.requests.lock().unwrap().iter() {
+        let _ = writeln!(text, "autostac_requests_total{{method=\"{}\",route=\"{}\"}} {}", method, route, n);
+      }
+
+      let latency = self.search_latency.lock().unwrap();
+      text.push_str("# HELP autostac_search_duration_seconds How long searches (POST /stac/search) took.\n");
+      text.push_str("# TYPE autostac_search_duration_seconds histogram\n");
+      for (n, le) in latency.buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+        let _ = writeln!(text, "autostac_search_duration_seconds_bucket{{le=\"{}\"}} {}", le, n);
+      }
+      let _ = writeln!(text, "autostac_search_duration_seconds_bucket{{le=\"+Inf\"}} {}", latency.count);
+      let _ = writeln!(text, "autostac_search_duration_seconds_sum {}", latency.sum);
+      let _ = writeln!(text, "autostac_search_duration_seconds_count {}", latency.count);
+
+      text.push_str("# HELP autostac_collections The number of collections in the catalog.\n");
+      text.push_str("# TYPE autostac_collections gauge\n");
+      let _ = writeln!(text, "autostac_collections {}", collections);
+      text.push_str("# HELP autostac_items The number of items in the catalog.\n");
+      text.push_str("# TYPE autostac_items gauge\n");
+      let _ = writeln!(text, "autostac_items {}", items);
+      text.push_str("# HELP autostac_gdal_open_failures_total The number of files that GDAL couldn't open while cataloguing.\n");
+      text.push_str("# TYPE autostac_gdal_open_failures_total counter\n");
+      let _ = writeln!(text, "autostac_gdal_open_failures_total {}", GDAL_OPEN_FAILURES.load(Ordering::Relaxed));
+      text.push_str("# HELP autostac_s3_breaker_state The state of the S3 circuit breaker (0 closed, 1 open, 2 half-open).\n");
+      text.push_str("# TYPE autostac_s3_breaker_state gauge\n");
+      let state = match s3_breaker {
+        BreakerState::Closed => 0,
+        BreakerState::Open => 1,
+        BreakerState::HalfOpen => 2,
+      };
+      let _ = writeln!(text, "autostac_s3_breaker_state {}", state);
+      text
+    }
+}
+
+/// the time a request was received, kept in the request's local cache.
+struct RequestStart(Instant);
+
+/// RequestMetrics records each request (and how long it took) in the Metrics in managed state.
+pub struct RequestMetrics;
+
+#[rocket::async_trait]
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+      Info {
+        name: "Request metrics",
+        kind: Kind::Request | Kind::Response,
+      }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+      request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, _response: &mut Response<'r>) {
+      if let Some(metrics) = request.rocket().state::<Metrics>() {
+        let start = request.local_cache(|| RequestStart(Instant::now()));
+        let route = request.route().map(|r| r.uri.path()).unwrap_or("none");
+        metrics.record(request.method(), route, start.0.elapsed().as_secs_f64());
+      }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+  use rocket::http::Method;
+  use crate::breaker::BreakerState;
+  use crate::metrics::Metrics;
+
+  #[test]
+  fn test_render() {
+    let metrics = Metrics::default();
+    metrics.record(Method::Get, "/collections", 0.001);
+    metrics.record(Method::Post, "/stac/search", 0.02);
+    metrics.record(Method::Post, "/stac/search", 3.);
+
+    let text = metrics.render(2, 10, BreakerState::Open);
+    assert!(text.contains("autostac_requests_total{method=\"GET\",route=\"/collections\"} 1\n"));
+    assert!(text.contains("autostac_requests_total{method=\"POST\",route=\"/stac/search\"} 2\n"));
+    assert!(text.contains("autostac_search_duration_seconds_bucket{le=\"0.01\"} 0\n"));
+    assert!(text.contains("autostac_search_duration_seconds_bucket{le=\"0.025\"} 1\n"));
+    assert!(text.contains("autostac_search_duration_seconds_bucket{le=\"5\"} 2\n"));
+    assert!(text.contains("autostac_search_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+    assert!(text.contains("autostac_search_duration_seconds_count 2\n"));
+    assert!(text.contains("autostac_collections 2\n"));
+    assert!(text.contains("autostac_items 10\n"));
+    assert!(text.contains("autostac_s3_breaker_state 1\n"));
+  }
+}
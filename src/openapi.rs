@@ -0,0 +1,150 @@
+use serde_json::{json, Value};
+use url::Url;
+
+/// the media type of OpenAPI 3.0 documents, used for the `service-desc` link.
+pub static OPENAPI_MEDIA_TYPE: &str = "application/vnd.oai.openapi+json;version=3.0";
+
+/// a minimal OpenAPI 3.0 description of the core STAC API endpoints, served at /api.
+/// Responses are only described by their media type; see the STAC API spec for their schemas.
+pub fn openapi(title: &str, description: &str, base_url: &Url) -> Value {
+  let json_response = |description: &str, media_type: &str| json!({
+    "description": description,
+    "content": { media_type: { "schema": { "type": "object" } } }
+  });
+  let error = |description: &str| json_response(description, "application/json");
+  let query = |name: &str, description: &str| json!({
+    "name": name,
+    "in": "query",
+    "description": description,
+    "required": false,
+    "schema": { "type": "string" }
+  });
+  let path = |name: &str, description: &str| json!({
+    "name": name,
+    "in": "path",
+    "description": description,
+    "required": true,
+    "schema": { "type": "string" }
+  });
+
+  json!({
+    "openapi": "3.0.3",
+    "info": {
+      "title": title,
+      "description": description,
+      "version": env!("CARGO_PKG_VERSION")
+    },
+    // paths are joined onto the server URL, so it doesn't end with a slash.
+    "servers": [{ "url": base_url.as_str().trim_end_matches('/') }],
+    "paths": {
+      "/": {
+        "get": {
+          "summary": "landing page",
+          "parameters": [query("f", "`json` (default) or `html`")],
+          "responses": { "200": json_response("The STAC API landing page", "application/json") }
+        }
+      },
+      "/api": {
+        "get": {
+          "summary": "this API description",
+          "responses": { "200": json_response("The OpenAPI description of the API", OPENAPI_MEDIA_TYPE) }
+        }
+      },
+      "/conformance": {
+        "get": {
+          "summary": "conformance classes",
+          "responses": { "200": json_response("The conformance classes that the API implements", "application/json") }
+        }
+      },
+      "/collections": {
+        "get": {
+          "summary": "list collections",
+          "parameters": [query("f", "`json` (default) or `html`")],
+          "responses": { "200": json_response("Every collection in the catalog", "application/json") }
+        }
+      },
+      "/collections/{collectionId}": {
+        "get": {
+          "summary": "a collection, or its items filtered by location",
+          "description": "Returns the collection, or a page of its items as a GeoJSON FeatureCollection if \
+            `bbox`, `intersects` or `contains` is given.",
+          "parameters": [
+            path("collectionId", "The collection's id"),
+            query("bbox", "Only items intersecting a bounding box: minx,miny,maxx,maxy"),
+            query("intersects", "Only items intersecting a WKT (or GeoJSON) geometry"),
+            query("contains", "Only items containing a WKT polygon, multipolygon or geometry collection of polygons"),
+            query("contains_mode", "`strict` (default) or `covers`"),
+            query("sortby", "`spatial_resolution`, `cloud_cover`, `datetime` or `ndvi_capable`, with a `-` prefix to sort descending"),
+            query("limit", "The number of items per page"),
+            query("offset", "The number of items to skip"),
+            query("f", "`html` to return the collection as an HTML page, or `ndjson` to return the items as newline-delimited GeoJSON")
+          ],
+          "responses": {
+            "200": json_response("The collection, or a page of its items", "application/json"),
+            "400": error("Invalid query"),
+            "404": error("Collection not found")
+          }
+        }
+      },
+      "/collections/{collectionId}/items": {
+        "get": {
+          "summary": "a page of a collection's items",
+          "parameters": [
+            path("collectionId", "The collection's id"),
+            query("bbox", "Only items intersecting a bounding box: minx,miny,maxx,maxy"),
+            query("datetime", "Only items at a datetime, or in an interval (e.g. `2021-01-01T00:00:00Z/..`)"),
+            query("limit", "The number of items per page"),
+            query("offset", "The number of items to skip"),
+            query("f", "`ndjson` to return the items as newline-delimited GeoJSON")
+          ],
+          "responses": {
+            "200": json_response("A page of the collection's items", "application/geo+json"),
+            "400": error("Invalid query"),
+            "404": error("Collection not found")
+          }
+        }
+      },
+      "/queryables": {
+        "get": {
+          "summary": "queryable item properties",
+          "responses": { "200": json_response("A JSON Schema of the properties that items can be filtered by", "application/schema+json") }
+        }
+      },
+      "/collections/{collectionId}/queryables": {
+        "get": {
+          "summary": "queryable item properties of a collection",
+          "parameters": [path("collectionId", "The collection's id")],
+          "responses": {
+            "200": json_response("A JSON Schema of the properties that the collection's items can be filtered by", "application/schema+json"),
+            "404": error("Collection not found")
+          }
+        }
+      },
+      "/collections/{collectionId}/{itemId}": {
+        "get": {
+          "summary": "a single item",
+          "parameters": [path("collectionId", "The collection's id"), path("itemId", "The item's id")],
+          "responses": {
+            "200": json_response("The item", "application/geo+json"),
+            "404": error("Item not found")
+          }
+        }
+      },
+      "/stac/search": {
+        "post": {
+          "summary": "search items in every collection",
+          "description": "Takes a JSON body with `collections`, `ids`, `bbox`, `intersects` (a GeoJSON geometry or WKT), \
+            `contains`, `datetime`, `sortby`, `limit`, `fields` and a CQL2-JSON `filter` on the properties listed at /queryables.",
+          "parameters": [query("f", "`ndjson` to return the items as newline-delimited GeoJSON")],
+          "requestBody": {
+            "content": { "application/json": { "schema": { "type": "object" } } }
+          },
+          "responses": {
+            "200": json_response("A page of matching items", "application/geo+json"),
+            "400": error("Invalid search")
+          }
+        }
+      }
+    }
+  })
+}
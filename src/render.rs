@@ -0,0 +1,225 @@
+use std::env;
+use std::fs;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use gdal::{Dataset, Driver, GeoTransform};
+use gdal::raster::{reproject, Buffer};
+use gdal_sys::{GDALComputeRasterMinMax, GDALGetRasterBand};
+use log::warn;
+use crate::catalog::ImageryFile;
+
+/// counts renders so concurrent renders write to different temporary files.
+static RENDER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Stretch controls the range of values that is scaled to 0-255 for each band of a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stretch {
+  /// the min/max of the file's pixels inside the rendered area.
+  Window,
+  /// the (approximate) min/max of the whole file, so that neighbouring renders
+  /// of the same file (e.g. tiles) use the same colors.
+  File,
+  /// a fixed min/max for every band, e.g. the range of elevations in a DEM.
+  Range(f64, f64)
+}
+
+/// Colormap colors the pixels of single band files (e.g. DEMs) after they are stretched to 0-255.
+/// Files with 3 or more bands are always drawn as RGB.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Colormap {
+  Gray,
+  Viridis,
+  Terrain
+}
+
+/// the colors of each colormap at positions between 0 and 1. Values between two positions are interpolated.
+const VIRIDIS: [(f64, [u8; 3]); 5] = [
+  (0., [68, 1, 84]),
+  (0.25, [59, 82, 139]),
+  (0.5, [33, 145, 140]),
+  (0.75, [94, 201, 98]),
+  (1., [253, 231, 37])
+];
+const TERRAIN: [(f64, [u8; 3]); 6] = [
+  (0., [51, 51, 153]),
+  (0.15, [0, 153, 255]),
+  (0.25, [0, 204, 102]),
+  (0.5, [255, 255, 153]),
+  (0.75, [128, 92, 84]),
+  (1., [255, 255, 255])
+];
+
+impl Colormap {
+    /// the colormap named `name` (`gray`, `viridis` or `terrain`).
+    pub fn parse(name: &str) -> Option<Colormap> {
+      match name {
+        "gray" => Some(Colormap::Gray),
+        "viridis" => Some(Colormap::Viridis),
+        "terrain" => Some(Colormap::Terrain),
+        _ => None,
+      }
+    }
+
+    /// the color of a stretched (0-255) value.
+    pub fn color(self, value: u8) -> [u8; 3] {
+      let stops: &[(f64, [u8; 3])] = match self {
+        Colormap::Gray => return [value; 3],
+        Colormap::Viridis => &VIRIDIS,
+        Colormap::Terrain => &TERRAIN,
+      };
+      let t = value as f64 / 255.;
+      let i = stops.iter().position(|(pos, _)| *pos >= t).unwrap_or(stops.len() - 1).max(1);
+      let ((p0, c0), (p1, c1)) = (stops[i - 1], stops[i]);
+      let f = (t - p0) / (p1 - p0);
+      let mut color = [0; 3];
+      for (c, (a, b)) in color.iter_mut().zip(c0.iter().zip(c1.iter())) {
+        *c = (*a as f64 + (*b as f64 - *a as f64) * f).round() as u8;
+      }
+      color
+    }
+}
+
+/// renders a PNG of `files` onto an image of `size` (width, height) pixels with the
+/// geotransform `geo_transform` in the CRS `wkt`.
+/// Each file is reprojected onto the image and stretched to 0-255 (see Stretch), and
+/// files later in the list are drawn over earlier ones.
+/// `bands` (starting at 1) are drawn as RGB if there are three of them, or with `colormap` if there is one.
+/// Without `bands`, files with 3 or more bands are drawn with their first three bands as RGB, and other files
+/// are drawn with `colormap`. Pixels that no file covers are transparent.
+pub fn render_png(
+  files: &[ImageryFile],
+  geo_transform: GeoTransform,
+  wkt: &str,
+  size: (usize, usize),
+  stretch: Stretch,
+  colormap: Colormap,
+  bands: Option<&[isize]>
+) -> Result<Vec<u8>, String> {
+  let (width, height) = size;
+
+  // RGBA pixels. Pixels that no file covers stay transparent.
+  let mut rgba: Vec<Vec<u8>> = vec![vec![0; width * height]; 4];
+
+  let mem = Driver::get("MEM").map_err(|e| e.to_string())?;
+  for f in files {
+    let src = match f.open() {
+      Ok(ds) => ds,
+      Err(e) => {
+        warn!("skipping {} in render: {}", f.properties.path, e);
+        continue
+      }
+    };
+
+    let bands: &[isize] = match bands {
+      Some(b) => b,
+      None if src.raster_count() >= 3 => &[1, 2, 3],
+      None => &[1],
+    };
+    let mut dst = mem.create_with_band_type::<f64>("", width as isize, height as isize, src.raster_count())
+      .map_err(|e| e.to_string())?;
+    dst.set_geo_transform(&geo_transform).map_err(|e| e.to_string())?;
+    dst.set_projection(wkt).map_err(|e| e.to_string())?;
+
+    // fill the destination with NaN so that pixels outside the file can be told apart from data.
+    for i in 1..=dst.raster_count() {
+      dst.rasterband(i)
+        .and_then(|mut b| b.write((0, 0), (width, height), &Buffer::new((width, height), vec![f64::NAN; width * height])))
+        .map_err(|e| e.to_string())?;
+    }
+
+    if let Err(e) = reproject(&src, &dst) {
+      warn!("skipping {} in render: {}", f.properties.path, e);
+      continue
+    }
+
+    for (channel, band) in bands.iter().enumerate() {
+      let data = dst.rasterband(*band)
+        .and_then(|b| b.read_as::<f64>((0, 0), (width, height), (width, height), None))
+        .map_err(|e| e.to_string())?
+        .data;
+
+      let (min, max) = match stretch {
+        Stretch::Window => data.iter()
+          .filter(|v| !v.is_nan())
+          .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| (min.min(*v), max.max(*v))),
+        Stretch::File => band_min_max(&src, *band),
+        Stretch::Range(min, max) => (min, max),
+      };
+      let range = if max > min { max - min } else { 1. };
+
+      for (i, v) in data.iter().enumerate() {
+        if v.is_nan() {
+          continue
+        }
+        let value = ((v - min) / range * 255.).round().clamp(0., 255.) as u8;
+        if bands.len() == 3 {
+          rgba[channel][i] = value;
+        } else {
+          let [r, g, b] = colormap.color(value);
+          rgba[0][i] = r;
+          rgba[1][i] = g;
+          rgba[2][i] = b;
+        }
+        rgba[3][i] = 255;
+      }
+    }
+  }
+
+  let image = mem.create("", width as isize, height as isize, 4).map_err(|e| e.to_string())?;
+  for (i, data) in rgba.into_iter().enumerate() {
+    image.rasterband(i as isize + 1)
+      .and_then(|mut b| b.write((0, 0), (width, height), &Buffer::new((width, height), data)))
+      .map_err(|e| e.to_string())?;
+  }
+
+  // the PNG driver can only write with CreateCopy, so write the PNG to a temporary file.
+  let path = env::temp_dir().join(format!("autostac-render-{}-{}.png", process::id(), RENDER_COUNT.fetch_add(1, Ordering::Relaxed)));
+  let path_str = path.to_str().ok_or("invalid temporary file path")?;
+  let png = Driver::get("PNG").map_err(|e| e.to_string())?;
+  image.create_copy(&png, path_str).map_err(|e| e.to_string())?;
+  let bytes = fs::read(&path).map_err(|e| e.to_string());
+  let _ = fs::remove_file(&path);
+  let _ = fs::remove_file(path.with_extension("png.aux.xml"));
+  bytes
+}
+
+/// the approximate min/max of a band (starting at 1) of a dataset.
+fn band_min_max(dataset: &Dataset, band: isize) -> (f64, f64) {
+  let mut min_max = [0.; 2];
+  unsafe {
+    let c_band = GDALGetRasterBand(dataset.c_dataset(), band as i32);
+    if !c_band.is_null() {
+      GDALComputeRasterMinMax(c_band, 1, min_max.as_mut_ptr());
+    }
+  }
+  (min_max[0], min_max[1])
+}
+
+#[cfg(test)]
+mod tests {
+  use gdal::Driver;
+  use gdal::raster::Buffer;
+  use crate::render::{band_min_max, Colormap};
+
+  #[test]
+  fn test_band_min_max() {
+    let dataset = Driver::get("MEM").unwrap().create("", 10, 10, 1).unwrap();
+    let data: Vec<u8> = (0..100).map(|v| v + 20).collect();
+    dataset.rasterband(1).unwrap().write((0, 0), (10, 10), &Buffer::new((10, 10), data)).unwrap();
+    assert_eq!(band_min_max(&dataset, 1), (20., 119.));
+  }
+
+  #[test]
+  fn test_colormap() {
+    assert_eq!(Colormap::parse("viridis"), Some(Colormap::Viridis));
+    assert_eq!(Colormap::parse("rainbow"), None);
+
+    assert_eq!(Colormap::Gray.color(100), [100, 100, 100]);
+    assert_eq!(Colormap::Viridis.color(0), [68, 1, 84]);
+    assert_eq!(Colormap::Viridis.color(255), [253, 231, 37]);
+    assert_eq!(Colormap::Terrain.color(0), [51, 51, 153]);
+    assert_eq!(Colormap::Terrain.color(255), [255, 255, 255]);
+    // 159 is about halfway between the 0.5 and 0.75 positions.
+    assert_eq!(Colormap::Viridis.color(159), [63, 173, 119]);
+  }
+}
@@ -0,0 +1,182 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use geo_types::MultiPolygon;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use crate::catalog::ImageryFileProperties;
+use crate::sidecar;
+
+/// ScanCache keeps the metadata read from each file in a JSON file (`--cache-file`), so that restarting
+/// the service only opens the files that are new or have changed since the last scan.
+///
+/// Entries are keyed by the file's GDAL path, and are only used while the file's version (its size and
+/// modification time, or its S3 ETag) is the same. The whole cache is discarded if the options that
+/// change how files are read (e.g. the property mapping) are different from the ones it was written with.
+#[derive(Debug)]
+pub struct ScanCache {
+  path: PathBuf,
+  inner: Mutex<CacheInner>
+}
+
+#[derive(Debug, Default)]
+struct CacheInner {
+  contents: CacheContents,
+  /// the keys that have been looked up or added since the cache was last saved.
+  used: HashSet<String>,
+  changed: bool
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheContents {
+  /// the scan options that the entries were read with (see ScanOptions::cache_key).
+  options: String,
+  entries: HashMap<String, CacheEntry>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+  version: String,
+  properties: ImageryFileProperties,
+  boundary: geojson::Geometry
+}
+
+impl ScanCache {
+    /// reads the cache file at `path`. If it doesn't exist, can't be read, or was written with
+    /// different options, the cache starts out empty.
+    pub fn load(path: &Path, options: &str) -> ScanCache {
+      let contents = match fs::read_to_string(path) {
+        Ok(json) => match serde_json::from_str::<CacheContents>(&json) {
+          Ok(c) if c.options == options => c,
+          Ok(_) => {
+            info!("scan options have changed since {} was written. Every file will be read again.", path.display());
+            CacheContents::default()
+          },
+          Err(e) => {
+            warn!("ignoring invalid cache file {}: {}", path.display(), e);
+            CacheContents::default()
+          },
+        },
+        Err(_) => CacheContents::default(),
+      };
+      info!("loaded {} cached files from {}", contents.entries.len(), path.display());
+
+      ScanCache {
+        path: path.to_owned(),
+        inner: Mutex::new(CacheInner {
+          contents: CacheContents { options: options.to_owned(), ..contents },
+          ..Default::default()
+        })
+      }
+    }
+
+    /// the cached properties and footprint of the file at `key`, if they were cached at this `version`.
+    pub fn get(&self, key: &str, version: &str) -> Option<(ImageryFileProperties, MultiPolygon<f64>)> {
+      let mut inner = self.inner.lock().unwrap();
+      let entry = inner.contents.entries.get(key).filter(|e| e.version == version)?.clone();
+      let boundary = MultiPolygon::try_from(entry.boundary.value).ok()?;
+      inner.used.insert(key.to_owned());
+      Some((entry.properties, boundary))
+    }
+
+    /// caches the properties and footprint of the file at `key`.
+    pub fn insert(&self, key: &str, version: &str, properties: &ImageryFileProperties, boundary: &MultiPolygon<f64>) {
+      let mut inner = self.inner.lock().unwrap();
+      inner.contents.entries.insert(key.to_owned(), CacheEntry {
+        version: version.to_owned(),
+        properties: properties.to_owned(),
+        boundary: geojson::Geometry::from(boundary)
+      });
+      inner.used.insert(key.to_owned());
+      inner.changed = true;
+    }
+
+    /// writes the cache file, if anything has changed. After a scan of the whole catalog, `prune`
+    /// drops the entries of files that weren't seen (e.g. because they were deleted).
+    pub fn save(&self, prune: bool) {
+      let mut inner = self.inner.lock().unwrap();
+      if prune {
+        let CacheInner { contents, used, changed } = &mut *inner;
+        let before = contents.entries.len();
+        contents.entries.retain(|key, _| used.contains(key));
+        *changed |= contents.entries.len() != before;
+        used.clear();
+      }
+      if !inner.changed {
+        return
+      }
+
+      // write to a temporary file first, so that an interrupted save doesn't leave a partial cache.
+      let tmp = self.path.with_extension("tmp");
+      let result = serde_json::to_string(&inner.contents)
+        .map_err(|e| e.to_string())
+        .and_then(|json| fs::write(&tmp, json).map_err(|e| e.to_string()))
+        .and_then(|_| fs::rename(&tmp, &self.path).map_err(|e| e.to_string()));
+      match result {
+        Ok(_) => inner.changed = false,
+        Err(e) => warn!("could not write cache file {}: {}", self.path.display(), e),
+      }
+    }
+}
+
+/// the version of a local file for the scan cache: its size and modification time.
+pub fn file_version(path: &Path) -> Option<String> {
+  let metadata = fs::metadata(path).ok()?;
+  let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+  Some(format!("{}-{}", metadata.len(), modified.as_nanos()))
+}
+
+/// the version of a local dataset for the scan cache: the version of its file and of each of its auxiliary files
+/// (see sidecar::auxiliary_files_of), since adding or rebuilding e.g. external overviews changes what GDAL reads.
+pub fn dataset_version(path: &Path) -> Option<String> {
+  let mut version = file_version(path)?;
+  for aux in sidecar::auxiliary_files_of(path) {
+    if let Some(v) = file_version(&aux) {
+      version = version + "+" + &v;
+    }
+  }
+  Some(version)
+}
+
+#[cfg(test)]
+mod tests {
+  use geo::{polygon, MultiPolygon};
+  use crate::catalog::test_utils::{imagery_file, test_dir};
+  use crate::scan_cache::ScanCache;
+
+  #[test]
+  fn test_scan_cache() {
+    let path = test_dir("scan-cache").join("cache.json");
+    let mut file = imagery_file("scene", "imagery", polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)]);
+    file.properties.nodata = vec![Some(f64::NAN), None, Some(0.)];
+    let boundary = MultiPolygon(vec![polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)]]);
+
+    let cache = ScanCache::load(&path, "options");
+    assert!(cache.get("scene.tif", "1").is_none());
+    cache.insert("scene.tif", "1", &file.properties, &boundary);
+    cache.save(true);
+
+    // entries are only used for the same version of the file.
+    let cache = ScanCache::load(&path, "options");
+    let (properties, cached_boundary) = cache.get("scene.tif", "1").unwrap();
+    assert_eq!(properties.filename, "scene");
+    assert!(properties.nodata[0].unwrap().is_nan());
+    assert_eq!(properties.nodata[1..], [None, Some(0.)]);
+    assert_eq!(cached_boundary, boundary);
+    assert!(cache.get("scene.tif", "2").is_none());
+
+    // files that aren't seen in a full scan are dropped.
+    let cache = ScanCache::load(&path, "options");
+    cache.save(true);
+    assert!(ScanCache::load(&path, "options").get("scene.tif", "1").is_none());
+
+    // the cache is discarded if the options change.
+    let cache = ScanCache::load(&path, "options");
+    cache.insert("scene.tif", "1", &file.properties, &boundary);
+    cache.save(true);
+    assert!(ScanCache::load(&path, "other options").get("scene.tif", "1").is_none());
+  }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use gdal::spatial_ref::SpatialRef;
+use geo::algorithm::area::Area;
+use geo::algorithm::coords_iter::CoordsIter;
+use crate::catalog::{ImageryCollection, ImageryFile};
+
+/// an item that failed one or more of the self-test checks.
+#[derive(Debug)]
+pub struct Failure {
+  pub collection_id: String,
+  pub item_id: String,
+  pub problems: Vec<String>
+}
+
+/// the result of checking a sample of the catalog.
+#[derive(Debug)]
+pub struct Report {
+  /// the number of items that were checked.
+  pub checked: usize,
+  /// the number of items in the catalog.
+  pub total: usize,
+  pub failures: Vec<Failure>
+}
+
+impl Report {
+    /// prints the report, listing each item that failed and why.
+    pub fn print(&self) {
+      println!("checked {} of {} items", self.checked, self.total);
+      for f in self.failures.iter() {
+        println!("{}/{}:", f.collection_id, f.item_id);
+        for problem in f.problems.iter() {
+          println!("  {}", problem);
+        }
+      }
+      println!("{} of {} checked items failed", self.failures.len(), self.checked);
+    }
+}
+
+/// checks up to `sample_size` items from the catalog (spread evenly over the collections).
+/// See check_file for the checks that are made.
+pub fn run(collections: &HashMap<String, ImageryCollection>, sample_size: usize) -> Report {
+  let files = sample_files(collections, sample_size);
+  let total = collections.values().map(|c| c.all().len()).sum();
+
+  let failures = files.iter()
+    .filter_map(|(collection_id, f)| {
+      let problems = check_file(f);
+      match problems.is_empty() {
+        true => None,
+        false => Some(Failure {
+          collection_id: collection_id.to_string(),
+          item_id: f.properties.filename.to_owned(),
+          problems
+        })
+      }
+    })
+    .collect();
+
+  Report {
+    checked: files.len(),
+    total,
+    failures
+  }
+}
+
+/// picks up to `sample_size` files from the catalog, evenly spaced through the collections (sorted by id)
+/// so that the same sample is checked every time the catalog is unchanged.
+fn sample_files(collections: &HashMap<String, ImageryCollection>, sample_size: usize) -> Vec<(&str, &ImageryFile)> {
+  let mut ids: Vec<&String> = collections.keys().collect();
+  ids.sort();
+  let files: Vec<(&str, &ImageryFile)> = ids.into_iter()
+    .flat_map(|id| collections[id].all().iter().map(move |f| (id.as_str(), f)))
+    .collect();
+
+  if files.len() <= sample_size {
+    return files
+  }
+  (0..sample_size).map(|i| files[i * files.len() / sample_size]).collect()
+}
+
+/// checks that a catalogued file can still be opened with GDAL, has a valid footprint, a datetime
+/// and a CRS. Returns a description of each check that failed.
+pub fn check_file(file: &ImageryFile) -> Vec<String> {
+  let mut problems = Vec::new();
+
+  if let Err(e) = file.open() {
+    problems.push(format!("could not open {} with GDAL: {}", file.properties.path, e));
+  }
+
+  if file.boundary.0.is_empty() {
+    problems.push(String::from("footprint is empty"));
+  } else if file.boundary.coords_iter().any(|c| !c.x.is_finite() || !c.y.is_finite()) {
+    problems.push(String::from("footprint has invalid coordinates"));
+  } else if file.boundary.unsigned_area() == 0. {
+    problems.push(String::from("footprint has no area"));
+  }
+
+  if file.properties.timestamp.is_none() || file.properties.timestamp_defaulted {
+    problems.push(String::from("datetime was not found in the file's metadata"));
+  }
+
+  if file.properties.crs.is_empty() {
+    problems.push(String::from("CRS could not be read"));
+  } else if SpatialRef::from_definition(&file.properties.crs).is_err() {
+    problems.push(format!("CRS could not be resolved: {}", file.properties.crs));
+  }
+
+  problems
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+  use gdal::Dataset;
+  use geo::polygon;
+  use crate::catalog::{ImageryFile, ScanOptions};
+  use crate::catalog::test_utils::{imagery_collection, imagery_file, test_dir, write_geotiff};
+  use crate::selftest::{check_file, run, sample_files};
+
+  #[test]
+  fn test_check_file() {
+    let dir = test_dir("selftest");
+    let src = dir.join("scene.tif");
+    write_geotiff(&src, 1);
+    let file = ImageryFile::new(
+      &Dataset::open(&src).unwrap(),
+      src.to_owned(),
+      src.display().to_string(),
+      "scene",
+      "imagery",
+      &ScanOptions::default()
+    ).unwrap();
+
+    // the test GeoTIFF has no timestamp in its metadata.
+    assert_eq!(check_file(&file), vec![String::from("datetime was not found in the file's metadata")]);
+
+    // a file that has since been removed, with a degenerate footprint and no CRS.
+    let mut missing = imagery_file("missing.tif", "imagery", polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 0., y: 0.)]);
+    missing.properties.crs = String::new();
+    let problems = check_file(&missing);
+    assert_eq!(problems.len(), 3);
+    assert!(problems[0].starts_with("could not open missing.tif"));
+    assert_eq!(problems[1], "footprint has no area");
+    assert_eq!(problems[2], "CRS could not be read");
+  }
+
+  #[test]
+  fn test_sample_files() {
+    let square = polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)];
+    let mut collections = HashMap::new();
+    collections.insert(String::from("b"), imagery_collection("b", (0..4).map(|i| imagery_file(&format!("b{}", i), "b", square.clone())).collect()));
+    collections.insert(String::from("a"), imagery_collection("a", (0..4).map(|i| imagery_file(&format!("a{}", i), "a", square.clone())).collect()));
+
+    let ids = |sample: Vec<(&str, &ImageryFile)>| sample.iter().map(|(_, f)| f.properties.filename.to_owned()).collect::<Vec<_>>();
+    assert_eq!(ids(sample_files(&collections, 4)), vec!["a0", "a2", "b0", "b2"]);
+    assert_eq!(sample_files(&collections, 100).len(), 8);
+
+    let report = run(&collections, 2);
+    assert_eq!((report.checked, report.total), (2, 8));
+  }
+}
@@ -0,0 +1,148 @@
+use gdal::{Dataset, Metadata};
+
+/// the dataset metadata keys that name the satellite an image is from, e.g. `SPACECRAFT_NAME=Sentinel-2A`
+/// in Sentinel-2 products and `SPACECRAFT_ID=LANDSAT_8` in Landsat products.
+const SPACECRAFT_KEYS: [&str; 3] = ["SPACECRAFT_NAME", "SPACECRAFT_ID", "SATELLITE"];
+
+/// Sensor is an instrument whose red and near-infrared bands are known.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sensor {
+  /// Sentinel-2 MSI
+  Sentinel2,
+  /// Landsat 8 and 9 OLI
+  LandsatOli,
+  /// Landsat 4, 5 and 7 TM/ETM+
+  LandsatTm
+}
+
+impl Sensor {
+    /// the sensor for a spacecraft name, e.g. `Sentinel-2A` or `LANDSAT_8`.
+    pub fn from_spacecraft(name: &str) -> Option<Sensor> {
+      let name: String = name.to_lowercase().chars().filter(|c| c.is_alphanumeric()).collect();
+      if name.starts_with("sentinel2") {
+        return Some(Sensor::Sentinel2);
+      }
+      match name.strip_prefix("landsat")? {
+        "8" | "9" => Some(Sensor::LandsatOli),
+        "4" | "5" | "7" => Some(Sensor::LandsatTm),
+        _ => None,
+      }
+    }
+
+    /// the sensor's names for its red and near-infrared bands.
+    fn red_nir_names(&self) -> (&'static str, &'static str) {
+      match self {
+        Sensor::Sentinel2 => ("B4", "B8"),
+        Sensor::LandsatOli => ("B4", "B5"),
+        Sensor::LandsatTm => ("B3", "B4"),
+      }
+    }
+
+    /// the number of bands in a file with every one of the sensor's bands, in order.
+    fn band_count(&self) -> usize {
+      match self {
+        Sensor::Sentinel2 => 13,
+        Sensor::LandsatOli => 11,
+        Sensor::LandsatTm => 7,
+      }
+    }
+}
+
+/// finds the red and near-infrared bands (starting at 1) of a dataset from the sensor named in its
+/// metadata and its band descriptions. Bands that can't be found are None.
+pub fn red_nir_bands(dataset: &Dataset) -> (Option<u16>, Option<u16>) {
+  let sensor = SPACECRAFT_KEYS.iter()
+    .filter_map(|key| dataset.metadata_item(key, ""))
+    .find_map(|name| Sensor::from_spacecraft(&name));
+  let descriptions: Vec<String> = (1..=dataset.raster_count())
+    .map(|i| dataset.rasterband(i).ok().and_then(|b| b.description().ok()).unwrap_or_default())
+    .collect();
+  find_red_nir(sensor, &descriptions)
+}
+
+/// finds the red and near-infrared bands from each band's description.
+/// Descriptions can be a common name (e.g. `Red` or `NIR`), or the sensor's name for the band (e.g. `B04`,
+/// `SR_B4` or `B4, central wavelength 665 nm`). If no band is described, a file with every one of the sensor's
+/// bands is assumed to have them in order.
+fn find_red_nir(sensor: Option<Sensor>, descriptions: &[String]) -> (Option<u16>, Option<u16>) {
+  let names: Vec<String> = descriptions.iter().map(|d| band_name(d)).collect();
+  let find = |common_names: &[&str], sensor_name: Option<&str>| -> Option<u16> {
+    names.iter()
+      .position(|n| common_names.contains(&n.as_str()) || Some(n.as_str()) == sensor_name)
+      .map(|i| i as u16 + 1)
+  };
+
+  let (red_name, nir_name) = match sensor {
+    Some(s) => {
+      let (red, nir) = s.red_nir_names();
+      (Some(red), Some(nir))
+    },
+    None => (None, None),
+  };
+  let red = find(&["RED"], red_name);
+  let nir = find(&["NIR", "NEARINFRARED"], nir_name);
+  if red.is_some() || nir.is_some() {
+    return (red, nir);
+  }
+
+  // bands without descriptions are numbered by the sensor.
+  match sensor {
+    Some(s) if names.iter().all(|n| n.is_empty()) && names.len() == s.band_count() => {
+      let number = |name: &str| name.trim_start_matches('B').parse::<u16>().ok();
+      (red_name.and_then(number), nir_name.and_then(number))
+    },
+    _ => (None, None),
+  }
+}
+
+/// normalizes a band description to a band name: the first word, in upper case, without a
+/// product prefix or leading zeros (e.g. `SR_B04, central wavelength 665 nm` becomes `B4`).
+/// Common names lose their spaces and dashes (`Near-Infrared` becomes `NEARINFRARED`).
+fn band_name(description: &str) -> String {
+  let name = description.trim().to_uppercase();
+  let first = name.split(',').next().unwrap_or_default().trim();
+  let first = first.strip_prefix("SR_").unwrap_or(first);
+  match first.strip_prefix('B') {
+    Some(number) if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) => {
+      format!("B{}", number.trim_start_matches('0'))
+    },
+    _ => first.chars().filter(|c| c.is_alphanumeric()).collect(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::sensor::{band_name, find_red_nir, Sensor};
+
+  #[test]
+  fn test_from_spacecraft() {
+    assert_eq!(Sensor::from_spacecraft("Sentinel-2A"), Some(Sensor::Sentinel2));
+    assert_eq!(Sensor::from_spacecraft("LANDSAT_8"), Some(Sensor::LandsatOli));
+    assert_eq!(Sensor::from_spacecraft("Landsat 7"), Some(Sensor::LandsatTm));
+    assert_eq!(Sensor::from_spacecraft("LANDSAT_1"), None);
+    assert_eq!(Sensor::from_spacecraft("WorldView-3"), None);
+  }
+
+  #[test]
+  fn test_find_red_nir() {
+    let descriptions = |d: &[&str]| d.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(band_name("B04, central wavelength 665 nm"), "B4");
+    assert_eq!(band_name("SR_B5"), "B5");
+    assert_eq!(band_name("Near Infrared"), "NEARINFRARED");
+
+    // bands are found by the sensor's band names.
+    let s2 = descriptions(&["B2", "B3", "B4", "B8"]);
+    assert_eq!(find_red_nir(Some(Sensor::Sentinel2), &s2), (Some(3), Some(4)));
+    let landsat = descriptions(&["SR_B2", "SR_B3", "SR_B4", "SR_B5"]);
+    assert_eq!(find_red_nir(Some(Sensor::LandsatOli), &landsat), (Some(3), Some(4)));
+    assert_eq!(find_red_nir(Some(Sensor::LandsatTm), &landsat), (Some(3), None));
+    assert_eq!(find_red_nir(None, &s2), (None, None));
+
+    // or by common names, for any sensor.
+    assert_eq!(find_red_nir(None, &descriptions(&["Blue", "Green", "Red", "NIR"])), (Some(3), Some(4)));
+
+    // undescribed bands are only numbered if the file has all of the sensor's bands.
+    assert_eq!(find_red_nir(Some(Sensor::Sentinel2), &vec![String::new(); 13]), (Some(4), Some(8)));
+    assert_eq!(find_red_nir(Some(Sensor::Sentinel2), &vec![String::new(); 4]), (None, None));
+  }
+}
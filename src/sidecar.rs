@@ -0,0 +1,159 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// the extension of sidecar STAC item files, e.g. `scene.json` next to `scene.tif`.
+pub const SIDECAR_EXTENSION: &str = "json";
+
+/// the suffixes of the auxiliary files GDAL reads along with a dataset, added to the dataset's file name
+/// (e.g. `scene.tif.ovr`): external overviews, PAM metadata and masks.
+pub const AUXILIARY_SUFFIXES: [&str; 3] = [".ovr", ".aux.xml", ".msk"];
+
+/// Sidecar is a STAC item written next to a catalogued file (e.g. by the pipeline that produced it),
+/// with the same name and a `.json` extension. Its properties and assets are merged over the ones
+/// autostac finds with GDAL, so that files can have richer metadata than their tags hold. The geometry
+/// and anything else in the sidecar is ignored; the footprint always comes from the file itself.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Sidecar {
+  pub properties: Map<String, Value>,
+  pub assets: Map<String, Value>
+}
+
+impl Sidecar {
+    /// parse the contents of a sidecar file. Relative asset hrefs are resolved against `dir`,
+    /// the directory the sidecar is in.
+    pub fn parse(contents: &str, dir: &Path) -> Result<Sidecar, String> {
+      let mut sidecar: Sidecar = serde_json::from_str(contents).map_err(|e| format!("invalid STAC item: {}", e))?;
+      for asset in sidecar.assets.values_mut() {
+        if let Some(Value::String(href)) = asset.get_mut("href") {
+          if !href.contains("://") && Path::new(href.as_str()).is_relative() {
+            *href = dir.join(href.as_str()).display().to_string();
+          }
+        }
+      }
+      Ok(sidecar)
+    }
+
+    /// read the sidecar of the file at `path`, if it has one. Invalid sidecars are logged and ignored.
+    pub fn for_file(path: &Path) -> Option<Sidecar> {
+      let sidecar_path = sidecar_path(path)?;
+      let contents = fs::read_to_string(&sidecar_path).ok()?;
+      let dir = sidecar_path.parent().unwrap_or_else(|| Path::new(""));
+      Sidecar::parse(&contents, dir).map_err(|e| warn!("{}: {}", sidecar_path.display(), e)).ok()
+    }
+
+    /// the item datetime set by the sidecar. This is `Some(None)` if the sidecar's datetime is null,
+    /// and `None` if it doesn't set one (or it isn't a valid RFC 3339 datetime).
+    pub fn datetime(&self) -> Option<Option<DateTime<Utc>>> {
+      match self.properties.get("datetime")? {
+        Value::Null => Some(None),
+        Value::String(s) => DateTime::parse_from_rfc3339(s).ok().map(|dt| Some(dt.with_timezone(&Utc))),
+        _ => None,
+      }
+    }
+}
+
+/// the path of the sidecar for the file at `path`. Sidecars don't have sidecars of their own.
+fn sidecar_path(path: &Path) -> Option<PathBuf> {
+  match path.extension() {
+    Some(ext) if ext == SIDECAR_EXTENSION => None,
+    _ => Some(path.with_extension(SIDECAR_EXTENSION)),
+  }
+}
+
+/// the files in `files` that are sidecars of another file in the list, and so shouldn't be catalogued themselves.
+pub fn sidecars(files: &[PathBuf]) -> HashSet<PathBuf> {
+  let listed: HashSet<&PathBuf> = files.iter().collect();
+  files.iter()
+    .filter_map(|f| sidecar_path(f))
+    .filter(|s| listed.contains(s))
+    .collect()
+}
+
+/// the paths that GDAL looks for auxiliary files of the dataset at `path` (see AUXILIARY_SUFFIXES).
+fn auxiliary_paths(path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+  AUXILIARY_SUFFIXES.iter().map(move |suffix| {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+  })
+}
+
+/// the auxiliary files of the dataset at `path` that exist.
+pub fn auxiliary_files_of(path: &Path) -> Vec<PathBuf> {
+  auxiliary_paths(path).filter(|p| p.is_file()).collect()
+}
+
+/// the files in `files` that are auxiliary files (see AUXILIARY_SUFFIXES). GDAL reads them when it opens
+/// the file they belong to (e.g. its overviews), so they aren't catalogued themselves, even if that file is missing.
+pub fn auxiliary_files(files: &[PathBuf]) -> HashSet<PathBuf> {
+  files.iter()
+    .filter(|f| AUXILIARY_SUFFIXES.iter().any(|suffix| f.to_string_lossy().ends_with(suffix)))
+    .cloned()
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::{Path, PathBuf};
+  use chrono::{TimeZone, Utc};
+  use serde_json::json;
+  use crate::sidecar::{auxiliary_files, sidecars, Sidecar};
+
+  #[test]
+  fn test_parse_sidecar() {
+    let contents = json!({
+      "type": "Feature",
+      "id": "scene",
+      "geometry": null,
+      "properties": {"datetime": "2021-08-09T18:59:19Z", "platform": "sentinel-2a"},
+      "assets": {
+        "metadata": {"href": "scene.xml", "roles": ["metadata"]},
+        "preview": {"href": "https://example.com/scene.png"},
+        "mask": {"href": "/data/masks/scene.tif"}
+      }
+    }).to_string();
+    let sidecar = Sidecar::parse(&contents, Path::new("/data/imagery")).unwrap();
+    assert_eq!(sidecar.properties["platform"], json!("sentinel-2a"));
+    assert_eq!(sidecar.assets["metadata"]["href"], json!("/data/imagery/scene.xml"));
+    assert_eq!(sidecar.assets["preview"]["href"], json!("https://example.com/scene.png"));
+    assert_eq!(sidecar.assets["mask"]["href"], json!("/data/masks/scene.tif"));
+    assert_eq!(sidecar.datetime(), Some(Some(Utc.ymd(2021, 8, 9).and_hms(18, 59, 19))));
+
+    let sidecar = Sidecar::parse(r#"{"properties": {"datetime": null}}"#, Path::new("")).unwrap();
+    assert_eq!(sidecar.datetime(), Some(None));
+    assert_eq!(Sidecar::parse("{}", Path::new("")).unwrap().datetime(), None);
+    assert!(Sidecar::parse("[]", Path::new("")).is_err());
+  }
+
+  #[test]
+  fn test_sidecars() {
+    let files: Vec<PathBuf> = ["a/scene.json", "a/scene.tif", "a/other.json", "b/scene.tif"].iter().map(PathBuf::from).collect();
+    let found = sidecars(&files);
+    assert_eq!(found.len(), 1);
+    assert!(found.contains(Path::new("a/scene.json")));
+  }
+
+  #[test]
+  fn test_auxiliary_files() {
+    let files: Vec<PathBuf> = ["scene.tif", "scene.tif.ovr", "scene.tif.aux.xml", "scene.tif.msk", "other.tif.ovr", "scene.ovr"]
+      .iter()
+      .map(PathBuf::from)
+      .collect();
+    let found = auxiliary_files(&files);
+    assert_eq!(found.len(), 5);
+    assert!(found.contains(Path::new("scene.tif.ovr")));
+    assert!(found.contains(Path::new("scene.tif.aux.xml")));
+    assert!(found.contains(Path::new("scene.tif.msk")));
+    assert!(!found.contains(Path::new("scene.tif")));
+
+    // auxiliary files are skipped even if the file they belong to isn't there.
+    assert!(found.contains(Path::new("other.tif.ovr")));
+    assert!(found.contains(Path::new("scene.ovr")));
+  }
+}
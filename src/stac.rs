@@ -1,13 +1,45 @@
 use chrono::{DateTime, Utc};
-use serde::{Serialize};
-use serde_json::{Map, Value, to_value};
+use jsonschema::JSONSchema;
+use jsonschema::error::ValidationErrorKind;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value, to_value};
+use crate::links;
 
 /// this STAC implementation was written against the v1.0.0-beta2 version of the
 /// STAC spec.
 /// The structs may contain additional fields (and methods) but the serialized representations
 /// should only include fields conforming to the STAC spec
-static STAC_VERSION: &str = "1.0.0";
-static STAC_CORE_DEF: &str = "https://api.stacspec.org/v1.0.0-beta.2/core";
+pub static STAC_VERSION: &str = "1.0.0";
+
+/// the license of collections that don't have one. The spec requires a license, and `other` is
+/// for licenses that aren't listed (or unknown).
+static DEFAULT_LICENSE: &str = "other";
+
+/// the conformance classes (v1.0.0-beta.2) that the API implements.
+/// Listed on the landing page and at /conformance.
+static CONFORMS_TO: [&str; 5] = [
+  "https://api.stacspec.org/v1.0.0-beta.2/core",
+  "https://api.stacspec.org/v1.0.0-beta.2/collections",
+  "https://api.stacspec.org/v1.0.0-beta.2/item-search",
+  "https://api.stacspec.org/v1.0.0-beta.2/item-search#context",
+  "https://api.stacspec.org/v1.0.0-beta.2/item-search#fields"
+];
+
+/// the alternate assets extension, for assets that can be downloaded from more than one location.
+/// https://github.com/stac-extensions/alternate-assets
+pub static ALTERNATE_ASSETS_EXT: &str = "https://stac-extensions.github.io/alternate-assets/v1.1.0/schema.json";
+
+/// the projection extension, describing the CRS of an item's data.
+/// https://github.com/stac-extensions/projection
+pub static PROJECTION_EXT: &str = "https://stac-extensions.github.io/projection/v1.0.0/schema.json";
+
+/// the electro-optical extension, for cloud cover and band common names.
+/// https://github.com/stac-extensions/eo
+pub static EO_EXT: &str = "https://stac-extensions.github.io/eo/v1.0.0/schema.json";
+
+/// the JSON schemas of STAC items and collections, which the catalog is checked against with --strict-stac.
+pub static ITEM_SCHEMA: &str = "https://schemas.stacspec.org/v1.0.0/item-spec/json-schema/item.json";
+pub static COLLECTION_SCHEMA: &str = "https://schemas.stacspec.org/v1.0.0/collection-spec/json-schema/collection.json";
 
 /// STAC Link relations help describe how each link relates to the current page.
 #[derive(Debug, Serialize)]
@@ -24,8 +56,36 @@ pub enum StacRel {
   /// A child item. e.g. a Collection is a child of a catalog
   Child,
 
+  /// The page one level up, e.g. the Collection that an Item belongs to.
+  Parent,
+
   /// An Item is a dataset that is part of a Collection.
-  Item
+  Item,
+
+  /// The Collection that an Item belongs to.
+  Collection,
+
+  /// The paged items of a Collection (`/collections/{id}/items`).
+  Items,
+
+  /// The TileJSON document for a Collection's raster tiles (`/collections/{id}/tilejson.json`), from the
+  /// web map links extension. https://github.com/stac-extensions/web-map-links
+  Tilejson,
+
+  /// The list of collections in the catalog (`/collections`).
+  Data,
+
+  /// The item search endpoint (`/stac/search`).
+  Search,
+
+  /// The OpenAPI description of the API (`/api`).
+  ServiceDesc,
+
+  /// The next page of results.
+  Next,
+
+  /// The previous page of results.
+  Prev
 }
 
 #[derive(Debug, Serialize)]
@@ -41,10 +101,6 @@ pub enum CollectionType {
 }
 
 
-pub trait ToStacLink {
-  fn to_stac_link(&self, collection_url: &url::Url) -> StacLink;
-}
-
 /// StacLink objects are used in the `links` field list of STAC endpoints.
 #[derive(Debug, Serialize)]
 pub struct StacLink {
@@ -55,22 +111,120 @@ pub struct StacLink {
   #[serde(rename = "type")]
   pub media_type: String,
   /// A hyperlink
-  pub href: String
+  pub href: String,
+  /// The HTTP method to use when following the link, if not GET. e.g. search pages are requested with POST.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub method: Option<String>,
+  /// The request body to send when following the link.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub body: Option<Value>,
+  /// Whether `body` should be merged into the current request's body (true), or sent as the whole body (false).
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub merge: Option<bool>
+}
+
+/// The context extension's paging metadata for a page of items: how many items matched the query,
+/// how many are in this page, and the page size.
+/// https://github.com/radiantearth/stac-api-spec/tree/master/fragments/context
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Context {
+  pub returned: usize,
+  pub limit: usize,
+  pub matched: usize
+}
+
+/// the URIs of the conformance classes that the API implements.
+pub fn conforms_to() -> Vec<String> {
+  CONFORMS_TO.iter().map(|c| String::from(*c)).collect()
 }
 
-impl ToStacLink for geojson::Feature {
-  fn to_stac_link(&self, collection_url: &url::Url) -> StacLink {
-    let id: String = match self.id.as_ref().unwrap() {
-        geojson::feature::Id::String(s) => s.to_string(),
-        geojson::feature::Id::Number(n) => n.to_string(),
-    };
+/// compiles the JSON schema at `url`, downloading it (and the schemas it refers to). Returns an error if
+/// it can't be downloaded. Downloads block, so compile and validate outside of async code.
+pub fn schema(url: &str) -> Result<JSONSchema, String> {
+  let schema = JSONSchema::compile(&json!({ "$ref": url })).map_err(|e| format!("invalid schema {}: {}", url, e))?;
 
-    StacLink {
-      rel: StacRel::Item,
-      media_type: String::from("application/geo+json"),
-      href: collection_url.join(&id).unwrap().to_string()
+  // references are only followed when something is validated, so validate an empty object to download them now.
+  if let Err(mut errors) = schema.validate(&json!({})) {
+    let unresolved = errors.find(|e| matches!(
+      e.kind,
+      ValidationErrorKind::Reqwest { .. } | ValidationErrorKind::JSONParse { .. } | ValidationErrorKind::InvalidReference { .. }
+    ));
+    if let Some(e) = unresolved {
+      return Err(format!("could not download schema {}: {}", url, e));
     }
   }
+  Ok(schema)
+}
+
+/// validates a serialized item or collection against a STAC schema (see `schema`).
+/// Each problem is described along with the path to the field that caused it; valid values have none.
+pub fn validate(schema: &JSONSchema, value: &Value) -> Vec<String> {
+  match schema.validate(value) {
+    Ok(()) => Vec::new(),
+    Err(errors) => errors
+      .map(|e| match e.instance_path.to_string() {
+        path if path.is_empty() => e.to_string(),
+        path => format!("{}: {}", path, e),
+      })
+      .collect(),
+  }
+}
+
+/// a JSON Schema of the item properties that can be queried (the Filter extension's queryables).
+/// The types match the properties in ItemProperties. `schema_id` is the URL the schema is served from.
+/// https://github.com/radiantearth/stac-api-spec/tree/master/fragments/filter#queryables
+pub fn queryables(schema_id: &url::Url) -> Value {
+  json!({
+    "$schema": "https://json-schema.org/draft/2019-09/schema",
+    "$id": schema_id.as_str(),
+    "type": "object",
+    "title": "Queryables",
+    "properties": {
+      "datetime": {
+        "description": "Datetime that the image was captured",
+        "type": "string",
+        "format": "date-time"
+      },
+      "eo:cloud_cover": {
+        "description": "Percent of the image covered by clouds",
+        "type": "number",
+        "minimum": 0,
+        "maximum": 100
+      },
+      "gsd": {
+        "description": "Ground sample distance (resolution), in meters",
+        "type": "number",
+        "exclusiveMinimum": 0
+      },
+      "spatial_resolution": {
+        "description": "Same as gsd",
+        "type": "number",
+        "exclusiveMinimum": 0
+      },
+      "proj:epsg": {
+        "description": "EPSG code of the image's CRS",
+        "type": "integer"
+      }
+    },
+    // items can have custom properties (see mapping.rs) that aren't listed here.
+    "additionalProperties": true
+  })
+}
+
+/// The conformance classes that the API implements.
+/// https://github.com/radiantearth/stac-api-spec/blob/master/overview.md#conformance-classes
+#[derive(Debug, Serialize)]
+pub struct Conformance {
+  #[serde(rename = "conformsTo")]
+  pub conforms_to: Vec<String>
+}
+
+/// The body of an error response, e.g. `{"code": "NotFound", "description": "collection imagery not found"}`.
+/// https://github.com/radiantearth/stac-api-spec/blob/master/core/commons.yaml (the exception schema)
+#[derive(Debug, Serialize)]
+pub struct Exception {
+  pub code: String,
+  pub description: String
 }
 
 /// A STAC landing page.
@@ -81,6 +235,7 @@ pub struct LandingPage {
   id: String,
   title: String,
   description: String,
+  #[serde(rename = "conformsTo")]
   conforms_to: Vec<String>,
   links: Vec<StacLink>
 }
@@ -88,63 +243,100 @@ pub struct LandingPage {
 impl LandingPage {
     /// create and return a new STAC Landing Page
     pub fn new(id: String, title: String, description: String, base_url: &url::Url, collections: Vec<Collection>) -> LandingPage {
-      // form the "conforms_to" field.
-      // this will have to be updated soon to allow new definitions that the service
-      // conforms to.  For now, add the "core" definition (v1.0.0-beta.2).
-      let conforms_to: Vec<String> = vec![
-        String::from(STAC_CORE_DEF)
-      ];
-
-      // Add root and self links to a list of links.
-      // again, this will have to support collection links.
-      let mut links: Vec<StacLink> = Vec::new();
-
-      let root_link = StacLink {
-        rel: StacRel::Root,
-        media_type: String::from("application/json"),
-        href: base_url.to_string()
-      };
-
-      let self_link = StacLink {
-        rel: StacRel::SelfRel,
-        media_type: String::from("application/json"),
-        href: base_url.to_string()
-      };
+      // Add root and self links, followed by a child link for each collection.
+      let mut landing_links: Vec<StacLink> = Vec::new();
 
       let mut collection_links = collections
         .into_iter()
         .map(|v| {
-          v.collection_link(base_url)
+          links::collection_child_link(base_url, &v.id)
         }).collect::<Vec<_>>();
 
-      links.push(root_link);
-      links.push(self_link);
-      links.append(&mut collection_links);
+      landing_links.push(links::root_link(base_url));
+      landing_links.push(links::root_self_link(base_url));
+      landing_links.push(links::collections_data_link(base_url));
+      landing_links.push(links::search_link(base_url));
+      landing_links.push(links::service_desc_link(base_url));
+      landing_links.append(&mut collection_links);
 
       LandingPage {
         stac_version: String::from(STAC_VERSION),
         id,
         title,
         description,
-        conforms_to,
-        links
+        conforms_to: conforms_to(),
+        links: landing_links
+      }
+    }
+}
+
+/// The list of collections in a catalog.
+/// https://github.com/radiantearth/stac-api-spec/blob/master/collections/README.md
+#[derive(Debug, Serialize)]
+pub struct Collections {
+  pub collections: Vec<Collection>,
+  pub links: Vec<StacLink>
+}
+
+impl Collections {
+    /// create a list of collections with root and self links.
+    pub fn new(base_url: &url::Url, collections: Vec<Collection>) -> Collections {
+      Collections {
+        collections,
+        links: vec![
+          links::root_link(base_url),
+          links::collections_self_link(base_url)
+        ]
       }
     }
 }
 
 /// Properties of a STAC Item.
+/// `gsd` (ground sample distance, in meters) is the canonical resolution of an item. `spatial_resolution`
+/// holds the same value and is kept for clients that already use it.
 /// https://github.com/radiantearth/stac-api-spec/blob/master/stac-spec/item-spec/item-spec.md#properties-object
 /// https://github.com/radiantearth/stac-api-spec/blob/master/stac-spec/item-spec/common-metadata.md#stac-common-metadata
 #[derive(Debug, Serialize)]
 pub struct ItemProperties {
   pub title: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub description: Option<String>,
-  pub datetime: DateTime<Utc>,
+  /// null for items without a datetime, which the STAC spec allows.
+  pub datetime: Option<DateTime<Utc>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub created: Option<DateTime<Utc>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   pub updated: Option<DateTime<Utc>>,
 
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub gsd: Option<f64>,
+
   // non-standard properties
-  pub spatial_resolution: Option<f64>
+  pub spatial_resolution: Option<f64>,
+
+  // projection extension. proj:wkt2 is only used when the CRS has no EPSG code.
+  #[serde(rename = "proj:epsg", skip_serializing_if = "Option::is_none")]
+  pub proj_epsg: Option<u32>,
+  #[serde(rename = "proj:wkt2", skip_serializing_if = "Option::is_none")]
+  pub proj_wkt2: Option<String>,
+
+  // electro-optical extension: percent of the image covered by clouds (0-100).
+  #[serde(rename = "eo:cloud_cover", skip_serializing_if = "Option::is_none")]
+  pub eo_cloud_cover: Option<f64>,
+
+  /// the nodata value of each band (null if the band has none).
+  pub nodata: Vec<Value>,
+
+  /// whether the red and near-infrared bands are known, so NDVI can be computed (non-standard).
+  pub ndvi_capable: bool,
+
+  /// the file's overviews, from the most to the least detailed (non-standard).
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub overview_levels: Vec<OverviewLevel>,
+
+  /// namespaced properties passed through from GDAL metadata (see mapping.rs)
+  #[serde(flatten)]
+  pub custom_properties: Map<String, Value>
 }
 
 impl ItemProperties {
@@ -156,11 +348,36 @@ impl ItemProperties {
       // This is a silly way to create a properties map...
       // Find a better way to convert to a format that fits in Feature.properties
       properties.insert(String::from("title"), to_value(&self.title).unwrap());
-      properties.insert(String::from("description"), to_value(&self.description).unwrap());
-      properties.insert(String::from("datetime"), to_value(&self.datetime).unwrap());
-      properties.insert(String::from("created"), to_value(&self.created).unwrap());
-      properties.insert(String::from("updated"), to_value(&self.updated).unwrap());
-      properties.insert(String::from("spatial_resolution"), to_value(&self.spatial_resolution).unwrap());
+      // description, created and updated are left out when they aren't known, since STAC doesn't allow them to be null.
+      if let Some(description) = &self.description {
+        properties.insert(String::from("description"), to_value(description).unwrap());
+      }
+      properties.insert(String::from("datetime"), to_value(self.datetime).unwrap());
+      if let Some(created) = self.created {
+        properties.insert(String::from("created"), to_value(created).unwrap());
+      }
+      if let Some(updated) = self.updated {
+        properties.insert(String::from("updated"), to_value(updated).unwrap());
+      }
+      if let Some(gsd) = self.gsd {
+        properties.insert(String::from("gsd"), to_value(gsd).unwrap());
+      }
+      properties.insert(String::from("spatial_resolution"), to_value(self.spatial_resolution).unwrap());
+      if let Some(epsg) = self.proj_epsg {
+        properties.insert(String::from("proj:epsg"), to_value(epsg).unwrap());
+      }
+      if let Some(wkt2) = &self.proj_wkt2 {
+        properties.insert(String::from("proj:wkt2"), to_value(wkt2).unwrap());
+      }
+      if let Some(cloud_cover) = self.eo_cloud_cover {
+        properties.insert(String::from("eo:cloud_cover"), to_value(cloud_cover).unwrap());
+      }
+      properties.insert(String::from("nodata"), to_value(&self.nodata).unwrap());
+      properties.insert(String::from("ndvi_capable"), to_value(self.ndvi_capable).unwrap());
+      if !self.overview_levels.is_empty() {
+        properties.insert(String::from("overview_levels"), to_value(&self.overview_levels).unwrap());
+      }
+      properties.extend(self.custom_properties.to_owned());
       properties
     }
 }
@@ -186,9 +403,100 @@ pub struct Item {
 /// https://github.com/radiantearth/stac-api-spec/blob/master/stac-spec/item-spec/item-spec.md#asset-object
 #[derive(Debug, Serialize)]
 pub struct ItemAsset {
+  pub href: String,
+  pub roles: Vec<String>,
+  /// other locations the asset can be downloaded from, keyed by the
+  /// kind of location (e.g. `s3` or `https`). See ALTERNATE_ASSETS_EXT.
+  #[serde(skip_serializing_if = "Map::is_empty")]
+  pub alternate: Map<String, Value>,
+  /// the file's bands, if any of their common names are known. See EO_EXT.
+  #[serde(rename = "eo:bands", skip_serializing_if = "Vec::is_empty")]
+  pub eo_bands: Vec<EoBand>
+}
+
+/// A reduced resolution overview of an item's data, in its `overview_levels` property.
+#[derive(Debug, Serialize)]
+pub struct OverviewLevel {
+  pub width: usize,
+  pub height: usize,
+  /// the average size of the overview's pixels, in meters.
+  pub spatial_resolution: f64
+}
+
+/// An item asset with a single band of the item's data.
+#[derive(Debug, Serialize)]
+pub struct BandAsset {
+  pub href: String,
+  #[serde(rename = "type")]
+  pub media_type: String,
+  pub roles: Vec<String>,
+  #[serde(rename = "eo:bands")]
+  pub eo_bands: Vec<EoBand>
+}
+
+/// A band of an item's data. See EO_EXT.
+/// https://github.com/stac-extensions/eo#band-object
+#[derive(Debug, Serialize)]
+pub struct EoBand {
+  pub name: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub common_name: Option<String>
+}
+
+/// An alternate location for an item asset.
+/// https://github.com/stac-extensions/alternate-assets#alternate-asset-object
+#[derive(Debug, Serialize)]
+pub struct AlternateAsset {
   pub href: String
 }
 
+/// A collection asset
+/// https://github.com/radiantearth/stac-api-spec/blob/master/stac-spec/collection-spec/collection-spec.md#asset-object
+#[derive(Debug, Serialize)]
+pub struct CollectionAsset {
+  pub href: String,
+  #[serde(rename = "type")]
+  pub media_type: String,
+  pub roles: Vec<String>
+}
+
+/// The extent of a collection.
+/// https://github.com/radiantearth/stac-api-spec/blob/master/stac-spec/collection-spec/collection-spec.md#extent-object
+#[derive(Debug, Serialize)]
+pub struct Extent {
+  pub spatial: SpatialExtent,
+  pub temporal: TemporalExtent
+}
+
+/// The spatial extent of a collection. The first (and currently only) bbox covers all
+/// the items in the collection, as `[minx, miny, maxx, maxy]` in lat/lng.
+#[derive(Debug, Serialize)]
+pub struct SpatialExtent {
+  pub bbox: Vec<[f64; 4]>
+}
+
+/// The temporal extent of a collection. The first (and currently only) interval covers all
+/// the items in the collection, as `[start, end]`. An unknown start or end is serialized as null
+/// (an open interval).
+#[derive(Debug, Serialize)]
+pub struct TemporalExtent {
+  pub interval: Vec<[Option<DateTime<Utc>>; 2]>
+}
+
+/// An organization that captured, processed or hosts a collection's data.
+/// https://github.com/radiantearth/stac-spec/blob/master/collection-spec/collection-spec.md#provider-object
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Provider {
+  pub name: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub description: Option<String>,
+  /// e.g. `licensor`, `producer`, `processor` or `host`.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub roles: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub url: Option<String>
+}
+
 /// A STAC Collection.
 /// https://github.com/radiantearth/stac-api-spec/blob/master/stac-spec/collection-spec/collection-spec.md
 #[derive(Debug, Serialize)]
@@ -199,15 +507,29 @@ pub struct Collection {
   pub id: String,
   pub title: String,
   pub description: String,
+  /// an SPDX license identifier, `various` or `proprietary`. Defaults to `other` when the license isn't known.
+  pub license: String,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub keywords: Vec<String>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub providers: Vec<Provider>,
+  pub extent: Extent,
   pub links: Vec<StacLink>,
+  /// collection-level assets, e.g. a thumbnail.
+  #[serde(skip_serializing_if = "Map::is_empty")]
+  pub assets: Map<String, Value>,
 }
 
 impl Collection {
-    /// create a new Collection containing a list of Items
+    /// create a new Collection containing a list of Items.
+    /// The license is `other` if it isn't given.
     pub fn new(
       id: String,
       title: String,
       description: String,
+      license: Option<String>,
+      providers: Option<Vec<Provider>>,
+      extent: Extent,
       links: Vec<StacLink>,
     ) -> Collection {
       Collection {
@@ -216,39 +538,89 @@ impl Collection {
         id,
         title,
         description,
-        links
+        license: license.unwrap_or_else(|| String::from(DEFAULT_LICENSE)),
+        keywords: Vec::new(),
+        providers: providers.unwrap_or_default(),
+        extent,
+        links,
+        assets: Map::new()
       }
     }
+}
 
-    /// return a link to this collection, with a ref of child
-    pub fn collection_link(&self, base_url: &url::Url) -> StacLink {
-      let collection_url = base_url
-        .join("collections/").unwrap()
-        .join(&(self.id.to_owned() + "/")).unwrap();
-      StacLink { rel: StacRel::Child,
-        href: collection_url.to_string(),
-        media_type: String::from("application/json")
-        }
-    }
+#[cfg(test)]
+mod tests {
+  use std::fs;
+  use std::path::Path;
+  use geo::polygon;
+  use jsonschema::JSONSchema;
+  use serde_json::{json, Value};
+  use crate::catalog::test_utils::{imagery_collection, imagery_file};
+  use crate::stac::{schema, validate, COLLECTION_SCHEMA, ITEM_SCHEMA};
 
-    /// return a self-link for this collection
-    pub fn self_link(&self, base_url: &url::Url) -> StacLink {
-      let collection_url = base_url
-        .join("collections/").unwrap()
-        .join(&(self.id.to_owned() + "/")).unwrap();
-      StacLink { rel: StacRel::SelfRel,
-        href: collection_url.to_string(),
-        media_type: String::from("application/json")
-        }
+  /// compiles the STAC schema at `url` from the copies of the v1.0.0 schemas (and the GeoJSON schemas they refer to)
+  /// in testdata/stac-schemas, so that tests don't download them.
+  fn bundled_schema(url: &str) -> JSONSchema {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join("stac-schemas");
+    let mut options = JSONSchema::options();
+    options.with_meta_schemas();
+    for file in ["basics", "collection", "datetime", "instrument", "item", "licensing", "provider"].iter() {
+      let document: Value = serde_json::from_str(&fs::read_to_string(dir.join("v1.0.0").join(format!("{}.json", file))).unwrap()).unwrap();
+      let id = document["$id"].as_str().unwrap().trim_end_matches('#').to_owned();
+      options.with_document(id, document);
+    }
+    for file in ["Feature", "Geometry"].iter() {
+      let document: Value = serde_json::from_str(&fs::read_to_string(dir.join("geojson").join(format!("{}.json", file))).unwrap()).unwrap();
+      options.with_document(format!("https://geojson.org/schema/{}.json", file), document);
     }
+    options.compile(&json!({ "$ref": url })).unwrap()
+  }
+
+  #[test]
+  fn test_schema() {
+    // schemas that can't be downloaded are an error, rather than a problem with every value validated against them.
+    let e = schema("http://127.0.0.1:1/item.json").err().unwrap();
+    assert!(e.starts_with("could not download schema http://127.0.0.1:1/item.json"), "{}", e);
+  }
 
-    /// return the root link for the service.
-    /// this probably doesn't need to be a method of a collection;
-    /// some refactoring is needed.
-    pub fn root_link(&self, base_url: &url::Url) -> StacLink {
-      StacLink { rel: StacRel::Root,
-        href: base_url.to_string(),
-        media_type: String::from("application/json")
-        }
+  #[test]
+  fn test_validate() {
+    let schema = JSONSchema::compile(&json!({
+      "type": "object",
+      "required": ["license"],
+      "properties": {"bbox": {"type": "array", "minItems": 4}}
+    })).unwrap();
+    assert!(validate(&schema, &json!({"license": "other", "bbox": [0, 0, 1, 1]})).is_empty());
+
+    let problems = validate(&schema, &json!({"bbox": [0, 0]}));
+    assert_eq!(problems.len(), 2);
+    assert!(problems.iter().any(|p| p.contains("license")));
+    assert!(problems.iter().any(|p| p.starts_with("/bbox: ")));
+  }
+
+  #[test]
+  fn test_validate_stac_schemas() {
+    let base_url = url::Url::parse("http://localhost:8000").unwrap();
+    let item_schema = bundled_schema(ITEM_SCHEMA);
+    let collection_schema = bundled_schema(COLLECTION_SCHEMA);
+
+    // the items and collections that autostac generates are valid STAC.
+    let mut file = imagery_file("scene", "imagery", polygon![(x: 0., y: 0.), (x: 1., y: 0.), (x: 1., y: 1.), (x: 0., y: 1.)]);
+    file.properties.red_band = Some(1);
+    for band_assets in [false, true].iter() {
+      let item = serde_json::to_value(file.to_stac_item(&base_url, *band_assets)).unwrap();
+      assert_eq!(validate(&item_schema, &item), Vec::<String>::new());
     }
+    let collection = serde_json::to_value(imagery_collection("imagery", vec![file.clone()]).stac_collection(&base_url)).unwrap();
+    assert_eq!(validate(&collection_schema, &collection), Vec::<String>::new());
+
+    // items without a stac_version, or with null common metadata, aren't.
+    let mut item = serde_json::to_value(file.to_stac_item(&base_url, false)).unwrap();
+    item.as_object_mut().unwrap().remove("stac_version");
+    item["properties"]["created"] = Value::Null;
+    let problems = validate(&item_schema, &item);
+    assert_eq!(problems.len(), 2);
+    assert!(problems.iter().any(|p| p.contains("stac_version")));
+    assert!(problems.iter().any(|p| p.starts_with("/properties/created: ")));
+  }
 }
@@ -0,0 +1,63 @@
+use std::fs;
+use std::path::Path;
+use glob::Pattern;
+use log::warn;
+
+/// the name of the ignore file read from each collection directory (or S3 prefix).
+pub const STACIGNORE: &str = ".stacignore";
+
+/// StacIgnore holds the glob patterns from a `.stacignore` file.
+/// Like a `.gitignore`, each line is a pattern (e.g. `*_tmp.tif`) and files matching any
+/// of the patterns are not catalogued. Blank lines and lines starting with `#` are skipped.
+#[derive(Debug, Default)]
+pub struct StacIgnore {
+  patterns: Vec<Pattern>
+}
+
+impl StacIgnore {
+    /// parse the contents of a .stacignore file.
+    pub fn parse(contents: &str) -> StacIgnore {
+      let mut patterns = Vec::new();
+      for line in contents.lines().map(|l| l.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+          continue;
+        }
+        match Pattern::new(line) {
+          Ok(p) => patterns.push(p),
+          Err(e) => warn!("skipping invalid {} pattern `{}`: {}", STACIGNORE, line, e),
+        }
+      }
+      StacIgnore { patterns }
+    }
+
+    /// read the .stacignore file in `dir`. If there isn't one, nothing is ignored.
+    pub fn from_dir(dir: &Path) -> StacIgnore {
+      match fs::read_to_string(dir.join(STACIGNORE)) {
+        Ok(contents) => StacIgnore::parse(&contents),
+        Err(_) => StacIgnore::default(),
+      }
+    }
+
+    /// check whether a file should be skipped. `path` is relative to the collection
+    /// directory or S3 prefix. Patterns are matched against both the relative path and the file name.
+    pub fn is_ignored(&self, path: &str) -> bool {
+      let filename = path.rsplit('/').next().unwrap_or(path);
+      self.patterns.iter().any(|p| p.matches(path) || p.matches(filename))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::stacignore::StacIgnore;
+
+  #[test]
+  fn test_stacignore_patterns() {
+    let ignore = StacIgnore::parse("# scratch files\n\n*_tmp.tif\nbad_scene.tif\n");
+
+    assert!(ignore.is_ignored("scene_tmp.tif"));
+    assert!(ignore.is_ignored("bad_scene.tif"));
+    assert!(ignore.is_ignored("2021/scene_tmp.tif"));
+    assert!(!ignore.is_ignored("scene.tif"));
+    assert!(!ignore.is_ignored("# scratch files"));
+  }
+}
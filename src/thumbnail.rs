@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use gdal::spatial_ref::SpatialRef;
+use crate::catalog::{files_bounding_rect, ImageryFile};
+use crate::render::{render_png, Colormap, Stretch};
+
+/// the length (in pixels) of the longest side of a thumbnail.
+pub const THUMBNAIL_SIZE: usize = 256;
+
+/// Thumbnails keeps the collection previews that have been rendered (see render_thumbnail), along with the
+/// version of the collection each was rendered from (see ImageryCollection::version).
+/// Thumbnails are only served if enabled with `--collection-thumbnails`.
+#[derive(Debug, Default)]
+pub struct Thumbnails {
+  pub enabled: bool,
+  cache: Mutex<HashMap<String, (u64, Vec<u8>)>>
+}
+
+impl Thumbnails {
+    pub fn new(enabled: bool) -> Thumbnails {
+      Thumbnails {
+        enabled,
+        cache: Mutex::new(HashMap::new())
+      }
+    }
+
+    /// the PNG thumbnail of a collection, if one has been rendered from this version of the collection.
+    pub fn get(&self, collection_id: &str, version: u64) -> Option<Vec<u8>> {
+      match self.cache.lock().unwrap().get(collection_id) {
+        Some((v, png)) if *v == version => Some(png.to_owned()),
+        _ => None,
+      }
+    }
+
+    /// keeps the thumbnail rendered from `version` of a collection, replacing the thumbnail of an older version.
+    pub fn insert(&self, collection_id: &str, version: u64, png: Vec<u8>) {
+      self.cache.lock().unwrap().insert(collection_id.to_owned(), (version, png));
+    }
+}
+
+/// the area covered by a thumbnail, in lat/lng.
+#[derive(Debug)]
+struct Extent {
+  min_x: f64,
+  min_y: f64,
+  max_x: f64,
+  max_y: f64
+}
+
+fn files_extent(files: &[ImageryFile]) -> Option<Extent> {
+  files_bounding_rect(files).map(|rect| Extent {
+    min_x: rect.min().x,
+    min_y: rect.min().y,
+    max_x: rect.max().x,
+    max_y: rect.max().y,
+  })
+}
+
+/// the pixel size of a thumbnail covering `extent`, keeping the extent's aspect ratio.
+fn thumbnail_size(extent: &Extent) -> (usize, usize) {
+  let width = extent.max_x - extent.min_x;
+  let height = extent.max_y - extent.min_y;
+  let scale = |side: f64, longest: f64| ((side / longest * THUMBNAIL_SIZE as f64).round() as usize).max(1);
+
+  if width >= height {
+    (THUMBNAIL_SIZE, scale(height, width))
+  } else {
+    (scale(width, height), THUMBNAIL_SIZE)
+  }
+}
+
+/// render a PNG mosaic of all the files in a collection, covering the collection's extent.
+/// Each file is stretched to its own min/max within the thumbnail (see render::render_png).
+pub fn render_thumbnail(files: &[ImageryFile]) -> Result<Vec<u8>, String> {
+  let extent = files_extent(files).ok_or("collection has no files")?;
+  let (width, height) = thumbnail_size(&extent);
+  let geo_transform = [
+    extent.min_x,
+    (extent.max_x - extent.min_x) / width as f64,
+    0.,
+    extent.max_y,
+    0.,
+    -(extent.max_y - extent.min_y) / height as f64
+  ];
+  let wkt = SpatialRef::from_epsg(4326).and_then(|srs| srs.to_wkt()).map_err(|e| e.to_string())?;
+  render_png(files, geo_transform, &wkt, (width, height), Stretch::Window, Colormap::Gray, None)
+}
+
+#[cfg(test)]
+mod tests {
+  use geo::polygon;
+  use crate::catalog::test_utils::imagery_file;
+  use crate::thumbnail::{files_extent, thumbnail_size, Thumbnails, THUMBNAIL_SIZE};
+
+  #[test]
+  fn test_thumbnail_cache() {
+    let thumbnails = Thumbnails::new(true);
+    assert_eq!(thumbnails.get("imagery", 1), None);
+
+    thumbnails.insert("imagery", 1, vec![1]);
+    assert_eq!(thumbnails.get("imagery", 1), Some(vec![1]));
+
+    // a rescanned collection has a new version, so its old thumbnail isn't served.
+    assert_eq!(thumbnails.get("imagery", 2), None);
+    thumbnails.insert("imagery", 2, vec![2]);
+    assert_eq!(thumbnails.get("imagery", 2), Some(vec![2]));
+    assert_eq!(thumbnails.get("imagery", 1), None);
+  }
+
+  #[test]
+  fn test_thumbnail_size() {
+    let wide = imagery_file("wide", "imagery", polygon![
+      (x: 0., y: 0.),
+      (x: 2., y: 0.),
+      (x: 2., y: 1.),
+      (x: 0., y: 1.)
+    ]);
+    let tall = imagery_file("tall", "imagery", polygon![
+      (x: 10., y: 0.),
+      (x: 11., y: 0.),
+      (x: 11., y: 4.),
+      (x: 10., y: 4.)
+    ]);
+
+    let extent = files_extent(std::slice::from_ref(&wide)).unwrap();
+    assert_eq!(thumbnail_size(&extent), (THUMBNAIL_SIZE, THUMBNAIL_SIZE / 2));
+
+    // the extent covers every file.
+    let extent = files_extent(&[wide, tall]).unwrap();
+    assert_eq!((extent.min_x, extent.min_y, extent.max_x, extent.max_y), (0., 0., 11., 4.));
+    assert_eq!(thumbnail_size(&extent), (THUMBNAIL_SIZE, 93));
+
+    assert!(files_extent(&[]).is_none());
+  }
+}
@@ -0,0 +1,35 @@
+use serde_json::{json, Value};
+use url::Url;
+use crate::links;
+use crate::stac;
+
+/// the version of the TileJSON spec that documents follow.
+const TILEJSON_VERSION: &str = "3.0.0";
+
+/// a TileJSON document for a collection's raster tiles, so that web maps (e.g. MapLibre) can add the collection
+/// as a raster layer from a single URL. `zoom` is the (min, max) zoom levels of the collection.
+/// https://github.com/mapbox/tilejson-spec/tree/master/3.0.0
+pub fn tilejson(base_url: &Url, collection: &stac::Collection, zoom: (u8, u8)) -> Value {
+  let [minx, miny, maxx, maxy] = collection.extent.spatial.bbox.first().copied().unwrap_or([-180., -90., 180., 90.]);
+  let (minzoom, maxzoom) = zoom;
+
+  // the center of a bbox that crosses the antimeridian is on the other side of the map.
+  let center_x = match minx <= maxx {
+    true => (minx + maxx) / 2.,
+    false => {
+      let x = (minx + maxx + 360.) / 2.;
+      if x > 180. { x - 360. } else { x }
+    },
+  };
+
+  json!({
+    "tilejson": TILEJSON_VERSION,
+    "name": collection.title,
+    "description": collection.description,
+    "tiles": [links::tile_template(base_url, &collection.id)],
+    "bounds": [minx, miny, maxx, maxy],
+    "center": [center_x, (miny + maxy) / 2., minzoom],
+    "minzoom": minzoom,
+    "maxzoom": maxzoom
+  })
+}
@@ -0,0 +1,374 @@
+use std::io::Cursor;
+use gdal::spatial_ref::SpatialRef;
+use geo_types::{Coordinate, LineString};
+use rocket::http::ContentType;
+use rocket::request::FromParam;
+use rocket::response::{self, Responder, Response};
+use rocket::Request;
+use crate::catalog::ImageryFile;
+use crate::render::{render_png, Colormap, Stretch};
+use crate::transform;
+
+/// the width and height (in pixels) of a raster tile.
+pub const TILE_SIZE: usize = 256;
+
+/// the sizes that raster tiles can be requested at: standard tiles, and "@2x" tiles for high DPI displays.
+pub const TILE_SIZES: [usize; 2] = [TILE_SIZE, TILE_SIZE * 2];
+
+/// the widest buffer (in pixels) that can be added around each edge of a raster tile.
+pub const MAX_TILE_BUFFER: usize = 256;
+
+/// TileOptions controls how a raster tile is rendered.
+#[derive(Debug, Clone)]
+pub struct TileOptions {
+  /// the width and height of the tile, in pixels (one of TILE_SIZES).
+  pub size: usize,
+  /// extra pixels around each edge of the tile, so the PNG is `size + 2 * buffer` pixels across.
+  pub buffer: usize,
+  /// the min/max that every band is stretched from. None stretches each band from the min/max of the whole file.
+  pub rescale: Option<(f64, f64)>,
+  /// the colormap for single band files (or a single selected band).
+  pub colormap: Colormap,
+  /// the bands (starting at 1) drawn as red, green and blue, or a single band drawn with the colormap.
+  /// None draws the first three bands of files with 3 or more bands, and the first band of other files.
+  pub bands: Option<Vec<isize>>
+}
+
+impl Default for TileOptions {
+  fn default() -> Self {
+    TileOptions {
+      size: TILE_SIZE,
+      buffer: 0,
+      rescale: None,
+      colormap: Colormap::Gray,
+      bands: None
+    }
+  }
+}
+
+/// the width and height of a vector tile, in tile coordinates.
+pub const MVT_EXTENT: u32 = 4096;
+
+/// the geometry type of polygon features in a vector tile (Tile.GeomType.POLYGON).
+const MVT_POLYGON: u64 = 3;
+
+/// property keys of vector tile features. The values of each feature are in the same order.
+const MVT_KEYS: [&str; 3] = ["filename", "spatial_resolution", "collection"];
+
+/// the y segment of a raster tile URL: the tile row, optionally with a `.png` extension.
+pub struct RasterTileRow(pub u32);
+
+impl<'a> FromParam<'a> for RasterTileRow {
+  type Error = &'a str;
+
+  fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+    param.strip_suffix(".png").unwrap_or(param).parse().map(RasterTileRow).map_err(|_| param)
+  }
+}
+
+/// the y segment of a vector tile URL: the tile row with a `.mvt` extension.
+pub struct VectorTileRow(pub u32);
+
+impl<'a> FromParam<'a> for VectorTileRow {
+  type Error = &'a str;
+
+  fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+    param.strip_suffix(".mvt").ok_or(param)?.parse().map(VectorTileRow).map_err(|_| param)
+  }
+}
+
+/// VectorTile responds with an encoded Mapbox Vector Tile.
+/// Tiles are sent uncompressed, so no Content-Encoding is set.
+pub struct VectorTile(pub Vec<u8>);
+
+impl<'r> Responder<'r, 'static> for VectorTile {
+  fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+    Response::build()
+      .header(ContentType::new("application", "vnd.mapbox-vector-tile"))
+      .sized_body(self.0.len(), Cursor::new(self.0))
+      .ok()
+  }
+}
+
+/// renders a PNG of `file` covering the web mercator (XYZ) tile at z/x/y (see TileOptions).
+/// Unless they are rescaled, bands are stretched to the min/max of the whole file, so that neighbouring tiles match.
+pub fn render_tile(file: &ImageryFile, x: u32, y: u32, z: u8, options: &TileOptions) -> Result<Vec<u8>, String> {
+  let [min_x, _, max_x, max_y] = transform::tile_mercator_bounds(x, y, z);
+  let pixel_size = (max_x - min_x) / options.size as f64;
+  let offset = options.buffer as f64 * pixel_size;
+  let geo_transform = [min_x - offset, pixel_size, 0., max_y + offset, 0., -pixel_size];
+  let wkt = SpatialRef::from_epsg(3857).and_then(|srs| srs.to_wkt()).map_err(|e| e.to_string())?;
+  let width = options.size + 2 * options.buffer;
+  let stretch = match options.rescale {
+    Some((min, max)) => Stretch::Range(min, max),
+    None => Stretch::File,
+  };
+  render_png(std::slice::from_ref(file), geo_transform, &wkt, (width, width), stretch, options.colormap, options.bands.as_deref())
+}
+
+/// encodes the footprints of `files` that are inside the web mercator (XYZ) tile at z/x/y as a
+/// Mapbox Vector Tile (https://github.com/mapbox/vector-tile-spec).
+/// The tile has a single layer named after the collection, with a polygon feature for each footprint
+/// (clipped to the tile) and the properties `filename`, `spatial_resolution` and `collection`.
+/// Returns an empty tile if no footprint is inside the tile.
+pub fn build_tile(files: &[&ImageryFile], collection_id: &str, x: u32, y: u32, z: u8) -> Vec<u8> {
+  let [min_x, _, max_x, max_y] = transform::tile_mercator_bounds(x, y, z);
+  let scale = MVT_EXTENT as f64 / (max_x - min_x);
+  let to_tile = |c: &Coordinate<f64>| {
+    let m = transform::to_mercator(*c);
+    ((m.x - min_x) * scale, (max_y - m.y) * scale)
+  };
+
+  // encoded Value messages, shared by all features.
+  let mut values: Vec<Vec<u8>> = Vec::new();
+  let mut features: Vec<Vec<u8>> = Vec::new();
+
+  for (id, f) in files.iter().enumerate() {
+    let mut geometry: Vec<u32> = Vec::new();
+    let mut cursor = (0, 0);
+    for poly in &f.boundary {
+      let exterior = match tile_ring(poly.exterior(), &to_tile, true) {
+        Some(ring) => ring,
+        None => continue
+      };
+      encode_ring(&mut geometry, &exterior, &mut cursor);
+      for interior in poly.interiors() {
+        if let Some(ring) = tile_ring(interior, &to_tile, false) {
+          encode_ring(&mut geometry, &ring, &mut cursor);
+        }
+      }
+    }
+    if geometry.is_empty() {
+      continue
+    }
+
+    let mut tags: Vec<u32> = Vec::new();
+    let feature_values = [
+      string_value(&f.properties.filename),
+      double_value(f.properties.resolution.avg()),
+      string_value(collection_id)
+    ];
+    for (key, value) in feature_values.iter().enumerate() {
+      let index = match values.iter().position(|v| v == value) {
+        Some(i) => i,
+        None => {
+          values.push(value.to_owned());
+          values.len() - 1
+        }
+      };
+      tags.push(key as u32);
+      tags.push(index as u32);
+    }
+
+    let mut feature = Vec::new();
+    write_varint_field(&mut feature, 1, id as u64);
+    write_packed_field(&mut feature, 2, &tags);
+    write_varint_field(&mut feature, 3, MVT_POLYGON);
+    write_packed_field(&mut feature, 4, &geometry);
+    features.push(feature);
+  }
+
+  if features.is_empty() {
+    return Vec::new()
+  }
+
+  let mut layer = Vec::new();
+  write_varint_field(&mut layer, 15, 2);
+  write_bytes_field(&mut layer, 1, collection_id.as_bytes());
+  for feature in &features {
+    write_bytes_field(&mut layer, 2, feature);
+  }
+  for key in &MVT_KEYS {
+    write_bytes_field(&mut layer, 3, key.as_bytes());
+  }
+  for value in &values {
+    write_bytes_field(&mut layer, 4, value);
+  }
+  write_varint_field(&mut layer, 5, MVT_EXTENT as u64);
+
+  let mut tile = Vec::new();
+  write_bytes_field(&mut tile, 3, &layer);
+  tile
+}
+
+/// converts a ring of lng/lat coordinates to integer tile coordinates, clipped to the tile.
+/// Exterior rings are wound clockwise and interior rings counter-clockwise (in tile coordinates, where y points down).
+/// Returns None if nothing is left of the ring after clipping.
+fn tile_ring(
+  ring: &LineString<f64>,
+  to_tile: &impl Fn(&Coordinate<f64>) -> (f64, f64),
+  exterior: bool
+) -> Option<Vec<(i64, i64)>> {
+  // rings are closed (the last point repeats the first), but MVT closes them with a ClosePath command.
+  let mut points: Vec<(f64, f64)> = ring.0.iter().map(to_tile).collect();
+  points.pop();
+
+  let mut clipped: Vec<(i64, i64)> = Vec::new();
+  for (x, y) in clip_ring(points, MVT_EXTENT as f64) {
+    let p = (x.round() as i64, y.round() as i64);
+    if clipped.last() != Some(&p) {
+      clipped.push(p);
+    }
+  }
+  while clipped.len() > 1 && clipped.first() == clipped.last() {
+    clipped.pop();
+  }
+
+  let area = ring_area(&clipped);
+  if clipped.len() < 3 || area == 0 {
+    return None
+  }
+  if (area > 0) != exterior {
+    clipped.reverse();
+  }
+  Some(clipped)
+}
+
+/// clips a ring to the square from 0 to `max` on both axes (Sutherland-Hodgman).
+fn clip_ring(ring: Vec<(f64, f64)>, max: f64) -> Vec<(f64, f64)> {
+  let mut points = ring;
+  // each edge of the square as (axis, bound, whether points below the bound are inside).
+  for &(axis, bound, below) in &[(0, 0., false), (0, max, true), (1, 0., false), (1, max, true)] {
+    let coord = |p: &(f64, f64)| if axis == 0 { p.0 } else { p.1 };
+    let inside = |p: &(f64, f64)| if below { coord(p) <= bound } else { coord(p) >= bound };
+    let crossing = |a: &(f64, f64), b: &(f64, f64)| {
+      let t = (bound - coord(a)) / (coord(b) - coord(a));
+      (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1))
+    };
+
+    let input = std::mem::take(&mut points);
+    for (i, p) in input.iter().enumerate() {
+      let prev = &input[(i + input.len() - 1) % input.len()];
+      if inside(p) {
+        if !inside(prev) {
+          points.push(crossing(prev, p));
+        }
+        points.push(*p);
+      } else if inside(prev) {
+        points.push(crossing(prev, p));
+      }
+    }
+  }
+  points
+}
+
+/// twice the signed area of a ring, using the vector tile spec's formula (positive when clockwise in tile coordinates).
+fn ring_area(ring: &[(i64, i64)]) -> i64 {
+  ring.iter()
+    .zip(ring.iter().cycle().skip(1))
+    .map(|(a, b)| a.0 * b.1 - b.0 * a.1)
+    .sum()
+}
+
+/// appends MoveTo, LineTo and ClosePath commands for a ring to a feature's geometry.
+/// `cursor` is the position after the previous command, since each point is relative to the one before.
+fn encode_ring(geometry: &mut Vec<u32>, ring: &[(i64, i64)], cursor: &mut (i64, i64)) {
+  for (i, &(x, y)) in ring.iter().enumerate() {
+    match i {
+      0 => geometry.push(command(1, 1)),
+      1 => geometry.push(command(2, ring.len() as u32 - 1)),
+      _ => ()
+    }
+    geometry.push(zigzag(x - cursor.0));
+    geometry.push(zigzag(y - cursor.1));
+    *cursor = (x, y);
+  }
+  geometry.push(command(7, 1));
+}
+
+fn command(id: u32, count: u32) -> u32 {
+  (id & 0x7) | (count << 3)
+}
+
+fn zigzag(n: i64) -> u32 {
+  ((n << 1) ^ (n >> 63)) as u32
+}
+
+/// a vector tile Value message holding a string.
+fn string_value(s: &str) -> Vec<u8> {
+  let mut value = Vec::new();
+  write_bytes_field(&mut value, 1, s.as_bytes());
+  value
+}
+
+/// a vector tile Value message holding a double.
+fn double_value(n: f64) -> Vec<u8> {
+  let mut value = Vec::new();
+  write_varint(&mut value, 3 << 3 | 1);
+  value.extend_from_slice(&n.to_le_bytes());
+  value
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut n: u64) {
+  while n >= 0x80 {
+    buf.push((n as u8 & 0x7f) | 0x80);
+    n >>= 7;
+  }
+  buf.push(n as u8);
+}
+
+fn write_varint_field(buf: &mut Vec<u8>, field: u64, n: u64) {
+  write_varint(buf, field << 3);
+  write_varint(buf, n);
+}
+
+fn write_bytes_field(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+  write_varint(buf, field << 3 | 2);
+  write_varint(buf, bytes.len() as u64);
+  buf.extend_from_slice(bytes);
+}
+
+fn write_packed_field(buf: &mut Vec<u8>, field: u64, ns: &[u32]) {
+  let mut packed = Vec::new();
+  for n in ns {
+    write_varint(&mut packed, *n as u64);
+  }
+  write_bytes_field(buf, field, &packed);
+}
+
+#[cfg(test)]
+mod tests {
+  use geo_types::polygon;
+  use crate::catalog::test_utils::imagery_file;
+  use crate::tiles::{build_tile, clip_ring, encode_ring, ring_area, zigzag};
+
+  #[test]
+  fn test_clip_ring() {
+    // a square overlapping the top left corner of the tile is cut down to the part inside it.
+    let clipped = clip_ring(vec![(-10., -10.), (10., -10.), (10., 10.), (-10., 10.)], 4096.);
+    assert_eq!(clipped.len(), 4);
+    for p in &[(0., 0.), (10., 0.), (10., 10.), (0., 10.)] {
+      assert!(clipped.contains(p));
+    }
+
+    // rings entirely outside the tile are removed.
+    assert!(clip_ring(vec![(5000., 0.), (6000., 0.), (6000., 100.)], 4096.).is_empty());
+  }
+
+  #[test]
+  fn test_encode_ring() {
+    // the example polygon from the vector tile spec (4.3.5.1).
+    let ring = [(3, 6), (8, 12), (20, 34)];
+    assert!(ring_area(&ring) > 0);
+    let mut geometry = Vec::new();
+    encode_ring(&mut geometry, &ring, &mut (0, 0));
+    assert_eq!(geometry, vec![9, 6, 12, 18, 10, 12, 24, 44, 15]);
+    assert_eq!(zigzag(-1), 1);
+  }
+
+  #[test]
+  fn test_build_tile() {
+    let inside = imagery_file("inside.tif", "imagery", polygon![
+      (x: -123.5, y: 48.5), (x: -123.4, y: 48.5), (x: -123.4, y: 48.6), (x: -123.5, y: 48.6), (x: -123.5, y: 48.5)
+    ]);
+    let tile = build_tile(&[&inside], "imagery", 160, 353, 10);
+    // a single layer (field 3), named after the collection, with the feature's properties.
+    assert_eq!(tile[0], 3 << 3 | 2);
+    for s in &["imagery", "inside.tif", "filename", "spatial_resolution", "collection"] {
+      assert!(tile.windows(s.len()).any(|w| w == s.as_bytes()), "missing {}", s);
+    }
+
+    // no features outside of the file's footprint.
+    assert!(build_tile(&[&inside], "imagery", 0, 0, 10).is_empty());
+  }
+}
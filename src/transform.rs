@@ -1,17 +1,143 @@
 use std::f64::consts::PI;
-use geo::algorithm::map_coords::MapCoords;
+use std::ffi::{CStr, CString};
+use std::ptr;
+use gdal::spatial_ref::SpatialRef;
+use gdal_sys::{OGRErr, OSRExportToWktEx, VSIFree};
+use geo::algorithm::map_coords::TryMapCoords;
 use geo::polygon;
 use proj::Proj;
-use geo_types::{Polygon, Point, Coordinate};
+use geo_types::{Polygon, MultiPolygon, Point, Coordinate};
+use crate::tiles::TILE_SIZE;
 
-pub fn transform_polygon(poly: &Polygon<f64>, from_crs: &str, to_crs: &str) -> Polygon<f64> {
-  let func = Proj::new_known_crs(from_crs, to_crs, None).unwrap();
-  poly.map_coords(|&x| func.convert(x).unwrap())
+/// circumference of the earth at the equator (WGS84), in meters.
+const EARTH_CIRCUMFERENCE: f64 = 40075016.686;
+
+/// the northern and southern limit of web mercator, in degrees.
+const MAX_LATITUDE: f64 = 85.0511287798;
+
+/// the most detailed web mercator zoom level that is advertised.
+pub const MAX_ZOOM: u8 = 24;
+
+/// reprojects a polygon. Returns an error if either CRS isn't known to PROJ, or a point can't be transformed.
+pub fn transform_polygon(poly: &Polygon<f64>, from_crs: &str, to_crs: &str) -> Result<Polygon<f64>, String> {
+  let func = Proj::new_known_crs(from_crs, to_crs, None)
+    .ok_or_else(|| format!("could not transform from {} to {}", from_crs, to_crs))?;
+  poly.try_map_coords(|&x| Ok(func.convert(x)?)).map_err(|e| e.to_string())
+}
+
+/// reprojects a point. Returns an error if either CRS isn't known to PROJ, or the point can't be transformed.
+pub fn transform_point(p: Point<f64>, from_crs: &str, to_crs: &str) -> Result<Point<f64>, String> {
+  let func = Proj::new_known_crs(from_crs, to_crs, None)
+    .ok_or_else(|| format!("could not transform from {} to {}", from_crs, to_crs))?;
+  func.convert(p).map_err(|e| e.to_string())
+}
+
+/// splits a lng/lat polygon that crosses the antimeridian into parts on either side of it, as recommended by
+/// the GeoJSON spec (RFC 7946, section 3.1.9). Polygons that don't cross the antimeridian are returned as is.
+/// A polygon crosses the antimeridian if one of its edges jumps more than 180 degrees of longitude (e.g. the extent
+/// of a UTM scene in the Bering Strait, after it's reprojected) or if it extends past 180/-180.
+/// Only the exterior ring is kept, since this is meant for image extents.
+pub fn split_antimeridian(poly: &Polygon<f64>) -> MultiPolygon<f64> {
+    // make the ring continuous by shifting points after a jump across the antimeridian by 360 degrees.
+    // the last point of the exterior is the same as the first one, so it's left out.
+    let coords = &poly.exterior().0;
+    let mut ring: Vec<Coordinate<f64>> = Vec::new();
+    let mut offset = 0.;
+    for c in coords.iter().take(coords.len().saturating_sub(1)) {
+        if let Some(prev) = ring.last() {
+            let jump = c.x + offset - prev.x;
+            if jump > 180. {
+                offset -= 360.;
+            } else if jump < -180. {
+                offset += 360.;
+            }
+        }
+        ring.push(Coordinate{x: c.x + offset, y: c.y});
+    }
+    let min_x = ring.iter().map(|c| c.x).fold(f64::INFINITY, f64::min);
+    let max_x = ring.iter().map(|c| c.x).fold(f64::NEG_INFINITY, f64::max);
+    if ring.is_empty() || (min_x >= -180. && max_x <= 180.) {
+        return poly.to_owned().into();
+    }
+
+    // clip the continuous ring to each 360 degree wide "copy" of the world that it overlaps,
+    // and shift each part back to the range -180 to 180.
+    let first = ((min_x + 180.) / 360.).floor() as i32;
+    let last = ((max_x + 180.) / 360.).ceil() as i32;
+    let parts: Vec<Polygon<f64>> = (first..last)
+        .map(|i| {
+            let shift = i as f64 * 360.;
+            clip_x(&ring, shift - 180., shift + 180.)
+                .into_iter()
+                .map(|c| Coordinate{x: c.x - shift, y: c.y})
+                .collect::<Vec<_>>()
+        })
+        .filter(|part| part.len() >= 3)
+        .map(|part| Polygon::new(part.into(), vec![]))
+        .collect();
+    MultiPolygon(parts)
+}
+
+/// clips a ring to the x range from `min` to `max` (Sutherland-Hodgman).
+fn clip_x(ring: &[Coordinate<f64>], min: f64, max: f64) -> Vec<Coordinate<f64>> {
+    let mut points = ring.to_vec();
+    for &(bound, below) in &[(min, false), (max, true)] {
+        let inside = |c: &Coordinate<f64>| if below { c.x <= bound } else { c.x >= bound };
+        let crossing = |a: &Coordinate<f64>, b: &Coordinate<f64>| {
+            let t = (bound - a.x) / (b.x - a.x);
+            Coordinate{x: bound, y: a.y + t * (b.y - a.y)}
+        };
+
+        let input = std::mem::take(&mut points);
+        for (i, c) in input.iter().enumerate() {
+            let prev = &input[(i + input.len() - 1) % input.len()];
+            if inside(c) {
+                if !inside(prev) {
+                    points.push(crossing(prev, c));
+                }
+                points.push(*c);
+            } else if inside(prev) {
+                points.push(crossing(prev, c));
+            }
+        }
+    }
+    // drop parts that only touch the bounds.
+    if points.iter().all(|c| c.x == min) || points.iter().all(|c| c.x == max) {
+        points.clear();
+    }
+    points
+}
+
+/// returns the EPSG code of a CRS given as WKT, if GDAL can identify one.
+pub fn epsg_code(wkt: &str) -> Option<u32> {
+  let mut srs = SpatialRef::from_wkt(wkt).ok()?;
+  // WKT without an AUTHORITY node can often still be matched to an EPSG code.
+  if srs.auth_name().is_err() {
+    let _ = srs.auto_identify_epsg();
+  }
+  match srs.auth_name() {
+    Ok(name) if name == "EPSG" => srs.auth_code().ok().map(|code| code as u32),
+    _ => None,
+  }
 }
 
-pub fn transform_point(p: Point<f64>, from_crs: &str, to_crs: &str) -> Point<f64> {
-  let func = Proj::new_known_crs(from_crs, to_crs, None).unwrap();
-  func.convert(p).unwrap()
+/// converts a CRS given as WKT (e.g. the WKT1 returned by GDAL) to WKT2.
+/// Returns None if GDAL can't read or convert it.
+pub fn to_wkt2(wkt: &str) -> Option<String> {
+  let srs = SpatialRef::from_wkt(wkt).ok()?;
+  let format = CString::new("FORMAT=WKT2").unwrap();
+  let options = [format.as_ptr(), ptr::null()];
+  unsafe {
+    let mut c_wkt = ptr::null_mut();
+    let rv = OSRExportToWktEx(srs.to_c_hsrs(), &mut c_wkt, options.as_ptr());
+    let result = if rv == OGRErr::OGRERR_NONE && !c_wkt.is_null() {
+      Some(CStr::from_ptr(c_wkt).to_string_lossy().into_owned())
+    } else {
+      None
+    };
+    VSIFree(c_wkt as *mut _);
+    result
+  }
 }
 
 /// convert XYZ tiles into lat/long.
@@ -25,6 +151,49 @@ fn to_lng_lat(x:u32, y:u32, z: u8) -> Coordinate<f64> {
     Coordinate{x: lon, y: lat}
 }
 
+/// returns the web mercator (EPSG:3857) bounds of a tile as [min x, min y, max x, max y].
+pub fn tile_mercator_bounds(x:u32, y:u32, z: u8) -> [f64; 4] {
+    let tile_width = EARTH_CIRCUMFERENCE / 2_f64.powi(z as i32);
+    let origin = EARTH_CIRCUMFERENCE / 2.;
+    [
+        -origin + x as f64 * tile_width,
+        origin - (y as f64 + 1.) * tile_width,
+        -origin + (x as f64 + 1.) * tile_width,
+        origin - y as f64 * tile_width
+    ]
+}
+
+/// converts a lng/lat coordinate to web mercator (EPSG:3857).
+/// Latitudes beyond the limits of web mercator are clamped to them.
+pub fn to_mercator(c: Coordinate<f64>) -> Coordinate<f64> {
+    let radius = EARTH_CIRCUMFERENCE / (2. * PI);
+    let lat = c.y.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    Coordinate{x: radius * c.x.to_radians(), y: radius * (PI / 4. + lat / 2.).tan().ln()}
+}
+
+/// checks whether x/y is a tile at zoom level z.
+pub fn valid_tile(x:u32, y:u32, z: u8) -> bool {
+    z <= 30 && x < (1 << z) && y < (1 << z)
+}
+
+/// the least detailed web mercator zoom level whose tiles have pixels at least as detailed as `resolution`
+/// (in meters, at the equator), up to MAX_ZOOM.
+pub fn zoom_for_resolution(resolution: f64) -> u8 {
+    if resolution.is_nan() || resolution <= 0. {
+        return MAX_ZOOM
+    }
+    let zoom_0_resolution = EARTH_CIRCUMFERENCE / TILE_SIZE as f64;
+    (zoom_0_resolution / resolution).log2().ceil().clamp(0., MAX_ZOOM as f64) as u8
+}
+
+/// ground resolution (meters per pixel) of a 256 pixel web mercator tile.
+/// Pixels get smaller away from the equator, so this is measured at the center of the tile.
+/// https://docs.microsoft.com/en-us/bingmaps/articles/bing-maps-tile-system#ground-resolution-and-map-scale
+pub fn tile_resolution(x:u32, y:u32, z: u8) -> f64 {
+    let lat = (to_lng_lat(x, y, z).y + to_lng_lat(x, y+1, z).y) / 2.;
+    EARTH_CIRCUMFERENCE * (lat * PI / 180.).cos() / (TILE_SIZE as f64 * 2_f64.powi(z as i32))
+}
+
 /// to_bounds returns the lat/lng tile boundaries as a geo_types::Polygon<f64>
 /// for a tile from a URL with z/x/y format.
 pub fn to_bounds(x:u32, y:u32, z: u8) -> Polygon<f64> {
@@ -37,8 +206,15 @@ pub fn to_bounds(x:u32, y:u32, z: u8) -> Polygon<f64> {
 }
 
 
+#[cfg(test)]
 mod tests {
-  use crate::transform::{to_lng_lat, Coordinate};
+  use gdal::spatial_ref::SpatialRef;
+  use geo::polygon;
+  use geo::prelude::BoundingRect;
+  use crate::transform::{
+    epsg_code, split_antimeridian, tile_mercator_bounds, to_lng_lat, to_mercator, to_wkt2, valid_tile, zoom_for_resolution,
+    Coordinate, MAX_ZOOM
+  };
   #[test]
   fn test_to_lng_lat() {
       // test case borrowed from mercantile's first example
@@ -48,4 +224,78 @@ mod tests {
       assert_eq!(true, (ul.x - expected.x).abs() < 0.0000001);
       assert_eq!(true, (ul.y - expected.y).abs() < 0.0000001);
   }
+
+  #[test]
+  fn test_split_antimeridian() {
+      // polygons that don't cross the antimeridian are unchanged.
+      let poly = polygon![(x: 170., y: 0.), (x: 180., y: 0.), (x: 180., y: 1.), (x: 170., y: 1.)];
+      assert_eq!(split_antimeridian(&poly).0, vec![poly]);
+
+      // an edge that jumps from 179 to -179 crosses the antimeridian, as does a polygon past 180.
+      let crossing = polygon![(x: 179., y: 0.), (x: -179., y: 0.), (x: -179., y: 1.), (x: 179., y: 1.)];
+      let past = polygon![(x: 179., y: 0.), (x: 181., y: 0.), (x: 181., y: 1.), (x: 179., y: 1.)];
+      for poly in &[crossing, past] {
+          let parts: Vec<_> = split_antimeridian(poly).iter().map(|p| p.bounding_rect().unwrap()).collect();
+          assert_eq!(parts.len(), 2);
+          assert_eq!((parts[0].min(), parts[0].max()), (Coordinate{x: 179., y: 0.}, Coordinate{x: 180., y: 1.}));
+          assert_eq!((parts[1].min(), parts[1].max()), (Coordinate{x: -180., y: 0.}, Coordinate{x: -179., y: 1.}));
+      }
+  }
+
+  #[test]
+  fn test_tile_mercator_bounds() {
+      // z0 covers the whole web mercator extent.
+      let [min_x, min_y, max_x, max_y] = tile_mercator_bounds(0, 0, 0);
+      assert!((min_x + 20037508.34).abs() < 0.01 && (max_y - 20037508.34).abs() < 0.01);
+      assert!((max_x - 20037508.34).abs() < 0.01 && (min_y + 20037508.34).abs() < 0.01);
+
+      // at z1, tile 1/0 is the north east quarter.
+      let [min_x, min_y, max_x, max_y] = tile_mercator_bounds(1, 0, 1);
+      assert_eq!((min_x, min_y), (0., 0.));
+      assert!((max_x - 20037508.34).abs() < 0.01 && (max_y - 20037508.34).abs() < 0.01);
+
+      assert!(valid_tile(1, 1, 1));
+      assert!(!valid_tile(2, 0, 1));
+      assert!(!valid_tile(0, 0, 31));
+  }
+
+  #[test]
+  fn test_to_mercator() {
+      // the top left corner of a tile is the top left of its mercator bounds.
+      let [min_x, _, _, max_y] = tile_mercator_bounds(486, 332, 10);
+      let ul = to_mercator(to_lng_lat(486, 332, 10));
+      assert!((ul.x - min_x).abs() < 0.01 && (ul.y - max_y).abs() < 0.01);
+
+      // the poles are clamped to the edge of the map.
+      let north = to_mercator(Coordinate{x: 0., y: 90.});
+      assert!((north.y - 20037508.34).abs() < 0.01);
+  }
+
+  #[test]
+  fn test_zoom_for_resolution() {
+      // pixels of zoom level 0 tiles are about 156 km across.
+      assert_eq!(zoom_for_resolution(156543.04), 0);
+      assert_eq!(zoom_for_resolution(1000000.), 0);
+      // 10 m (e.g. Sentinel-2) is between zoom levels 13 (19.1 m) and 14 (9.6 m).
+      assert_eq!(zoom_for_resolution(10.), 14);
+      assert_eq!(zoom_for_resolution(9.6), 14);
+      assert_eq!(zoom_for_resolution(0.001), MAX_ZOOM);
+      assert_eq!(zoom_for_resolution(0.), MAX_ZOOM);
+  }
+
+  #[test]
+  fn test_epsg_code() {
+      let wgs84 = SpatialRef::from_epsg(4326).unwrap().to_wkt().unwrap();
+      assert_eq!(epsg_code(&wgs84), Some(4326));
+      let utm = SpatialRef::from_epsg(32610).unwrap().to_wkt().unwrap();
+      assert_eq!(epsg_code(&utm), Some(32610));
+
+      // a custom projection has no EPSG code, but can still be described with WKT2.
+      let custom = SpatialRef::from_proj4("+proj=aea +lat_1=51 +lat_2=56 +lat_0=47.5 +lon_0=-124 +x_0=10000 +y_0=0 +datum=WGS84 +units=m")
+        .unwrap().to_wkt().unwrap();
+      assert_eq!(epsg_code(&custom), None);
+      assert!(to_wkt2(&custom).unwrap().starts_with("PROJCRS"));
+
+      assert_eq!(epsg_code(""), None);
+  }
 }
@@ -0,0 +1,100 @@
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+use gdal_sys::{VSIFree, VSIIngestFile, VSIReadDir, VSIReadDirRecursive, VSIStatBufL, VSIStatL};
+
+/// the file type bits of st_mode, and the type of a directory.
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+
+/// lists the names of the files and directories in a directory on one of GDAL's virtual
+/// filesystems (e.g. `/vsis3/mybucket`). Returns an empty list if the directory can't be read.
+pub fn read_dir(path: &str) -> Vec<String> {
+  let c_path = match CString::new(path) {
+    Ok(p) => p,
+    Err(_) => return Vec::new(),
+  };
+  unsafe { string_list(VSIReadDir(c_path.as_ptr())) }
+}
+
+/// lists the paths of everything under a directory, relative to the directory.
+/// Directories are listed with a trailing `/`.
+pub fn read_dir_recursive(path: &str) -> Vec<String> {
+  let c_path = match CString::new(path) {
+    Ok(p) => p,
+    Err(_) => return Vec::new(),
+  };
+  unsafe { string_list(VSIReadDirRecursive(c_path.as_ptr())) }
+}
+
+/// checks whether `path` is a directory.
+pub fn is_dir(path: &str) -> bool {
+  let c_path = match CString::new(path) {
+    Ok(p) => p,
+    Err(_) => return false,
+  };
+  unsafe {
+    let mut stat: VSIStatBufL = std::mem::zeroed();
+    VSIStatL(c_path.as_ptr(), &mut stat) == 0 && (stat.st_mode as u32 & S_IFMT) == S_IFDIR
+  }
+}
+
+/// reads a whole (small) file. Returns None if the file can't be read.
+pub fn read_to_string(path: &str) -> Option<String> {
+  let c_path = CString::new(path).ok()?;
+  unsafe {
+    let mut data: *mut u8 = ptr::null_mut();
+    let mut size = 0;
+    if VSIIngestFile(ptr::null_mut(), c_path.as_ptr(), &mut data, &mut size, -1) == 0 {
+      return None
+    }
+    let contents = String::from_utf8_lossy(std::slice::from_raw_parts(data, size as usize)).into_owned();
+    VSIFree(data as *mut _);
+    Some(contents)
+  }
+}
+
+/// converts a NULL terminated list of strings returned by GDAL into a Vec, freeing the list.
+unsafe fn string_list(list: *mut *mut c_char) -> Vec<String> {
+  let mut strings = Vec::new();
+  if list.is_null() {
+    return strings
+  }
+
+  let mut i = 0;
+  while !(*list.add(i)).is_null() {
+    let s = *list.add(i);
+    strings.push(CStr::from_ptr(s).to_string_lossy().into_owned());
+    VSIFree(s as *mut _);
+    i += 1;
+  }
+  VSIFree(list as *mut _);
+  strings
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+  use crate::catalog::test_utils::test_dir;
+  use crate::vsi::{is_dir, read_dir, read_dir_recursive, read_to_string};
+
+  #[test]
+  fn test_vsi_listing() {
+    let dir = test_dir("vsi");
+    fs::create_dir(dir.join("imagery")).unwrap();
+    fs::write(dir.join("imagery").join("scene.tif"), "").unwrap();
+    fs::write(dir.join("imagery").join(".stacignore"), "*_tmp.tif\n").unwrap();
+    let path = dir.to_str().unwrap();
+
+    assert_eq!(read_dir(path), vec!["imagery"]);
+    assert!(is_dir(&format!("{}/imagery", path)));
+    assert!(!is_dir(&format!("{}/imagery/scene.tif", path)));
+
+    let mut files = read_dir_recursive(&format!("{}/imagery", path));
+    files.sort();
+    assert_eq!(files, vec![".stacignore", "scene.tif"]);
+
+    assert_eq!(read_to_string(&format!("{}/imagery/.stacignore", path)), Some(String::from("*_tmp.tif\n")));
+    assert_eq!(read_to_string(&format!("{}/missing", path)), None);
+  }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use log::{error, info};
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use crate::catalog::{self, ScanOptions, SharedCollections};
+
+/// how long the catalog directory has to be quiet before it's rescanned. Copying a batch of files
+/// produces many events, which are gathered up so that each changed collection is only rescanned once.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// watches the catalog directory `dir` in a background thread, and rescans the collections that
+/// have files added, changed or removed (see catalog::rescan_collections).
+pub fn watch(dir: &str, options: ScanOptions, collections: SharedCollections) {
+  // events are reported with the path that was watched, so watch the canonical path to be able to
+  // find the collection directory of each event.
+  let root = match fs::canonicalize(dir) {
+    Ok(root) => root,
+    Err(e) => {
+      error!("could not watch catalog directory {}: {}", dir, e);
+      return
+    },
+  };
+
+  thread::spawn(move || {
+    let (tx, rx) = channel();
+    let mut watcher = match watcher(tx, DEBOUNCE) {
+      Ok(w) => w,
+      Err(e) => {
+        error!("could not watch catalog directory {}: {}", root.display(), e);
+        return
+      },
+    };
+    if let Err(e) = watcher.watch(&root, RecursiveMode::Recursive) {
+      error!("could not watch catalog directory {}: {}", root.display(), e);
+      return
+    }
+    info!("watching {} for changes", root.display());
+
+    let dir = root.to_string_lossy().to_string();
+    while let Some(changed) = next_batch(&rx, &root) {
+      info!("rescanning changed collections: {}", changed.iter().cloned().collect::<Vec<_>>().join(", "));
+      catalog::rescan_collections(&dir, &changed, &options, &collections);
+    }
+  });
+}
+
+/// waits for a change to the catalog directory, then collects events until the directory has been quiet
+/// for DEBOUNCE. Returns the names of the collection directories that changed, or None if the watcher stopped.
+fn next_batch(rx: &Receiver<DebouncedEvent>, root: &Path) -> Option<HashSet<String>> {
+  let mut changed = HashSet::new();
+  loop {
+    // block until something changes, then keep collecting until the events stop.
+    let event = match changed.is_empty() {
+      true => rx.recv().ok()?,
+      false => match rx.recv_timeout(DEBOUNCE) {
+        Ok(event) => event,
+        Err(RecvTimeoutError::Timeout) => return Some(changed),
+        Err(RecvTimeoutError::Disconnected) => return None,
+      },
+    };
+    changed.extend(event_collections(&event, root));
+  }
+}
+
+/// the names of the collection directories that an event changed.
+fn event_collections(event: &DebouncedEvent, root: &Path) -> Vec<String> {
+  let paths: Vec<&PathBuf> = match event {
+    DebouncedEvent::Create(p) | DebouncedEvent::Write(p) | DebouncedEvent::Remove(p) => vec![p],
+    DebouncedEvent::Rename(from, to) => vec![from, to],
+    // events were missed, so every collection could have changed.
+    DebouncedEvent::Rescan => {
+      return fs::read_dir(root)
+        .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.file_name().to_string_lossy().to_string()).collect())
+        .unwrap_or_default()
+    },
+    _ => vec![],
+  };
+  paths.into_iter().filter_map(|p| collection_name(p, root)).collect()
+}
+
+/// the name of the collection directory (the first directory under `root`) that `path` is in.
+fn collection_name(path: &Path, root: &Path) -> Option<String> {
+  match path.strip_prefix(root).ok()?.components().next()? {
+    Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::{Path, PathBuf};
+  use notify::DebouncedEvent;
+  use crate::watch::{collection_name, event_collections};
+
+  #[test]
+  fn test_event_collections() {
+    let root = Path::new("/data/imagery");
+    assert_eq!(collection_name(Path::new("/data/imagery/landsat/2021/scene.tif"), root), Some(String::from("landsat")));
+    assert_eq!(collection_name(Path::new("/data/imagery/landsat"), root), Some(String::from("landsat")));
+    assert_eq!(collection_name(Path::new("/data/imagery"), root), None);
+    assert_eq!(collection_name(Path::new("/tmp/scene.tif"), root), None);
+
+    let rename = DebouncedEvent::Rename(PathBuf::from("/data/imagery/a/scene.tif"), PathBuf::from("/data/imagery/b/scene.tif"));
+    assert_eq!(event_collections(&rename, root), vec![String::from("a"), String::from("b")]);
+    // notices are followed by the event they announce, so they're ignored.
+    assert!(event_collections(&DebouncedEvent::NoticeWrite(PathBuf::from("/data/imagery/a/scene.tif")), root).is_empty());
+  }
+}
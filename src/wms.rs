@@ -0,0 +1,297 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::Cursor;
+use gdal::spatial_ref::SpatialRef;
+use geo::polygon;
+use geo_types::Geometry;
+use rocket::http::{ContentType, Status};
+use rocket::request::{self, FromRequest, Outcome};
+use rocket::response::{self, Responder, Response};
+use rocket::Request;
+use url::Url;
+use crate::catalog::ImageryFile;
+use crate::html::escape;
+use crate::links;
+use crate::render::{render_png, Colormap, Stretch};
+use crate::stac;
+use crate::transform;
+
+/// the WMS version that capabilities are advertised with. GetMap requests can also use 1.1.1.
+const WMS_VERSION: &str = "1.3.0";
+
+/// the widest (and tallest) map that can be requested, in pixels.
+pub const MAX_MAP_SIZE: usize = 4096;
+
+/// the CRSs that every layer advertises. Maps can be requested in any CRS with an EPSG code.
+const LAYER_CRS: [&str; 3] = ["CRS:84", "EPSG:4326", "EPSG:3857"];
+
+/// WmsParams are the query parameters of a WMS request. WMS parameter names aren't case sensitive
+/// (e.g. `REQUEST` and `request` are the same), so names are lowercased.
+pub struct WmsParams(HashMap<String, String>);
+
+impl WmsParams {
+    /// the value of the parameter `name` (in lowercase).
+    pub fn get(&self, name: &str) -> Option<&str> {
+      self.0.get(name).map(|v| v.as_str())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WmsParams {
+  type Error = ();
+
+  async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+    let params = request.uri().query()
+      .map(|q| q.segments().map(|(name, value)| (name.to_lowercase(), value.to_owned())).collect())
+      .unwrap_or_default();
+    Outcome::Success(WmsParams(params))
+  }
+}
+
+/// WmsError responds with a WMS ServiceExceptionReport (with a 400 status), which WMS clients show to their users.
+/// `code` is one of the exception codes from the WMS spec (e.g. `LayerNotDefined`), if one applies.
+#[derive(Debug)]
+pub struct WmsError {
+  code: Option<&'static str>,
+  message: String
+}
+
+impl WmsError {
+    pub fn new(code: Option<&'static str>, message: impl Into<String>) -> WmsError {
+      WmsError { code, message: message.into() }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for WmsError {
+  fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+    let code = self.code.map(|c| format!(r#" code="{}""#, c)).unwrap_or_default();
+    let xml = format!(
+r#"<?xml version="1.0" encoding="UTF-8"?>
+<ServiceExceptionReport xmlns="http://www.opengis.net/ogc" version="{version}">
+  <ServiceException{code}>{message}</ServiceException>
+</ServiceExceptionReport>
+"#,
+      version = WMS_VERSION,
+      code = code,
+      message = escape(&self.message)
+    );
+    Response::build()
+      .status(Status::BadRequest)
+      .header(ContentType::XML)
+      .sized_body(xml.len(), Cursor::new(xml))
+      .ok()
+  }
+}
+
+/// a WMS 1.3.0 GetCapabilities document with a layer for each collection, covering the collection's spatial extent.
+/// https://www.ogc.org/standards/wms
+pub fn capabilities(title: &str, description: &str, base_url: &Url, collections: &[stac::Collection]) -> String {
+  let url = escape(links::wms_url(base_url).as_str());
+  let dcp_type = format!(r#"<DCPType><HTTP><Get><OnlineResource xlink:href="{}?"/></Get></HTTP></DCPType>"#, url);
+  let crs: String = LAYER_CRS.iter().map(|c| format!("      <CRS>{}</CRS>\n", c)).collect();
+  let layers: String = collections.iter().map(layer).collect();
+  format!(
+r#"<?xml version="1.0" encoding="UTF-8"?>
+<WMS_Capabilities xmlns="http://www.opengis.net/wms" xmlns:xlink="http://www.w3.org/1999/xlink" version="{version}">
+  <Service>
+    <Name>WMS</Name>
+    <Title>{title}</Title>
+    <Abstract>{description}</Abstract>
+    <OnlineResource xlink:href="{url}"/>
+    <MaxWidth>{max_size}</MaxWidth>
+    <MaxHeight>{max_size}</MaxHeight>
+  </Service>
+  <Capability>
+    <Request>
+      <GetCapabilities>
+        <Format>text/xml</Format>
+        {dcp_type}
+      </GetCapabilities>
+      <GetMap>
+        <Format>image/png</Format>
+        {dcp_type}
+      </GetMap>
+    </Request>
+    <Exception>
+      <Format>XML</Format>
+    </Exception>
+    <Layer>
+      <Title>{title}</Title>
+{crs}{layers}    </Layer>
+  </Capability>
+</WMS_Capabilities>
+"#,
+    version = WMS_VERSION,
+    title = escape(title),
+    description = escape(description),
+    url = url,
+    max_size = MAX_MAP_SIZE,
+    dcp_type = dcp_type,
+    crs = crs,
+    layers = layers
+  )
+}
+
+/// the Layer for a collection. Its maps are rendered by GetMap.
+fn layer(collection: &stac::Collection) -> String {
+  let [minx, miny, maxx, maxy] = collection.extent.spatial.bbox.first().copied().unwrap_or([-180., -90., 180., 90.]);
+  format!(
+r#"      <Layer queryable="0">
+        <Name>{id}</Name>
+        <Title>{title}</Title>
+        <Abstract>{description}</Abstract>
+        <EX_GeographicBoundingBox>
+          <westBoundLongitude>{minx}</westBoundLongitude>
+          <eastBoundLongitude>{maxx}</eastBoundLongitude>
+          <southBoundLatitude>{miny}</southBoundLatitude>
+          <northBoundLatitude>{maxy}</northBoundLatitude>
+        </EX_GeographicBoundingBox>
+        <BoundingBox CRS="CRS:84" minx="{minx}" miny="{miny}" maxx="{maxx}" maxy="{maxy}"/>
+      </Layer>
+"#,
+    id = escape(&collection.id),
+    title = escape(&collection.title),
+    description = escape(&collection.description),
+    minx = minx,
+    miny = miny,
+    maxx = maxx,
+    maxy = maxy
+  )
+}
+
+/// GetMap is a WMS GetMap request: a map of one or more layers (collections), drawn in order.
+#[derive(Debug, PartialEq)]
+pub struct GetMap {
+  pub layers: Vec<String>,
+  /// the EPSG code of the map's CRS.
+  epsg: u32,
+  /// the map's extent in its CRS, as [min x, min y, max x, max y] (east/north order, whatever the CRS's axis order).
+  bbox: [f64; 4],
+  width: usize,
+  height: usize
+}
+
+impl GetMap {
+    /// reads a GetMap request from its parameters. Both WMS 1.3.0 (`CRS`) and 1.1.1 (`SRS`) requests are
+    /// supported. In 1.3.0, EPSG:4326 bboxes are in latitude/longitude order.
+    pub fn parse(params: &WmsParams) -> Result<GetMap, WmsError> {
+      let required = |name: &str| params.get(name).ok_or_else(|| WmsError::new(
+        Some("MissingParameterValue"), format!("GetMap requires the {} parameter", name.to_uppercase())
+      ));
+
+      let layers: Vec<String> = required("layers")?.split(',').map(|l| l.trim().to_owned()).filter(|l| !l.is_empty()).collect();
+      if layers.is_empty() {
+        return Err(WmsError::new(Some("LayerNotDefined"), "LAYERS must list at least one layer"))
+      }
+
+      let format = params.get("format").unwrap_or("image/png");
+      if format != "image/png" {
+        return Err(WmsError::new(Some("InvalidFormat"), format!("unsupported FORMAT {}. Maps are only available as image/png", format)))
+      }
+
+      let version = params.get("version").unwrap_or(WMS_VERSION);
+      let crs = match version {
+        "1.1.1" | "1.1.0" => required("srs")?,
+        _ => required("crs")?,
+      };
+      let invalid_crs = || WmsError::new(Some("InvalidCRS"), format!("unsupported CRS {}", crs));
+      let (epsg, lat_lng) = match crs.to_uppercase().as_str() {
+        "CRS:84" => (4326, false),
+        c => {
+          let code: u32 = c.strip_prefix("EPSG:").and_then(|code| code.parse().ok()).ok_or_else(invalid_crs)?;
+          (code, code == 4326 && version == WMS_VERSION)
+        },
+      };
+      SpatialRef::from_epsg(epsg).map_err(|_| invalid_crs())?;
+
+      let bbox: Vec<f64> = required("bbox")?.split(',')
+        .map(|v| v.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| WmsError::new(None, "BBOX must be four numbers: minx,miny,maxx,maxy"))?;
+      let bbox = match (bbox.as_slice(), lat_lng) {
+        (&[miny, minx, maxy, maxx], true) | (&[minx, miny, maxx, maxy], false) if minx < maxx && miny < maxy => [minx, miny, maxx, maxy],
+        _ => return Err(WmsError::new(None, "BBOX must be four numbers: minx,miny,maxx,maxy")),
+      };
+
+      let size = |name: &str| required(name)?.parse::<usize>().ok()
+        .filter(|s| *s > 0 && *s <= MAX_MAP_SIZE)
+        .ok_or_else(|| WmsError::new(None, format!("{} must be between 1 and {}", name.to_uppercase(), MAX_MAP_SIZE)));
+
+      Ok(GetMap { layers, epsg, bbox, width: size("width")?, height: size("height")? })
+    }
+
+    /// the map's extent in lat/lng, for finding the files that are on the map.
+    pub fn footprint(&self) -> Result<Geometry<f64>, WmsError> {
+      let [minx, miny, maxx, maxy] = self.bbox;
+      let extent = polygon![(x: minx, y: miny), (x: maxx, y: miny), (x: maxx, y: maxy), (x: minx, y: maxy)];
+      transform::transform_polygon(&extent, &format!("EPSG:{}", self.epsg), "EPSG:4326")
+        .map(Geometry::from)
+        .map_err(|e| WmsError::new(Some("InvalidCRS"), e))
+    }
+
+    /// renders a PNG of the map. Each file is reprojected onto the map, and more detailed files are drawn over
+    /// less detailed ones. Bands are stretched to the min/max of each file (like raster tiles), so that
+    /// neighbouring maps match.
+    pub fn render(&self, mut files: Vec<ImageryFile>) -> Result<Vec<u8>, String> {
+      files.sort_by(|a, b| b.properties.resolution.avg().partial_cmp(&a.properties.resolution.avg()).unwrap_or(Ordering::Equal));
+
+      let [minx, miny, maxx, maxy] = self.bbox;
+      let geo_transform = [minx, (maxx - minx) / self.width as f64, 0., maxy, 0., -(maxy - miny) / self.height as f64];
+      let wkt = SpatialRef::from_epsg(self.epsg).and_then(|srs| srs.to_wkt()).map_err(|e| e.to_string())?;
+      render_png(&files, geo_transform, &wkt, (self.width, self.height), Stretch::File, Colormap::Gray, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+  use crate::wms::{GetMap, WmsParams};
+
+  fn params(query: &[(&str, &str)]) -> WmsParams {
+    WmsParams(query.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<HashMap<_, _>>())
+  }
+
+  #[test]
+  fn test_parse_get_map() {
+    let web_mercator = params(&[
+      ("layers", "imagery,dem"), ("crs", "EPSG:3857"), ("bbox", "-13775786,6183449,-13736651,6222585"),
+      ("width", "256"), ("height", "128")
+    ]);
+    assert_eq!(GetMap::parse(&web_mercator).unwrap(), GetMap {
+      layers: vec![String::from("imagery"), String::from("dem")],
+      epsg: 3857,
+      bbox: [-13775786., 6183449., -13736651., 6222585.],
+      width: 256,
+      height: 128
+    });
+
+    // EPSG:4326 bboxes are in lat/lng order in WMS 1.3.0, but not in 1.1.1 or with CRS:84.
+    let bbox = |query: &[(&str, &str)]| GetMap::parse(&params(query)).unwrap().bbox;
+    let map = [("layers", "imagery"), ("width", "256"), ("height", "256")];
+    let expected = [-123.5, 48.5, -123.4, 48.6];
+    assert_eq!(bbox(&[&map[..], &[("crs", "EPSG:4326"), ("bbox", "48.5,-123.5,48.6,-123.4")]].concat()), expected);
+    assert_eq!(bbox(&[&map[..], &[("crs", "CRS:84"), ("bbox", "-123.5,48.5,-123.4,48.6")]].concat()), expected);
+    assert_eq!(
+      bbox(&[&map[..], &[("version", "1.1.1"), ("srs", "EPSG:4326"), ("bbox", "-123.5,48.5,-123.4,48.6")]].concat()),
+      expected
+    );
+
+    let invalid = [
+      vec![("crs", "EPSG:4326"), ("bbox", "48.6,-123.5,48.5,-123.4")],
+      vec![("crs", "EPSG:4326"), ("bbox", "48.5,-123.5,48.6")],
+      vec![("crs", "EPSG:999999"), ("bbox", "48.5,-123.5,48.6,-123.4")],
+      vec![("crs", "EPSG:4326"), ("bbox", "48.5,-123.5,48.6,-123.4"), ("width", "5000")],
+      vec![("crs", "EPSG:4326"), ("bbox", "48.5,-123.5,48.6,-123.4"), ("format", "image/jpeg")],
+      vec![("bbox", "48.5,-123.5,48.6,-123.4")],
+    ];
+    for query in &invalid {
+      let mut query = query.to_owned();
+      for p in &map {
+        if !query.iter().any(|(k, _)| k == &p.0) {
+          query.push(*p);
+        }
+      }
+      assert!(GetMap::parse(&params(&query)).is_err(), "{:?} should be invalid", query);
+    }
+  }
+}
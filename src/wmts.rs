@@ -0,0 +1,112 @@
+use url::Url;
+use crate::html::escape;
+use crate::links;
+use crate::stac;
+use crate::tiles::TILE_SIZE;
+use crate::transform::MAX_ZOOM;
+
+/// the only tile matrix set: web mercator, with the same tiles as the XYZ tile endpoint.
+/// http://docs.opengeospatial.org/is/17-083r2/17-083r2.html#72
+const TILE_MATRIX_SET: &str = "WebMercatorQuad";
+
+/// the scale denominator of zoom level 0, for the standard 0.28 mm pixel. Each zoom level halves it.
+const ZOOM_0_SCALE_DENOMINATOR: f64 = 559082264.028717;
+
+/// the web mercator coordinate of the top left corner of the tile matrix set.
+const ORIGIN: f64 = 20037508.3427892;
+
+/// a WMTS 1.0.0 GetCapabilities document (for the RESTful encoding) with a layer for each collection.
+/// Layers are in the WebMercatorQuad tile matrix set, and cover the collection's spatial extent.
+/// https://www.ogc.org/standards/wmts
+pub fn capabilities(title: &str, description: &str, base_url: &Url, collections: &[stac::Collection]) -> String {
+  let layers: String = collections.iter().map(|c| layer(base_url, c)).collect();
+  format!(
+r#"<?xml version="1.0" encoding="UTF-8"?>
+<Capabilities xmlns="http://www.opengis.net/wmts/1.0" xmlns:ows="http://www.opengis.net/ows/1.1" xmlns:xlink="http://www.w3.org/1999/xlink" version="1.0.0">
+  <ows:ServiceIdentification>
+    <ows:Title>{title}</ows:Title>
+    <ows:Abstract>{description}</ows:Abstract>
+    <ows:ServiceType>OGC WMTS</ows:ServiceType>
+    <ows:ServiceTypeVersion>1.0.0</ows:ServiceTypeVersion>
+  </ows:ServiceIdentification>
+  <Contents>
+{layers}{tile_matrix_set}  </Contents>
+  <ServiceMetadataURL xlink:href="{url}"/>
+</Capabilities>
+"#,
+    title = escape(title),
+    description = escape(description),
+    layers = layers,
+    tile_matrix_set = tile_matrix_set(),
+    url = escape(links::wmts_capabilities_url(base_url).as_str())
+  )
+}
+
+/// the Layer for a collection. Its tiles are served by the WMTS tile endpoint.
+fn layer(base_url: &Url, collection: &stac::Collection) -> String {
+  let [minx, miny, maxx, maxy] = collection.extent.spatial.bbox.first().copied().unwrap_or([-180., -90., 180., 90.]);
+  format!(
+r#"    <Layer>
+      <ows:Title>{title}</ows:Title>
+      <ows:Abstract>{description}</ows:Abstract>
+      <ows:WGS84BoundingBox>
+        <ows:LowerCorner>{minx} {miny}</ows:LowerCorner>
+        <ows:UpperCorner>{maxx} {maxy}</ows:UpperCorner>
+      </ows:WGS84BoundingBox>
+      <ows:Identifier>{id}</ows:Identifier>
+      <Style isDefault="true">
+        <ows:Identifier>default</ows:Identifier>
+      </Style>
+      <Format>image/png</Format>
+      <TileMatrixSetLink>
+        <TileMatrixSet>{tile_matrix_set}</TileMatrixSet>
+      </TileMatrixSetLink>
+      <ResourceURL format="image/png" resourceType="tile" template="{template}"/>
+    </Layer>
+"#,
+    title = escape(&collection.title),
+    description = escape(&collection.description),
+    minx = minx,
+    miny = miny,
+    maxx = maxx,
+    maxy = maxy,
+    id = escape(&collection.id),
+    tile_matrix_set = TILE_MATRIX_SET,
+    template = escape(&links::wmts_tile_template(base_url, &collection.id))
+  )
+}
+
+/// the WebMercatorQuad TileMatrixSet, with a TileMatrix for each zoom level up to MAX_ZOOM.
+fn tile_matrix_set() -> String {
+  let matrices: String = (0..=MAX_ZOOM).map(|z| {
+    let n = 1u32 << z;
+    format!(
+r#"      <TileMatrix>
+        <ows:Identifier>{z}</ows:Identifier>
+        <ScaleDenominator>{scale}</ScaleDenominator>
+        <TopLeftCorner>{left} {top}</TopLeftCorner>
+        <TileWidth>{size}</TileWidth>
+        <TileHeight>{size}</TileHeight>
+        <MatrixWidth>{n}</MatrixWidth>
+        <MatrixHeight>{n}</MatrixHeight>
+      </TileMatrix>
+"#,
+      z = z,
+      scale = ZOOM_0_SCALE_DENOMINATOR / n as f64,
+      left = -ORIGIN,
+      top = ORIGIN,
+      size = TILE_SIZE,
+      n = n
+    )
+  }).collect();
+  format!(
+r#"    <TileMatrixSet>
+      <ows:Identifier>{id}</ows:Identifier>
+      <ows:SupportedCRS>urn:ogc:def:crs:EPSG::3857</ows:SupportedCRS>
+      <WellKnownScaleSet>urn:ogc:def:wkss:OGC:1.0:GoogleMapsCompatible</WellKnownScaleSet>
+{matrices}    </TileMatrixSet>
+"#,
+    id = TILE_MATRIX_SET,
+    matrices = matrices
+  )
+}